@@ -0,0 +1,83 @@
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use fret_dance_rust::guitar::guitar_chord::{
+    Chord, NotePosition, get_note_positions, iter_playable_chords,
+};
+use fret_dance_rust::guitar::guitar_instance::Guitar;
+use fret_dance_rust::guitar::instrument_profile::InstrumentProfile;
+
+/// 旧的做法：先计算完整的笛卡尔积，再逐个检查是否可演奏
+fn naive_convert_notes_to_chord(notes: &[i32], guitar: &Guitar) -> Vec<Chord> {
+    let note_positions = get_note_positions(notes, guitar);
+    if note_positions.is_empty() {
+        return vec![];
+    }
+
+    let mut combinations: Vec<Vec<NotePosition>> = vec![vec![]];
+    for inner_vec in &note_positions {
+        if inner_vec.is_empty() {
+            continue;
+        }
+        let mut temp = Vec::new();
+        for existing in &combinations {
+            for item in inner_vec {
+                let mut new_vec = existing.clone();
+                new_vec.push(item.clone());
+                temp.push(new_vec);
+            }
+        }
+        combinations = temp;
+    }
+
+    combinations
+        .into_iter()
+        .map(Chord::new)
+        .filter(|chord| chord.is_playable())
+        .collect()
+}
+
+/// 一段由六音和弦组成的密集段落
+fn dense_chord_passage() -> Vec<Vec<i32>> {
+    vec![
+        vec![40, 47, 52, 55, 59, 64], // Em
+        vec![43, 47, 50, 55, 59, 67], // G
+        vec![48, 52, 55, 60, 64, 67], // C
+        vec![50, 57, 62, 66, 69, 74], // D
+        vec![45, 52, 57, 60, 64, 69], // Am
+        vec![41, 48, 53, 57, 60, 65], // F
+        vec![47, 54, 59, 62, 66, 71], // Bm
+        vec![52, 59, 64, 68, 71, 76], // E (高把位)
+    ]
+}
+
+fn benchmark_chord_enumeration(c: &mut Criterion) {
+    let guitar = InstrumentProfile::default().create_guitar(true);
+    let passage = dense_chord_passage();
+
+    // 两种做法的结果必须一致
+    for notes in &passage {
+        assert_eq!(
+            naive_convert_notes_to_chord(notes, &guitar).len(),
+            iter_playable_chords(notes, &guitar).count()
+        );
+    }
+
+    let mut group = c.benchmark_group("chord_enumeration");
+    group.bench_function("cartesian_product", |b| {
+        b.iter(|| {
+            for notes in &passage {
+                black_box(naive_convert_notes_to_chord(black_box(notes), &guitar));
+            }
+        })
+    });
+    group.bench_function("backtracking", |b| {
+        b.iter(|| {
+            for notes in &passage {
+                black_box(iter_playable_chords(black_box(notes), &guitar).collect::<Vec<_>>());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_chord_enumeration);
+criterion_main!(benches);
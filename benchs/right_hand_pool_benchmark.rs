@@ -1,5 +1,10 @@
 use criterion::{Criterion, criterion_group, criterion_main};
+use fret_dance_rust::guitar::instrument_profile::InstrumentProfile;
+use fret_dance_rust::hand::left_finger::LeftFinger;
+use fret_dance_rust::hand::left_hand::LeftHand;
 use fret_dance_rust::hand::right_hand::RightHand;
+use fret_dance_rust::midi::midi_to_note::{MidiProcessor, NoteInfo};
+use fret_dance_rust::recorder::left_hand_recorder::LeftHandRecorder;
 use fret_dance_rust::recorder::recorder_pool::{HandPoseRecordPool, HandRecorder};
 use fret_dance_rust::recorder::right_hand_recorder::RightHandRecorder;
 
@@ -67,5 +72,93 @@ fn benchmark_update_right_hand_recorder_pool(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, benchmark_update_right_hand_recorder_pool);
+/// 生成一段确定的合成曲子，每小节先弹一个和弦，再弹七个旋律音
+fn synthetic_piece(event_count: usize) -> Vec<NoteInfo> {
+    let chords: [Vec<i32>; 4] = [
+        vec![48, 52, 55, 60, 64],
+        vec![45, 52, 57, 60, 64],
+        vec![41, 48, 53, 57, 60, 65],
+        vec![43, 47, 50, 55, 59, 67],
+    ];
+    let melody = [64, 67, 69, 71, 72, 74, 76, 79, 77, 74, 72, 69];
+
+    (0..event_count)
+        .map(|i| {
+            let notes = if i % 8 == 0 {
+                chords[(i / 8) % chords.len()].clone()
+            } else {
+                // 用简单的线性同余让旋律不是单纯的循环
+                let step = (i * 7 + i / 8 * 3) % melody.len();
+                vec![melody[step]]
+            };
+            NoteInfo {
+                notes,
+                real_tick: i as f64 * 240.0,
+                string_indices: Vec::new(),
+            }
+        })
+        .collect()
+}
+
+fn benchmark_update_left_hand_recorder_pool(c: &mut Criterion) {
+    let guitar = InstrumentProfile::default().create_guitar(false);
+    let notes_map = synthetic_piece(5000);
+    let midi_processor = MidiProcessor::new();
+
+    let mut group = c.benchmark_group("left_hand_pool");
+    group.sample_size(10);
+    let available_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut thread_counts = vec![1];
+    if available_threads > 1 {
+        thread_counts.push(available_threads);
+    }
+
+    for thread_count in thread_counts {
+        let name = format!(
+            "update_left_handrecorder_pool_5000_events_{}_threads",
+            thread_count
+        );
+        group.bench_function(&name, |b| {
+            b.iter(|| {
+                let init_string = &guitar.guitar_strings[2];
+                let fingers = (1..5)
+                    .map(|i| LeftFinger::new(i, init_string, i, "Pressed"))
+                    .collect();
+                let mut init_left_hand_recorder = LeftHandRecorder::new();
+                init_left_hand_recorder.add_hand_pose(
+                    LeftHand::new(fingers, false, 5.73),
+                    0.0,
+                    0.0,
+                );
+
+                let mut left_hand_record_pool = HandPoseRecordPool::new(100);
+                left_hand_record_pool.set_thread_count(thread_count);
+                left_hand_record_pool.insert_new_hand_pose_recorder(
+                    HandRecorder::Left(init_left_hand_recorder),
+                    Some(0),
+                );
+
+                let mut current_recorder_num = 0;
+                let mut previous_recorder_num = 0;
+                left_hand_record_pool.update_left_handrecorder_pool(
+                    &guitar,
+                    &notes_map,
+                    &midi_processor,
+                    &mut current_recorder_num,
+                    &mut previous_recorder_num,
+                    |_| {},
+                );
+
+                left_hand_record_pool.get_best_recorder().current_entropy()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    benchmark_update_right_hand_recorder_pool,
+    benchmark_update_left_hand_recorder_pool
+);
 criterion_main!(benches);
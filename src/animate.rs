@@ -1 +1,2 @@
 pub mod animator;
+pub mod handedness;
@@ -5,14 +5,27 @@ use std::error::Error;
 use std::fs::File;
 use std::io::BufReader;
 
+use crate::animate::handedness::{Handedness, is_rotation_controller};
 use crate::hand::left_finger::PressState;
 use crate::midi::midi_to_note::PitchWheelInfo;
+use crate::recorder::finger_hold::FingerRelease;
+use crate::recorder::legato::{LegatoInfo, LegatoTechnique};
+use crate::recorder::long_rest::LongRest;
 use crate::utils::util_methods::{
     Quaternion, Vector3, add_vectors, get_string_touch_position, lerp_by_fret_quaternion,
     lerp_by_fret_vector3, lerp_by_weight_vector3, scale_vector, slerp, subtract_vectors,
     vector_norm,
 };
 
+/// 长休止时双手移动到休息姿势、以及从休息姿势回来需要的秒数
+const REST_POSE_TRANSITION_SECONDS: f64 = 0.3;
+
+/// 人物没有定义休息姿势时，手离开琴弦的距离，相对于按弦距离的比例
+const REST_POSE_LIFT_RATIO: f64 = 10.0;
+
+/// 闷音时手指离开指板的距离，相对于不按弦的手指抬起距离的比例
+const MUTE_TOUCH_RATIO: f64 = 0.3;
+
 /// 左手手指索引字典常量
 const LEFT_FINGER_INDEX_DICT: [(i32, &str); 4] = [
     (1, "I_L"), // 食指
@@ -66,6 +79,10 @@ pub struct Animator {
     fps: f64,
     /// 乐器最大弦索引
     max_string_index: f64,
+    /// 持琴方式，左手持琴时输出的动画会被镜像
+    handedness: Handedness,
+    /// 曲子里的长休止，双手在长休止时回到休息姿势
+    long_rests: Vec<LongRest>,
 
     /// 三种扫弦方式的数据
     arpeggio_patterns: Vec<ArpeggioPattern>,
@@ -109,12 +126,30 @@ impl Animator {
             animation_file,
             fps,
             max_string_index,
+            handedness: Handedness::default(),
+            long_rests: Vec::new(),
             arpeggio_patterns,
             current_arpeggio_pattern_index,
             current_arpeggio_pattern_index_call_count,
         })
     }
 
+    /// 设置持琴方式，左撇子角色的动画会在输出时整体镜像
+    pub fn with_handedness(mut self, handedness: Handedness) -> Self {
+        self.handedness = handedness;
+        self
+    }
+
+    /// 设置曲子里的长休止，双手会在长休止时回到休息姿势
+    pub fn with_long_rests(mut self, long_rests: Vec<LongRest>) -> Self {
+        self.long_rests = long_rests;
+        self
+    }
+
+    pub fn long_rests(&self) -> &[LongRest] {
+        &self.long_rests
+    }
+
     /// 获取avatar文件路径
     pub fn avatar_file(&self) -> &str {
         &self.avatar_file
@@ -325,79 +360,13 @@ impl Animator {
     /// * `is_electric` - 是否为电琴
     pub fn left_hand_2_animation(&self, disable_barre: bool) -> Result<(), Box<dyn Error>> {
         // 这是人物按下弦需要的时间，还是挺快的
-        let press_duration = self.fps / 16.0;
+        let press_duration = self.press_duration();
         // 这个就是两个不同姿势之间切换时需要的帧数
         let elapsed_frame = press_duration * 3.0;
         // 这是手指从按弦变成松开需要的帧数
         let finger_return_to_rest_frame = press_duration * 1.2;
 
-        // 获取手指位置数据
-        let left_finger_positions = self
-            .get_avatar_field("LEFT_FINGER_POSITIONS")
-            .ok_or("Missing LEFT_FINGER_POSITIONS in avatar data")?;
-
-        let finger_position_p0: Vec<f64> = left_finger_positions
-            .get("P0")
-            .ok_or("Missing P0 in LEFT_FINGER_POSITIONS")?
-            .as_array()
-            .ok_or("P0 is not an array")?
-            .iter()
-            .map(|v| v.as_f64().ok_or("P0 values are not numbers"))
-            .collect::<Result<Vec<f64>, _>>()?;
-
-        let finger_position_p1: Vec<f64> = left_finger_positions
-            .get("P1")
-            .ok_or("Missing P1 in LEFT_FINGER_POSITIONS")?
-            .as_array()
-            .ok_or("P1 is not an array")?
-            .iter()
-            .map(|v| v.as_f64().ok_or("P1 values are not numbers"))
-            .collect::<Result<Vec<f64>, _>>()?;
-
-        let finger_position_p2: Vec<f64> = left_finger_positions
-            .get("P2")
-            .ok_or("Missing P2 in LEFT_FINGER_POSITIONS")?
-            .as_array()
-            .ok_or("P2 is not an array")?
-            .iter()
-            .map(|v| v.as_f64().ok_or("P2 values are not numbers"))
-            .collect::<Result<Vec<f64>, _>>()?;
-
-        // 计算p0和p1的距离
-        let p0_p1_diff: Vec<f64> = finger_position_p0
-            .iter()
-            .zip(finger_position_p1.iter())
-            .map(|(a, b)| a - b)
-            .collect();
-
-        let p0_p1_distance = p0_p1_diff.iter().map(|x| x * x).sum::<f64>().sqrt();
-
-        // 按弦接下的距离直接取p0和p1距离的1/5
-        let press_distance = p0_p1_distance / 5.0;
-
-        // 计算法向量
-        let p0_p1: Vec<f64> = finger_position_p0
-            .iter()
-            .zip(finger_position_p1.iter())
-            .map(|(a, b)| a - b)
-            .collect();
-
-        let p2_p1: Vec<f64> = finger_position_p2
-            .iter()
-            .zip(finger_position_p1.iter())
-            .map(|(a, b)| a - b)
-            .collect();
-
-        // 计算叉积
-        let normal = vec![
-            p0_p1[1] * p2_p1[2] - p0_p1[2] * p2_p1[1],
-            p0_p1[2] * p2_p1[0] - p0_p1[0] * p2_p1[2],
-            p0_p1[0] * p2_p1[1] - p0_p1[1] * p2_p1[0],
-        ];
-
-        let normal_length = normal.iter().map(|x| x * x).sum::<f64>().sqrt();
-
-        let normal: Vec<f64> = normal.iter().map(|x| x / normal_length).collect();
+        let (normal, press_distance) = self.fretboard_normal()?;
 
         // 读取记录文件
         let file = File::open(self.left_hand_recorder_file.clone())?;
@@ -436,6 +405,12 @@ impl Animator {
             // 获取需要抬指的手指索引集合
             let mut finger_index_set_need_to_change = std::collections::HashSet::new();
 
+            // 下一个音如果是连奏，就不用抬指再按下
+            let next_legato = hand_dicts
+                .get(i + 1)
+                .and_then(|next_item| next_item.get("legato"))
+                .and_then(|v| serde_json::from_value::<LegatoInfo>(v.clone()).ok());
+
             // 初始化下一帧信息
             let (next_frame, next_finger_infos, next_pitchwheel) = if i != hand_dicts.len() - 1 {
                 let next_item = &hand_dicts[i + 1];
@@ -559,10 +534,111 @@ impl Animator {
             }));
 
             // 插入中间帧
+            if let (Some(legato), Some(next_frame), Some(next_finger_infos)) =
+                (&next_legato, next_frame, next_finger_infos.as_ref())
+            {
+                // 击弦的手指要先抬高一些再砸下去
+                let next_ready_state = if legato.technique == LegatoTechnique::HammerOn {
+                    self.raise_finger(
+                        next_finger_infos,
+                        legato.finger_index,
+                        2.0 * press_distance,
+                        &normal,
+                    )?
+                } else {
+                    next_finger_infos.clone()
+                };
+                let frames_to_insert = self.interpolate_legato_frames(
+                    frame,
+                    next_frame,
+                    &current_finger_infos,
+                    &next_ready_state,
+                    legato,
+                    pitchwheel,
+                )?;
+                for frame_data in frames_to_insert {
+                    data_for_animation.push(Value::Object(frame_data));
+                }
+                continue;
+            }
+
+            // 音符提前结束的手指在结束时抬起，之后的插值帧都从抬起以后的状态开始
+            let release_limit = match next_frame {
+                Some(next_frame) => {
+                    next_frame - press_duration - elapsed_frame - finger_return_to_rest_frame
+                }
+                None => frame + finger_return_to_rest_frame,
+            };
+            let mut releases: Vec<FingerRelease> = item
+                .get("releases")
+                .and_then(|v| serde_json::from_value(v.clone()).ok())
+                .unwrap_or_default();
+            releases.retain(|release| {
+                release.frame > frame && release.frame + press_duration <= release_limit
+            });
+            releases.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+
+            let mut released_state = current_finger_infos.clone();
+            let mut release_frames: Vec<(f64, Map<String, Value>)> = Vec::new();
+            for release in &releases {
+                // 小拇指抬得高一些，和休息状态一致
+                let distance = if release.finger_index == 4 {
+                    2.0 * press_distance
+                } else {
+                    press_distance
+                };
+                let merged = release_frames
+                    .last()
+                    .is_some_and(|(lifted_frame, _)| release.frame < *lifted_frame);
+                if !merged {
+                    // 保持按弦直到音符结束
+                    release_frames.push((release.frame, released_state.clone()));
+                }
+                released_state =
+                    self.raise_finger(&released_state, release.finger_index, distance, &normal)?;
+                if merged {
+                    // 和上一根手指几乎同时松开，一起抬起
+                    if let Some((_, state)) = release_frames.last_mut() {
+                        *state = released_state.clone();
+                    }
+                } else {
+                    release_frames.push((release.frame + press_duration, released_state.clone()));
+                }
+            }
+            let released_frame = release_frames
+                .last()
+                .map_or(frame, |(lifted_frame, _)| *lifted_frame);
+            for (release_frame, state) in release_frames {
+                data_for_animation.push(serde_json::json!({
+                    "frame": release_frame,
+                    "fingerInfos": state,
+                    "pitchwheel": pitchwheel
+                }));
+            }
+
+            // 长休止时手指全部抬起，手移动到休息姿势，在下一个音之前回来
+            if let (Some(next_frame), Some(next_finger_infos)) =
+                (next_frame, next_finger_infos.as_ref())
+                && let Some(rest_frames) = self.left_hand_rest_frames(
+                    frame,
+                    next_frame,
+                    released_frame.max(frame + press_duration),
+                    &released_state,
+                    next_finger_infos,
+                    &normal,
+                    press_distance,
+                    press_duration,
+                    (pitchwheel, next_pitchwheel),
+                )?
+            {
+                data_for_animation.extend(rest_frames);
+                continue;
+            }
+
             let frames_to_insert = self.interpolate_left_hand_frames(
                 frame,
                 next_frame,
-                &current_finger_infos,
+                &released_state,
                 next_finger_infos.as_ref(),
                 &finger_index_set_need_to_change,
                 &normal,
@@ -588,6 +664,7 @@ impl Animator {
             return Err(format!("无效的动画文件路径: {:?}", self.animation_file).into());
         }
 
+        let data_for_animation = self.handedness.mirror_animation(data_for_animation);
         let file = File::create(self.animation_file.clone())?;
         serde_json::to_writer_pretty(file, &data_for_animation)?;
 
@@ -622,6 +699,11 @@ impl Animator {
                 continue;
             }
 
+            // 击弦、勾弦和滑弦由左手发声，右手不用拨弦
+            if item.get("legato").is_some_and(|v| !v.is_null()) {
+                continue;
+            }
+
             let left_hand = item
                 .get("left_hand")
                 .and_then(|v| v.as_array())
@@ -656,7 +738,8 @@ impl Animator {
                     .unwrap_or(-1);
 
                 // 如果是无效手指索引或者按压力度在0到5之间（不包括0和5），则添加弦索引
-                if finger_index == -1 || (press > 0.0 && press < 5.0) {
+                // 闷音的弦同样需要右手拨弦
+                if finger_index == -1 || (press > 0.0 && press < 5.0) || press == 6.0 {
                     strings.push(string_index);
                 }
             }
@@ -722,6 +805,7 @@ impl Animator {
             let played_frame = frame + elapsed_frame * time_multiplier as f64;
 
             let mut played_finished_frame = None;
+            let mut long_rest_window = None;
             if i < right_hand_data.len() - 1 {
                 let next_frame = right_hand_data[i + 1]
                     .get("frame")
@@ -731,6 +815,12 @@ impl Animator {
                 if next_frame > played_frame + elapsed_frame {
                     played_finished_frame = Some(played_frame + elapsed_frame);
                 }
+                long_rest_window = self.long_rest_window(
+                    frame,
+                    next_frame,
+                    played_frame + elapsed_frame,
+                    next_frame - self.rest_transition_frames(),
+                );
             }
 
             // 如果pick当前的位置是在最低弦下面，那么以最低弦为演奏弦并且上扫弦
@@ -788,9 +878,27 @@ impl Animator {
                     "fingerInfos": played
                 }));
             }
+
+            // 长休止时手离开琴弦回到休息姿势，在下一个音之前回来
+            if let Some((leave_frame, return_frame)) = long_rest_window {
+                let rest_state = self.rest_pose("RIGHT", &played)?;
+                data_for_animation.push(serde_json::json!({
+                    "frame": leave_frame,
+                    "fingerInfos": played
+                }));
+                data_for_animation.push(serde_json::json!({
+                    "frame": leave_frame + self.rest_transition_frames(),
+                    "fingerInfos": rest_state
+                }));
+                data_for_animation.push(serde_json::json!({
+                    "frame": return_frame,
+                    "fingerInfos": rest_state
+                }));
+            }
         }
 
         // 写入动画文件
+        let data_for_animation = self.handedness.mirror_animation(data_for_animation);
         let file = File::create(animation_file)?;
         serde_json::to_writer_pretty(file, &data_for_animation)?;
 
@@ -846,20 +954,28 @@ impl Animator {
             };
             let played_frame = frame + elapsed_frame * time_multiplier;
 
-            let played_finished_frame = if i != hand_count - 1 {
-                let next_frame = hand_dicts[i + 1]
-                    .get("frame")
-                    .and_then(|v| v.as_f64())
-                    .ok_or("Missing frame in next data")?;
-                if next_frame > played_frame + 2.0 * elapsed_frame {
-                    // 这个2 * elapsed_frame 相当于留给手掌移动到下一个位置的时间
-                    Some(next_frame - 2.0 * elapsed_frame)
-                } else {
-                    None
-                }
+            let next_frame = if i != hand_count - 1 {
+                Some(
+                    hand_dicts[i + 1]
+                        .get("frame")
+                        .and_then(|v| v.as_f64())
+                        .ok_or("Missing frame in next data")?,
+                )
             } else {
                 None
             };
+            // 这个2 * elapsed_frame 相当于留给手掌移动到下一个位置的时间
+            let played_finished_frame = next_frame
+                .filter(|&next_frame| next_frame > played_frame + 2.0 * elapsed_frame)
+                .map(|next_frame| next_frame - 2.0 * elapsed_frame);
+            let long_rest_window = next_frame.and_then(|next_frame| {
+                self.long_rest_window(
+                    frame,
+                    next_frame,
+                    played_frame + elapsed_frame,
+                    next_frame - self.rest_transition_frames(),
+                )
+            });
 
             let ready = self.calculate_right_hand_fingers(
                 right_finger_positions,
@@ -889,8 +1005,23 @@ impl Animator {
                 "fingerInfos": played,
             }));
 
-            // 拨弦后慢慢弹回来
-            if let Some(finished_frame) = played_finished_frame {
+            if let Some((leave_frame, return_frame)) = long_rest_window {
+                // 长休止时手离开琴弦回到休息姿势，在下一个音之前回来
+                let rest_state = self.rest_pose("RIGHT", &ready)?;
+                data_for_animation.push(serde_json::json!({
+                    "frame": leave_frame,
+                    "fingerInfos": ready,
+                }));
+                data_for_animation.push(serde_json::json!({
+                    "frame": leave_frame + self.rest_transition_frames(),
+                    "fingerInfos": rest_state,
+                }));
+                data_for_animation.push(serde_json::json!({
+                    "frame": return_frame,
+                    "fingerInfos": rest_state,
+                }));
+            } else if let Some(finished_frame) = played_finished_frame {
+                // 拨弦后慢慢弹回来
                 data_for_animation.push(serde_json::json!({
                     "frame": finished_frame,
                     "fingerInfos": ready,
@@ -899,6 +1030,7 @@ impl Animator {
         }
 
         // 写入动画文件
+        let data_for_animation = self.handedness.mirror_animation(data_for_animation);
         let file = File::create(animation_file)?;
         serde_json::to_writer_pretty(file, &data_for_animation)?;
 
@@ -1443,6 +1575,8 @@ impl Animator {
 
             // 创建一个映射来存储每根弦的最高品级
             let mut string_fret_map: HashMap<i64, i64> = HashMap::new();
+            // 左手轻触制音的弦，不产生振动
+            let mut muted_strings: Vec<i64> = Vec::new();
 
             if let Some(fingers) = left_hand.as_array() {
                 // 第一次遍历：确定每根弦的最高品级
@@ -1464,6 +1598,10 @@ impl Animator {
                     }
 
                     let string_index = finger_info["string_index"].as_i64().unwrap_or(0);
+                    if press == 6 {
+                        muted_strings.push(string_index);
+                        continue;
+                    }
                     let fret = if finger_index == -1 {
                         0
                     } else {
@@ -1493,6 +1631,9 @@ impl Animator {
 
                 // 第二次遍历：为每根需要振动的弦生成动画数据
                 for (string_index, fret) in &string_fret_map {
+                    if muted_strings.contains(string_index) {
+                        continue;
+                    }
                     let ready = serde_json::json!({
                         "frame": frame - 1.0,
                         "stringIndex": string_index,
@@ -1582,6 +1723,8 @@ impl Animator {
         let mut finger_infos = Map::new();
         let mut barre_finger_string_index = 0.0;
         let mut finger_string_numbers = HashMap::new();
+        // 拇指从琴颈上方按弦时的位置
+        let mut thumb_over_position = None;
 
         // 初始化手指弦号统计
         for i in 1..=4 {
@@ -1626,6 +1769,12 @@ impl Animator {
                 continue;
             }
 
+            // 拇指按弦时，大拇指的控制器直接放到按弦的位置上
+            if finger_index == 0 {
+                thumb_over_position = Some(self.twice_lerp_fingers(fret, string_index)?);
+                continue;
+            }
+
             // 不按弦的手指会稍微移动，以避免和按弦的手指挤在一起
             if press == 0 {
                 // PRESSSTATE['Open']
@@ -1672,6 +1821,13 @@ impl Animator {
                         }
                     }
 
+                    // 闷音的手指只轻触琴弦，比按弦的位置稍高一点
+                    if press == 6 {
+                        for i in 0..3 {
+                            pos[i] -= normal[i] * rest_finger_distance * MUTE_TOUCH_RATIO;
+                        }
+                    }
+
                     let name = match finger_index {
                         1 => "I_L".to_string(),
                         2 => "M_L".to_string(),
@@ -1767,7 +1923,8 @@ impl Animator {
             ),
         );
 
-        let thumb_position_vec = thumb_position.to_vector64();
+        let thumb_position_vec =
+            thumb_over_position.unwrap_or_else(|| thumb_position.to_vector64());
 
         finger_infos.insert(
             "T_L".to_string(),
@@ -1981,6 +2138,323 @@ impl Animator {
         Ok(frames_to_insert)
     }
 
+    /// 人物按下弦需要的帧数
+    fn press_duration(&self) -> f64 {
+        self.fps / 16.0
+    }
+
+    /// 指板的法向量和按弦时手指移动的距离，手指沿法向量的反方向抬起
+    fn fretboard_normal(&self) -> Result<(Vec<f64>, f64), Box<dyn Error>> {
+        // 获取手指位置数据
+        let left_finger_positions = self
+            .get_avatar_field("LEFT_FINGER_POSITIONS")
+            .ok_or("Missing LEFT_FINGER_POSITIONS in avatar data")?;
+
+        let finger_position_p0: Vec<f64> = left_finger_positions
+            .get("P0")
+            .ok_or("Missing P0 in LEFT_FINGER_POSITIONS")?
+            .as_array()
+            .ok_or("P0 is not an array")?
+            .iter()
+            .map(|v| v.as_f64().ok_or("P0 values are not numbers"))
+            .collect::<Result<Vec<f64>, _>>()?;
+
+        let finger_position_p1: Vec<f64> = left_finger_positions
+            .get("P1")
+            .ok_or("Missing P1 in LEFT_FINGER_POSITIONS")?
+            .as_array()
+            .ok_or("P1 is not an array")?
+            .iter()
+            .map(|v| v.as_f64().ok_or("P1 values are not numbers"))
+            .collect::<Result<Vec<f64>, _>>()?;
+
+        let finger_position_p2: Vec<f64> = left_finger_positions
+            .get("P2")
+            .ok_or("Missing P2 in LEFT_FINGER_POSITIONS")?
+            .as_array()
+            .ok_or("P2 is not an array")?
+            .iter()
+            .map(|v| v.as_f64().ok_or("P2 values are not numbers"))
+            .collect::<Result<Vec<f64>, _>>()?;
+
+        // 计算p0和p1的距离
+        let p0_p1_diff: Vec<f64> = finger_position_p0
+            .iter()
+            .zip(finger_position_p1.iter())
+            .map(|(a, b)| a - b)
+            .collect();
+
+        let p0_p1_distance = p0_p1_diff.iter().map(|x| x * x).sum::<f64>().sqrt();
+
+        // 按弦接下的距离直接取p0和p1距离的1/5
+        let press_distance = p0_p1_distance / 5.0;
+
+        // 计算法向量
+        let p0_p1: Vec<f64> = finger_position_p0
+            .iter()
+            .zip(finger_position_p1.iter())
+            .map(|(a, b)| a - b)
+            .collect();
+
+        let p2_p1: Vec<f64> = finger_position_p2
+            .iter()
+            .zip(finger_position_p1.iter())
+            .map(|(a, b)| a - b)
+            .collect();
+
+        // 计算叉积
+        let normal = [
+            p0_p1[1] * p2_p1[2] - p0_p1[2] * p2_p1[1],
+            p0_p1[2] * p2_p1[0] - p0_p1[0] * p2_p1[2],
+            p0_p1[0] * p2_p1[1] - p0_p1[1] * p2_p1[0],
+        ];
+
+        let normal_length = normal.iter().map(|x| x * x).sum::<f64>().sqrt();
+
+        let normal: Vec<f64> = normal.iter().map(|x| x / normal_length).collect();
+
+        Ok((normal, press_distance))
+    }
+
+    /// 移动到休息姿势需要的帧数
+    fn rest_transition_frames(&self) -> f64 {
+        self.fps * REST_POSE_TRANSITION_SECONDS
+    }
+
+    /// frame和next_frame之间的长休止里双手休息的时间，返回离开的帧和最晚开始返回的帧
+    ///
+    /// 两个音之间没有长休止，或者来不及移动到休息姿势再回来时返回None
+    ///
+    /// # 参数
+    /// * `earliest` - 最早可以离开的帧，当前的动作要先做完
+    /// * `latest` - 最晚开始返回的帧，要留出回到下一个音的时间
+    fn long_rest_window(
+        &self,
+        frame: f64,
+        next_frame: f64,
+        earliest: f64,
+        latest: f64,
+    ) -> Option<(f64, f64)> {
+        let rest = self.long_rests.iter().find(|rest| {
+            rest.start_frame >= frame && rest.end_frame <= next_frame + f64::EPSILON
+        })?;
+        let leave_frame = rest.start_frame.max(earliest);
+        (leave_frame + 2.0 * self.rest_transition_frames() <= latest)
+            .then_some((leave_frame, latest))
+    }
+
+    /// 长休止时左手的关键帧，没有长休止或者来不及休息时返回None
+    ///
+    /// 手指全部抬起，手移动到休息姿势，在下一个音之前用和右手相同的时间回到下一个音的预备状态
+    ///
+    /// # 参数
+    /// * `earliest` - 最早可以离开的帧，按弦和提前松开的动作要先做完
+    /// * `beat_state` - 当前的按弦状态，提前松开的手指已经抬起
+    /// * `next_ready_state` - 下一个音的预备状态
+    /// * `pitchwheels` - 当前和下一个音的弯音
+    #[allow(clippy::too_many_arguments)]
+    fn left_hand_rest_frames(
+        &self,
+        frame: f64,
+        next_frame: f64,
+        earliest: f64,
+        beat_state: &Map<String, Value>,
+        next_ready_state: &Map<String, Value>,
+        normal: &[f64],
+        press_distance: f64,
+        press_duration: f64,
+        pitchwheels: (i32, i32),
+    ) -> Result<Option<Vec<Value>>, Box<dyn Error>> {
+        let Some((leave_frame, return_frame)) = self.long_rest_window(
+            frame,
+            next_frame,
+            earliest,
+            next_frame - self.rest_transition_frames().max(press_duration),
+        ) else {
+            return Ok(None);
+        };
+        let (pitchwheel, next_pitchwheel) = pitchwheels;
+
+        let all_fingers = LEFT_FINGER_INDEX_DICT
+            .iter()
+            .map(|&(finger_index, _)| finger_index)
+            .collect();
+        let lifted = self.create_rest_state(beat_state, press_distance, &all_fingers, normal)?;
+        let positions: HashMap<String, Vec<f64>> = lifted
+            .iter()
+            .filter_map(|(name, value)| {
+                let values = value
+                    .as_array()?
+                    .iter()
+                    .map(|v| v.as_f64())
+                    .collect::<Option<Vec<f64>>>()?;
+                Some((name.clone(), values))
+            })
+            .collect();
+        let mut rest_state = lifted.clone();
+        for (name, values) in self.rest_pose("LEFT", &positions)? {
+            rest_state.insert(name, serde_json::json!(values));
+        }
+        Ok(Some(vec![
+            serde_json::json!({
+                "frame": leave_frame,
+                "fingerInfos": beat_state,
+                "pitchwheel": pitchwheel
+            }),
+            serde_json::json!({
+                "frame": leave_frame + self.rest_transition_frames(),
+                "fingerInfos": rest_state,
+                "pitchwheel": pitchwheel
+            }),
+            serde_json::json!({
+                "frame": return_frame,
+                "fingerInfos": rest_state,
+                "pitchwheel": pitchwheel
+            }),
+            serde_json::json!({
+                "frame": next_frame - press_duration,
+                "fingerInfos": next_ready_state,
+                "pitchwheel": next_pitchwheel
+            }),
+        ]))
+    }
+
+    /// 长休止时的休息姿势
+    ///
+    /// 人物JSON的REST_POSE里可以分别为LEFT和RIGHT定义控制器的位置或旋转，
+    /// 和其它数据一样按右手持琴录制，没有定义的控制器保持原来的数值；
+    /// 没有定义REST_POSE时整只手沿指板的法向离开琴弦
+    fn rest_pose(
+        &self,
+        side: &str,
+        state: &HashMap<String, Vec<f64>>,
+    ) -> Result<HashMap<String, Vec<f64>>, Box<dyn Error>> {
+        let overrides = self.rest_pose_overrides(side);
+        let mut rest_state = state.clone();
+        if !overrides.is_empty() {
+            rest_state.extend(overrides);
+            return Ok(rest_state);
+        }
+
+        let (normal, press_distance) = self.fretboard_normal()?;
+        let offset = scale_vector(&normal, -REST_POSE_LIFT_RATIO * press_distance);
+        // 只移动位置，旋转保持不变
+        for (_, position) in rest_state
+            .iter_mut()
+            .filter(|(name, values)| values.len() == 3 && !is_rotation_controller(name))
+        {
+            *position = add_vectors(position, &offset);
+        }
+        Ok(rest_state)
+    }
+
+    /// 人物数据里定义的休息姿势，每一项是控制器的名字和数值
+    fn rest_pose_overrides(&self, side: &str) -> HashMap<String, Vec<f64>> {
+        self.get_avatar_nested_field(&["REST_POSE", side])
+            .and_then(|v| v.as_object())
+            .map(|overrides| {
+                overrides
+                    .iter()
+                    .filter_map(|(name, value)| {
+                        let values = value
+                            .as_array()?
+                            .iter()
+                            .map(|v| v.as_f64())
+                            .collect::<Option<Vec<f64>>>()?;
+                        Some((name.clone(), values))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// 连奏时两个音之间的中间帧，手指不抬起再按下
+    ///
+    /// 滑弦时按着弦滑到下一个品格；击弦时手指先抬起来，再快速砸到弦上；勾弦时在下一个音之前快速松开手指
+    ///
+    /// # 参数
+    /// * `next_ready_state` - 下一个音的预备状态，击弦时是抬起击弦手指的状态，其它情况就是下一个音的按弦状态
+    pub fn interpolate_legato_frames(
+        &self,
+        current_frame: f64,
+        next_frame: f64,
+        current_beat_state: &Map<String, Value>,
+        next_ready_state: &Map<String, Value>,
+        legato: &LegatoInfo,
+        pitchwheel: i32,
+    ) -> Result<Vec<Map<String, Value>>, Box<dyn std::error::Error>> {
+        let press_duration = self.press_duration();
+        let mut key_frames = Vec::new();
+
+        match legato.technique {
+            LegatoTechnique::Slide => {
+                // 保持按弦到最后一段时间再滑过去
+                key_frames.push((next_frame - press_duration * 3.0, current_beat_state));
+            }
+            LegatoTechnique::HammerOn => {
+                key_frames.push((next_frame - press_duration, current_beat_state));
+                key_frames.push((next_frame - press_duration * 0.5, next_ready_state));
+            }
+            LegatoTechnique::PullOff => {
+                key_frames.push((next_frame - press_duration * 0.5, current_beat_state));
+            }
+        }
+
+        let mut frames_to_insert = Vec::new();
+        for (time, state) in key_frames {
+            // 时间不够时就去掉这一帧
+            if time <= current_frame || time >= next_frame {
+                continue;
+            }
+            let mut frame_data = Map::new();
+            frame_data.insert(
+                "frame".to_string(),
+                Value::Number(serde_json::Number::from_f64(time).unwrap()),
+            );
+            frame_data.insert("fingerInfos".to_string(), Value::Object(state.clone()));
+            frame_data.insert("pitchwheel".to_string(), Value::Number(pitchwheel.into()));
+            frames_to_insert.push(frame_data);
+        }
+
+        Ok(frames_to_insert)
+    }
+
+    /// 把一根手指沿着指板法向量抬高一段距离
+    fn raise_finger(
+        &self,
+        state: &Map<String, Value>,
+        finger_index: i32,
+        distance: f64,
+        normal: &[f64],
+    ) -> Result<Map<String, Value>, Box<dyn std::error::Error>> {
+        let mut raised_state = state.clone();
+        let Some(&(_, controller_name)) = LEFT_FINGER_INDEX_DICT
+            .iter()
+            .find(|&&(idx, _)| idx == finger_index)
+        else {
+            return Ok(raised_state);
+        };
+
+        if let Some(position_value) = state.get(controller_name) {
+            let position_array = position_value
+                .as_array()
+                .ok_or(format!("{} is not an array", controller_name))?;
+            let new_position = position_array
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let pos = v.as_f64().ok_or("Position component is not a number")?;
+                    Ok(Value::Number(
+                        serde_json::Number::from_f64(pos - normal[i] * distance).unwrap(),
+                    ))
+                })
+                .collect::<Result<Vec<Value>, Box<dyn std::error::Error>>>()?;
+            raised_state.insert(controller_name.to_string(), Value::Array(new_position));
+        }
+
+        Ok(raised_state)
+    }
+
     pub fn twice_lerp_vector3(
         &self,
         hand_state: i32,
@@ -3508,3 +3982,119 @@ impl Animator {
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 指板在xy平面上，按弦距离是0.01的人物
+    fn animator_with_rest(rest_pose: Option<Value>) -> Animator {
+        let mut avatar_info = serde_json::json!({
+            "LEFT_FINGER_POSITIONS": {
+                "P0": [0.0, 0.05, 0.0],
+                "P1": [0.0, 0.0, 0.0],
+                "P2": [0.5, 0.0, 0.0],
+            }
+        });
+        if let Some(rest_pose) = rest_pose {
+            avatar_info["REST_POSE"] = rest_pose;
+        }
+        Animator {
+            avatar_info,
+            avatar_file: String::new(),
+            left_hand_recorder_file: String::new(),
+            animation_file: String::new(),
+            fps: 32.0,
+            max_string_index: 5.0,
+            handedness: Handedness::default(),
+            long_rests: vec![LongRest {
+                start_tick: 960.0,
+                end_tick: 3840.0,
+                start_frame: 40.0,
+                end_frame: 100.0,
+            }],
+            arpeggio_patterns: Vec::new(),
+            current_arpeggio_pattern_index: 0,
+            current_arpeggio_pattern_index_call_count: 0,
+        }
+    }
+
+    fn state(entries: &[(&str, Vec<f64>)]) -> Map<String, Value> {
+        entries
+            .iter()
+            .map(|(name, values)| (name.to_string(), serde_json::json!(values)))
+            .collect()
+    }
+
+    fn values(frame: &Value, name: &str) -> Vec<f64> {
+        serde_json::from_value(frame["fingerInfos"][name].clone()).unwrap()
+    }
+
+    #[test]
+    fn test_left_hand_rests_and_returns_with_right_hand_budget() {
+        let beat_state = state(&[
+            ("H_L", vec![0.0, 0.0, 0.0]),
+            ("I_L", vec![0.0, 0.0, 0.0]),
+            ("H_rotation_L", vec![0.1, 0.2, 0.3]),
+        ]);
+        let next_state = state(&[
+            ("H_L", vec![1.0, 0.0, 0.0]),
+            ("I_L", vec![1.0, 0.0, 0.0]),
+            ("H_rotation_L", vec![0.1, 0.2, 0.3]),
+        ]);
+        let rest_frames = |animator: &Animator| {
+            let (normal, press_distance) = animator.fretboard_normal().unwrap();
+            let press_duration = animator.press_duration();
+            animator
+                .left_hand_rest_frames(
+                    30.0,
+                    100.0,
+                    32.0,
+                    &beat_state,
+                    &next_state,
+                    &normal,
+                    press_distance,
+                    press_duration,
+                    (0, 0),
+                )
+                .unwrap()
+                .unwrap()
+        };
+
+        let animator = animator_with_rest(None);
+        let frames = rest_frames(&animator);
+        let frame_numbers: Vec<f64> = frames
+            .iter()
+            .map(|f| f["frame"].as_f64().unwrap())
+            .collect();
+        // 和右手一样在下一个音之前rest_transition_frames开始返回
+        assert_eq!(frame_numbers, vec![40.0, 49.6, 100.0 - 9.6, 98.0]);
+        // 没有定义休息姿势时整只手离开琴弦，手指只抬起一次
+        let near = |a: Vec<f64>, b: [f64; 3], tolerance: f64| {
+            a.iter()
+                .zip(b)
+                .map(|(x, y)| (x - y).powi(2))
+                .sum::<f64>()
+                .sqrt()
+                <= tolerance
+        };
+        // 抬指时手还会随机动一点，不超过按弦距离的一半
+        assert!(near(
+            values(&frames[1], "H_L"),
+            [0.0, 0.0, 0.1],
+            0.005 + 1e-9
+        ));
+        assert!(near(values(&frames[1], "I_L"), [0.0, 0.0, 0.11], 1e-9));
+        // 3个分量的欧拉角旋转不会被当成位置移动
+        assert_eq!(values(&frames[1], "H_rotation_L"), vec![0.1, 0.2, 0.3]);
+        assert_eq!(frames[2]["fingerInfos"], frames[1]["fingerInfos"]);
+        assert_eq!(frames[3]["fingerInfos"], Value::Object(next_state.clone()));
+
+        // 定义了休息姿势时只改变定义的控制器
+        let animator =
+            animator_with_rest(Some(serde_json::json!({"LEFT": {"H_L": [5.0, 5.0, 5.0]}})));
+        let frames = rest_frames(&animator);
+        assert_eq!(values(&frames[1], "H_L"), vec![5.0, 5.0, 5.0]);
+        assert!(near(values(&frames[1], "I_L"), [0.0, 0.0, 0.01], 1e-9));
+    }
+}
@@ -0,0 +1,151 @@
+// Handedness.rs
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// 持琴方式
+///
+/// 求解器和动画生成器都按右手持琴（左手按弦，右手拨弦）计算，左撇子角色在输出动画时
+/// 再整体镜像：控制器名的_L和_R互换，位置和旋转以角色的左右方向（X轴）为镜面翻转。
+/// 这样同一份人物数据可以直接用在镜像过的模型上，不需要手动修改人物JSON
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Handedness {
+    /// 左手按弦，右手拨弦
+    #[default]
+    Right,
+    /// 右手按弦，左手拨弦
+    Left,
+}
+
+impl Handedness {
+    pub fn all() -> [Handedness; 2] {
+        [Handedness::Right, Handedness::Left]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Handedness::Right => "右手持琴",
+            Handedness::Left => "左手持琴",
+        }
+    }
+
+    pub fn is_left(&self) -> bool {
+        *self == Handedness::Left
+    }
+
+    /// 把按右手持琴计算出来的控制器名换成当前持琴方式下的控制器名
+    pub fn controller_name(&self, name: &str) -> String {
+        if !self.is_left() {
+            return name.to_string();
+        }
+        if let Some(stem) = name.strip_suffix("_L") {
+            format!("{}_R", stem)
+        } else if let Some(stem) = name.strip_suffix("_R") {
+            format!("{}_L", stem)
+        } else {
+            name.to_string()
+        }
+    }
+
+    /// 镜像一个控制器的数值
+    ///
+    /// 旋转控制器的3个分量是欧拉角，4个分量是(w, x, y, z)四元数，其它控制器的3个分量是位置。
+    /// 以X轴为镜面翻转时，位置的x取反，欧拉角和四元数的y和z取反
+    ///
+    /// # 参数
+    /// * `name` - 按右手持琴计算时的控制器名，用来区分位置和旋转
+    /// * `values` - 控制器的数值
+    pub fn mirror_values(&self, name: &str, values: &[f64]) -> Vec<f64> {
+        if !self.is_left() {
+            return values.to_vec();
+        }
+        match (is_rotation_controller(name), values.len()) {
+            (true, 3) => vec![values[0], -values[1], -values[2]],
+            (_, 4) => vec![values[0], values[1], -values[2], -values[3]],
+            (false, 3) => vec![-values[0], values[1], values[2]],
+            _ => values.to_vec(),
+        }
+    }
+
+    /// 镜像一帧里所有控制器的名字和数值
+    pub fn mirror_finger_infos(&self, finger_infos: &Map<String, Value>) -> Map<String, Value> {
+        finger_infos
+            .iter()
+            .map(|(name, value)| {
+                let values: Option<Vec<f64>> = value
+                    .as_array()
+                    .and_then(|array| array.iter().map(|v| v.as_f64()).collect());
+                let value = match values {
+                    Some(values) => serde_json::json!(self.mirror_values(name, &values)),
+                    None => value.clone(),
+                };
+                (self.controller_name(name), value)
+            })
+            .collect()
+    }
+
+    /// 镜像整个动画，每一帧的fingerInfos都会被转换，其它字段保持不变
+    pub fn mirror_animation(&self, frames: Vec<Value>) -> Vec<Value> {
+        if !self.is_left() {
+            return frames;
+        }
+        frames
+            .into_iter()
+            .map(|mut frame| {
+                if let Some(finger_infos) = frame.get("fingerInfos").and_then(|v| v.as_object()) {
+                    let mirrored = self.mirror_finger_infos(finger_infos);
+                    frame["fingerInfos"] = Value::Object(mirrored);
+                }
+                frame
+            })
+            .collect()
+    }
+}
+
+/// 控制器是否是旋转，例如H_rotation_L，旋转可能是3个分量的欧拉角
+pub fn is_rotation_controller(name: &str) -> bool {
+    name.contains("rotation")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_right_handed_animation_is_unchanged() {
+        let frames = vec![serde_json::json!({
+            "frame": 0.0,
+            "fingerInfos": {"H_L": [1.0, 2.0, 3.0]},
+        })];
+        assert_eq!(Handedness::Right.mirror_animation(frames.clone()), frames);
+    }
+
+    #[test]
+    fn test_left_handed_animation_swaps_sides_and_mirrors_values() {
+        let frames = vec![serde_json::json!({
+            "frame": 10.0,
+            "fingerInfos": {
+                "I_L": [1.0, 2.0, 3.0],
+                "H_rotation_R": [0.5, 0.1, 0.2, 0.3],
+                "H_rotation_L": [0.1, 0.2, 0.3],
+            },
+            "pitchwheel": 0,
+        })];
+        let mirrored = Handedness::Left.mirror_animation(frames);
+        let finger_infos = &mirrored[0]["fingerInfos"];
+
+        assert_eq!(finger_infos["I_R"], serde_json::json!([-1.0, 2.0, 3.0]));
+        assert_eq!(
+            finger_infos["H_rotation_L"],
+            serde_json::json!([0.5, 0.1, -0.2, -0.3])
+        );
+        // 3个分量的欧拉角按旋转镜像，而不是当成位置
+        assert_eq!(
+            finger_infos["H_rotation_R"],
+            serde_json::json!([0.1, -0.2, -0.3])
+        );
+        assert!(finger_infos.get("I_L").is_none());
+        assert_eq!(mirrored[0]["frame"], serde_json::json!(10.0));
+    }
+}
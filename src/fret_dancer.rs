@@ -1,19 +1,41 @@
 use serde_json;
+use std::collections::HashSet;
 use std::fs::File;
 use std::path::Path;
 use std::sync::mpsc;
 
 use crate::animate::animator::Animator;
+use crate::guitar::guitar_chord::UnplayableReason;
 use crate::guitar::guitar_instance::Guitar;
-use crate::guitar::guitar_string::create_guitar_strings;
+use crate::guitar::instrument_profile::{InstrumentProfile, RightHandTechnique};
 use crate::guitar::music_note::MusicNote;
+use crate::hand::chord_shape::ChordShapeLibrary;
 use crate::hand::left_finger::LeftFinger;
 use crate::hand::left_hand::LeftHand;
+use crate::hand::left_hand_cost::{hand_shift_distance, required_hand_speed};
 use crate::hand::right_hand::RightHand;
-use crate::midi::midi_to_note::{MessageInfo, MidiProcessor, NoteInfo};
-use crate::recorder::left_hand_recorder::LeftHandRecorder;
-use crate::recorder::recorder_pool::{HandPoseRecordPool, HandRecorder};
+use crate::job_config::JobConfig;
+use crate::midi::midi_to_note::{
+    ChannelPerString, MessageInfo, MidiProcessor, MutedNote, NoteInfo, NoteSpan, SectionMarker,
+    TempoMap, TimeSignatureChange,
+};
+use crate::recorder::alternatives::{
+    AlternativeInfo, AlternativeSection, AlternativesReport, ComposedRecorder,
+    find_section_options, select_distinct_recorders,
+};
+use crate::recorder::finger_hold::apply_note_durations;
+use crate::recorder::fingering_constraint::FingeringConstraints;
+use crate::recorder::left_hand_mute::apply_muted_notes;
+use crate::recorder::left_hand_recorder::{ChordNaming, LeftHandRecorder, RecordedLeftHand};
+use crate::recorder::left_hand_solve::{
+    LeftHandSolveInput, LeftHandSolveOutput, alternatives_enabled, solve_left_hand,
+};
+use crate::recorder::legato::LegatoTechnique;
+use crate::recorder::long_rest::{LongRest, detect_long_rests};
+use crate::recorder::recorder_pool::{HandPoseRecordPool, HandRecorder, UnprocessableNoteInfo};
 use crate::recorder::right_hand_recorder::RightHandRecorder;
+use crate::recorder::segment_solver::split_segments;
+use crate::recorder::viterbi_solver::count_different_poses;
 use crate::ui::app::{AvatarInfo, FretDanceApp};
 
 pub struct FretDancer;
@@ -25,16 +47,29 @@ pub struct FretDancerState {
     pub track_number_string: String,
     pub tempo_changes: Vec<crate::midi::midi_to_note::TempoChange>,
     pub ticks_per_beat: u16,
+    pub time_signatures: Vec<TimeSignatureChange>,
+    pub section_markers: Vec<SectionMarker>,
     pub notes_map: Vec<NoteInfo>,
+    pub processed_notes_map: Vec<NoteInfo>,
+    pub muted_notes: Vec<MutedNote>,
+    pub note_spans: Vec<NoteSpan>,
     pub messages: Vec<MessageInfo>,
     pub guitar: Guitar,
+    pub instrument_profile: InstrumentProfile,
     pub max_string_index: usize,
     pub fps: f64,
     pub disable_barre: bool,
     pub use_harm_notes: bool,
+    pub chord_shape_file: Option<String>,
+    pub fingering_constraint_file: Option<String>,
+    pub job_config: JobConfig,
     pub capo_number: i32,
     pub avatar_info: AvatarInfo,
     pub left_hand_recorder_file: String,
+    pub unplayable_notes_file: String,
+    pub speed_limit_file: String,
+    pub left_hand_trace_file: String,
+    pub left_hand_alternatives_file: String,
     pub left_hand_animation_file: String,
     pub right_hand_recorder_file: String,
     pub right_hand_animation_file: String,
@@ -59,9 +94,6 @@ impl FretDancer {
 
         let track_numbers = track_numbers.unwrap_or_else(|_| vec![1]);
 
-        let guitar_string_notes: Vec<&str> =
-            app.guitar_string_notes.iter().map(|s| s.as_str()).collect();
-
         let avatar_info = app
             .current_avatar_info
             .clone()
@@ -92,6 +124,22 @@ impl FretDancer {
             "output/hand_recorder/{}_{}_lefthand_recorder.json",
             filename, track_number_string
         );
+        let unplayable_notes_file = format!(
+            "output/hand_recorder/{}_{}_unplayable_notes.json",
+            filename, track_number_string
+        );
+        let speed_limit_file = format!(
+            "output/hand_recorder/{}_{}_speed_limit_violations.json",
+            filename, track_number_string
+        );
+        let left_hand_trace_file = format!(
+            "output/hand_recorder/{}_{}_lefthand_trace.json",
+            filename, track_number_string
+        );
+        let left_hand_alternatives_file = format!(
+            "output/hand_recorder/{}_{}_lefthand_alternatives.json",
+            filename, track_number_string
+        );
         let left_hand_animation_file = format!(
             "output/hand_animation/{}_{}_{}_lefthand_animation.json",
             avatar_info.name, filename, track_number_string
@@ -114,17 +162,57 @@ impl FretDancer {
         // 获取MIDI信息
         let (tempo_changes, ticks_per_beat) =
             midi_processor.get_tempo_changes(&app.midi_file_path)?;
-        let (notes_map, _pitch_wheel_map, messages) = midi_processor.midi_to_guitar_notes(
+        let time_signatures = midi_processor.get_time_signatures(&app.midi_file_path)?;
+        let mute_detection = &app.job_config.mute_detection;
+        // 表示闷音的标记不作为段落标记
+        let (mute_markers, section_markers): (Vec<SectionMarker>, Vec<SectionMarker>) =
+            midi_processor
+                .get_section_markers(&app.midi_file_path)?
+                .into_iter()
+                .partition(|marker| mute_detection.is_mute_marker(marker));
+        // 按弦分通道时，每个通道对应一根弦
+        let channel_per_string = app.channel_per_string.then_some(ChannelPerString {
+            first_channel: app.first_string_channel,
+            string_count: app.guitar_string_notes.len(),
+        });
+        let (notes_map, _pitch_wheel_map, messages, mut muted_notes, note_spans) = midi_processor
+            .midi_to_guitar_notes(
             &app.midi_file_path,
             &tempo_changes,
             ticks_per_beat,
             app.fps,
             &track_numbers,
             app.channel_number,
+            channel_per_string,
             app.octave_down_checkbox,
             app.capo_number,
+            mute_detection,
         )?;
 
+        // 标记处开始的音符都是闷音
+        for note_info in &notes_map {
+            if !mute_markers
+                .iter()
+                .any(|marker| marker.time as f64 == note_info.real_tick)
+            {
+                continue;
+            }
+            for (index, &note) in note_info.notes.iter().enumerate() {
+                let muted_note = MutedNote {
+                    real_tick: note_info.real_tick,
+                    note,
+                    string_index: note_info.string_indices.get(index).copied().unwrap_or(-1),
+                };
+                if !muted_notes.contains(&muted_note) {
+                    muted_notes.push(muted_note);
+                }
+            }
+        }
+        muted_notes.sort_by(|a, b| a.real_tick.total_cmp(&b.real_tick));
+        if !muted_notes.is_empty() {
+            console_callback(&format!("识别出{}个闷音", muted_notes.len()));
+        }
+
         // 保存MIDI信息
         let notes_map_file_handle = File::create(&notes_map_file)?;
         serde_json::to_writer_pretty(notes_map_file_handle, &notes_map)?;
@@ -159,26 +247,60 @@ impl FretDancer {
             app.fps, total_tick, total_frame, total_time
         ));
 
-        // 初始化吉他
-        let guitar_string_list = create_guitar_strings(&guitar_string_notes);
-        let max_string_index = guitar_string_list.len() - 1;
-        let guitar = Guitar::with_defaults(guitar_string_list, app.use_harm_notes);
+        // 初始化吉他，调弦以界面上的设置为准
+        let instrument_profile = app
+            .instrument_profile
+            .with_tuning(app.guitar_string_notes.clone());
+        if instrument_profile.string_count() == 0 {
+            return Err("乐器至少需要一根弦".into());
+        }
+        let max_string_index = instrument_profile.max_string_index();
+        let guitar = instrument_profile.create_guitar(app.use_harm_notes);
+        // 每个音符事件只处理一次，求解器、把位规划和约束检查都使用同样的音符
+        let (min_note, max_note) = guitar.get_note_range();
+        let processed_notes_map = midi_processor.processed_notes_map(
+            &notes_map,
+            min_note,
+            max_note,
+            guitar.guitar_strings.len(),
+        );
 
         let state = FretDancerState {
             filename: filename.to_string(),
             track_number_string,
             tempo_changes,
             ticks_per_beat,
+            time_signatures,
+            section_markers,
             notes_map,
+            processed_notes_map,
+            muted_notes,
+            note_spans,
             messages,
             guitar,
+            instrument_profile,
             max_string_index,
             fps: app.fps,
             disable_barre: app.disable_barre,
             use_harm_notes: app.use_harm_notes,
+            chord_shape_file: if app.use_chord_shapes {
+                Some(app.chord_shape_file.clone())
+            } else {
+                None
+            },
+            fingering_constraint_file: if app.use_fingering_constraints {
+                Some(app.fingering_constraint_file.clone())
+            } else {
+                None
+            },
             capo_number: app.capo_number,
+            job_config: app.job_config.clone(),
             avatar_info,
             left_hand_recorder_file,
+            unplayable_notes_file,
+            speed_limit_file,
+            left_hand_trace_file,
+            left_hand_alternatives_file,
             left_hand_animation_file,
             right_hand_recorder_file,
             right_hand_animation_file,
@@ -200,96 +322,313 @@ impl FretDancer {
         // 更新吉他配置以使用泛音
         let guitar = Guitar::with_defaults(state.guitar.guitar_strings.clone(), app.use_harm_notes);
 
-        // 设定各手指状态
+        // 设定各手指状态，弦数较少的乐器放在最低的弦上
+        let init_string = &guitar.guitar_strings[std::cmp::min(2, state.max_string_index)];
         let left_fingers = vec![
-            LeftFinger::new(1, &guitar.guitar_strings[2].clone(), 1, "Pressed"),
-            LeftFinger::new(2, &guitar.guitar_strings[2].clone(), 2, "Pressed"),
-            LeftFinger::new(3, &guitar.guitar_strings[2].clone(), 3, "Pressed"),
-            LeftFinger::new(4, &guitar.guitar_strings[2].clone(), 4, "Pressed"),
+            LeftFinger::new(1, init_string, 1, "Pressed"),
+            LeftFinger::new(2, init_string, 2, "Pressed"),
+            LeftFinger::new(3, init_string, 3, "Pressed"),
+            LeftFinger::new(4, init_string, 4, "Pressed"),
         ];
 
-        // 初始化左手
-        let init_left_hand = LeftHand::new(left_fingers, false, 5.73);
+        // 按角色的手型数据初始化左手
+        let hand_profile = state.avatar_info.hand_profile;
+        let init_left_hand = LeftHand::with_profile(left_fingers, false, hand_profile);
+
+        // 读取和弦指型库
+        let chord_shape_library = match &state.chord_shape_file {
+            Some(chord_shape_file) => match ChordShapeLibrary::load_from_file(chord_shape_file) {
+                Ok(library) => {
+                    console_callback(&format!("已读取{}个和弦指型", library.len()));
+                    Some(library)
+                }
+                Err(e) => {
+                    console_callback(&format!(
+                        "警告：读取和弦指型库失败，将只使用通用按法: {}",
+                        e
+                    ));
+                    None
+                }
+            },
+            None => None,
+        };
 
-        // 初始化第一个记录器
-        let mut left_hand_pose_record = LeftHandRecorder::new();
-        left_hand_pose_record.add_hand_pose(init_left_hand, 0.0, 0.0);
+        // 读取按法约束，读取失败时不能忽略，否则编曲者指定的按法会悄悄失效
+        let fingering_constraints = match &state.fingering_constraint_file {
+            Some(constraint_file) => {
+                let mut constraints = FingeringConstraints::load_from_file(constraint_file)
+                    .map_err(|e| format!("读取按法约束失败: {}", e))?;
+                let unused = constraints.bind_events(
+                    &state.notes_map,
+                    &state.time_signatures,
+                    state.ticks_per_beat,
+                );
+                console_callback(&format!("已读取{}条按法约束", constraints.len()));
+                for index in unused {
+                    console_callback(&format!(
+                        "警告：第{}条按法约束（{}）没有对应的音符",
+                        index + 1,
+                        constraints.constraints[index].describe()
+                    ));
+                }
+                Some(constraints)
+            }
+            None => None,
+        };
 
-        // 初始化记录池
-        let mut left_hand_pose_record_pool = HandPoseRecordPool::new(100);
-        left_hand_pose_record_pool
-            .insert_new_hand_pose_recorder(HandRecorder::Left(left_hand_pose_record), Some(0));
+        // 段落标记的tick，分段求解、把位规划和备选按法都在段落标记处分段
+        let section_ticks: Vec<f64> = state
+            .section_markers
+            .iter()
+            .map(|marker| marker.time as f64)
+            .collect();
 
-        let mut current_recorder_num = 0;
-        let mut previous_recorder_num = 0;
+        let solve_input = LeftHandSolveInput {
+            guitar: &guitar,
+            notes_map: &state.processed_notes_map,
+            init_hand: init_left_hand,
+            job_config: &state.job_config,
+            tempo_changes: &state.tempo_changes,
+            ticks_per_beat: state.ticks_per_beat,
+            section_ticks: &section_ticks,
+            chord_shape_library: chord_shape_library.as_ref(),
+            fingering_constraints: fingering_constraints.as_ref(),
+        };
+        let LeftHandSolveOutput {
+            recorder: mut left_hand_recorder,
+            unprocessable_notes,
+            trace,
+            ranked_recorders,
+            ..
+        } = solve_left_hand(
+            &solve_input,
+            &app.left_hand_segment_cache,
+            &console_callback,
+        )?;
+        let tempo_map = TempoMap::new(&state.tempo_changes, state.ticks_per_beat);
 
-        console_callback("==============================");
-        console_callback("开始生成左手按弦数据");
+        console_callback(&format!(
+            "最小消耗熵为：{}",
+            left_hand_recorder.current_entropy
+        ));
+        console_callback(&format!("总音符数应该为{}", state.notes_map.len()));
+        console_callback(&format!(
+            "实际输出音符数为{}",
+            left_hand_recorder.hand_pose_list.len()
+        ));
+
+        // 报告没有满足的按法约束
+        if let Some(constraints) = &fingering_constraints {
+            let midi_processor = MidiProcessor::new();
+            let unsatisfied = constraints.find_unsatisfied(
+                &left_hand_recorder,
+                &state.processed_notes_map,
+                &guitar,
+            );
+            if unsatisfied.is_empty() {
+                console_callback("所有按法约束都已满足");
+            } else {
+                console_callback(&format!(
+                    "警告：有{}处按法约束没有满足：",
+                    unsatisfied.len()
+                ));
+            }
+            for info in &unsatisfied {
+                let constraint = &constraints.constraints[info.constraint_index];
+                let (bar, beat) = midi_processor.calculate_bar_position(
+                    &state.time_signatures,
+                    state.ticks_per_beat,
+                    info.real_tick,
+                );
+                console_callback(&format!(
+                    "第{}小节第{:.2}拍 (tick: {})：第{}条{}约束（{}）",
+                    bar,
+                    beat,
+                    info.real_tick,
+                    info.constraint_index + 1,
+                    if constraint.is_hard() { "硬" } else { "软" },
+                    constraint.describe()
+                ));
+            }
+        }
+
+        // 把演奏闷音的手指改成轻触弦，在识别连奏之前处理，闷音不能用连奏演奏
+        if !state.muted_notes.is_empty() {
+            let unmuted = apply_muted_notes(&mut left_hand_recorder, &state.muted_notes, &guitar);
+            console_callback(&format!(
+                "标记了{}个闷音",
+                state.muted_notes.len() - unmuted.len()
+            ));
+            if !unmuted.is_empty() {
+                let midi_processor = MidiProcessor::new();
+                console_callback(&format!(
+                    "警告：有{}个闷音没有对应的按弦手指或者由横按发出，仍然会发声：",
+                    unmuted.len()
+                ));
+                for muted_note in &unmuted {
+                    let (bar, beat) = midi_processor.calculate_bar_position(
+                        &state.time_signatures,
+                        state.ticks_per_beat,
+                        muted_note.real_tick,
+                    );
+                    console_callback(&format!(
+                        "第{}小节第{:.2}拍 (tick: {})：{}",
+                        bar,
+                        beat,
+                        muted_note.real_tick,
+                        MusicNote::new(muted_note.note).get_keynote()
+                    ));
+                }
+            }
+        }
 
-        // 更新记录器池
-        left_hand_pose_record_pool.update_left_handrecorder_pool(
+        // 按音符的时值决定手指按住和松开的时间
+        if state.job_config.hold_note_durations {
+            let summary = apply_note_durations(&mut left_hand_recorder, &state.note_spans, &guitar);
+            console_callback(&format!(
+                "按音符时值：{}次手指留在弦上保持按弦，{}次手指提前松开",
+                summary.anchored, summary.released
+            ));
+        }
+
+        // 标记可以用击弦、勾弦和滑弦演奏的音，这些音右手不拨弦
+        let legato_count = left_hand_recorder.mark_legato(
             &guitar,
             &state.notes_map,
-            &MidiProcessor::new(),
-            &mut current_recorder_num,
-            &mut previous_recorder_num,
-            &console_callback,
+            &state.note_spans,
+            &tempo_map,
+            state.job_config.legato_max_gap,
         );
+        if legato_count > 0 {
+            let mut technique_counts = Vec::new();
+            for technique in [
+                LegatoTechnique::HammerOn,
+                LegatoTechnique::PullOff,
+                LegatoTechnique::Slide,
+            ] {
+                let count = left_hand_recorder
+                    .legato
+                    .iter()
+                    .flatten()
+                    .filter(|info| info.technique == technique)
+                    .count();
+                technique_counts.push(format!("{}{}处", technique.label(), count));
+            }
+            console_callback(&format!(
+                "识别出{}处连奏：{}",
+                legato_count,
+                technique_counts.join("，")
+            ));
+        }
 
-        // 获取最优解
-        let best_hand_pose_record = left_hand_pose_record_pool.get_best_recorder();
-        let best_entropy = best_hand_pose_record.current_entropy();
-
-        console_callback(&format!("最小消耗熵为：{}", best_entropy));
-        console_callback(&format!("总音符数应该为{}", state.notes_map.len()));
-        console_callback(&format!("实际输出音符数为{}", best_hand_pose_record.len()));
-
-        // 转换HandRecorder为LeftHandRecorder
-        let left_hand_recorder = match best_hand_pose_record {
-            HandRecorder::Left(recorder) => recorder,
-            _ => return Err("Expected LeftHandRecorder".into()),
-        };
+        // 输出左手求解追踪，没有开启时删除之前的追踪，避免界面显示过期的内容
+        match trace {
+            Some(trace) => {
+                trace.save_to_file(&state.left_hand_trace_file)?;
+                console_callback(&format!(
+                    "左手求解追踪已保存至: {}",
+                    state.left_hand_trace_file
+                ));
+            }
+            None => {
+                let _ = std::fs::remove_file(&state.left_hand_trace_file);
+            }
+        }
 
-        left_hand_recorder.save(
+        left_hand_recorder.save_with_chord_names(
             &state.left_hand_recorder_file,
             &state.tempo_changes,
             state.ticks_per_beat,
             state.fps,
+            Some(ChordNaming {
+                guitar: &guitar,
+                notes_map: &state.processed_notes_map,
+                capo_number: state.capo_number,
+            }),
         )?;
 
-        let unprocessable_notes = left_hand_pose_record_pool.get_unprocessable_notes();
-        if !unprocessable_notes.is_empty() {
-            console_callback("生成过程中碰到左手无法按弦的音符组合：");
-
-            // 使用 HashSet 去重
-            let mut unique_notes = std::collections::HashSet::new();
-            for note in unprocessable_notes {
-                unique_notes.insert(&note.notes);
-            }
-
-            // 转换为 Vec 并排序以便输出一致
-            let mut sorted_notes: Vec<_> = unique_notes.into_iter().collect();
-            sorted_notes.sort();
-
-            for notes in sorted_notes {
-                // 将数字音符转换为音符名称
-                let note_names: Vec<String> = notes
+        // 输出备选按法，没有开启时删除之前的备选，避免界面显示过期的内容
+        if alternatives_enabled(&state.job_config) {
+            // 最优解换成经过后处理的结果
+            let ranked = std::iter::once(left_hand_recorder.clone())
+                .chain(ranked_recorders.into_iter().skip(1))
+                .collect();
+            let report =
+                FretDancer::save_left_hand_alternatives(&state, ranked, &guitar, &tempo_map)?;
+            console_callback(&format!(
+                "输出了{}个备选按法，{}个段落可以选择不同的按法，已保存至: {}",
+                report.alternatives.len(),
+                report
+                    .sections
                     .iter()
-                    .map(|&num| {
-                        let music_note = MusicNote::new(num);
-                        music_note.get_keynote()
-                    })
-                    .collect();
+                    .filter(|section| section.options.len() > 1)
+                    .count(),
+                state.left_hand_alternatives_file
+            ));
+        } else {
+            if state.job_config.segment_solving && state.job_config.alternative_count > 0 {
+                console_callback("注意：分段求解时不输出备选按法");
+            }
+            let _ = std::fs::remove_file(&state.left_hand_alternatives_file);
+        }
 
+        // 输出无法演奏的音符组合，以及实际采用的退化方式
+        let unplayable_reports = FretDancer::build_unplayable_reports(&state, &unprocessable_notes);
+        if !unplayable_reports.is_empty() {
+            console_callback(&format!(
+                "警告：生成过程中碰到{}处左手无法按弦的音符组合：",
+                unplayable_reports.len()
+            ));
+            for report in &unplayable_reports {
+                console_callback(&FretDancer::describe_unplayable_report(report));
+            }
+        }
+        let unplayable_file = File::create(&state.unplayable_notes_file)?;
+        serde_json::to_writer_pretty(unplayable_file, &unplayable_reports)?;
+
+        // 输出换把速度超过上限的地方
+        let max_hand_speed = state.job_config.left_hand_cost.max_hand_speed;
+        let speed_reports =
+            FretDancer::build_speed_limit_reports(&state, &left_hand_recorder, &tempo_map);
+        if !speed_reports.is_empty() {
+            console_callback(&format!(
+                "警告：有{}处换把速度超过了{}cm/s的上限：",
+                speed_reports.len(),
+                max_hand_speed
+            ));
+            for report in &speed_reports {
                 console_callback(&format!(
-                    "音符数字: {:?}, 对应的音符名是: {:?}",
-                    notes, note_names
+                    "第{}小节第{}拍 (tick: {}, {}秒)：从第{}品换到第{}品，移动{}cm，只有{}秒，需要{}cm/s",
+                    report["bar"],
+                    report["beat"],
+                    report["real_tick"],
+                    report["time"],
+                    report["from_position"],
+                    report["to_position"],
+                    report["distance"],
+                    report["time_gap"],
+                    report["speed"],
                 ));
             }
         }
+        let speed_limit_file = File::create(&state.speed_limit_file)?;
+        serde_json::to_writer_pretty(speed_limit_file, &speed_reports)?;
 
         Ok(())
     }
+    /// 按任务配置找出曲子里的长休止，双手在长休止时回到休息姿势
+    fn long_rests(state: &FretDancerState) -> Vec<LongRest> {
+        detect_long_rests(
+            &state.notes_map,
+            &state.note_spans,
+            state.job_config.rest_pose_min_length,
+            state.job_config.rest_pose_unit,
+            &state.tempo_changes,
+            state.ticks_per_beat,
+            state.fps,
+        )
+    }
+
     pub fn generate_left_hand_animation(
         app: &mut FretDanceApp,
     ) -> Result<String, Box<dyn std::error::Error>> {
@@ -300,7 +639,9 @@ impl FretDancer {
             state.left_hand_animation_file.clone(),
             state.fps,
             state.max_string_index as f64,
-        )?;
+        )?
+        .with_handedness(state.avatar_info.handedness)
+        .with_long_rests(FretDancer::long_rests(state));
 
         animator.left_hand_2_animation(state.disable_barre)?;
 
@@ -323,16 +664,25 @@ impl FretDancer {
             state.left_hand_animation_file.clone(),
             state.fps,
             state.max_string_index as f64,
-        )?;
+        )?
+        .with_handedness(state.avatar_info.handedness)
+        .with_long_rests(FretDancer::long_rests(&state));
         let _ = animator.init_arpeggio_patterns()?;
+        if !animator.long_rests().is_empty() {
+            progress_callback(&format!(
+                "共{}段长休止，双手会回到休息姿势",
+                animator.long_rests().len()
+            ));
+        }
 
         // 处理右手部分
         progress_callback(&format!(
             "开始生成右手演奏数据：{}",
-            state.avatar_info.instrument
+            state.instrument_profile.right_hand_technique.as_str()
         ));
 
-        if state.avatar_info.instrument == "electric_guitar" {
+        let right_hand_technique = state.instrument_profile.right_hand_technique;
+        if right_hand_technique == RightHandTechnique::Pick {
             let right_hand_recorder_data = animator.left_hand_2_electronic_right_hand(
                 &state.left_hand_recorder_file,
                 &state.right_hand_recorder_file,
@@ -345,7 +695,7 @@ impl FretDancer {
 
             progress_callback("完成右手数据生成");
         } else {
-            let is_playing_bass = state.avatar_info.instrument == "bass";
+            let is_playing_bass = right_hand_technique == RightHandTechnique::Bass;
             let init_right_hand = RightHand::new(
                 vec![],
                 vec![state.max_string_index as i32, 2, 1, 0],
@@ -407,7 +757,8 @@ impl FretDancer {
             state.left_hand_animation_file.clone(),
             state.fps,
             state.max_string_index as f64,
-        )?;
+        )?
+        .with_handedness(state.avatar_info.handedness);
 
         // 输出分隔符
         console_callback("==============================");
@@ -462,10 +813,30 @@ impl FretDancer {
             .to_string_lossy()
             .to_string();
 
+        // 从左手记录中读取和弦名，只在和弦变化时记录一次
+        let chords = FretDancer::collect_chord_changes(&state.left_hand_recorder_file)?;
+
+        // 左手无法演奏的音符，文件不存在时为空
+        let unplayable_notes: Vec<serde_json::Value> =
+            match File::open(&state.unplayable_notes_file) {
+                Ok(file) => serde_json::from_reader(std::io::BufReader::new(file))?,
+                Err(_) => Vec::new(),
+            };
+
+        // 换把速度超过上限的地方，文件不存在时为空
+        let speed_limit_violations: Vec<serde_json::Value> =
+            match File::open(&state.speed_limit_file) {
+                Ok(file) => serde_json::from_reader(std::io::BufReader::new(file))?,
+                Err(_) => Vec::new(),
+            };
+
         let content = serde_json::json!({
             "left_hand_animation_file": left_hand_absolute_path,
             "right_hand_animation_file": right_hand_absolute_path,
             "guitar_string_recorder_file": guitar_string_absolute_path,
+            "chords": chords,
+            "unplayable_notes": unplayable_notes,
+            "speed_limit_violations": speed_limit_violations,
         });
 
         let report_file = format!(
@@ -481,6 +852,361 @@ impl FretDancer {
         console_callback(&format!("报告已保存至:\n{}", report_absolute_path));
         Ok(())
     }
+    /// 选出按法互不相同的备选并分别保存，再按段落整理出每一段可以选择的备选
+    ///
+    /// # 参数
+    /// * `ranked` - 按熵排序的记录器，第一个是最终使用的解
+    pub fn save_left_hand_alternatives(
+        state: &FretDancerState,
+        ranked: Vec<LeftHandRecorder>,
+        guitar: &Guitar,
+        tempo_map: &TempoMap,
+    ) -> Result<AlternativesReport, Box<dyn std::error::Error>> {
+        let mut alternatives = select_distinct_recorders(
+            ranked,
+            state.job_config.alternative_count,
+            state.job_config.alternative_min_different_poses,
+        );
+
+        let file_prefix = state.left_hand_alternatives_file.trim_end_matches(".json");
+        let mut infos = Vec::new();
+        for (index, recorder) in alternatives.iter_mut().enumerate() {
+            if index > 0 {
+                apply_muted_notes(recorder, &state.muted_notes, guitar);
+                if state.job_config.hold_note_durations {
+                    apply_note_durations(recorder, &state.note_spans, guitar);
+                }
+                recorder.mark_legato(
+                    guitar,
+                    &state.notes_map,
+                    &state.note_spans,
+                    tempo_map,
+                    state.job_config.legato_max_gap,
+                );
+            }
+            let file = format!("{}_{}.json", file_prefix, index);
+            recorder.save_with_chord_names(
+                &file,
+                &state.tempo_changes,
+                state.ticks_per_beat,
+                state.fps,
+                Some(ChordNaming {
+                    guitar,
+                    notes_map: &state.processed_notes_map,
+                    capo_number: state.capo_number,
+                }),
+            )?;
+            infos.push(AlternativeInfo {
+                index,
+                file,
+                entropy: recorder.current_entropy,
+                different_poses: 0,
+            });
+        }
+        for (info, recorder) in infos.iter_mut().zip(&alternatives) {
+            info.different_poses = count_different_poses(&alternatives[0], recorder);
+        }
+
+        // 和分段求解使用相同的段落划分
+        let min_rest_ticks = state.job_config.segment_min_rest_beats * state.ticks_per_beat as f64;
+        let section_ticks: Vec<f64> = state
+            .section_markers
+            .iter()
+            .map(|marker| marker.time as f64)
+            .collect();
+        let tick_ranges: Vec<(f64, f64)> =
+            split_segments(&state.notes_map, min_rest_ticks, &section_ticks)
+                .into_iter()
+                .map(|range| {
+                    (
+                        state.notes_map[range.start].real_tick,
+                        state.notes_map[range.end - 1].real_tick,
+                    )
+                })
+                .collect();
+        let options = find_section_options(&alternatives, &tick_ranges);
+
+        let midi_processor = MidiProcessor::new();
+        let bar_of = |tick: f64| {
+            midi_processor
+                .calculate_bar_position(&state.time_signatures, state.ticks_per_beat, tick)
+                .0
+        };
+        let sections = tick_ranges
+            .into_iter()
+            .zip(options)
+            .map(|((start_tick, end_tick), options)| AlternativeSection {
+                start_tick,
+                end_tick,
+                start_bar: bar_of(start_tick),
+                end_bar: bar_of(end_tick),
+                options,
+            })
+            .collect();
+
+        let report = AlternativesReport {
+            alternatives: infos,
+            sections,
+            use_harm_notes: guitar.use_harm_notes,
+        };
+        report.save_to_file(&state.left_hand_alternatives_file)?;
+        Ok(report)
+    }
+
+    /// 按每个段落选择的备选拼接左手记录，保存到左手记录文件
+    ///
+    /// 拼接以后在新的序列上重新计算闷音、手指保持和连奏，
+    /// 并检查每个拼接处的换把速度，返回超过速度上限的拼接处的说明
+    ///
+    /// # 参数
+    /// * `report` - 备选按法的汇总
+    /// * `choices` - 与段落一一对应的备选序号
+    pub fn apply_left_hand_alternatives(
+        state: &FretDancerState,
+        report: &AlternativesReport,
+        choices: &[usize],
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        // 和求解时一样按当时的泛音设置重建吉他，否则泛音的手型在后处理时对不上
+        let guitar =
+            &Guitar::with_defaults(state.guitar.guitar_strings.clone(), report.use_harm_notes);
+        let tempo_map = TempoMap::new(&state.tempo_changes, state.ticks_per_beat);
+        let ComposedRecorder {
+            mut recorder,
+            splices,
+        } = report.compose_recorder(choices, state.avatar_info.hand_profile)?;
+
+        apply_muted_notes(&mut recorder, &state.muted_notes, guitar);
+        if state.job_config.hold_note_durations {
+            apply_note_durations(&mut recorder, &state.note_spans, guitar);
+        }
+        recorder.mark_legato(
+            guitar,
+            &state.notes_map,
+            &state.note_spans,
+            &tempo_map,
+            state.job_config.legato_max_gap,
+        );
+
+        let midi_processor = MidiProcessor::new();
+        let max_hand_speed = state.job_config.left_hand_cost.max_hand_speed;
+        let warnings = splices
+            .into_iter()
+            .filter_map(|index| {
+                let from_position = recorder.hand_pose_list[index - 1].hand_position;
+                let to_position = recorder.hand_pose_list[index].hand_position;
+                let (from_tick, real_tick) =
+                    (recorder.real_ticks[index - 1], recorder.real_ticks[index]);
+                let distance = hand_shift_distance(guitar, from_position, to_position);
+                let speed =
+                    required_hand_speed(distance, tempo_map.seconds_between(from_tick, real_tick));
+                if speed <= max_hand_speed {
+                    return None;
+                }
+                let (bar, _) = midi_processor.calculate_bar_position(
+                    &state.time_signatures,
+                    state.ticks_per_beat,
+                    real_tick,
+                );
+                Some(format!(
+                    "第{}小节(tick {})的拼接处从{}品换到{}品，需要的速度{:.1}超过上限{}",
+                    bar, real_tick, from_position, to_position, speed, max_hand_speed
+                ))
+            })
+            .collect();
+
+        recorder.save_with_chord_names(
+            &state.left_hand_recorder_file,
+            &state.tempo_changes,
+            state.ticks_per_beat,
+            state.fps,
+            Some(ChordNaming {
+                guitar,
+                notes_map: &state.processed_notes_map,
+                capo_number: state.capo_number,
+            }),
+        )?;
+        Ok(warnings)
+    }
+
+    /// 把无法处理的音符整理成报告，每一项包含tick、小节、时间、原因和最接近的可演奏组合
+    pub fn build_unplayable_reports(
+        state: &FretDancerState,
+        unprocessable_notes: &[UnprocessableNoteInfo],
+    ) -> Vec<serde_json::Value> {
+        let midi_processor = MidiProcessor::new();
+        let note_names = |notes: &[i32]| -> Vec<String> {
+            notes
+                .iter()
+                .map(|&num| MusicNote::new(num).get_keynote())
+                .collect()
+        };
+
+        // 同一个tick只报告第一次
+        let mut reported_ticks = HashSet::new();
+        unprocessable_notes
+            .iter()
+            .filter(|info| reported_ticks.insert(info.real_tick.to_bits()))
+            .map(|info| {
+                let (bar, beat) = midi_processor.calculate_bar_position(
+                    &state.time_signatures,
+                    state.ticks_per_beat,
+                    info.real_tick,
+                );
+                // fps为1时计算出来的帧数就是秒数
+                let time = midi_processor.calculate_frame(
+                    &state.tempo_changes,
+                    state.ticks_per_beat,
+                    1.0,
+                    info.real_tick,
+                );
+                let diagnosis = &info.diagnosis;
+
+                let mut report = serde_json::json!({
+                    "real_tick": info.real_tick,
+                    "bar": bar,
+                    "beat": (beat * 100.0).round() / 100.0,
+                    "time": (time * 1000.0).round() / 1000.0,
+                    "frame": time * state.fps,
+                    "notes": info.notes,
+                    "note_names": note_names(&info.notes),
+                    "reason": diagnosis.reason,
+                    "reason_description": diagnosis.reason.description(),
+                    "out_of_range_notes": diagnosis.out_of_range_notes,
+                    "fallback": info.fallback.strategy,
+                    "fallback_description": info.fallback.description(),
+                    "played_notes": info.fallback.played,
+                    "sacrificed_notes": info.fallback.dropped_notes,
+                    "sacrificed_note_names": note_names(&info.fallback.dropped_notes),
+                    "shifted_notes": info.fallback.shifted_notes,
+                });
+                // 无法过渡时单独演奏这组音符是可以的，最接近的可演奏组合没有意义
+                if diagnosis.reason != UnplayableReason::NoValidTransition
+                    && let Some(closest) = &diagnosis.closest_alternative
+                {
+                    report["closest_alternative"] = serde_json::json!(closest);
+                    report["closest_alternative_names"] = serde_json::json!(note_names(closest));
+                    report["dropped_notes"] = serde_json::json!(diagnosis.dropped_notes);
+                    report["dropped_note_names"] =
+                        serde_json::json!(note_names(&diagnosis.dropped_notes));
+                }
+                report
+            })
+            .collect()
+    }
+
+    /// 把一条无法演奏的音符报告整理成控制台输出的一行文字
+    pub fn describe_unplayable_report(report: &serde_json::Value) -> String {
+        let names = |key: &str| -> String {
+            report[key]
+                .as_array()
+                .map(|names| {
+                    names
+                        .iter()
+                        .filter_map(|name| name.as_str())
+                        .collect::<Vec<&str>>()
+                        .join(" ")
+                })
+                .unwrap_or_default()
+        };
+        let number = |key: &str| report[key].as_f64().unwrap_or_default();
+        let text = |key: &str| report[key].as_str().unwrap_or_default();
+
+        let mut line = format!(
+            "第{}小节第{}拍 (tick: {}, {}秒)：{}，原因：{}",
+            number("bar"),
+            number("beat"),
+            number("real_tick"),
+            number("time"),
+            names("note_names"),
+            text("reason_description"),
+        );
+        if report.get("closest_alternative_names").is_some() {
+            line.push_str(&format!(
+                "，最接近的可演奏组合：{}（去掉{}）",
+                names("closest_alternative_names"),
+                names("dropped_note_names"),
+            ));
+        }
+        line.push_str(&format!("，处理方式：{}", text("fallback_description")));
+        let sacrificed = names("sacrificed_note_names");
+        if !sacrificed.is_empty() {
+            line.push_str(&format!("，放弃的音符：{}", sacrificed));
+        }
+        line
+    }
+
+    /// 找出左手记录中换把速度超过上限的地方，每一项包含tick、小节、时间、换把的品格和需要的速度
+    pub fn build_speed_limit_reports(
+        state: &FretDancerState,
+        recorder: &LeftHandRecorder,
+        tempo_map: &TempoMap,
+    ) -> Vec<serde_json::Value> {
+        let midi_processor = MidiProcessor::new();
+        let max_hand_speed = state.job_config.left_hand_cost.max_hand_speed;
+        let round = |value: f64, scale: f64| (value * scale).round() / scale;
+
+        recorder
+            .hand_pose_list
+            .windows(2)
+            .zip(recorder.real_ticks.windows(2))
+            .filter_map(|(hands, ticks)| {
+                let (from_position, to_position) = (hands[0].hand_position, hands[1].hand_position);
+                let distance = hand_shift_distance(&state.guitar, from_position, to_position);
+                let time_gap = tempo_map.seconds_between(ticks[0], ticks[1]);
+                let speed = required_hand_speed(distance, time_gap);
+                if speed <= max_hand_speed {
+                    return None;
+                }
+
+                let real_tick = ticks[1];
+                let (bar, beat) = midi_processor.calculate_bar_position(
+                    &state.time_signatures,
+                    state.ticks_per_beat,
+                    real_tick,
+                );
+                let time = tempo_map.seconds_at(real_tick);
+                Some(serde_json::json!({
+                    "real_tick": real_tick,
+                    "bar": bar,
+                    "beat": round(beat, 100.0),
+                    "time": round(time, 1000.0),
+                    "frame": time * state.fps,
+                    "from_position": from_position,
+                    "to_position": to_position,
+                    "distance": round(distance, 100.0),
+                    "time_gap": round(time_gap, 1000.0),
+                    "speed": round(speed, 10.0),
+                    "max_hand_speed": max_hand_speed,
+                }))
+            })
+            .collect()
+    }
+
+    /// 读取左手记录文件，返回和弦变化的列表，每一项包含real_tick、frame和和弦名
+    pub fn collect_chord_changes(
+        left_hand_recorder_file: &str,
+    ) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
+        let file = File::open(left_hand_recorder_file)?;
+        let recorded_hands: Vec<RecordedLeftHand> =
+            serde_json::from_reader(std::io::BufReader::new(file))?;
+
+        let mut chords = Vec::new();
+        let mut last_name: Option<String> = None;
+        for hand in recorded_hands {
+            let name = hand.chord.map(|chord| chord.name);
+            if name.is_some() && name != last_name {
+                chords.push(serde_json::json!({
+                    "real_tick": hand.real_tick,
+                    "frame": hand.frame,
+                    "name": name,
+                }));
+            }
+            last_name = name;
+        }
+
+        Ok(chords)
+    }
+
     pub fn main(
         app: &mut FretDanceApp,
         tx: mpsc::Sender<String>,
@@ -514,3 +1240,45 @@ impl FretDancer {
         Ok(final_info)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unplayable_report_is_described_without_raw_json() {
+        let report = serde_json::json!({
+            "real_tick": 1920.0,
+            "bar": 2,
+            "beat": 1.5,
+            "time": 2.25,
+            "note_names": ["C4", "E4", "G4"],
+            "reason_description": "把位跨度太大",
+            "closest_alternative_names": ["C4", "G4"],
+            "dropped_note_names": ["E4"],
+            "fallback_description": "放弃音符",
+            "sacrificed_note_names": ["E4"],
+        });
+        assert_eq!(
+            FretDancer::describe_unplayable_report(&report),
+            "第2小节第1.5拍 (tick: 1920, 2.25秒)：C4 E4 G4，原因：把位跨度太大，\
+             最接近的可演奏组合：C4 G4（去掉E4），处理方式：放弃音符，放弃的音符：E4"
+        );
+
+        // 无法过渡时没有最接近的组合，没有放弃音符时也不输出
+        let report = serde_json::json!({
+            "real_tick": 480.0,
+            "bar": 1,
+            "beat": 2.0,
+            "time": 0.5,
+            "note_names": ["A2"],
+            "reason_description": "无法从之前的手型过渡到任何按法",
+            "fallback_description": "琶音",
+            "sacrificed_note_names": [],
+        });
+        assert_eq!(
+            FretDancer::describe_unplayable_report(&report),
+            "第1小节第2拍 (tick: 480, 0.5秒)：A2，原因：无法从之前的手型过渡到任何按法，处理方式：琶音"
+        );
+    }
+}
@@ -2,8 +2,10 @@
 //!
 //! 包含吉他的各种组件和功能实现
 
+pub mod chord_recognition;
 pub mod guitar_chord;
 pub mod guitar_instance;
 pub mod guitar_note;
 pub mod guitar_string;
+pub mod instrument_profile;
 pub mod music_note;
@@ -0,0 +1,296 @@
+// ChordRecognition.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+const PITCH_CLASS_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// 识别出来的和弦
+///
+/// Params:
+/// - name: 完整和弦名，如 `Am7`, `C/E`, `G7(9)`
+/// - root: 根音
+/// - quality: 和弦性质，如 `m`, `maj7`, `sus4`，大三和弦为空字符串
+/// - extensions: 延伸音和附加音，如 `9`, `#11`
+/// - inversion: 转位，0为原位，1为第一转位，2为第二转位，3为第三转位，-1表示低音不是和弦音
+/// - bass: 低音与根音不同时的低音音名（斜线和弦）
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChordName {
+    pub name: String,
+    pub root: String,
+    pub quality: String,
+    pub extensions: Vec<String>,
+    pub inversion: i32,
+    pub bass: Option<String>,
+}
+
+/// 获取音符的音名，不带八度信息，C为48
+pub fn pitch_class_name(note: i32) -> &'static str {
+    PITCH_CLASS_NAMES[note.rem_euclid(12) as usize]
+}
+
+/// 以某个音为根音解析和弦，返回和弦和它的评分，评分越低越好
+fn analyze_with_root(intervals: &BTreeSet<i32>, root: i32, bass: i32) -> Option<(ChordName, i32)> {
+    let has = |i: i32| intervals.contains(&i);
+    let mut used = BTreeSet::from([0]);
+    let mut score = 0;
+
+    // 三度音决定大小，没有三度音时尝试挂留和弦
+    let third = if has(4) {
+        Some(4)
+    } else if has(3) {
+        Some(3)
+    } else {
+        None
+    };
+    let sus = match third {
+        Some(_) => None,
+        None if has(5) => Some(5),
+        None if has(2) => Some(2),
+        None => None,
+    };
+
+    // 只有根音和五度音时视为强力和弦
+    if third.is_none() && sus.is_none() {
+        if has(7) && intervals.len() == 2 {
+            let name = format!("{}5", pitch_class_name(root));
+            return Some((build_chord_name(name, root, "5", vec![], bass, &[0, 7]), 1));
+        }
+        return None;
+    }
+    if let Some(t) = third {
+        used.insert(t);
+    }
+    if let Some(s) = sus {
+        used.insert(s);
+        score += 1;
+    }
+
+    // 五度音
+    let fifth = if has(7) {
+        Some(7)
+    } else if third == Some(3) && has(6) {
+        Some(6)
+    } else if third == Some(4) && has(8) {
+        Some(8)
+    } else {
+        score += 1;
+        None
+    };
+    if let Some(f) = fifth {
+        used.insert(f);
+    }
+
+    // 七度音，减七和弦的七度是9
+    let seventh = if has(10) {
+        Some(10)
+    } else if has(11) {
+        Some(11)
+    } else if third == Some(3) && fifth == Some(6) && has(9) {
+        Some(9)
+    } else {
+        None
+    };
+    if let Some(s) = seventh {
+        used.insert(s);
+    }
+
+    let mut quality = match (third, sus, fifth, seventh) {
+        (Some(4), _, Some(8), None) => "aug".to_string(),
+        (Some(4), _, Some(8), Some(10)) => "aug7".to_string(),
+        (Some(4), _, _, Some(10)) => "7".to_string(),
+        (Some(4), _, _, Some(11)) => "maj7".to_string(),
+        (Some(4), _, _, _) => String::new(),
+        (Some(3), _, Some(6), Some(9)) => "dim7".to_string(),
+        (Some(3), _, Some(6), Some(10)) => "m7b5".to_string(),
+        (Some(3), _, Some(6), None) => "dim".to_string(),
+        (Some(3), _, _, Some(10)) => "m7".to_string(),
+        (Some(3), _, _, Some(11)) => "m(maj7)".to_string(),
+        (Some(3), _, _, _) => "m".to_string(),
+        (None, Some(s), _, Some(10)) => format!("7sus{}", if s == 5 { 4 } else { 2 }),
+        (None, Some(s), _, _) => format!("sus{}", if s == 5 { 4 } else { 2 }),
+        _ => return None,
+    };
+
+    // 剩下的音都作为延伸音或附加音
+    let mut extensions = Vec::new();
+    for &interval in intervals.iter().filter(|i| !used.contains(i)) {
+        let extension = match interval {
+            1 => "b9",
+            2 => "9",
+            3 => "#9",
+            5 => "11",
+            6 => "#11",
+            8 => "b13",
+            9 if seventh.is_none() && extensions.is_empty() => {
+                // 没有七度音时，大六度作为六和弦
+                quality = match quality.as_str() {
+                    "" => "6".to_string(),
+                    "m" => "m6".to_string(),
+                    _ => format!("{}6", quality),
+                };
+                continue;
+            }
+            9 => "13",
+            10 => "7",
+            11 => "maj7",
+            _ => continue,
+        };
+        extensions.push(extension.to_string());
+        score += 2;
+    }
+
+    let mut name = format!("{}{}", pitch_class_name(root), quality);
+    if !extensions.is_empty() {
+        if seventh.is_some() {
+            name.push_str(&format!("({})", extensions.join(",")));
+        } else {
+            name.push_str(&format!("add{}", extensions.join(",")));
+        }
+    }
+
+    let chord_tones: Vec<i32> = [Some(0), third.or(sus), fifth, seventh]
+        .into_iter()
+        .flatten()
+        .collect();
+    let chord = build_chord_name(name, root, &quality, extensions, bass, &chord_tones);
+    if chord.bass.is_some() {
+        score += 1;
+    }
+
+    Some((chord, score))
+}
+
+/// 根据根音、低音和和弦音生成和弦名，处理转位和斜线和弦
+fn build_chord_name(
+    name: String,
+    root: i32,
+    quality: &str,
+    extensions: Vec<String>,
+    bass: i32,
+    chord_tones: &[i32],
+) -> ChordName {
+    let bass_interval = (bass - root).rem_euclid(12);
+    let inversion = chord_tones
+        .iter()
+        .position(|&tone| tone == bass_interval)
+        .map_or(-1, |index| index as i32);
+
+    let bass_name = if bass_interval == 0 {
+        None
+    } else {
+        Some(pitch_class_name(bass).to_string())
+    };
+    let name = match &bass_name {
+        Some(bass_name) => format!("{}/{}", name, bass_name),
+        None => name,
+    };
+
+    ChordName {
+        name,
+        root: pitch_class_name(root).to_string(),
+        quality: quality.to_string(),
+        extensions,
+        inversion,
+        bass: bass_name,
+    }
+}
+
+/// 根据发声的音符识别和弦
+///
+/// 会尝试以每一个音为根音进行解析，选择最简单的解释，评分相同时优先选择低音为根音的解释。
+/// 少于两个不同音名时返回None
+pub fn identify_chord(notes: &[i32]) -> Option<ChordName> {
+    let bass = *notes.iter().min()?;
+    let pitch_classes: BTreeSet<i32> = notes.iter().map(|n| n.rem_euclid(12)).collect();
+    if pitch_classes.len() < 2 {
+        return None;
+    }
+
+    let bass_class = bass.rem_euclid(12);
+    let mut best: Option<(ChordName, i32)> = None;
+    for &root in &pitch_classes {
+        let intervals: BTreeSet<i32> = pitch_classes
+            .iter()
+            .map(|pc| (pc - root).rem_euclid(12))
+            .collect();
+
+        if let Some((chord, score)) = analyze_with_root(&intervals, root, bass) {
+            let is_better = match &best {
+                None => true,
+                Some((_, best_score)) => {
+                    score < *best_score || (score == *best_score && root == bass_class)
+                }
+            };
+            if is_better {
+                best = Some((chord, score));
+            }
+        }
+    }
+
+    best.map(|(chord, _)| chord)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identify_common_chords() {
+        // C大三和弦（吉他开放和弦 C E G C E）
+        let c_major = identify_chord(&[48, 52, 55, 60, 64]).unwrap();
+        assert_eq!(c_major.name, "C");
+        assert_eq!(c_major.inversion, 0);
+
+        // Am7
+        assert_eq!(identify_chord(&[45, 52, 55, 60, 64]).unwrap().name, "Am7");
+
+        // C/E 第一转位
+        let c_over_e = identify_chord(&[40, 48, 55, 64]).unwrap();
+        assert_eq!(c_over_e.name, "C/E");
+        assert_eq!(c_over_e.inversion, 1);
+
+        // 强力和弦
+        assert_eq!(identify_chord(&[40, 47, 52]).unwrap().name, "E5");
+
+        // 单音不是和弦
+        assert!(identify_chord(&[52, 64]).is_none());
+    }
+
+    #[test]
+    fn test_tie_prefers_bass_as_root() {
+        // D# F# C 既可以是Cdim/D#，也可以是D#m6，评分相同时以低音为根音
+        let chord = identify_chord(&[51, 54, 60]).unwrap();
+        assert_eq!(chord.name, "D#m6");
+        assert_eq!(chord.root, "D#");
+        assert_eq!(chord.bass, None);
+    }
+
+    #[test]
+    fn test_barre_over_unplayed_strings_does_not_change_chord() {
+        use crate::guitar::instrument_profile::InstrumentProfile;
+        use crate::hand::left_finger::LeftFinger;
+        use crate::hand::left_hand::LeftHand;
+
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let strings = &guitar.guitar_strings;
+        // 第三品横按，无名指和小指按出G5强力和弦，横按还压着没有弹的高音弦
+        let hand = LeftHand::new(
+            vec![
+                LeftFinger::new(1, &strings[5], 3, "Barre"),
+                LeftFinger::new(2, &strings[4], 4, "Open"),
+                LeftFinger::new(3, &strings[4], 5, "Pressed"),
+                LeftFinger::new(4, &strings[3], 5, "Pressed"),
+            ],
+            true,
+            5.73,
+        );
+        assert_eq!(identify_chord(&hand.sounding_notes(&guitar)).unwrap().name, "Gm");
+
+        let played = hand.played_notes(&guitar, &[43, 50, 55]);
+        assert_eq!(played, vec![43, 50, 55]);
+        assert_eq!(identify_chord(&played).unwrap().name, "G5");
+    }
+}
@@ -1,5 +1,9 @@
 use crate::guitar::guitar_instance::Guitar;
-use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// 默认手型在低把位时食指到小指最多能跨越的品格数
+pub const DEFAULT_MAX_SPAN: i32 = 5;
 
 #[derive(Debug, Clone)]
 pub struct Chord {
@@ -37,43 +41,100 @@ impl Chord {
     }
 
     pub fn is_playable(&self) -> bool {
+        self.check_playable().is_ok()
+    }
+
+    /// 检查和弦是否可以演奏，不能演奏时返回原因
+    pub fn check_playable(&self) -> Result<(), UnplayableReason> {
+        self.check_playable_with_span(DEFAULT_MAX_SPAN)
+    }
+
+    /// 按指定的最大跨度检查和弦是否可以演奏
+    ///
+    /// # 参数
+    /// * `max_span` - 低把位时食指到小指最多能跨越的品格数
+    pub fn check_playable_with_span(&self, max_span: i32) -> Result<(), UnplayableReason> {
         if self.has_duplicate_strings() {
-            return false;
+            return Err(UnplayableReason::DuplicateString);
         }
 
         let frets = self.get_frets();
         if frets.is_empty() {
-            return true;
+            return Ok(());
         }
 
         // 四个手指按不下超过4个不同的fret
         let unique_frets_count: HashSet<_> = frets.iter().collect();
         let unique_frets_count: usize = unique_frets_count.len();
         if unique_frets_count > 4 {
-            return false;
+            return Err(UnplayableReason::TooManyFrets);
         }
 
         // 检查把位跨度限制
-        if let (Some(&max_fret), Some(&min_fret)) = (frets.iter().max(), frets.iter().min()) {
-            let span = max_fret - min_fret;
-            let out_limit_on_low_bar = min_fret < 8 && span > 5;
-            let out_limit_on_high_bar = min_fret >= 8 && span > 6;
-
-            if out_limit_on_low_bar || out_limit_on_high_bar {
-                return false;
-            }
+        if let (Some(&max_fret), Some(&min_fret)) = (frets.iter().max(), frets.iter().min())
+            && !fret_span_is_playable(min_fret, max_fret, max_span)
+        {
+            return Err(UnplayableReason::SpanTooWide);
         }
 
-        true
+        Ok(())
+    }
+}
+
+/// 和弦无法演奏的原因
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum UnplayableReason {
+    /// 两个音符只能在同一根弦上演奏
+    DuplicateString,
+    /// 需要按的品格超过4个
+    TooManyFrets,
+    /// 把位跨度太大
+    SpanTooWide,
+    /// 音符超出了乐器的音域
+    OutOfRange,
+    /// 找不到合适的手指分配
+    NoFingerAssignment,
+    /// 无法从之前的任何手型过渡到这个按法
+    NoValidTransition,
+}
+
+impl UnplayableReason {
+    pub fn description(&self) -> &'static str {
+        match self {
+            UnplayableReason::DuplicateString => "多个音符只能在同一根弦上演奏",
+            UnplayableReason::TooManyFrets => "需要按的品格超过4个",
+            UnplayableReason::SpanTooWide => "把位跨度太大",
+            UnplayableReason::OutOfRange => "音符超出了乐器的音域",
+            UnplayableReason::NoFingerAssignment => "没有找到合适的手指分配",
+            UnplayableReason::NoValidTransition => "无法从之前的手型过渡到任何按法",
+        }
     }
 }
 
 pub fn convert_notes_to_chord(notes: &Vec<i32>, guitar: &Guitar) -> Vec<Chord> {
+    iter_playable_chords(notes, guitar).collect()
+}
+
+/// 以迭代器的方式生成所有可演奏的和弦，不会一次性生成全部组合
+pub fn iter_playable_chords(notes: &[i32], guitar: &Guitar) -> PlayableChords {
+    PlayableChords::new(get_note_positions(notes, guitar))
+}
+
+/// 按指定的最大跨度生成所有可演奏的和弦
+pub fn iter_playable_chords_with_span(
+    notes: &[i32],
+    guitar: &Guitar,
+    max_span: i32,
+) -> PlayableChords {
+    PlayableChords::with_max_span(get_note_positions(notes, guitar), max_span)
+}
+
+/// 为每个音符找到所有可能的弦和品位组合
+pub fn get_note_positions(notes: &[i32], guitar: &Guitar) -> Vec<Vec<NotePosition>> {
     let use_harm_notes = guitar.use_harm_notes;
     let mut note_positions: Vec<Vec<NotePosition>> = Vec::new();
-    let mut result: Vec<Chord> = Vec::new();
 
-    // 为每个音符找到所有可能的弦和品位组合
     for &note in notes {
         let mut possible_positions: Vec<NotePosition> = Vec::new();
 
@@ -107,46 +168,282 @@ pub fn convert_notes_to_chord(notes: &Vec<i32>, guitar: &Guitar) -> Vec<Chord> {
         note_positions.push(possible_positions);
     }
 
-    // 生成所有可能的组合
-    if !note_positions.is_empty() {
-        let combinations = cartesian_product(&note_positions);
+    note_positions
+}
+
+/// 每个音符只使用指定的弦，用于已经知道弦的输入
+///
+/// 音符在指定的弦上弹不出来时没有可选位置
+pub fn get_note_positions_on_strings(
+    notes: &[i32],
+    string_indices: &[i32],
+    guitar: &Guitar,
+) -> Vec<Vec<NotePosition>> {
+    notes
+        .iter()
+        .zip(string_indices)
+        .map(|(&note, &string_index)| {
+            guitar
+                .guitar_strings
+                .iter()
+                .find(|guitar_string| guitar_string.get_string_index() == string_index)
+                .and_then(|guitar_string| guitar_string.get_fret_by_note(note))
+                .map(|fret| NotePosition { string_index, fret })
+                .into_iter()
+                .collect()
+        })
+        .collect()
+}
+
+/// 回溯生成可演奏和弦的迭代器
+///
+/// 每放置一个音符就检查重复弦、不同品格数和把位跨度，部分组合一旦不合法就不再继续展开。
+/// 这三个条件在继续添加音符时只会变得更严格，所以剪枝不会漏掉合法的和弦。
+/// 生成顺序与逐层计算笛卡尔积的顺序一致，没有可选位置的音符会被忽略
+pub struct PlayableChords {
+    note_positions: Vec<Vec<NotePosition>>,
+    /// 每一层下一个要尝试的位置下标
+    next_choice: Vec<usize>,
+    /// 当前已经放置的位置
+    current: Vec<NotePosition>,
+    /// 每根弦是否已经被使用
+    used_strings: HashSet<i32>,
+    /// 每个品格被按下的次数，不包含空弦
+    fret_counter: HashMap<i32, usize>,
+    /// 低把位时食指到小指最多能跨越的品格数
+    max_span: i32,
+    finished: bool,
+}
+
+impl PlayableChords {
+    pub fn new(note_positions: Vec<Vec<NotePosition>>) -> Self {
+        Self::with_max_span(note_positions, DEFAULT_MAX_SPAN)
+    }
+
+    pub fn with_max_span(note_positions: Vec<Vec<NotePosition>>, max_span: i32) -> Self {
+        let finished = note_positions.is_empty();
+        let note_positions: Vec<Vec<NotePosition>> = note_positions
+            .into_iter()
+            .filter(|positions| !positions.is_empty())
+            .collect();
+
+        PlayableChords {
+            next_choice: vec![0; note_positions.len() + 1],
+            note_positions,
+            current: Vec::new(),
+            used_strings: HashSet::new(),
+            fret_counter: HashMap::new(),
+            max_span,
+            finished,
+        }
+    }
+
+    /// 尝试放置一个位置，如果放置后仍然可能演奏就返回true
+    fn try_push(&mut self, position: &NotePosition) -> bool {
+        if self.used_strings.contains(&position.string_index) {
+            return false;
+        }
 
-        for combination in combinations {
-            let chord = Chord::new(combination);
+        if position.fret > 0 {
+            let is_new_fret = !self.fret_counter.contains_key(&position.fret);
+            if is_new_fret && self.fret_counter.len() >= 4 {
+                return false;
+            }
 
-            // 检查是否可演奏
-            if chord.is_playable() {
-                result.push(chord);
+            let min_fret = self
+                .fret_counter
+                .keys()
+                .copied()
+                .fold(position.fret, i32::min);
+            let max_fret = self
+                .fret_counter
+                .keys()
+                .copied()
+                .fold(position.fret, i32::max);
+            if !fret_span_is_playable(min_fret, max_fret, self.max_span) {
+                return false;
             }
+
+            *self.fret_counter.entry(position.fret).or_insert(0) += 1;
         }
+
+        self.used_strings.insert(position.string_index);
+        self.current.push(position.clone());
+        true
     }
 
-    result
+    /// 撤销最后一次放置
+    fn pop(&mut self) {
+        if let Some(position) = self.current.pop() {
+            self.used_strings.remove(&position.string_index);
+            if position.fret > 0
+                && let Some(count) = self.fret_counter.get_mut(&position.fret)
+            {
+                *count -= 1;
+                if *count == 0 {
+                    self.fret_counter.remove(&position.fret);
+                }
+            }
+        }
+    }
 }
 
-// 计算多个向量的笛卡尔积
-fn cartesian_product<T: Clone>(v: &[Vec<T>]) -> Vec<Vec<T>> {
-    if v.is_empty() {
-        return vec![];
+impl Iterator for PlayableChords {
+    type Item = Chord;
+
+    fn next(&mut self) -> Option<Chord> {
+        if self.finished {
+            return None;
+        }
+
+        loop {
+            let depth = self.current.len();
+
+            // 所有音符都已放置，输出一个和弦，然后回退一层继续搜索
+            if depth == self.note_positions.len() {
+                let chord = Chord::new(self.current.clone());
+                if depth == 0 {
+                    self.finished = true;
+                } else {
+                    self.pop();
+                }
+                return Some(chord);
+            }
+
+            let choice = self.next_choice[depth];
+            if choice < self.note_positions[depth].len() {
+                self.next_choice[depth] += 1;
+                let position = self.note_positions[depth][choice].clone();
+                if self.try_push(&position) {
+                    self.next_choice[depth + 1] = 0;
+                }
+                continue;
+            }
+
+            // 当前层已经尝试完毕，回溯
+            if depth == 0 {
+                self.finished = true;
+                return None;
+            }
+            self.pop();
+        }
     }
+}
+
+/// 检查把位跨度限制，第8品以上品格变窄，可以多跨一品
+fn fret_span_is_playable(min_fret: i32, max_fret: i32, max_span: i32) -> bool {
+    let span = max_fret - min_fret;
+    let out_limit_on_low_bar = min_fret < 8 && span > max_span;
+    let out_limit_on_high_bar = min_fret >= 8 && span > max_span + 1;
 
-    let mut result = vec![vec![]];
+    !(out_limit_on_low_bar || out_limit_on_high_bar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+
+    fn as_pairs(chord: &Chord) -> Vec<(i32, i32)> {
+        chord
+            .positions
+            .iter()
+            .map(|pos| (pos.string_index, pos.fret))
+            .collect()
+    }
 
-    for inner_vec in v {
-        if inner_vec.is_empty() {
-            continue;
+    /// 逐层计算笛卡尔积，再用check_playable_with_span过滤
+    fn naive_playable_chords(
+        note_positions: &[Vec<NotePosition>],
+        max_span: i32,
+    ) -> Vec<Vec<(i32, i32)>> {
+        let mut combinations: Vec<Vec<NotePosition>> = vec![Vec::new()];
+        for positions in note_positions
+            .iter()
+            .filter(|positions| !positions.is_empty())
+        {
+            combinations = combinations
+                .into_iter()
+                .flat_map(|combination| {
+                    positions.iter().map(move |position| {
+                        let mut combination = combination.clone();
+                        combination.push(position.clone());
+                        combination
+                    })
+                })
+                .collect();
         }
+        combinations
+            .into_iter()
+            .map(Chord::new)
+            .filter(|chord| chord.check_playable_with_span(max_span).is_ok())
+            .map(|chord| as_pairs(&chord))
+            .collect()
+    }
 
-        let mut temp = Vec::new();
-        for existing in &result {
-            for item in inner_vec {
-                let mut new_vec = existing.clone();
-                new_vec.push(item.clone());
-                temp.push(new_vec);
+    #[test]
+    fn test_playable_chords_match_naive_enumeration() {
+        let profile = InstrumentProfile::default();
+        let guitar = profile.create_guitar(false);
+        let harmonic_guitar = profile.create_guitar(true);
+        let cases: Vec<(&Guitar, Vec<i32>)> = vec![
+            // 六根弦都有音的密集和弦
+            (&guitar, vec![40, 47, 52, 56, 59, 64]),
+            (&guitar, vec![52, 55, 59, 62, 64]),
+            // 同一个音出现两次，只能放在不同的弦上
+            (&guitar, vec![60, 60, 64, 67]),
+            // 40和41都只能在最低的弦上演奏
+            (&guitar, vec![40, 41, 64]),
+            // 超出音域的音符被忽略
+            (&guitar, vec![20, 55, 59]),
+            // 泛音和普通音符可能落在同一个位置上
+            (&harmonic_guitar, vec![52, 59, 64, 69]),
+            (&harmonic_guitar, vec![57, 62, 67, 71, 76]),
+        ];
+
+        for (guitar, notes) in cases {
+            let note_positions = get_note_positions(&notes, guitar);
+            for max_span in [3, DEFAULT_MAX_SPAN] {
+                let chords: Vec<Vec<(i32, i32)>> =
+                    iter_playable_chords_with_span(&notes, guitar, max_span)
+                        .map(|chord| as_pairs(&chord))
+                        .collect();
+                assert_eq!(
+                    chords,
+                    naive_playable_chords(&note_positions, max_span),
+                    "notes: {:?}, max_span: {}",
+                    notes,
+                    max_span
+                );
             }
         }
-        result = temp;
+
+        // 泛音确实提供了额外的位置
+        let notes = [52, 59, 64, 69];
+        assert!(
+            get_note_positions(&notes, &harmonic_guitar)
+                .iter()
+                .map(Vec::len)
+                .sum::<usize>()
+                > get_note_positions(&notes, &guitar)
+                    .iter()
+                    .map(Vec::len)
+                    .sum::<usize>()
+        );
+        assert_eq!(iter_playable_chords(&[40, 41, 64], &guitar).count(), 0);
     }
 
-    result
+    #[test]
+    fn test_empty_input_behavior() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        // 没有音符时不生成和弦
+        assert_eq!(iter_playable_chords(&[], &guitar).count(), 0);
+        assert_eq!(PlayableChords::new(Vec::new()).count(), 0);
+
+        // 所有音符都没有可选位置时被忽略，只生成一个空和弦
+        let chords: Vec<Chord> = iter_playable_chords(&[10, 20], &guitar).collect();
+        assert_eq!(chords.len(), 1);
+        assert!(chords[0].positions.is_empty());
+        assert!(chords[0].is_playable());
+    }
 }
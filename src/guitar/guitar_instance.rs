@@ -55,39 +55,41 @@ impl Guitar {
         for string in &self.guitar_strings {
             let base_note_num = &string.get_base_note();
             let string_index = string.get_string_index();
+            // 短弦的泛音点要从它的起始品格开始算
+            let start_fret = string.get_start_fret();
 
             // 5th fret harmonic (24 semitones higher)
             all_harm_notes.push(HarmonicNote {
                 index: string_index,
-                fret: 5,
+                fret: start_fret + 5,
                 note: base_note_num + 24,
             });
 
             // 7th fret harmonic (19 semitones higher)
             all_harm_notes.push(HarmonicNote {
                 index: string_index,
-                fret: 7,
+                fret: start_fret + 7,
                 note: base_note_num + 19,
             });
 
             // 12th fret harmonic (12 semitones higher)
             all_harm_notes.push(HarmonicNote {
                 index: string_index,
-                fret: 12,
+                fret: start_fret + 12,
                 note: base_note_num + 12,
             });
 
             // 4th fret harmonic (28 semitones higher)
             all_harm_notes.push(HarmonicNote {
                 index: string_index,
-                fret: 4,
+                fret: start_fret + 4,
                 note: base_note_num + 28,
             });
 
             // 9th fret harmonic (28 semitones higher)
             all_harm_notes.push(HarmonicNote {
                 index: string_index,
-                fret: 9,
+                fret: start_fret + 9,
                 note: base_note_num + 28,
             });
         }
@@ -95,6 +97,33 @@ impl Guitar {
         all_harm_notes
     }
 
+    /// 获取乐器的音域，返回(最低音, 最高音)
+    /// 不假设0弦最高、最后一弦最低，以兼容尤克里里、班卓琴等重入调弦
+    pub fn get_note_range(&self) -> (i32, i32) {
+        let min_note = self
+            .guitar_strings
+            .iter()
+            .map(|s| s.get_base_note())
+            .min()
+            .unwrap_or(0);
+        let max_note = self
+            .guitar_strings
+            .iter()
+            .map(|s| s.get_max_note())
+            .max()
+            .unwrap_or(0);
+        (min_note, max_note)
+    }
+
+    /// 获取指板的品数
+    pub fn get_fret_count(&self) -> i32 {
+        self.guitar_strings
+            .iter()
+            .map(|s| s.get_fret_count())
+            .max()
+            .unwrap_or(0)
+    }
+
     pub fn get_guitar_strings(&self) -> &Vec<GuitarString> {
         &self.guitar_strings
     }
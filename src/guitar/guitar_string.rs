@@ -6,18 +6,34 @@ use crate::guitar::music_note::{KEYNOTES, MusicNote};
 /// Params:
 /// - base_note: Base note of the string. 弦的基音
 /// - string_index: Index of the string, starting with the highest pitch as string 0. 弦的索引,以最高音为0弦开始计算
+/// - start_fret: Fret where the nut of a short string sits, 0 for normal strings. 短弦的起始品格
+/// - fret_count: Number of frets on the fretboard. 指板品数
 #[derive(Debug, Clone)]
 pub struct GuitarString {
     base_note: MusicNote,
     string_index: i32,
+    start_fret: i32,
+    fret_count: i32,
 }
 
 impl GuitarString {
     /// Creates a new GuitarString instance.
     pub fn new(base_note: MusicNote, string_index: i32) -> Self {
+        Self::with_frets(base_note, string_index, 0, 23)
+    }
+
+    /// Creates a GuitarString with a custom start fret and fret count.
+    pub fn with_frets(
+        base_note: MusicNote,
+        string_index: i32,
+        start_fret: i32,
+        fret_count: i32,
+    ) -> Self {
         GuitarString {
             base_note,
             string_index,
+            start_fret,
+            fret_count,
         }
     }
 
@@ -31,12 +47,46 @@ impl GuitarString {
         self.string_index
     }
 
+    /// Returns the fret where the string starts.
+    pub fn get_start_fret(&self) -> i32 {
+        self.start_fret
+    }
+
+    /// Returns the number of frets on the fretboard.
+    pub fn get_fret_count(&self) -> i32 {
+        self.fret_count
+    }
+
+    /// Returns the highest note that can be played on this string.
+    pub fn get_max_note(&self) -> i32 {
+        self.base_note.num + self.fret_count - self.start_fret
+    }
+
+    /// Calculates the note for a given fret, the inverse of `get_fret_by_note`.
+    ///
+    /// Returns None if the fret does not exist on this string.
+    pub fn get_note_by_fret(&self, fret: i32) -> Option<i32> {
+        if fret == 0 {
+            return Some(self.base_note.num);
+        }
+        if fret <= self.start_fret || fret > self.fret_count {
+            None
+        } else {
+            Some(self.base_note.num + fret - self.start_fret)
+        }
+    }
+
     /// Calculates the fret position for a given note.
+    /// 短弦上的品格以指板上的实际品格计算，空弦仍然返回0
     ///
     /// Returns the fret number if valid, otherwise returns None.
     pub fn get_fret_by_note(&self, note: i32) -> Option<i32> {
-        let fret = note - self.base_note.num;
-        if fret < 0 || fret > 23 {
+        let semitone = note - self.base_note.num;
+        if semitone == 0 {
+            return Some(0);
+        }
+        let fret = semitone + self.start_fret;
+        if semitone < 0 || fret > self.fret_count {
             None
         } else {
             Some(fret)
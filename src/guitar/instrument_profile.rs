@@ -0,0 +1,346 @@
+// InstrumentProfile.rs
+
+use crate::guitar::guitar_instance::Guitar;
+use crate::guitar::guitar_string::{GuitarString, get_keynote_by_value};
+use crate::guitar::music_note::MusicNote;
+use serde::{Deserialize, Serialize};
+
+/// 右手演奏方式
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RightHandTechnique {
+    /// 指弹，使用p、i、m、a四个手指拨弦
+    FingerStyle,
+    /// 拨片
+    Pick,
+    /// 贝斯指法
+    Bass,
+}
+
+impl RightHandTechnique {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RightHandTechnique::FingerStyle => "finger_style",
+            RightHandTechnique::Pick => "pick",
+            RightHandTechnique::Bass => "bass",
+        }
+    }
+
+    /// 根据avatar的乐器类型得到默认的右手演奏方式
+    pub fn from_instrument(instrument: &str) -> Self {
+        match instrument {
+            "bass" => RightHandTechnique::Bass,
+            "electric_guitar" => RightHandTechnique::Pick,
+            _ => RightHandTechnique::FingerStyle,
+        }
+    }
+}
+
+/// 短弦信息，比如班卓琴的第五弦是从第5品开始的
+///
+/// Params:
+/// - string_index: 弦的索引
+/// - start_fret: 弦枕所在的品格，空弦音在这个品格上发声
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ShortString {
+    pub string_index: i32,
+    pub start_fret: i32,
+}
+
+/// 乐器配置，包含弦数、调弦、品数和右手演奏方式
+///
+/// Params:
+/// - name: 配置名
+/// - tuning: 调弦，从0弦开始排列，0弦不一定是最高音（比如尤克里里的高G调弦）
+/// - fret_count: 指板上的品数
+/// - right_hand_technique: 右手演奏方式
+/// - short_strings: 不从琴枕开始的短弦
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct InstrumentProfile {
+    pub name: String,
+    pub tuning: Vec<String>,
+    pub fret_count: i32,
+    pub right_hand_technique: RightHandTechnique,
+    #[serde(default)]
+    pub short_strings: Vec<ShortString>,
+}
+
+impl InstrumentProfile {
+    pub fn new(
+        name: &str,
+        tuning: &[&str],
+        fret_count: i32,
+        right_hand_technique: RightHandTechnique,
+    ) -> Self {
+        InstrumentProfile {
+            name: name.to_string(),
+            tuning: tuning.iter().map(|s| s.to_string()).collect(),
+            fret_count,
+            right_hand_technique,
+            short_strings: Vec::new(),
+        }
+    }
+
+    /// 添加一根短弦
+    pub fn with_short_string(mut self, string_index: i32, start_fret: i32) -> Self {
+        self.short_strings.push(ShortString {
+            string_index,
+            start_fret,
+        });
+        self
+    }
+
+    /// 使用用户设置的调弦替换当前调弦，超出弦数的短弦配置会被忽略
+    pub fn with_tuning(&self, tuning: Vec<String>) -> Self {
+        let mut profile = self.clone();
+        let string_count = tuning.len() as i32;
+        profile.tuning = tuning;
+        profile
+            .short_strings
+            .retain(|s| s.string_index < string_count);
+        profile
+    }
+
+    pub fn string_count(&self) -> usize {
+        self.tuning.len()
+    }
+
+    pub fn max_string_index(&self) -> usize {
+        self.tuning.len().saturating_sub(1)
+    }
+
+    /// 获取某根弦的起始品格，普通弦为0
+    pub fn start_fret_of(&self, string_index: i32) -> i32 {
+        self.short_strings
+            .iter()
+            .find(|s| s.string_index == string_index)
+            .map_or(0, |s| s.start_fret)
+    }
+
+    pub fn create_guitar_strings(&self) -> Vec<GuitarString> {
+        self.tuning
+            .iter()
+            .enumerate()
+            .map(|(index, note)| {
+                let index = index as i32;
+                GuitarString::with_frets(
+                    MusicNote::new(get_keynote_by_value(note)),
+                    index,
+                    self.start_fret_of(index),
+                    self.fret_count,
+                )
+            })
+            .collect()
+    }
+
+    pub fn create_guitar(&self, use_harm_notes: bool) -> Guitar {
+        Guitar::with_defaults(self.create_guitar_strings(), use_harm_notes)
+    }
+
+    /// 内置的乐器配置
+    pub fn presets() -> Vec<InstrumentProfile> {
+        vec![
+            // 默认的6弦吉他和4弦Bass保持原来GuitarString::new的23品，已有的任务音域不变
+            InstrumentProfile::new(
+                "6弦吉他",
+                &["e", "b", "G", "D", "A", "E1"],
+                23,
+                RightHandTechnique::FingerStyle,
+            ),
+            InstrumentProfile::new(
+                "7弦吉他",
+                &["e", "b", "G", "D", "A", "E1", "B1"],
+                24,
+                RightHandTechnique::FingerStyle,
+            ),
+            InstrumentProfile::new(
+                "8弦吉他",
+                &["e", "b", "G", "D", "A", "E1", "B1", "F2#"],
+                24,
+                RightHandTechnique::FingerStyle,
+            ),
+            InstrumentProfile::new(
+                "4弦Bass",
+                &["G", "D", "A", "E1"],
+                23,
+                RightHandTechnique::Bass,
+            ),
+            InstrumentProfile::new(
+                "5弦Bass",
+                &["G", "D", "A", "E1", "B1"],
+                24,
+                RightHandTechnique::Bass,
+            ),
+            InstrumentProfile::new(
+                "6弦Bass",
+                &["c", "G", "D", "A", "E1", "B1"],
+                24,
+                RightHandTechnique::Bass,
+            ),
+            InstrumentProfile::new(
+                "尤克里里 (高G调弦)",
+                &["a1", "e", "c", "g"],
+                18,
+                RightHandTechnique::FingerStyle,
+            ),
+            InstrumentProfile::new(
+                "尤克里里 (低G调弦)",
+                &["a1", "e", "c", "G"],
+                18,
+                RightHandTechnique::FingerStyle,
+            ),
+            InstrumentProfile::new(
+                "5弦班卓琴 (Open G)",
+                &["d", "b", "G", "D", "g"],
+                22,
+                RightHandTechnique::FingerStyle,
+            )
+            .with_short_string(4, 5),
+        ]
+    }
+
+    /// 根据avatar的乐器类型选择默认配置
+    pub fn default_for_instrument(instrument: &str) -> Self {
+        let technique = RightHandTechnique::from_instrument(instrument);
+        let preset_name = match technique {
+            RightHandTechnique::Bass => "4弦Bass",
+            _ => "6弦吉他",
+        };
+        let mut profile = Self::presets()
+            .into_iter()
+            .find(|p| p.name == preset_name)
+            .unwrap();
+        profile.right_hand_technique = technique;
+        profile
+    }
+}
+
+impl Default for InstrumentProfile {
+    fn default() -> Self {
+        Self::default_for_instrument("finger_style_guitar")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::midi_to_note::{MidiProcessor, NoteInfo};
+
+    fn preset(name: &str) -> InstrumentProfile {
+        InstrumentProfile::presets()
+            .into_iter()
+            .find(|profile| profile.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_banjo_short_string_starts_at_its_nut() {
+        let guitar = preset("5弦班卓琴 (Open G)").create_guitar(false);
+        let short_string = &guitar.guitar_strings[4];
+        let open_note = short_string.get_base_note();
+
+        // 空弦仍然是0品，往上的音从第5品的弦枕开始数
+        assert_eq!(short_string.get_fret_by_note(open_note), Some(0));
+        assert_eq!(short_string.get_fret_by_note(open_note + 2), Some(7));
+        assert_eq!(short_string.get_note_by_fret(7), Some(open_note + 2));
+        // 弦枕之下没有可以按的品格
+        assert_eq!(short_string.get_note_by_fret(3), None);
+        assert_eq!(short_string.get_max_note(), open_note + 22 - 5);
+    }
+
+    #[test]
+    fn test_note_range_follows_tuning_not_string_order() {
+        // 高G调弦的最后一根弦不是最低音
+        let ukulele = preset("尤克里里 (高G调弦)").create_guitar(false);
+        let lowest = ukulele
+            .guitar_strings
+            .iter()
+            .map(|string| string.get_base_note())
+            .min()
+            .unwrap();
+        assert!(ukulele.guitar_strings[3].get_base_note() > lowest);
+        assert_eq!(ukulele.get_note_range().0, lowest);
+
+        // 7弦吉他的低音到B，24品的指板高音也更高
+        let six = preset("6弦吉他").create_guitar(false);
+        let seven = preset("7弦吉他").create_guitar(false);
+        assert_eq!(seven.get_note_range().0, six.get_note_range().0 - 5);
+        assert_eq!(seven.get_note_range().1, six.get_note_range().1 + 1);
+        assert_eq!(seven.get_fret_count(), 24);
+    }
+
+    #[test]
+    fn test_default_profile_keeps_original_fret_count() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        assert_eq!(guitar.get_fret_count(), 23);
+        assert_eq!(guitar.get_note_range(), (40, 64 + 23));
+        let bass = InstrumentProfile::default_for_instrument("bass").create_guitar(false);
+        assert_eq!(bass.get_fret_count(), 23);
+    }
+
+    /// 四根手指在同一根弦上从top_fret往下排开的手型是否合法
+    fn reaches(profile: &InstrumentProfile, string_index: usize, top_fret: i32) -> bool {
+        use crate::hand::hand_profile::HandProfile;
+        use crate::hand::left_finger::LeftFinger;
+        use crate::hand::left_hand::LeftHand;
+
+        let guitar = profile.create_guitar(false);
+        let string = &guitar.guitar_strings[string_index];
+        let fingers = (1..5)
+            .map(|i| LeftFinger::new(i, string, top_fret - 4 + i, "Pressed"))
+            .collect();
+        let hand = LeftHand::with_profile(fingers, false, HandProfile::default());
+        hand.verify_valid(None, guitar.get_fret_count())
+    }
+
+    #[test]
+    fn test_default_profile_keeps_original_reach_limit() {
+        // 和原来22 - (4 - 手指) - 弦的限制一致
+        let guitar = InstrumentProfile::default();
+        assert!(reaches(&guitar, 0, 22));
+        assert!(!reaches(&guitar, 0, 23));
+        assert!(reaches(&guitar, 5, 17));
+        assert!(!reaches(&guitar, 5, 18));
+    }
+
+    #[test]
+    fn test_extra_low_strings_share_the_sixth_string_reach() {
+        let seven = preset("7弦吉他");
+        assert!(reaches(&seven, 5, 18));
+        assert!(!reaches(&seven, 5, 19));
+        assert!(reaches(&seven, 6, 18));
+        assert!(!reaches(&seven, 6, 19));
+    }
+
+    #[test]
+    fn test_extended_range_keeps_one_note_per_string() {
+        let midi_processor = MidiProcessor::new();
+        let chord = [35, 40, 45, 50, 55, 59, 64];
+        // 7弦吉他可以演奏7个音，6弦吉他需要去掉一个
+        assert_eq!(midi_processor.simplify_notes(&chord, 7), chord.to_vec());
+        let simplified = midi_processor.simplify_notes(&chord, 6);
+        assert_eq!(simplified.len(), 6);
+        assert_eq!((simplified[0], simplified[5]), (35, 64));
+    }
+
+    #[test]
+    fn test_processed_notes_map_is_stable() {
+        let midi_processor = MidiProcessor::new();
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let (min_note, max_note) = guitar.get_note_range();
+        // 8个音的和弦需要随机去掉两个中间的音，还有一个音高出音域
+        let notes_map = vec![NoteInfo {
+            notes: vec![40, 43, 47, 50, 53, 57, 60, 100],
+            real_tick: 0.0,
+            string_indices: Vec::new(),
+        }];
+        let processed = midi_processor.processed_notes_map(&notes_map, min_note, max_note, 6);
+        assert_eq!(processed[0].notes.len(), 6);
+        assert!(processed[0].notes.iter().all(|&note| note <= max_note));
+        // 求解器再次处理时不会去掉不同的音
+        for _ in 0..20 {
+            let again = midi_processor.processed_notes_map(&processed, min_note, max_note, 6);
+            assert_eq!(again[0].notes, processed[0].notes);
+        }
+    }
+}
@@ -1,4 +1,8 @@
+pub mod chord_diagnosis;
+pub mod chord_shape;
+pub mod hand_profile;
 pub mod left_finger;
 pub mod left_hand;
+pub mod left_hand_cost;
 pub mod right_finger;
 pub mod right_hand;
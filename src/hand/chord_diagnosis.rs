@@ -0,0 +1,281 @@
+// ChordDiagnosis.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::guitar::guitar_chord::{
+    Chord, NotePosition, PlayableChords, UnplayableReason, get_note_positions,
+    get_note_positions_on_strings,
+};
+use crate::guitar::guitar_instance::Guitar;
+use crate::hand::hand_profile::HandProfile;
+use crate::hand::left_hand::convert_chord_to_finger_positions_for_hand;
+
+/// 统计失败原因时最多检查的组合数
+const MAX_DIAGNOSED_COMBINATIONS: usize = 50000;
+
+/// 一组音符无法演奏的诊断结果
+///
+/// Params:
+/// - reason: 主要原因
+/// - out_of_range_notes: 在任何弦上都找不到位置的音符
+/// - closest_alternative: 去掉最少音符后可以演奏的音符组合
+/// - dropped_notes: 得到closest_alternative时去掉的音符
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UnplayableDiagnosis {
+    pub reason: UnplayableReason,
+    pub out_of_range_notes: Vec<i32>,
+    pub closest_alternative: Option<Vec<i32>>,
+    pub dropped_notes: Vec<i32>,
+}
+
+impl UnplayableDiagnosis {
+    /// 音符有按法但是无法从之前的手型过渡，这时去掉音符也没有意义，不给出替代的组合
+    pub fn no_valid_transition() -> Self {
+        UnplayableDiagnosis {
+            reason: UnplayableReason::NoValidTransition,
+            out_of_range_notes: Vec::new(),
+            closest_alternative: None,
+            dropped_notes: Vec::new(),
+        }
+    }
+}
+
+/// 判断一组音符是否存在可以分配手指的按法
+pub fn is_notes_playable(notes: &[i32], guitar: &Guitar, hand_profile: &HandProfile) -> bool {
+    is_positions_playable(get_note_positions(notes, guitar), guitar, hand_profile)
+}
+
+/// 判断每个音符的候选位置能否组成可以分配手指的按法
+fn is_positions_playable(
+    note_positions: Vec<Vec<NotePosition>>,
+    guitar: &Guitar,
+    hand_profile: &HandProfile,
+) -> bool {
+    PlayableChords::with_max_span(note_positions, hand_profile.max_span).any(|chord| {
+        !convert_chord_to_finger_positions_for_hand(
+            &chord.positions,
+            guitar.guitar_strings.len() as i32,
+            hand_profile,
+        )
+        .is_empty()
+    })
+}
+
+/// 分析一组音符为什么无法演奏，并给出最接近的可演奏组合
+///
+/// # 参数
+/// * `string_indices` - 每个音符指定的弦，为空时音符可以在任何弦上演奏
+pub fn diagnose_unplayable_notes(
+    notes: &[i32],
+    string_indices: &[i32],
+    guitar: &Guitar,
+    hand_profile: &HandProfile,
+) -> UnplayableDiagnosis {
+    let note_positions = if string_indices.is_empty() {
+        get_note_positions(notes, guitar)
+    } else {
+        get_note_positions_on_strings(notes, string_indices, guitar)
+    };
+    let out_of_range_notes: Vec<i32> = notes
+        .iter()
+        .zip(&note_positions)
+        .filter(|(_, positions)| positions.is_empty())
+        .map(|(&note, _)| note)
+        .collect();
+
+    let reason = if out_of_range_notes.len() == notes.len() {
+        UnplayableReason::OutOfRange
+    } else {
+        find_main_reason(&note_positions, hand_profile.max_span)
+    };
+
+    let closest_alternative = if string_indices.is_empty() {
+        find_closest_playable_subset(notes, guitar, hand_profile)
+    } else {
+        find_closest_playable_subset_on_strings(notes, string_indices, guitar, hand_profile)
+    };
+    let dropped_notes = match &closest_alternative {
+        Some(alternative) => notes
+            .iter()
+            .filter(|note| !alternative.contains(note))
+            .copied()
+            .collect(),
+        None => notes.to_vec(),
+    };
+
+    UnplayableDiagnosis {
+        reason,
+        out_of_range_notes,
+        closest_alternative,
+        dropped_notes,
+    }
+}
+
+/// 逐个检查所有组合，返回最常见的失败原因
+///
+/// 如果存在满足和弦规则的组合，但是都无法分配手指，返回NoFingerAssignment
+fn find_main_reason(note_positions: &[Vec<NotePosition>], max_span: i32) -> UnplayableReason {
+    let levels: Vec<&Vec<NotePosition>> = note_positions.iter().filter(|p| !p.is_empty()).collect();
+    let mut reason_counter: HashMap<UnplayableReason, usize> = HashMap::new();
+    let mut indices = vec![0; levels.len()];
+
+    for _ in 0..MAX_DIAGNOSED_COMBINATIONS {
+        let chord = Chord::new(
+            levels
+                .iter()
+                .zip(&indices)
+                .map(|(positions, &i)| positions[i].clone())
+                .collect(),
+        );
+        let reason = match chord.check_playable_with_span(max_span) {
+            Ok(()) => UnplayableReason::NoFingerAssignment,
+            Err(reason) => reason,
+        };
+        *reason_counter.entry(reason).or_insert(0) += 1;
+
+        // 像里程表一样推进下标，全部进位时结束
+        let mut level = levels.len();
+        loop {
+            if level == 0 {
+                return pick_main_reason(&reason_counter);
+            }
+            level -= 1;
+            indices[level] += 1;
+            if indices[level] < levels[level].len() {
+                break;
+            }
+            indices[level] = 0;
+        }
+    }
+
+    pick_main_reason(&reason_counter)
+}
+
+fn pick_main_reason(reason_counter: &HashMap<UnplayableReason, usize>) -> UnplayableReason {
+    // 只要有组合满足和弦规则，问题就出在手指分配上
+    if reason_counter.contains_key(&UnplayableReason::NoFingerAssignment) {
+        return UnplayableReason::NoFingerAssignment;
+    }
+
+    reason_counter
+        .iter()
+        .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+        .map_or(UnplayableReason::OutOfRange, |(&reason, _)| reason)
+}
+
+/// 找到去掉最少音符后可以演奏的组合
+///
+/// 去掉的音符数相同时，优先保留最低音（低音）和最高音（旋律），再优先保留较低的音
+pub fn find_closest_playable_subset(
+    notes: &[i32],
+    guitar: &Guitar,
+    hand_profile: &HandProfile,
+) -> Option<Vec<i32>> {
+    iter_playable_subsets(notes, guitar, hand_profile).next()
+}
+
+/// 已经知道弦的音符去掉最少音符后可以演奏的组合，保留下来的音符仍然在指定的弦上
+///
+/// 优先顺序和find_closest_playable_subset相同
+fn find_closest_playable_subset_on_strings(
+    notes: &[i32],
+    string_indices: &[i32],
+    guitar: &Guitar,
+    hand_profile: &HandProfile,
+) -> Option<Vec<i32>> {
+    let mut sorted: Vec<(i32, i32)> = notes
+        .iter()
+        .copied()
+        .zip(string_indices.iter().copied())
+        .collect();
+    sorted.sort();
+    sorted.dedup();
+    subset_masks(sorted.len())
+        .map(|mask| mask_subset(&sorted, mask))
+        .find(|subset| {
+            let (notes, string_indices): (Vec<i32>, Vec<i32>) = subset.iter().copied().unzip();
+            let note_positions = get_note_positions_on_strings(&notes, &string_indices, guitar);
+            // 指定的弦上没有这个音时不能演奏
+            note_positions.iter().all(|positions| !positions.is_empty())
+                && is_positions_playable(note_positions, guitar, hand_profile)
+        })
+        .map(|subset| subset.into_iter().map(|(note, _)| note).collect())
+}
+
+/// 按照find_closest_playable_subset的优先顺序依次返回所有可以演奏的组合
+///
+/// 组合是惰性生成的，只需要第一个结果时不会检查其它组合
+pub fn iter_playable_subsets<'a>(
+    notes: &[i32],
+    guitar: &'a Guitar,
+    hand_profile: &'a HandProfile,
+) -> impl Iterator<Item = Vec<i32>> + 'a {
+    let mut sorted_notes = notes.to_vec();
+    sorted_notes.sort();
+    sorted_notes.dedup();
+
+    subset_masks(sorted_notes.len()).filter_map(move |mask| {
+        let subset = mask_subset(&sorted_notes, mask);
+        is_notes_playable(&subset, guitar, hand_profile).then_some(subset)
+    })
+}
+
+/// 按优先顺序返回从低到高排好的item_count个元素的所有子集
+///
+/// 保留的元素多的在前，数量相同时优先保留最低和最高的元素，再优先保留较低的元素
+fn subset_masks(item_count: usize) -> impl Iterator<Item = u32> {
+    // 音符太多时枚举组合的代价太大，直接放弃
+    let max_keep_count = if item_count > 16 { 0 } else { item_count };
+
+    (1..=max_keep_count).rev().flat_map(move |keep_count| {
+        let mut masks: Vec<u32> = (0..1u32 << item_count)
+            .filter(|mask| mask.count_ones() as usize == keep_count)
+            .collect();
+        masks.sort_by_key(|&mask| {
+            let keeps_bass = mask & 1 != 0;
+            let keeps_melody = mask & (1 << (item_count - 1)) != 0;
+            (
+                !keeps_bass,
+                !keeps_melody,
+                std::cmp::Reverse(mask.reverse_bits()),
+            )
+        });
+        masks
+    })
+}
+
+fn mask_subset<T: Copy>(items: &[T], mask: u32) -> Vec<T> {
+    items
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| mask & (1 << i) != 0)
+        .map(|(_, &item)| item)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+
+    #[test]
+    fn test_diagnosis_respects_string_indices() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let hand_profile = HandProfile::default();
+        // E4和B3都指定在第二弦上，E4可以换到第一弦，指定了弦就只能去掉一个音
+        let notes = [64, 59];
+        assert!(is_notes_playable(&notes, &guitar, &hand_profile));
+
+        let diagnosis = diagnose_unplayable_notes(&notes, &[1, 1], &guitar, &hand_profile);
+        assert_eq!(diagnosis.reason, UnplayableReason::DuplicateString);
+        assert_eq!(diagnosis.closest_alternative, Some(vec![59]));
+        assert_eq!(diagnosis.dropped_notes, vec![64]);
+
+        // 指定的弦上没有这个音
+        let diagnosis = diagnose_unplayable_notes(&[40], &[0], &guitar, &hand_profile);
+        assert_eq!(diagnosis.reason, UnplayableReason::OutOfRange);
+        assert_eq!(diagnosis.out_of_range_notes, vec![40]);
+        assert_eq!(diagnosis.closest_alternative, None);
+    }
+}
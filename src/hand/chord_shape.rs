@@ -0,0 +1,223 @@
+// ChordShape.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufReader;
+
+use crate::guitar::guitar_instance::Guitar;
+use crate::hand::left_finger::FingerPosition;
+use crate::hand::left_hand::HandPosition;
+
+/// 指型中的一个按弦位置
+///
+/// Params:
+/// - string_index: 弦的索引
+/// - fret: 品格，可移动指型中是相对于食指所在品格的偏移量
+/// - finger: 手指，1-4，-1表示空弦
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ShapePosition {
+    pub string_index: i32,
+    pub fret: i32,
+    pub finger: i32,
+}
+
+/// 常用的和弦指型
+///
+/// Params:
+/// - name: 指型名，如 `C`, `E型横按`
+/// - category: 分类，如 `open`, `caged_barre`, `power`, `bass`
+/// - movable: 是否可以沿指板和跨弦移动，可移动指型中不能包含空弦
+/// - positions: 指型的按弦位置
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChordShape {
+    pub name: String,
+    pub category: String,
+    #[serde(default)]
+    pub movable: bool,
+    pub positions: Vec<ShapePosition>,
+}
+
+impl ChordShape {
+    /// 把指型放到指定的品格和弦偏移上，返回实际的按弦位置
+    fn place(&self, base_fret: i32, string_offset: i32) -> Vec<FingerPosition> {
+        self.positions
+            .iter()
+            .map(|p| FingerPosition {
+                string_index: p.string_index + string_offset,
+                fret: if self.movable {
+                    base_fret + p.fret
+                } else {
+                    p.fret
+                },
+                finger: p.finger,
+            })
+            .collect()
+    }
+}
+
+/// 和弦指型库
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ChordShapeLibrary {
+    pub shapes: Vec<ChordShape>,
+}
+
+impl ChordShapeLibrary {
+    pub fn new(shapes: Vec<ChordShape>) -> Self {
+        ChordShapeLibrary { shapes }
+    }
+
+    /// 从JSON文件读取指型库，文件内容是一个ChordShape数组
+    pub fn load_from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = File::open(path)?;
+        let shapes: Vec<ChordShape> = serde_json::from_reader(BufReader::new(file))?;
+        Ok(Self::new(shapes))
+    }
+
+    pub fn len(&self) -> usize {
+        self.shapes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+
+    /// 查找能够演奏这组音符的指型，返回对应的按法
+    ///
+    /// 指型中音高在notes里的位置会被选中，只有当选中的位置恰好一一对应所有音符，
+    /// 并且至少用到指型一半的位置时才算匹配，因此同一个指型在不同调弦下会自然地匹配或不匹配。
+    /// 单音不使用指型库
+    pub fn match_notes(&self, notes: &[i32], guitar: &Guitar) -> Vec<HandPosition> {
+        let note_set: HashSet<i32> = notes.iter().copied().collect();
+        if note_set.len() < 2 {
+            return Vec::new();
+        }
+        let string_count = guitar.guitar_strings.len() as i32;
+        let fret_count = guitar.get_fret_count();
+        let mut seen = HashSet::new();
+        let mut result = Vec::new();
+
+        for shape in &self.shapes {
+            if shape.positions.is_empty() {
+                continue;
+            }
+
+            let (base_frets, string_offsets) = if shape.movable {
+                let min_string = shape
+                    .positions
+                    .iter()
+                    .map(|p| p.string_index)
+                    .min()
+                    .unwrap();
+                let max_string = shape
+                    .positions
+                    .iter()
+                    .map(|p| p.string_index)
+                    .max()
+                    .unwrap();
+                let max_offset = shape.positions.iter().map(|p| p.fret).max().unwrap();
+                (
+                    1..=fret_count - max_offset,
+                    -min_string..=string_count - 1 - max_string,
+                )
+            } else {
+                (0..=0, 0..=0)
+            };
+
+            for string_offset in string_offsets {
+                for base_fret in base_frets.clone() {
+                    let placed = shape.place(base_fret, string_offset);
+                    if let Some(hand_position) =
+                        select_matching_positions(&placed, &note_set, guitar)
+                        && seen.insert(hand_position.fingerprint())
+                    {
+                        result.push(hand_position);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// 从放置好的指型中选出发出目标音符的位置，每个音符必须恰好对应一个位置
+fn select_matching_positions(
+    placed: &[FingerPosition],
+    note_set: &HashSet<i32>,
+    guitar: &Guitar,
+) -> Option<HandPosition> {
+    let mut selected = Vec::new();
+    let mut matched_notes = HashSet::new();
+
+    for position in placed {
+        let guitar_string = guitar.guitar_strings.get(position.string_index as usize)?;
+        let note = guitar_string.get_note_by_fret(position.fret)?;
+        if note_set.contains(&note) {
+            if !matched_notes.insert(note) {
+                return None;
+            }
+            selected.push(position.clone());
+        }
+    }
+
+    if matched_notes.len() == note_set.len() && selected.len() * 2 >= placed.len() {
+        Some(HandPosition::new(selected))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+
+    fn power_chord() -> ChordShape {
+        let position = |string_index: i32, fret: i32, finger: i32| ShapePosition {
+            string_index,
+            fret,
+            finger,
+        };
+        ChordShape {
+            name: "强力和弦".to_string(),
+            category: "power".to_string(),
+            movable: true,
+            positions: vec![position(5, 0, 1), position(4, 2, 3)],
+        }
+    }
+
+    #[test]
+    fn test_open_shape_matches_from_library_file() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let library = ChordShapeLibrary::load_from_file("asset/chord_shapes.json").unwrap();
+        let fingerprints: Vec<String> = library
+            .match_notes(&[48, 52, 55, 60, 64], &guitar)
+            .iter()
+            .map(HandPosition::fingerprint)
+            .collect();
+        assert!(fingerprints.contains(&"0:0:-1|1:1:1|2:0:-1|3:2:2|4:3:3".to_string()));
+        // 单音不使用指型库
+        assert!(library.match_notes(&[48], &guitar).is_empty());
+    }
+
+    #[test]
+    fn test_movable_shape_follows_fret_and_tuning() {
+        let library = ChordShapeLibrary::new(vec![power_chord()]);
+        // G5在标准调弦下只能放在第3品的六弦和五弦上
+        let standard = InstrumentProfile::default().create_guitar(false);
+        let matches = library.match_notes(&[43, 50], &standard);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].fingerprint(), "4:5:3|5:3:1");
+
+        // Drop D调弦时六弦和五弦差7个半音，同样的指型按不出G5
+        let drop_d = InstrumentProfile::default()
+            .with_tuning(["e", "b", "G", "D", "A", "D1"].map(String::from).to_vec())
+            .create_guitar(false);
+        assert_eq!(
+            drop_d.guitar_strings[5].get_base_note(),
+            standard.guitar_strings[5].get_base_note() - 2
+        );
+        assert!(library.match_notes(&[43, 50], &drop_d).is_empty());
+    }
+}
@@ -0,0 +1,53 @@
+// HandProfile.rs
+
+use serde::{Deserialize, Serialize};
+
+use crate::guitar::guitar_chord::DEFAULT_MAX_SPAN;
+
+/// 左手的身体数据，不同体型的角色能按出的跨度不同
+///
+/// Params:
+/// - finger_spans: 相邻两根手指最多能张开的品格数，依次是食指-中指、中指-无名指、无名指-小指
+/// - low_position_stretch: 低把位（第10品以下）时中指-无名指、无名指-小指最多能张开的品格数
+/// - max_span: 低把位（第8品以下）时食指到小指最多能跨越的品格数，高把位品格变窄，可以多跨一品
+/// - can_barre: 是否能够横按
+/// - max_finger_distance: 两只相邻手指所能打开的最大距离，单位是cm
+/// - thumb_over: 是否能用拇指从琴颈上方绕过来按最低的两根弦
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct HandProfile {
+    pub finger_spans: [i32; 3],
+    pub low_position_stretch: i32,
+    pub max_span: i32,
+    pub can_barre: bool,
+    pub max_finger_distance: f64,
+    pub thumb_over: bool,
+}
+
+impl Default for HandProfile {
+    fn default() -> Self {
+        HandProfile {
+            finger_spans: [2, 2, 2],
+            low_position_stretch: 1,
+            max_span: DEFAULT_MAX_SPAN,
+            can_barre: true,
+            max_finger_distance: 5.73,
+            thumb_over: false,
+        }
+    }
+}
+
+impl HandProfile {
+    /// 两根手指之间最多能张开的品格数
+    ///
+    /// 中间隔着的每一对相邻手指的跨度累加起来，不是1到4号的手指按每相隔一指两品计算
+    pub fn finger_span_limit(&self, from_finger: i32, to_finger: i32) -> i32 {
+        let (low, high) = (from_finger.min(to_finger), from_finger.max(to_finger));
+        if low < 1 || high > 4 {
+            return 2 * (high - low);
+        }
+        (low..high)
+            .map(|finger| self.finger_spans[(finger - 1) as usize])
+            .sum()
+    }
+}
@@ -14,6 +14,8 @@ pub enum PressState {
     PartialBarre2Strings = 3,
     PartialBarre3Strings = 4,
     Keep = 5,
+    /// 轻触弦但是不按到指板，用来制音或者演奏闷音
+    Mute = 6,
 }
 
 impl PressState {
@@ -22,9 +24,11 @@ impl PressState {
             "Open" => PressState::Open,
             "Pressed" => PressState::Pressed,
             "Barre" => PressState::Barre,
-            "Partial_barre_2_strings" => PressState::PartialBarre2Strings,
-            "Partial_barre_3_strings" => PressState::PartialBarre3Strings,
+            // 左手记录文件里保存的是枚举名
+            "Partial_barre_2_strings" | "PartialBarre2Strings" => PressState::PartialBarre2Strings,
+            "Partial_barre_3_strings" | "PartialBarre3Strings" => PressState::PartialBarre3Strings,
             "Keep" => PressState::Keep,
+            "Mute" => PressState::Mute,
             _ => PressState::Open, // 默认值
         }
     }
@@ -38,6 +42,7 @@ impl PressState {
             PressState::PartialBarre2Strings => "Partial_barre_2_strings",
             PressState::PartialBarre3Strings => "Partial_barre_3_strings",
             PressState::Keep => "Keep",
+            PressState::Mute => "Mute",
         }
     }
 
@@ -133,6 +138,7 @@ impl LeftFinger {
             PressState::PartialBarre2Strings => "Partial_barre_2_strings",
             PressState::PartialBarre3Strings => "Partial_barre_3_strings",
             PressState::Keep => "Keep",
+            PressState::Mute => "Mute",
         };
 
         println!(
@@ -188,5 +194,5 @@ impl LeftFinger {
 pub struct FingerPosition {
     pub string_index: i32,
     pub fret: i32,
-    pub finger: i32, // 1-4, -1和0表示不按弦
+    pub finger: i32, // 1-4, -1表示空弦，0表示拇指从琴颈上方绕过来按弦
 }
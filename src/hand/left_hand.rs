@@ -5,7 +5,11 @@ use std::vec::Vec;
 // 假设已存在的模块和结构体
 use crate::guitar::guitar_chord::NotePosition;
 use crate::guitar::guitar_instance::Guitar;
+use crate::hand::hand_profile::HandProfile;
 use crate::hand::left_finger::{FingerPosition, LeftFinger, PressState};
+use crate::hand::left_hand_cost::{
+    LeftHandCostModel, LeftHandCostTerms, LeftHandTransition, WeightedLeftHandCostModel,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct HandPosition {
@@ -28,10 +32,13 @@ impl HandPosition {
     }
 }
 
+/// 手型的唯一标识：每根手指的(手指, 弦, 品格, 按弦状态)，加上是否横按
+pub type LeftHandPoseKey = (Vec<(i32, i32, i32, i32)>, bool);
+
 #[derive(Debug, Clone)]
 pub struct LeftHand {
     pub fingers: Vec<LeftFinger>,
-    max_finger_distance: f64,
+    hand_profile: HandProfile,
     finger_distance_to_fretboard: f64,
     pub hand_position: i32,
     pub use_barre: bool,
@@ -45,9 +52,27 @@ impl LeftHand {
     /// * `use_barre` - 是否使用横按
     /// * `max_finger_distance` - 两只相邻手指所能打开的最大距离，单位是cm
     pub fn new(left_fingers: Vec<LeftFinger>, use_barre: bool, max_finger_distance: f64) -> Self {
+        let hand_profile = HandProfile {
+            max_finger_distance,
+            ..HandProfile::default()
+        };
+        Self::with_profile(left_fingers, use_barre, hand_profile)
+    }
+
+    /// 按角色的手型数据创建左手实例
+    ///
+    /// # 参数
+    /// * `left_fingers` - 手指列表
+    /// * `use_barre` - 是否使用横按
+    /// * `hand_profile` - 手的跨度限制
+    pub fn with_profile(
+        left_fingers: Vec<LeftFinger>,
+        use_barre: bool,
+        hand_profile: HandProfile,
+    ) -> Self {
         let mut hand = LeftHand {
             fingers: left_fingers,
-            max_finger_distance,
+            hand_profile,
             finger_distance_to_fretboard: 0.025,
             hand_position: 1,
             use_barre,
@@ -61,7 +86,33 @@ impl LeftHand {
 
     /// 获取最大手指距离
     pub fn get_max_finger_distance(&self) -> f64 {
-        self.max_finger_distance
+        self.hand_profile.max_finger_distance
+    }
+
+    /// 获取手型数据
+    pub fn get_hand_profile(&self) -> &HandProfile {
+        &self.hand_profile
+    }
+
+    /// 用新的手指生成下一个手型，保留当前的手型数据
+    pub fn next_hand(&self, left_fingers: Vec<LeftFinger>, use_barre: bool) -> LeftHand {
+        Self::with_profile(left_fingers, use_barre, self.hand_profile)
+    }
+
+    /// 获取手指抬起时到指板的距离
+    pub fn get_finger_distance_to_fretboard(&self) -> f64 {
+        self.finger_distance_to_fretboard
+    }
+
+    /// 手型的唯一标识，标识相同的手型之后的消耗也完全相同
+    pub fn pose_key(&self) -> LeftHandPoseKey {
+        let mut fingers: Vec<(i32, i32, i32, i32)> = self
+            .fingers
+            .iter()
+            .map(|f| (f.finger_index, f.string_index, f.fret, f.press.to_i32()))
+            .collect();
+        fingers.sort();
+        (fingers, self.use_barre)
     }
 
     /// 将所有手指设置为抬起
@@ -219,7 +270,11 @@ impl LeftHand {
     }
 
     /// 验证手型是否合法
-    pub fn verify_valid(&self, all_fingers: Option<&Vec<LeftFinger>>) -> bool {
+    ///
+    /// # 参数
+    /// * `all_fingers` - 要验证的手指列表，为None时验证当前手型
+    /// * `fret_count` - 指板的品数
+    pub fn verify_valid(&self, all_fingers: Option<&Vec<LeftFinger>>, fret_count: i32) -> bool {
         let fingers = match all_fingers {
             Some(f) => f,
             None => &self.fingers,
@@ -234,8 +289,12 @@ impl LeftHand {
                 continue;
             }
 
-            // 最高的把位限制，四指在0弦上最多按到22品，在1弦上最多按到21品，其它弦和其它手指依此类推
-            if finger.fret > 22 - (4 - finger.finger_index) - finger.string_index {
+            // 最高的把位限制，以默认的23品指板为例，四指在0弦上最多按到22品，在1弦上最多按到21品，其它弦和其它手指依此类推。
+            // 越靠低音弦，手越容易被琴身挡住，这个限制是按六弦吉他的6根弦调出来的。
+            // 扩展音域乐器多出来的低音弦和第6弦一样贴着琴身，按第6弦的限制处理，
+            // 否则8弦吉他的最低弦会比第6弦少按两个品
+            let string_index = std::cmp::min(finger.string_index, 5);
+            if finger.fret > fret_count - 1 - (4 - finger.finger_index) - string_index {
                 return false;
             }
         }
@@ -243,14 +302,15 @@ impl LeftHand {
         let mut sorted_fingers = fingers.clone();
         sorted_fingers.sort_by_key(|f| f.finger_index);
 
-        // 如果在低把位，不能出现小拇指或者无名指延展两个品格的情况
+        // 如果在低把位，小拇指和无名指不能延展超过手型允许的品格数
+        let stretch = self.hand_profile.low_position_stretch;
         if self.hand_position < 10
             && (sorted_fingers[sorted_fingers.len() - 1].fret
                 - sorted_fingers[sorted_fingers.len() - 2].fret
-                > 1
+                > stretch
                 || sorted_fingers[sorted_fingers.len() - 2].fret
                     - sorted_fingers[sorted_fingers.len() - 3].fret
-                    > 1)
+                    > stretch)
         {
             return false;
         }
@@ -280,10 +340,12 @@ impl LeftHand {
 
             let both_finger_is_not_zero =
                 sorted_fingers[i].fret != 0 && sorted_fingers[i + 1].fret != 0;
-            let finger_distance_is_too_large = (sorted_fingers[i].fret
-                - sorted_fingers[i + 1].fret)
-                .abs()
-                > 2 * (sorted_fingers[i].finger_index - sorted_fingers[i + 1].finger_index).abs();
+            let finger_distance_is_too_large =
+                (sorted_fingers[i].fret - sorted_fingers[i + 1].fret).abs()
+                    > self.hand_profile.finger_span_limit(
+                        sorted_fingers[i].finger_index,
+                        sorted_fingers[i + 1].finger_index,
+                    );
 
             if finger_distance_is_too_large && both_finger_is_not_zero {
                 return false;
@@ -310,6 +372,78 @@ impl LeftHand {
         true
     }
 
+    /// 有手指轻触的弦，轻触的手指让整根弦都不发声，包括它下面按弦的手指
+    pub fn muted_strings(&self) -> HashSet<i32> {
+        self.fingers
+            .iter()
+            .filter(|finger| finger.press == PressState::Mute)
+            .map(|finger| finger.string_index)
+            .collect()
+    }
+
+    /// 每根发声的弦上品格最高的手指，返回弦到(品格, 手指)的映射，空弦的手指为-1
+    ///
+    /// 与右手拨弦的判断一致：横按覆盖从横按弦到0弦的所有弦，部分横按只覆盖2根或3根弦，
+    /// 同一根弦上取最高的品格，有手指轻触的弦不发声
+    pub fn sounding_frets(&self) -> HashMap<i32, (i32, i32)> {
+        let muted_strings = self.muted_strings();
+        let mut owners: HashMap<i32, (i32, i32)> = HashMap::new();
+
+        for finger in &self.fingers {
+            let string_index = finger.string_index;
+            let (covered_strings, fret) = match (finger.finger_index, finger.press) {
+                (_, PressState::Mute) => continue,
+                (-1, _) => (string_index..=string_index, 0),
+                (_, PressState::Pressed) => (string_index..=string_index, finger.fret),
+                (_, PressState::Barre) => (0..=string_index, finger.fret),
+                (_, PressState::PartialBarre2Strings) => {
+                    ((string_index - 1).max(0)..=string_index, finger.fret)
+                }
+                (_, PressState::PartialBarre3Strings) => {
+                    ((string_index - 2).max(0)..=string_index, finger.fret)
+                }
+                _ => continue,
+            };
+
+            for string_index in covered_strings.filter(|index| !muted_strings.contains(index)) {
+                let owner = owners
+                    .entry(string_index)
+                    .or_insert((fret, finger.finger_index));
+                if fret > owner.0 {
+                    *owner = (fret, finger.finger_index);
+                }
+            }
+        }
+        owners
+    }
+
+    /// 计算当前手型下会发声的音符，发声的位置由sounding_frets决定
+    pub fn sounding_notes(&self, guitar: &Guitar) -> Vec<i32> {
+        let mut notes: Vec<i32> = self
+            .sounding_frets()
+            .into_iter()
+            .filter_map(|(string_index, (fret, _))| {
+                guitar
+                    .guitar_strings
+                    .get(string_index as usize)?
+                    .get_note_by_fret(fret)
+            })
+            .collect();
+        notes.sort();
+        notes
+    }
+
+    /// 音符事件实际弹奏的音，用于识别和弦
+    ///
+    /// 横按会让没有弹奏的弦也按在品格上，这些弦的音不属于这个事件，
+    /// 只保留发声的弦上音高属于事件音符的音
+    pub fn played_notes(&self, guitar: &Guitar, event_notes: &[i32]) -> Vec<i32> {
+        self.sounding_notes(guitar)
+            .into_iter()
+            .filter(|note| event_notes.contains(note))
+            .collect()
+    }
+
     /// 计算手型的位置
     /// 返回手的位置，也就是食指所在的品格
     pub fn calculate_hand_position(&self) -> i32 {
@@ -324,12 +458,73 @@ impl LeftHand {
         hand_position
     }
 
-    /// 生成下一个手型
+    /// 生成下一个手型，使用默认的消耗模型
     pub fn generate_next_hands(
         &self,
         guitar: &Guitar,
         finger_positions: &Vec<FingerPosition>,
     ) -> Option<(Vec<LeftFinger>, f64, bool)> {
+        self.generate_next_hands_with_cost_model(
+            guitar,
+            finger_positions,
+            &WeightedLeftHandCostModel::default(),
+            None,
+        )
+    }
+
+    /// 生成下一个手型，用指定的消耗模型计算熵
+    ///
+    /// # 参数
+    /// * `time_gap` - 距离上一个手型的时间，单位是秒，None表示不考虑速度
+    pub fn generate_next_hands_with_cost_model(
+        &self,
+        guitar: &Guitar,
+        finger_positions: &[FingerPosition],
+        cost_model: &dyn LeftHandCostModel,
+        time_gap: Option<f64>,
+    ) -> Option<(Vec<LeftFinger>, f64, bool)> {
+        let (all_fingers, new_hand_position, use_barre) =
+            self.plan_next_fingers(guitar, finger_positions)?;
+        let diff = cost_model.transition_cost(&LeftHandTransition {
+            old_hand: self,
+            new_fingers: &all_fingers,
+            new_hand_position,
+            use_barre,
+            guitar,
+            time_gap,
+        });
+
+        Some((all_fingers, diff, use_barre))
+    }
+
+    /// 生成下一个手型，返回消耗的各项组成而不是总消耗，用于输出求解过程的追踪
+    pub fn generate_next_hands_with_cost_terms(
+        &self,
+        guitar: &Guitar,
+        finger_positions: &[FingerPosition],
+        cost_model: &dyn LeftHandCostModel,
+        time_gap: Option<f64>,
+    ) -> Option<(Vec<LeftFinger>, LeftHandCostTerms, bool)> {
+        let (all_fingers, new_hand_position, use_barre) =
+            self.plan_next_fingers(guitar, finger_positions)?;
+        let terms = cost_model.cost_terms(&LeftHandTransition {
+            old_hand: self,
+            new_fingers: &all_fingers,
+            new_hand_position,
+            use_barre,
+            guitar,
+            time_gap,
+        });
+
+        Some((all_fingers, terms, use_barre))
+    }
+
+    /// 根据按法安排下一个手型的所有手指，返回手指、新的把位和是否横按
+    fn plan_next_fingers(
+        &self,
+        guitar: &Guitar,
+        finger_positions: &[FingerPosition],
+    ) -> Option<(Vec<LeftFinger>, i32, bool)> {
         // 初始化空弦数据，按弦数据，横按数据，休息数据
         let mut empty_fingers = Vec::new();
         let mut empty_string_index_set = HashSet::new();
@@ -353,7 +548,7 @@ impl LeftHand {
         let mut rest_finger_index_set = HashSet::new();
         let mut rest_fingers = Vec::new();
 
-        // 1~4 号手指的触弦数，下标 0 不用
+        // 0~4 号手指的触弦数，0号是拇指
         let mut finger_touch_string_counter = vec![0; 5];
 
         let mut index_finger_fret: i32 = 0;
@@ -382,6 +577,12 @@ impl LeftHand {
                 // 统计按弦手指的触弦总数
                 finger_touch_string_counter[finger_index as usize] += 1;
 
+                // 拇指从琴颈上方按弦，不参与计算手的位置
+                if finger_index == 0 {
+                    pressed_finger_dict.insert(finger_index, (fret, string_index));
+                    continue;
+                }
+
                 // 添加按弦品格数据
                 used_finger_fret_set.insert(fret);
 
@@ -408,7 +609,10 @@ impl LeftHand {
 
         // 生成按弦手指
         for (pressed_finger_index, (pressed_fret, pressed_string_index)) in &pressed_finger_dict {
-            if *pressed_fret == index_finger_fret && *pressed_string_index != 1 {
+            if *pressed_finger_index != 0
+                && *pressed_fret == index_finger_fret
+                && *pressed_string_index != 1
+            {
                 // 如果碰到非食指但与食指的 fret 值相同，则添加到休息手指列表中
                 rest_finger_index_set.insert(*pressed_finger_index);
             } else {
@@ -427,6 +631,11 @@ impl LeftHand {
         for (barre_finger_index, (barre_fret, barre_string_index_val)) in &barre_finger_dict {
             let touch_count = finger_touch_string_counter[*barre_finger_index as usize];
 
+            // 不能横按的手只能用单个手指按弦
+            if touch_count > 1 && !self.hand_profile.can_barre {
+                return None;
+            }
+
             let press_state = if touch_count > 1 && *barre_finger_index == 1 {
                 need_barre = true;
                 // 横按所按的弦，要比其它手指至少低一根弦，否则动画会很难看
@@ -458,6 +667,11 @@ impl LeftHand {
             barre_fingers.push(barre_finger);
         }
 
+        // 拇指绕过琴颈按弦时，食指无法再横按
+        if need_barre && pressed_finger_dict.contains_key(&0) {
+            return None;
+        }
+
         // 基于新按弦手指计算初步手位置
         let new_hand_position =
             if !used_finger_index_set.is_empty() && !used_finger_fret_set.is_empty() {
@@ -660,58 +874,13 @@ impl LeftHand {
         ]
         .concat();
 
-        if !self.verify_valid(Some(&all_fingers)) {
+        if !self.verify_valid(Some(&all_fingers), guitar.get_fret_count()) {
             return None;
         }
 
-        let diff = self.calculate_diff(&all_fingers, new_hand_position, guitar);
-
         use_barre = need_barre || keep_barre;
-        Some((all_fingers, diff, use_barre))
-    }
-
-    /// 计算手型变化的差异（熵）
-    pub fn calculate_diff(
-        &self,
-        all_fingers: &Vec<LeftFinger>,
-        new_hand_position: i32,
-        guitar: &Guitar,
-    ) -> f64 {
-        let mut entropy = 0.0;
-        let hand_position_diff = (self.hand_position - new_hand_position).abs();
-
-        // 如果要换把，首先要抬指
-        if hand_position_diff > 0 {
-            for finger in &self.fingers {
-                if finger.press != PressState::Open {
-                    entropy += self.finger_distance_to_fretboard;
-                }
-            }
-        }
 
-        // 计算每一个手指的位移
-        for index in 1..5 {
-            let old_finger = self.fingers.iter().find(|f| f.finger_index == index);
-            let new_finger = all_fingers.iter().find(|f| f.finger_index == index);
-
-            let distance = match (old_finger, new_finger) {
-                (Some(old), Some(new)) => {
-                    let dist = old.distance_to(guitar, new);
-                    entropy += dist;
-                    Some(dist)
-                }
-                _ => None,
-            };
-
-            // 计算按下手指的熵
-            if let (Some(dist), Some(new)) = (distance, new_finger) {
-                if dist > 0.0 && new.press != PressState::Open {
-                    entropy += self.finger_distance_to_fretboard;
-                }
-            }
-        }
-
-        entropy
+        Some((all_fingers, new_hand_position, use_barre))
     }
 }
 
@@ -737,6 +906,90 @@ pub fn convert_chord_to_finger_positions(chord: &Vec<NotePosition>) -> Vec<HandP
     result
 }
 
+/// 按角色的手型数据生成和弦的所有按法，能用拇指按弦时包含拇指的按法
+pub fn convert_chord_to_finger_positions_for_hand(
+    chord: &Vec<NotePosition>,
+    string_count: i32,
+    hand_profile: &HandProfile,
+) -> Vec<HandPosition> {
+    let mut result = convert_chord_to_finger_positions(chord);
+    if hand_profile.thumb_over {
+        result.extend(convert_chord_to_thumb_positions(chord, string_count));
+    }
+    result
+}
+
+/// 拇指绕过琴颈能按到的弦数
+const THUMB_REACH_STRINGS: i32 = 2;
+
+/// 拇指能否按到这根弦
+///
+/// 乐器配置里的弦按在琴颈上的位置从高音侧排到低音侧，拇指只能按低音侧边缘的两根弦。
+/// 按位置判断而不是按音高判断，重入调弦的乐器（高G调弦的尤克里里、五弦班卓琴的短弦）
+/// 低音侧边缘的弦并不是音最低的弦
+pub fn thumb_can_reach_string(string_index: i32, string_count: i32) -> bool {
+    string_index >= string_count - THUMB_REACH_STRINGS
+}
+
+/// 生成用拇指按一个音的按法
+///
+/// 拇指只能从琴颈上方绕过来按低音侧边缘的两根弦，位置在食指的同一品或者后一品，
+/// 而且只在其它手指也要按弦时使用，其它的音都要在比拇指更靠高音侧的弦上
+pub fn convert_chord_to_thumb_positions(
+    chord: &[NotePosition],
+    string_count: i32,
+) -> Vec<HandPosition> {
+    let mut result = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (thumb_note_index, thumb_note) in chord.iter().enumerate() {
+        let thumb_can_reach =
+            thumb_note.fret > 0 && thumb_can_reach_string(thumb_note.string_index, string_count);
+        if !thumb_can_reach {
+            continue;
+        }
+
+        let other_notes: Vec<NotePosition> = chord
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != thumb_note_index)
+            .map(|(_, note)| note.clone())
+            .collect();
+        if other_notes
+            .iter()
+            .any(|note| note.string_index >= thumb_note.string_index)
+        {
+            continue;
+        }
+
+        for mut combination in convert_chord_to_finger_positions(&other_notes) {
+            // 与生成手型时一样，用最低的品格和最小的手指估计食指的位置
+            let fingered = combination.positions.iter().filter(|p| p.finger > 0);
+            let min_fret = fingered.clone().map(|p| p.fret).min();
+            let min_finger = fingered.map(|p| p.finger).min();
+            let index_fret = min_fret
+                .zip(min_finger)
+                .map(|(fret, finger)| fret - (finger - 1));
+            let thumb_is_near_index = index_fret
+                .is_some_and(|fret| thumb_note.fret == fret || thumb_note.fret == fret - 1);
+            if !thumb_is_near_index {
+                continue;
+            }
+
+            combination.positions.push(FingerPosition {
+                string_index: thumb_note.string_index,
+                fret: thumb_note.fret,
+                finger: 0,
+            });
+            if seen.insert(combination.fingerprint()) {
+                result.push(combination);
+            }
+        }
+    }
+
+    result
+}
+
 fn generate_combinations_iter(
     note_list: &[NotePosition],
     finger_list: &[i32],
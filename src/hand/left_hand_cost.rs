@@ -0,0 +1,504 @@
+// LeftHandCost.rs
+
+use serde::{Deserialize, Serialize};
+
+use crate::guitar::guitar_instance::Guitar;
+use crate::hand::left_finger::{LeftFinger, PressState};
+use crate::hand::left_hand::LeftHand;
+
+/// 一次左手手型变化
+///
+/// Params:
+/// - old_hand: 变化前的手型
+/// - new_fingers: 变化后的所有手指
+/// - new_hand_position: 变化后的把位
+/// - use_barre: 变化后是否使用横按
+/// - guitar: 吉他
+/// - time_gap: 完成变化的时间，单位是秒，None表示不考虑速度
+pub struct LeftHandTransition<'a> {
+    pub old_hand: &'a LeftHand,
+    pub new_fingers: &'a [LeftFinger],
+    pub new_hand_position: i32,
+    pub use_barre: bool,
+    pub guitar: &'a Guitar,
+    pub time_gap: Option<f64>,
+}
+
+/// 手型变化消耗的各项组成，每一项都已经乘过权重
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct LeftHandCostTerms {
+    /// 手指移动的距离
+    pub finger_travel: f64,
+    /// 抬指和按下的消耗
+    pub lift: f64,
+    /// 换把的消耗
+    pub position_shift: f64,
+    /// 手指张开超过一指一品的消耗
+    pub stretch: f64,
+    /// 横按的消耗
+    pub barre: f64,
+    /// 空弦音的奖励，是一个不大于0的值
+    pub open_string: f64,
+    /// 高把位的消耗
+    pub high_fret: f64,
+    /// 同一根手指换到别的位置继续按弦的消耗
+    pub finger_reuse: f64,
+    /// 换把速度的消耗
+    pub hand_speed: f64,
+    /// 换把速度超过上限的消耗
+    pub over_speed: f64,
+    /// 拇指按弦的消耗
+    pub thumb: f64,
+}
+
+impl LeftHandCostTerms {
+    /// 总消耗，不小于0
+    pub fn total(&self) -> f64 {
+        (self.finger_travel
+            + self.lift
+            + self.position_shift
+            + self.stretch
+            + self.barre
+            + self.open_string
+            + self.high_fret
+            + self.finger_reuse
+            + self.hand_speed
+            + self.over_speed
+            + self.thumb)
+            .max(0.0)
+    }
+
+    /// 每一项消耗的名字和数值，用于显示
+    pub fn named_terms(&self) -> [(&'static str, f64); 11] {
+        [
+            ("手指移动", self.finger_travel),
+            ("抬指按下", self.lift),
+            ("换把", self.position_shift),
+            ("张开", self.stretch),
+            ("横按", self.barre),
+            ("空弦", self.open_string),
+            ("高把位", self.high_fret),
+            ("换位按弦", self.finger_reuse),
+            ("换把速度", self.hand_speed),
+            ("超速", self.over_speed),
+            ("拇指", self.thumb),
+        ]
+    }
+}
+
+/// 左手消耗模型，用来计算手型变化的熵
+pub trait LeftHandCostModel: Send + Sync {
+    /// 计算手型变化的各项消耗
+    fn cost_terms(&self, transition: &LeftHandTransition) -> LeftHandCostTerms;
+
+    /// 计算手型变化的总消耗
+    fn transition_cost(&self, transition: &LeftHandTransition) -> f64 {
+        self.cost_terms(transition).total()
+    }
+}
+
+/// 左手消耗模型的权重
+///
+/// 默认值与最初写死在`LeftHand::calculate_diff`里的计算结果一致，
+/// 手指移动距离的单位是cm，其它各项的权重也按cm来估计
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct LeftHandCostWeights {
+    /// 手指移动距离的权重
+    pub finger_travel: f64,
+    /// 抬指和按下消耗的权重，每次抬指或按下的基础消耗是手指到指板的距离
+    pub lift: f64,
+    /// 每换一个品格把位的消耗
+    pub position_shift: f64,
+    /// 手指每多张开一个品格的消耗
+    pub stretch: f64,
+    /// 每根横按手指的消耗
+    pub barre: f64,
+    /// 每个空弦音的奖励
+    pub open_string: f64,
+    /// 超过high_fret_start的品格，每高一品的消耗
+    pub high_fret: f64,
+    /// 开始计算高把位消耗的品格
+    pub high_fret_start: i32,
+    /// 每根换了位置继续按弦的手指的消耗
+    pub finger_reuse: f64,
+    /// 换把速度每1cm/s的消耗
+    pub hand_speed: f64,
+    /// 换把速度的上限，单位是cm/s，超过上限的换把会被标记出来
+    pub max_hand_speed: f64,
+    /// 换把速度超过上限时，每超过1cm/s的消耗
+    pub over_speed: f64,
+    /// 拇指每按一个音，以及拇指绕上或离开琴颈一次的消耗
+    pub thumb: f64,
+}
+
+impl Default for LeftHandCostWeights {
+    fn default() -> Self {
+        LeftHandCostWeights {
+            finger_travel: 1.0,
+            lift: 1.0,
+            position_shift: 0.0,
+            stretch: 0.0,
+            barre: 0.0,
+            open_string: 0.0,
+            high_fret: 0.0,
+            high_fret_start: 12,
+            finger_reuse: 0.0,
+            hand_speed: 0.0,
+            max_hand_speed: 250.0,
+            over_speed: 0.0,
+            thumb: 1.0,
+        }
+    }
+}
+
+impl LeftHandCostWeights {
+    /// 古典吉他风格：尽量保持把位，少用横按，一指一品
+    pub fn classical() -> Self {
+        LeftHandCostWeights {
+            position_shift: 2.0,
+            stretch: 2.0,
+            barre: 0.5,
+            high_fret: 0.2,
+            finger_reuse: 1.5,
+            hand_speed: 0.02,
+            over_speed: 1.0,
+            thumb: 5.0,
+            ..Default::default()
+        }
+    }
+
+    /// 摇滚风格：换把很随意，喜欢横按和强力和弦
+    pub fn rock() -> Self {
+        LeftHandCostWeights {
+            position_shift: 0.5,
+            stretch: 1.0,
+            hand_speed: 0.01,
+            max_hand_speed: 350.0,
+            over_speed: 0.5,
+            thumb: 0.5,
+            ..Default::default()
+        }
+    }
+
+    /// 初学者风格：优先空弦和低把位，避免横按和大跨度
+    pub fn beginner() -> Self {
+        LeftHandCostWeights {
+            position_shift: 1.5,
+            stretch: 4.0,
+            barre: 3.0,
+            open_string: 1.0,
+            high_fret: 0.5,
+            high_fret_start: 5,
+            finger_reuse: 0.5,
+            hand_speed: 0.05,
+            max_hand_speed: 120.0,
+            over_speed: 2.0,
+            thumb: 3.0,
+            ..Default::default()
+        }
+    }
+
+    /// 内置的风格预设，第一项是默认值
+    pub fn presets() -> Vec<(&'static str, Self)> {
+        vec![
+            ("default", Self::default()),
+            ("classical", Self::classical()),
+            ("rock", Self::rock()),
+            ("beginner", Self::beginner()),
+        ]
+    }
+
+    pub fn preset(style: &str) -> Option<Self> {
+        Self::presets()
+            .into_iter()
+            .find(|(name, _)| *name == style)
+            .map(|(_, weights)| weights)
+    }
+}
+
+/// 按权重线性组合各项消耗的模型
+#[derive(Clone, Debug, Default)]
+pub struct WeightedLeftHandCostModel {
+    pub weights: LeftHandCostWeights,
+}
+
+impl WeightedLeftHandCostModel {
+    pub fn new(weights: LeftHandCostWeights) -> Self {
+        WeightedLeftHandCostModel { weights }
+    }
+}
+
+impl LeftHandCostModel for WeightedLeftHandCostModel {
+    fn cost_terms(&self, transition: &LeftHandTransition) -> LeftHandCostTerms {
+        let weights = &self.weights;
+        let old_hand = transition.old_hand;
+        let new_fingers = transition.new_fingers;
+        let finger_distance_to_fretboard = old_hand.get_finger_distance_to_fretboard();
+        let hand_position_diff = (old_hand.hand_position - transition.new_hand_position).abs();
+
+        let mut finger_travel = 0.0;
+        let mut lift_count = 0;
+        let mut reused_finger_count = 0;
+
+        // 如果要换把，首先要抬指
+        if hand_position_diff > 0 {
+            lift_count += old_hand
+                .fingers
+                .iter()
+                .filter(|finger| finger.press != PressState::Open)
+                .count();
+        }
+
+        // 计算每一个手指的位移，移动后按下的手指还要加上按下的消耗
+        for index in 1..5 {
+            let old_finger = old_hand.fingers.iter().find(|f| f.finger_index == index);
+            let new_finger = new_fingers.iter().find(|f| f.finger_index == index);
+
+            if let (Some(old), Some(new)) = (old_finger, new_finger) {
+                let distance = old.distance_to(transition.guitar, new);
+                finger_travel += distance;
+
+                if distance > 0.0 && new.press != PressState::Open {
+                    lift_count += 1;
+                    if old.press != PressState::Open {
+                        reused_finger_count += 1;
+                    }
+                }
+            }
+        }
+
+        let pressed_fingers: Vec<&LeftFinger> = new_fingers
+            .iter()
+            .filter(|finger| finger.finger_index > 0 && finger.press != PressState::Open)
+            .collect();
+
+        // 超过一指一品的张开程度
+        let stretch = match (
+            pressed_fingers.iter().min_by_key(|f| f.finger_index),
+            pressed_fingers.iter().max_by_key(|f| f.finger_index),
+        ) {
+            (Some(lowest), Some(highest)) => {
+                let fret_span = (highest.fret - lowest.fret).abs();
+                let finger_span = highest.finger_index - lowest.finger_index;
+                (fret_span - finger_span).max(0)
+            }
+            _ => 0,
+        };
+
+        let barre_count = pressed_fingers
+            .iter()
+            .filter(|finger| {
+                matches!(
+                    finger.press,
+                    PressState::Barre
+                        | PressState::PartialBarre2Strings
+                        | PressState::PartialBarre3Strings
+                )
+            })
+            .count();
+
+        let open_string_count = new_fingers
+            .iter()
+            .filter(|finger| finger.finger_index == -1)
+            .count();
+
+        // 换把需要的速度，没有时间信息时不计算
+        let speed = transition
+            .time_gap
+            .map(|time_gap| {
+                let distance = hand_shift_distance(
+                    transition.guitar,
+                    old_hand.hand_position,
+                    transition.new_hand_position,
+                );
+                required_hand_speed(distance, time_gap)
+            })
+            .unwrap_or(0.0);
+
+        // 拇指按弦，以及拇指绕上或离开琴颈
+        let is_thumb = |finger: &&LeftFinger| finger.finger_index == 0;
+        let thumb_count = new_fingers.iter().filter(is_thumb).count();
+        let old_uses_thumb = old_hand
+            .fingers
+            .iter()
+            .any(|finger| finger.finger_index == 0);
+        let thumb_switch = usize::from(old_uses_thumb != (thumb_count > 0));
+
+        let high_fret: i32 = pressed_fingers
+            .iter()
+            .map(|finger| (finger.fret - weights.high_fret_start).max(0))
+            .sum();
+
+        LeftHandCostTerms {
+            finger_travel: weights.finger_travel * finger_travel,
+            lift: weights.lift * finger_distance_to_fretboard * lift_count as f64,
+            position_shift: weights.position_shift * hand_position_diff as f64,
+            stretch: weights.stretch * stretch as f64,
+            barre: weights.barre * barre_count as f64,
+            open_string: -weights.open_string * open_string_count as f64,
+            high_fret: weights.high_fret * high_fret as f64,
+            finger_reuse: weights.finger_reuse * reused_finger_count as f64,
+            hand_speed: weights.hand_speed * speed,
+            over_speed: weights.over_speed * (speed - weights.max_hand_speed).max(0.0),
+            thumb: weights.thumb * (thumb_count + thumb_switch) as f64,
+        }
+    }
+}
+
+/// 计算速度时的最短时间，避免时间间隔为0时速度变成无穷大
+const MIN_TIME_GAP: f64 = 0.01;
+
+/// 换把时手移动的距离，单位是cm
+pub fn hand_shift_distance(guitar: &Guitar, from_position: i32, to_position: i32) -> f64 {
+    // 第n品到琴枕的距离是弦长 * (1 - 2^(-n/12))
+    let fret_offset = |fret: i32| 1.0 - 2.0_f64.powf(-(fret.max(0) as f64) / 12.0);
+    guitar.get_full_string() * (fret_offset(to_position) - fret_offset(from_position)).abs()
+}
+
+/// 在time_gap秒内移动distance厘米需要的速度，单位是cm/s
+pub fn required_hand_speed(distance: f64, time_gap: f64) -> f64 {
+    distance / time_gap.max(MIN_TIME_GAP)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+    use crate::hand::left_finger::FingerPosition;
+
+    #[test]
+    fn test_fast_shift_costs_more_than_slow_shift() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let init_string = &guitar.guitar_strings[2];
+        let fingers = (1..5)
+            .map(|i| LeftFinger::new(i, init_string, i, "Pressed"))
+            .collect();
+        let hand = LeftHand::new(fingers, false, 5.73);
+        let target = [FingerPosition {
+            string_index: 2,
+            fret: 8,
+            finger: 1,
+        }];
+        let model = WeightedLeftHandCostModel::new(LeftHandCostWeights::classical());
+
+        let cost = |time_gap: Option<f64>| {
+            hand.generate_next_hands_with_cost_model(&guitar, &target, &model, time_gap)
+                .map(|(_, cost, _)| cost)
+                .unwrap()
+        };
+        let fast = cost(Some(0.04));
+        let slow = cost(Some(2.0));
+        assert!(fast > slow);
+        assert!(slow > cost(None));
+
+        // 40ms内移动7品远远超过了上限
+        let distance = hand_shift_distance(&guitar, 1, 8);
+        assert!(
+            required_hand_speed(distance, 0.04) > LeftHandCostWeights::classical().max_hand_speed
+        );
+    }
+
+    /// 三弦一到四品，每根手指按一品
+    fn first_position_hand(guitar: &Guitar) -> LeftHand {
+        let string = &guitar.guitar_strings[2];
+        let fingers = (1..5)
+            .map(|i| LeftFinger::new(i, string, i, "Pressed"))
+            .collect();
+        LeftHand::new(fingers, false, 5.73)
+    }
+
+    #[test]
+    fn test_default_weights_count_travel_and_presses() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let hand = first_position_hand(&guitar);
+        let model = WeightedLeftHandCostModel::default();
+        let terms = |new_fingers: &[LeftFinger]| {
+            model.cost_terms(&LeftHandTransition {
+                old_hand: &hand,
+                new_fingers,
+                new_hand_position: 1,
+                use_barre: false,
+                guitar: &guitar,
+                time_gap: None,
+            })
+        };
+
+        // 手型不变时没有消耗
+        assert_eq!(terms(&hand.fingers).total(), 0.0);
+
+        // 小指换到二弦，移动的距离加上一次按下的消耗
+        let mut moved = hand.fingers.clone();
+        moved[3] = LeftFinger::new(4, &guitar.guitar_strings[1], 4, "Pressed");
+        let travel = hand.fingers[3].distance_to(&guitar, &moved[3]);
+        let moved_terms = terms(&moved);
+        assert_eq!(moved_terms.finger_travel, travel);
+        assert_eq!(moved_terms.lift, hand.get_finger_distance_to_fretboard());
+        assert_eq!(
+            moved_terms.total(),
+            travel + hand.get_finger_distance_to_fretboard()
+        );
+    }
+
+    #[test]
+    fn test_style_presets_weigh_barres_open_strings_and_high_frets() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let strings = &guitar.guitar_strings;
+        let hand = first_position_hand(&guitar);
+        let terms = |weights: LeftHandCostWeights, new_fingers: &[LeftFinger]| {
+            WeightedLeftHandCostModel::new(weights).cost_terms(&LeftHandTransition {
+                old_hand: &hand,
+                new_fingers,
+                new_hand_position: 1,
+                use_barre: false,
+                guitar: &guitar,
+                time_gap: None,
+            })
+        };
+
+        let mut open = hand.fingers.clone();
+        open.push(LeftFinger::new(-1, &strings[0], 0, "Open"));
+        assert_eq!(
+            terms(LeftHandCostWeights::default(), &open).open_string,
+            0.0
+        );
+        assert_eq!(
+            terms(LeftHandCostWeights::beginner(), &open).open_string,
+            -1.0
+        );
+
+        let mut barre = hand.fingers.clone();
+        barre[0] = LeftFinger::new(1, &strings[5], 1, "Barre");
+        assert_eq!(terms(LeftHandCostWeights::default(), &barre).barre, 0.0);
+        assert_eq!(terms(LeftHandCostWeights::classical(), &barre).barre, 0.5);
+        assert_eq!(terms(LeftHandCostWeights::beginner(), &barre).barre, 3.0);
+
+        // 初学者从第5品开始计算高把位，小指按到第8品时多出3品
+        let mut high = hand.fingers.clone();
+        high[3] = LeftFinger::new(4, &strings[2], 8, "Pressed");
+        assert_eq!(terms(LeftHandCostWeights::default(), &high).high_fret, 0.0);
+        assert_eq!(terms(LeftHandCostWeights::beginner(), &high).high_fret, 1.5);
+    }
+
+    #[test]
+    fn test_job_config_weights_fill_defaults_and_switch_styles() {
+        use crate::job_config::JobConfig;
+
+        let mut config: JobConfig =
+            serde_json::from_str(r#"{"left_hand_cost": {"stretch": 3.0}}"#).unwrap();
+        assert_eq!(
+            config.left_hand_cost,
+            LeftHandCostWeights {
+                stretch: 3.0,
+                ..LeftHandCostWeights::default()
+            }
+        );
+
+        config.apply_left_hand_style("rock");
+        assert_eq!(config.left_hand_cost, LeftHandCostWeights::rock());
+        // 未知的风格不改变当前的权重
+        config.apply_left_hand_style("unknown");
+        assert_eq!(config.left_hand_style, "rock");
+        assert_eq!(config.left_hand_cost, LeftHandCostWeights::rock());
+    }
+}
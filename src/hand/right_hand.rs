@@ -4,7 +4,7 @@ use std::collections::{HashMap, HashSet};
 use std::f64;
 use std::vec::Vec;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct RightHand {
     pub used_fingers: Vec<String>,
     pub right_finger_positions: Vec<i32>,
@@ -0,0 +1,156 @@
+// JobConfig.rs
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+
+use crate::hand::left_hand_cost::LeftHandCostWeights;
+use crate::midi::midi_to_note::MuteDetection;
+use crate::recorder::long_rest::RestLengthUnit;
+use crate::recorder::note_fallback::FallbackStrategy;
+use crate::recorder::phrase_repeat::PhraseConsistencyMode;
+
+/// 左手求解器
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LeftHandSolverKind {
+    /// 束搜索，速度快但不保证最优
+    Beam,
+    /// 动态规划，得到全局最优解，但是状态数多时较慢
+    Viterbi,
+}
+
+impl LeftHandSolverKind {
+    pub fn all() -> [LeftHandSolverKind; 2] {
+        [LeftHandSolverKind::Beam, LeftHandSolverKind::Viterbi]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LeftHandSolverKind::Beam => "束搜索",
+            LeftHandSolverKind::Viterbi => "动态规划最优解",
+        }
+    }
+}
+
+/// 任务配置，保存求解器相关的参数，可以从JSON文件读取和保存
+///
+/// 文件里没有写的字段使用默认值，所以旧的配置文件在添加新字段后依然可以读取
+///
+/// Params:
+/// - left_hand_style: 左手风格预设名，只用于界面显示
+/// - left_hand_cost: 左手消耗模型的权重
+/// - left_hand_solver: 左手求解器
+/// - beam_width: 束搜索保留的候选数
+/// - compare_left_hand_solvers: 同时运行两种求解器，并报告束搜索结果与最优解的差距
+/// - thread_count: 束搜索扩展候选时使用的线程数，0表示使用所有可用的CPU核心
+/// - chord_shape_bonus: 使用和弦指型库里的按法时每一步减少的熵值
+/// - chord_shapes_compete: 指型库按法和通用按法一起参与比较，关闭时只在指型库按法无法过渡时才枚举通用按法
+/// - legato_max_gap: 同一根弦上两个单音间隔不超过这个秒数时识别为击弦、勾弦或滑弦，0表示不识别
+/// - left_hand_trace: 输出左手求解追踪，记录胜出路径上每一步的消耗组成和落选的按法
+/// - trace_runner_up_count: 追踪里每一步最多记录的落选按法数
+/// - fallback_strategies: 左手找不到按法时依次尝试的退化方式，为空时直接重复上一个手型
+/// - roll_gap_beats: 和弦分解成琶音时相邻两组之间间隔的拍数
+/// - phrase_consistency: 重复乐句的按法一致性，动态规划求解器和分段求解时不同分段之间的重复乐句只能在求解后复制按法
+/// - phrase_min_events: 重复乐句最少包含的音符事件数
+/// - phrase_bonus: 重复乐句使用和第一次相同的按法时每一步减少的熵值
+/// - segment_solving: 在长休止和段落标记处分段求解，只重新计算有变化的分段
+/// - segment_min_rest_beats: 相邻两个音符的间隔不小于这个拍数时分段
+/// - alternative_count: 输出的备选按法数，包含最优解，为0时不输出
+/// - alternative_min_different_poses: 两个备选按法至少要有多少个手型不同
+/// - position_planning: 先为每个乐句规划把位，再在规划的把位附近求解按法
+/// - position_phrase_events: 规划把位时每个乐句最多包含的音符事件数
+/// - position_tolerance: 按法的把位和规划的把位最多相差的品数
+/// - mute_detection: 从MIDI里识别闷音的方式
+/// - hold_note_durations: 按MIDI里音符的时值按弦，音符持续时空闲的手指留在弦上，音符结束时手指提前松开
+/// - rest_pose_min_length: 休止不短于这个长度时双手回到休息姿势，0表示不回到休息姿势
+/// - rest_pose_unit: rest_pose_min_length的单位
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct JobConfig {
+    pub left_hand_style: String,
+    pub left_hand_cost: LeftHandCostWeights,
+    pub left_hand_solver: LeftHandSolverKind,
+    pub beam_width: usize,
+    pub compare_left_hand_solvers: bool,
+    pub thread_count: usize,
+    pub chord_shape_bonus: f64,
+    pub chord_shapes_compete: bool,
+    pub legato_max_gap: f64,
+    pub left_hand_trace: bool,
+    pub trace_runner_up_count: usize,
+    pub fallback_strategies: Vec<FallbackStrategy>,
+    pub roll_gap_beats: f64,
+    pub phrase_consistency: PhraseConsistencyMode,
+    pub phrase_min_events: usize,
+    pub phrase_bonus: f64,
+    pub segment_solving: bool,
+    pub segment_min_rest_beats: f64,
+    pub alternative_count: usize,
+    pub alternative_min_different_poses: usize,
+    pub position_planning: bool,
+    pub position_phrase_events: usize,
+    pub position_tolerance: i32,
+    pub mute_detection: MuteDetection,
+    pub hold_note_durations: bool,
+    pub rest_pose_min_length: f64,
+    pub rest_pose_unit: RestLengthUnit,
+}
+
+impl Default for JobConfig {
+    fn default() -> Self {
+        JobConfig {
+            left_hand_style: "default".to_string(),
+            left_hand_cost: LeftHandCostWeights::default(),
+            left_hand_solver: LeftHandSolverKind::Beam,
+            beam_width: 100,
+            compare_left_hand_solvers: false,
+            thread_count: 0,
+            chord_shape_bonus: 0.5,
+            chord_shapes_compete: false,
+            legato_max_gap: 0.25,
+            left_hand_trace: false,
+            trace_runner_up_count: 5,
+            fallback_strategies: FallbackStrategy::default_order(),
+            roll_gap_beats: 0.0625,
+            phrase_consistency: PhraseConsistencyMode::Off,
+            phrase_min_events: 4,
+            phrase_bonus: 0.5,
+            segment_solving: false,
+            segment_min_rest_beats: 2.0,
+            alternative_count: 0,
+            alternative_min_different_poses: 3,
+            position_planning: false,
+            position_phrase_events: 8,
+            position_tolerance: 1,
+            mute_detection: MuteDetection::default(),
+            hold_note_durations: true,
+            rest_pose_min_length: 4.0,
+            rest_pose_unit: RestLengthUnit::Beats,
+        }
+    }
+}
+
+impl JobConfig {
+    pub fn load_from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = File::open(path)?;
+        let config = serde_json::from_reader(BufReader::new(file))?;
+        Ok(config)
+    }
+
+    pub fn save_to_file(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = std::path::Path::new(path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// 切换左手风格预设，会覆盖当前的左手消耗权重
+    pub fn apply_left_hand_style(&mut self, style: &str) {
+        if let Some(weights) = LeftHandCostWeights::preset(style) {
+            self.left_hand_style = style.to_string();
+            self.left_hand_cost = weights;
+        }
+    }
+}
@@ -3,6 +3,7 @@ pub mod egui;
 pub mod fret_dancer;
 pub mod guitar;
 pub mod hand;
+pub mod job_config;
 pub mod midi;
 pub mod recorder;
 pub mod ui;
@@ -6,6 +6,7 @@
 use midly::{MetaMessage, Smf, Timing, TrackEventKind};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Write;
 
@@ -20,10 +21,214 @@ pub struct TempoChange {
     pub time: u64,
 }
 
+/// 速度表，用来把tick换算成秒
+///
+/// 第一个速度变化之前，或者没有速度信息时，按MIDI标准的120BPM计算
+#[derive(Debug, Clone)]
+pub struct TempoMap {
+    tempo_changes: Vec<TempoChange>,
+    ticks_per_beat: u16,
+}
+
+impl TempoMap {
+    /// MIDI标准的默认速度，每拍500000微秒
+    pub const DEFAULT_TEMPO: u32 = 500_000;
+
+    pub fn new(tempo_changes: &[TempoChange], ticks_per_beat: u16) -> Self {
+        let mut tempo_changes = tempo_changes.to_vec();
+        tempo_changes.sort_by_key(|change| change.time);
+        TempoMap {
+            tempo_changes,
+            ticks_per_beat: ticks_per_beat.max(1),
+        }
+    }
+
+    /// 从乐曲开始到real_tick经过的秒数
+    pub fn seconds_at(&self, real_tick: f64) -> f64 {
+        let mut seconds = 0.0;
+        let mut segment_start = 0.0;
+        let mut tempo = Self::DEFAULT_TEMPO;
+
+        for change in &self.tempo_changes {
+            let change_time = change.time as f64;
+            if change_time >= real_tick {
+                break;
+            }
+            seconds += self.ticks_to_seconds(change_time - segment_start, tempo);
+            segment_start = change_time;
+            tempo = change.tempo;
+        }
+
+        seconds + self.ticks_to_seconds(real_tick - segment_start, tempo)
+    }
+
+    /// 两个real_tick之间经过的秒数
+    pub fn seconds_between(&self, from_tick: f64, to_tick: f64) -> f64 {
+        self.seconds_at(to_tick) - self.seconds_at(from_tick)
+    }
+
+    fn ticks_to_seconds(&self, ticks: f64, tempo: u32) -> f64 {
+        ticks * tempo as f64 / (self.ticks_per_beat as f64 * 1_000_000.0)
+    }
+}
+
+/// 拍号变化，denominator是实际的分母，比如4/4拍的denominator为4
+#[derive(Debug, Clone)]
+pub struct TimeSignatureChange {
+    pub numerator: u8,
+    pub denominator: u32,
+    pub time: u64,
+}
+
+/// MIDI文件里的段落标记，例如"Verse"、"Chorus"
+#[derive(Debug, Clone)]
+pub struct SectionMarker {
+    pub name: String,
+    pub time: u64,
+}
+
+/// 同一时刻开始的一组音符
+///
+/// Params:
+/// - notes: 音高，从低到高排列
+/// - real_tick: 开始的tick
+/// - string_indices: 每个音符所在的弦，与notes一一对应，只有按弦分通道读取时才有
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NoteInfo {
     pub notes: Vec<i32>,
     pub real_tick: f64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub string_indices: Vec<i32>,
+}
+
+/// 闷音的识别方式，三种方式可以同时使用
+///
+/// Params:
+/// - keyswitch_note: 按住这个音高的键时开始的音符都是闷音，键本身不会作为音符读入，小于0时不使用
+/// - max_velocity: 力度不大于这个值的音符是闷音，为0时不使用
+/// - marker_text: 和这个文字相同的MIDI标记处开始的音符是闷音，为空时不使用
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MuteDetection {
+    pub keyswitch_note: i32,
+    pub max_velocity: u8,
+    pub marker_text: String,
+}
+
+impl Default for MuteDetection {
+    fn default() -> Self {
+        MuteDetection {
+            keyswitch_note: -1,
+            max_velocity: 0,
+            marker_text: String::new(),
+        }
+    }
+}
+
+impl MuteDetection {
+    /// 标记是否表示闷音，比较时忽略大小写和首尾的空白
+    pub fn is_mute_marker(&self, marker: &SectionMarker) -> bool {
+        let text = self.marker_text.trim();
+        !text.is_empty() && marker.name.trim().eq_ignore_ascii_case(text)
+    }
+}
+
+/// 一个闷音
+///
+/// Params:
+/// - real_tick: 开始的tick
+/// - note: 音高，和notes_map里的音高一样已经处理过八度和变调夹
+/// - string_index: 所在的弦，不知道时为-1
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MutedNote {
+    pub real_tick: f64,
+    pub note: i32,
+    pub string_index: i32,
+}
+
+/// 一个音符从按下到松开的时间
+///
+/// Params:
+/// - real_tick: 开始的tick，和notes_map里的real_tick相同
+/// - end_tick: 音符关闭的tick
+/// - note: 音高，和notes_map里的音高一样已经处理过八度和变调夹
+/// - string_index: 所在的弦，不知道时为-1
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NoteSpan {
+    pub real_tick: f64,
+    pub end_tick: f64,
+    pub note: i32,
+    pub string_index: i32,
+}
+
+/// 每根弦使用一个MIDI通道的输入方式，Guitar Pro导出的文件和吉他MIDI拾音器常用这种方式
+///
+/// Params:
+/// - first_channel: 第0弦（最细的弦）使用的通道，之后的弦依次使用下一个通道
+/// - string_count: 弦数，超出弦数的通道会被忽略
+#[derive(Debug, Clone, Copy)]
+pub struct ChannelPerString {
+    pub first_channel: i32,
+    pub string_count: usize,
+}
+
+impl ChannelPerString {
+    /// 通道对应的弦，不是弦的通道返回None
+    pub fn string_index(&self, channel: i32) -> Option<i32> {
+        let string_index = channel - self.first_channel;
+        (0..self.string_count as i32)
+            .contains(&string_index)
+            .then_some(string_index)
+    }
+}
+
+/// midi_to_guitar_notes读到的音符、弯音、所有MIDI消息、闷音和每个音符的时值
+pub type MidiGuitarNotes = (
+    Vec<NoteInfo>,
+    Vec<PitchWheelInfo>,
+    Vec<MessageInfo>,
+    Vec<MutedNote>,
+    Vec<NoteSpan>,
+);
+
+/// 关闭一个音符，记录它的时值，没有对应的开始事件时忽略
+fn close_note(
+    open_notes: &mut HashMap<(i32, u8), (f64, i32, i32)>,
+    note_spans: &mut Vec<NoteSpan>,
+    key: (i32, u8),
+    real_tick: f64,
+) {
+    if let Some((start_tick, note, string_index)) = open_notes.remove(&key) {
+        note_spans.push(NoteSpan {
+            real_tick: start_tick,
+            end_tick: real_tick,
+            note,
+            string_index,
+        });
+    }
+}
+
+/// 把收集到的一组音符写入notes_map，音符和所在的弦一起从低到高排序
+fn flush_notes(
+    notes_map: &mut Vec<NoteInfo>,
+    note: &mut Vec<(i32, i32)>,
+    real_tick: f64,
+    with_strings: bool,
+) {
+    if note.is_empty() {
+        return;
+    }
+    note.sort();
+    let (notes, string_indices): (Vec<i32>, Vec<i32>) = note.drain(..).unzip();
+    notes_map.push(NoteInfo {
+        notes,
+        real_tick,
+        string_indices: if with_strings {
+            string_indices
+        } else {
+            Vec::new()
+        },
+    });
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -247,6 +452,111 @@ impl MidiProcessor {
         Ok((tempo_changes, ticks_per_beat))
     }
 
+    /// 读取全曲的拍号变化，按时间排序
+    pub fn get_time_signatures(
+        &self,
+        midi_file_path: &str,
+    ) -> Result<Vec<TimeSignatureChange>, Box<dyn std::error::Error>> {
+        let data = std::fs::read(midi_file_path)?;
+        let smf = Smf::parse(&data)?;
+
+        let mut time_signatures = Vec::new();
+
+        for track in smf.tracks.iter() {
+            let mut absolute_time = 0u64;
+
+            for event in track {
+                absolute_time += event.delta.as_int() as u64;
+
+                if let TrackEventKind::Meta(MetaMessage::TimeSignature(
+                    numerator,
+                    denominator,
+                    _,
+                    _,
+                )) = event.kind
+                {
+                    time_signatures.push(TimeSignatureChange {
+                        numerator,
+                        denominator: 1u32 << denominator.min(31),
+                        time: absolute_time,
+                    });
+                }
+            }
+        }
+
+        time_signatures.sort_by_key(|change| change.time);
+        Ok(time_signatures)
+    }
+
+    /// 读取全曲的段落标记，按时间排序
+    pub fn get_section_markers(
+        &self,
+        midi_file_path: &str,
+    ) -> Result<Vec<SectionMarker>, Box<dyn std::error::Error>> {
+        let data = std::fs::read(midi_file_path)?;
+        let smf = Smf::parse(&data)?;
+
+        let mut markers = Vec::new();
+
+        for track in smf.tracks.iter() {
+            let mut absolute_time = 0u64;
+
+            for event in track {
+                absolute_time += event.delta.as_int() as u64;
+
+                if let TrackEventKind::Meta(MetaMessage::Marker(name)) = event.kind {
+                    markers.push(SectionMarker {
+                        name: String::from_utf8_lossy(name).trim().to_string(),
+                        time: absolute_time,
+                    });
+                }
+            }
+        }
+
+        markers.sort_by_key(|marker| marker.time);
+        Ok(markers)
+    }
+
+    /// 计算real_tick所在的小节和拍，都从1开始计数，没有拍号信息时按4/4拍计算
+    pub fn calculate_bar_position(
+        &self,
+        time_signatures: &[TimeSignatureChange],
+        ticks_per_beat: u16,
+        real_tick: f64,
+    ) -> (i32, f64) {
+        let mut bar = 1;
+        let mut segment_start = 0.0;
+        let mut numerator = 4.0;
+        let mut denominator = 4.0;
+
+        for change in time_signatures {
+            let change_time = change.time as f64;
+            if change_time > real_tick {
+                break;
+            }
+
+            // 拍号变化之前经过的完整小节，不完整的小节也算一个
+            let bar_ticks = ticks_per_beat as f64 * 4.0 * numerator / denominator;
+            if bar_ticks > 0.0 {
+                bar += ((change_time - segment_start) / bar_ticks).ceil() as i32;
+            }
+            segment_start = change_time;
+            numerator = change.numerator as f64;
+            denominator = change.denominator as f64;
+        }
+
+        let beat_ticks = ticks_per_beat as f64 * 4.0 / denominator;
+        let bar_ticks = beat_ticks * numerator;
+        if bar_ticks <= 0.0 {
+            return (bar, 1.0);
+        }
+        let elapsed = real_tick - segment_start;
+        bar += (elapsed / bar_ticks).floor() as i32;
+        let beat = (elapsed % bar_ticks) / beat_ticks + 1.0;
+
+        (bar, beat)
+    }
+
     pub fn export_midi_info(
         &self,
         midi_file_path: &str,
@@ -336,6 +646,13 @@ impl MidiProcessor {
 
         Ok(result)
     }
+    /// 读取MIDI文件里的音符，同一时刻开始的音符合并成一组
+    ///
+    /// # 参数
+    /// * `use_channel` - 只读取这个通道的音符，-1表示读取所有通道
+    /// * `channel_per_string` - 按弦分通道读取，设置后忽略use_channel，每个音符记录所在的弦
+    /// * `mute_detection` - 闷音的识别方式，闷音和普通音符一样写入notes_map，另外单独返回
+    #[allow(clippy::too_many_arguments)]
     pub fn midi_to_guitar_notes(
         &self,
         midi_file_path: &str,
@@ -344,16 +661,20 @@ impl MidiProcessor {
         fps: f64,
         use_tracks: &[i32],
         use_channel: i32,
+        channel_per_string: Option<ChannelPerString>,
         octave_down_checkbox: bool,
         capo_number: i32,
-    ) -> Result<(Vec<NoteInfo>, Vec<PitchWheelInfo>, Vec<MessageInfo>), Box<dyn std::error::Error>>
-    {
+        mute_detection: &MuteDetection,
+    ) -> Result<MidiGuitarNotes, Box<dyn std::error::Error>> {
         let data = std::fs::read(midi_file_path)?;
         let smf = Smf::parse(&data)?;
 
         let mut notes_map = Vec::new();
         let mut pitch_wheel_map = Vec::new();
         let mut messages = Vec::new();
+        let mut muted_notes = Vec::new();
+        let mut note_spans = Vec::new();
+        let with_strings = channel_per_string.is_some();
 
         for &track_index in use_tracks {
             if track_index as usize >= smf.tracks.len() {
@@ -361,9 +682,14 @@ impl MidiProcessor {
             }
 
             let track = &smf.tracks[track_index as usize];
-            let mut note = Vec::new();
+            // 音符和所在的弦，不按弦分通道时弦都是-1
+            let mut note: Vec<(i32, i32)> = Vec::new();
             let mut real_tick: f64 = 0.0;
             let mut current_tick: f64 = 0.0; // 当前正在处理的音符时间点
+            // 闷音的键开关是否按住
+            let mut keyswitch_held = false;
+            // 还没有关闭的音符，按通道和键记录开始的tick、音高和所在的弦
+            let mut open_notes: HashMap<(i32, u8), (f64, i32, i32)> = HashMap::new();
 
             for event in track {
                 let ticks = event.delta.as_int() as f64;
@@ -373,117 +699,156 @@ impl MidiProcessor {
                     TrackEventKind::Midi { channel, message } => {
                         let channel_num = channel.as_int() as i32;
 
-                        if channel_num == use_channel || use_channel == -1 {
-                            messages.push(MessageInfo {
-                                message: format!("{:?}", event),
-                                real_tick,
-                            });
-
-                            match message {
-                                midly::MidiMessage::NoteOn { key, vel } => {
-                                    // 只有当音符开启(velocity > 0)时才处理
-                                    if vel.as_int() > 0 {
-                                        // 如果当前时间与之前记录的时间不同，说明是新的一组音符
-                                        if current_tick != real_tick && !note.is_empty() {
-                                            // 保存之前收集的音符
-                                            note.sort();
-                                            notes_map.push(NoteInfo {
-                                                notes: note.clone(),
-                                                real_tick: current_tick,
-                                            });
-                                            note.clear(); // 重置音符列表
-                                        }
-
-                                        // 更新当前时间点
-                                        current_tick = real_tick;
-
-                                        // 添加新音符
-                                        let mut note_value = key.as_int() as i32;
-                                        if octave_down_checkbox {
-                                            note_value -= 12;
-                                        }
-                                        note_value -= capo_number;
-                                        note.push(note_value);
-                                    } else {
-                                        // velocity为0表示音符关闭，处理非note_on事件，如果当前有音符则保存
-                                        if !note.is_empty() {
-                                            note.sort();
-                                            notes_map.push(NoteInfo {
-                                                notes: note.clone(),
-                                                real_tick: current_tick,
-                                            });
-                                            note.clear();
-                                        }
+                        // 键开关可以在任何通道上，只改变状态，不作为音符读入
+                        let keyswitch = mute_detection.keyswitch_note;
+                        match message {
+                            midly::MidiMessage::NoteOn { key, vel }
+                                if key.as_int() as i32 == keyswitch =>
+                            {
+                                keyswitch_held = vel.as_int() > 0;
+                                // 和键开关同时开始、但是排在它前面的音符也是闷音
+                                if keyswitch_held && current_tick == real_tick {
+                                    for &(note_value, string_index) in &note {
+                                        muted_notes.push(MutedNote {
+                                            real_tick,
+                                            note: note_value,
+                                            string_index,
+                                        });
                                     }
                                 }
-                                midly::MidiMessage::NoteOff { .. } => {
-                                    // 处理note_off事件，如果当前有音符则保存
-                                    if !note.is_empty() {
-                                        note.sort();
-                                        notes_map.push(NoteInfo {
-                                            notes: note.clone(),
-                                            real_tick: current_tick,
+                                continue;
+                            }
+                            midly::MidiMessage::NoteOff { key, .. }
+                                if key.as_int() as i32 == keyswitch =>
+                            {
+                                keyswitch_held = false;
+                                continue;
+                            }
+                            _ => {}
+                        }
+
+                        let string_index = match channel_per_string {
+                            Some(mapping) => match mapping.string_index(channel_num) {
+                                Some(string_index) => string_index,
+                                None => continue,
+                            },
+                            None if channel_num == use_channel || use_channel == -1 => -1,
+                            None => continue,
+                        };
+
+                        messages.push(MessageInfo {
+                            message: format!("{:?}", event),
+                            real_tick,
+                        });
+
+                        match message {
+                            midly::MidiMessage::NoteOn { key, vel } => {
+                                // 只有当音符开启(velocity > 0)时才处理
+                                if vel.as_int() > 0 {
+                                    // 如果当前时间与之前记录的时间不同，说明是新的一组音符
+                                    if current_tick != real_tick {
+                                        // 保存之前收集的音符
+                                        flush_notes(
+                                            &mut notes_map,
+                                            &mut note,
+                                            current_tick,
+                                            with_strings,
+                                        );
+                                    }
+
+                                    // 更新当前时间点
+                                    current_tick = real_tick;
+
+                                    // 添加新音符
+                                    let mut note_value = key.as_int() as i32;
+                                    if octave_down_checkbox {
+                                        note_value -= 12;
+                                    }
+                                    note_value -= capo_number;
+                                    note.push((note_value, string_index));
+
+                                    // 同一个键重复按下时，之前的音符在这里结束
+                                    if let Some((start_tick, note, string_index)) = open_notes
+                                        .insert(
+                                            (channel_num, key.as_int()),
+                                            (real_tick, note_value, string_index),
+                                        )
+                                    {
+                                        note_spans.push(NoteSpan {
+                                            real_tick: start_tick,
+                                            end_tick: real_tick,
+                                            note,
+                                            string_index,
                                         });
-                                        note.clear();
                                     }
-                                }
-                                midly::MidiMessage::PitchBend { bend } => {
-                                    pitch_wheel_map.push(PitchWheelInfo {
-                                        pitchwheel: bend.0.as_int() as i16,
-                                        real_tick,
-                                        frame: self.calculate_frame(
-                                            &tempo_changes,
-                                            ticks_per_beat,
-                                            fps,
+
+                                    let max_velocity = mute_detection.max_velocity;
+                                    if keyswitch_held || vel.as_int() <= max_velocity {
+                                        muted_notes.push(MutedNote {
                                             real_tick,
-                                        ),
-                                    });
-                                }
-                                _ => {
-                                    // 处理其他MIDI事件，如果当前有音符则保存
-                                    if !note.is_empty() {
-                                        note.sort();
-                                        notes_map.push(NoteInfo {
-                                            notes: note.clone(),
-                                            real_tick: current_tick,
+                                            note: note_value,
+                                            string_index,
                                         });
-                                        note.clear();
                                     }
+                                } else {
+                                    close_note(
+                                        &mut open_notes,
+                                        &mut note_spans,
+                                        (channel_num, key.as_int()),
+                                        real_tick,
+                                    );
+                                    // velocity为0表示音符关闭，处理非note_on事件，如果当前有音符则保存
+                                    flush_notes(
+                                        &mut notes_map,
+                                        &mut note,
+                                        current_tick,
+                                        with_strings,
+                                    );
                                 }
                             }
-                        }
-                    }
-                    TrackEventKind::Meta(_) => {
-                        // 处理元事件，如果当前有音符则保存
-                        if !note.is_empty() {
-                            note.sort();
-                            notes_map.push(NoteInfo {
-                                notes: note.clone(),
-                                real_tick: current_tick,
-                            });
-                            note.clear();
+                            midly::MidiMessage::PitchBend { bend } => {
+                                pitch_wheel_map.push(PitchWheelInfo {
+                                    pitchwheel: bend.0.as_int() as i16,
+                                    real_tick,
+                                    frame: self.calculate_frame(
+                                        tempo_changes,
+                                        ticks_per_beat,
+                                        fps,
+                                        real_tick,
+                                    ),
+                                });
+                            }
+                            midly::MidiMessage::NoteOff { key, .. } => {
+                                close_note(
+                                    &mut open_notes,
+                                    &mut note_spans,
+                                    (channel_num, key.as_int()),
+                                    real_tick,
+                                );
+                                flush_notes(&mut notes_map, &mut note, current_tick, with_strings);
+                            }
+                            _ => {
+                                // 处理note_off和其他MIDI事件，如果当前有音符则保存
+                                flush_notes(&mut notes_map, &mut note, current_tick, with_strings);
+                            }
                         }
                     }
                     _ => {
-                        // 处理其他事件，如果当前有音符则保存
-                        if !note.is_empty() {
-                            note.sort();
-                            notes_map.push(NoteInfo {
-                                notes: note.clone(),
-                                real_tick: current_tick,
-                            });
-                            note.clear();
-                        }
+                        // 处理元事件和其他事件，如果当前有音符则保存
+                        flush_notes(&mut notes_map, &mut note, current_tick, with_strings);
                     }
                 }
             }
 
             // 处理最后一个音符组
-            if !note.is_empty() {
-                note.sort();
-                notes_map.push(NoteInfo {
-                    notes: note,
-                    real_tick: current_tick,
+            flush_notes(&mut notes_map, &mut note, current_tick, with_strings);
+            // 没有关闭的音符持续到音轨结束
+            for (_, (start_tick, note, string_index)) in open_notes {
+                note_spans.push(NoteSpan {
+                    real_tick: start_tick,
+                    end_tick: real_tick,
+                    note,
+                    string_index,
                 });
             }
         }
@@ -492,12 +857,62 @@ impl MidiProcessor {
         notes_map.sort_by(|a, b| a.real_tick.partial_cmp(&b.real_tick).unwrap());
         pitch_wheel_map.sort_by(|a, b| a.real_tick.partial_cmp(&b.real_tick).unwrap());
         messages.sort_by(|a, b| a.real_tick.partial_cmp(&b.real_tick).unwrap());
+        muted_notes.sort_by(|a, b| a.real_tick.total_cmp(&b.real_tick));
+        note_spans.sort_by(|a, b| a.real_tick.total_cmp(&b.real_tick));
+
+        Ok((
+            notes_map,
+            pitch_wheel_map,
+            messages,
+            muted_notes,
+            note_spans,
+        ))
+    }
 
-        Ok((notes_map, pitch_wheel_map, messages))
+    /// 处理一个音符事件，已经知道弦的音符不能移动八度或者去掉，保持原样
+    pub fn processed_note_info(
+        &self,
+        note_info: &NoteInfo,
+        min: i32,
+        max: i32,
+        string_count: usize,
+    ) -> NoteInfo {
+        if !note_info.string_indices.is_empty() {
+            return note_info.clone();
+        }
+        NoteInfo {
+            notes: self.processed_notes(&note_info.notes, min, max, string_count),
+            real_tick: note_info.real_tick,
+            string_indices: Vec::new(),
+        }
+    }
+
+    /// 处理所有音符事件，每个事件只处理一次
+    ///
+    /// 精简音符时会随机去掉多余的音，所以求解、把位规划和约束检查都要使用这一次的结果，
+    /// 处理过的音符已经在音域里并且不多于弦数，再次处理不会改变
+    pub fn processed_notes_map(
+        &self,
+        notes_map: &[NoteInfo],
+        min: i32,
+        max: i32,
+        string_count: usize,
+    ) -> Vec<NoteInfo> {
+        notes_map
+            .iter()
+            .map(|note_info| self.processed_note_info(note_info, min, max, string_count))
+            .collect()
     }
-    pub fn processed_notes(&self, chord_notes: &[i32], min: i32, max: i32) -> Vec<i32> {
+
+    pub fn processed_notes(
+        &self,
+        chord_notes: &[i32],
+        min: i32,
+        max: i32,
+        string_count: usize,
+    ) -> Vec<i32> {
         let compressed = self.compress_notes(chord_notes, min, max);
-        self.simplify_notes(&compressed)
+        self.simplify_notes(&compressed, string_count)
     }
 
     pub fn compress_notes(&self, chord_notes: &[i32], min: i32, max: i32) -> Vec<i32> {
@@ -523,16 +938,17 @@ impl MidiProcessor {
         new_chord
     }
 
-    pub fn simplify_notes(&self, chord_notes: &[i32]) -> Vec<i32> {
-        // 如果音符数量不大于6，直接返回音符
-        if chord_notes.len() <= 6 {
+    /// 把音符数量精简到不超过弦数
+    pub fn simplify_notes(&self, chord_notes: &[i32], string_count: usize) -> Vec<i32> {
+        // 如果音符数量不大于弦数，直接返回音符
+        if chord_notes.len() <= string_count || chord_notes.len() < 2 {
             return chord_notes.to_vec();
         }
 
         let lowest_note = chord_notes[0];
         let highest_note = chord_notes[chord_notes.len() - 1];
         let mut middle_notes = chord_notes[1..chord_notes.len() - 1].to_vec();
-        let number_of_notes_need_remove = chord_notes.len() - 6;
+        let number_of_notes_need_remove = chord_notes.len() - string_count.max(2);
         let mut number_of_note_removed = 0;
 
         // 移除与最低音或者最高音有八度关系的音符
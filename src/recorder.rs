@@ -1,3 +1,17 @@
+pub mod alternatives;
+pub mod finger_hold;
+pub mod fingering_constraint;
+pub mod left_hand_expansion;
+pub mod left_hand_mute;
 pub mod left_hand_recorder;
+pub mod left_hand_solve;
+pub mod left_hand_trace;
+pub mod legato;
+pub mod long_rest;
+pub mod note_fallback;
+pub mod phrase_repeat;
+pub mod position_plan;
 pub mod recorder_pool;
 pub mod right_hand_recorder;
+pub mod segment_solver;
+pub mod viterbi_solver;
@@ -0,0 +1,375 @@
+// Alternatives.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::ops::Range;
+
+use crate::hand::hand_profile::HandProfile;
+use crate::hand::left_finger::{Finger, LeftFinger, PressState};
+use crate::hand::left_hand::{LeftHand, LeftHandPoseKey};
+use crate::recorder::left_hand_recorder::{LeftHandRecorder, RecordedLeftHand};
+use crate::recorder::viterbi_solver::count_different_poses;
+
+/// 从按熵排序的记录器中选出按法互不相同的几个
+///
+/// 第一个记录器总是保留，之后的记录器和所有已经选中的记录器都至少有
+/// min_different_poses个手型不同时才保留，所以熵相近但只差一两个手型的解不会重复出现
+///
+/// # 参数
+/// * `ranked` - 按熵从小到大排列的记录器
+/// * `count` - 最多保留的数量，包含第一个
+/// * `min_different_poses` - 两个解至少要有多少个手型不同
+pub fn select_distinct_recorders(
+    ranked: Vec<LeftHandRecorder>,
+    count: usize,
+    min_different_poses: usize,
+) -> Vec<LeftHandRecorder> {
+    let mut selected: Vec<LeftHandRecorder> = Vec::new();
+    for recorder in ranked {
+        if selected.len() >= count {
+            break;
+        }
+        let distinct = selected
+            .iter()
+            .all(|chosen| count_different_poses(chosen, &recorder) >= min_different_poses.max(1));
+        if distinct {
+            selected.push(recorder);
+        }
+    }
+    selected
+}
+
+/// 一个备选按法
+///
+/// Params:
+/// - index: 备选的序号，0是熵最小的解
+/// - file: 保存的左手记录文件
+/// - entropy: 总熵
+/// - different_poses: 和熵最小的解不同的手型数
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AlternativeInfo {
+    pub index: usize,
+    pub file: String,
+    pub entropy: f64,
+    pub different_poses: usize,
+}
+
+/// 一个段落的备选按法
+///
+/// Params:
+/// - start_tick, end_tick: 段落第一个和最后一个音符事件的tick
+/// - start_bar, end_bar: 段落开始和结束的小节
+/// - options: 在这个段落里按法互不相同的备选序号，第一个总是0
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AlternativeSection {
+    pub start_tick: f64,
+    pub end_tick: f64,
+    pub start_bar: i32,
+    pub end_bar: i32,
+    pub options: Vec<usize>,
+}
+
+/// 备选按法的汇总，界面根据它让用户为每个段落选择按法
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AlternativesReport {
+    pub alternatives: Vec<AlternativeInfo>,
+    pub sections: Vec<AlternativeSection>,
+    /// 求解时是否使用了泛音，应用备选时要用同样的设置重建吉他
+    #[serde(default)]
+    pub use_harm_notes: bool,
+}
+
+impl AlternativesReport {
+    pub fn load_from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    pub fn save_to_file(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// 读取第index个备选按法的左手记录
+    pub fn load_alternative(
+        &self,
+        index: usize,
+    ) -> Result<Vec<RecordedLeftHand>, Box<dyn std::error::Error>> {
+        let info = self
+            .alternatives
+            .get(index)
+            .ok_or_else(|| format!("没有第{}个备选按法", index))?;
+        let file = File::open(&info.file)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+    /// 按每个段落选择的备选序号拼接出新的左手记录
+    ///
+    /// 保存的备选已经按各自的前后手型处理过闷音、手指保持和连奏，拼接以后前后手型变了，
+    /// 所以这里把保持按弦和闷音的手指还原成求解时的状态，由调用者在拼接后的序列上重新计算
+    ///
+    /// # 参数
+    /// * `choices` - 与sections一一对应的备选序号，缺少的段落使用熵最小的解
+    /// * `hand_profile` - 还原手型时使用的手型数据
+    pub fn compose_recorder(
+        &self,
+        choices: &[usize],
+        hand_profile: HandProfile,
+    ) -> Result<ComposedRecorder, Box<dyn std::error::Error>> {
+        // 每个备选只读取一次，取出选择了它的所有段落
+        // 段落一直延续到下一段开始，琶音拆开的手型也跟着所在的段落
+        let mut ranges: HashMap<usize, Vec<Range<f64>>> = HashMap::new();
+        for (index, (section, &choice)) in self.sections.iter().zip(choices).enumerate() {
+            if choice != 0 {
+                let end_tick = self
+                    .sections
+                    .get(index + 1)
+                    .map_or(f64::INFINITY, |next| next.start_tick);
+                ranges
+                    .entry(choice)
+                    .or_default()
+                    .push(section.start_tick..end_tick);
+            }
+        }
+        let in_ranges =
+            |ranges: &[Range<f64>], tick: f64| ranges.iter().any(|range| range.contains(&tick));
+
+        let mut hands: Vec<(usize, RecordedLeftHand)> = self
+            .load_alternative(0)?
+            .into_iter()
+            .map(|hand| (0, hand))
+            .collect();
+        for (choice, ranges) in ranges {
+            hands.retain(|(_, hand)| !in_ranges(&ranges, hand.real_tick));
+            hands.extend(
+                self.load_alternative(choice)?
+                    .into_iter()
+                    .filter(|hand| in_ranges(&ranges, hand.real_tick))
+                    .map(|hand| (choice, hand)),
+            );
+        }
+        hands.sort_by(|a, b| a.1.real_tick.total_cmp(&b.1.real_tick));
+
+        let mut recorder = LeftHandRecorder::new();
+        let mut splices = Vec::new();
+        // 第一个手型是初始手型，用第一个记录的手型代替
+        if let Some((_, first)) = hands.first() {
+            recorder.add_hand_pose(restore_hand(first, hand_profile), 0.0, first.real_tick);
+        }
+        for (index, (source, hand)) in hands.iter().enumerate() {
+            if index > 0 && hands[index - 1].0 != *source {
+                splices.push(recorder.hand_pose_list.len());
+            }
+            recorder.add_hand_pose(restore_hand(hand, hand_profile), 0.0, hand.real_tick);
+        }
+        Ok(ComposedRecorder { recorder, splices })
+    }
+}
+
+/// 拼接出来的左手记录
+///
+/// Params:
+/// - recorder: 拼接后的记录器，第一个手型是初始手型
+/// - splices: 和前一个手型来自不同备选的手型序号
+#[derive(Clone, Debug)]
+pub struct ComposedRecorder {
+    pub recorder: LeftHandRecorder,
+    pub splices: Vec<usize>,
+}
+
+/// 把保存的手型还原成求解时的手型
+///
+/// 保持按弦的手指还原成抬起；闷音的手指还原成按弦，
+/// 空弦闷音时轻触弦的手指求解时是抬起的，也还原成抬起
+fn restore_hand(recorded: &RecordedLeftHand, hand_profile: HandProfile) -> LeftHand {
+    let muted_open_strings: Vec<i32> = recorded
+        .left_hand
+        .iter()
+        .filter(|finger| finger.finger_index == -1)
+        .filter(|finger| PressState::from_str(&finger.finger_info.press) == PressState::Mute)
+        .map(|finger| finger.finger_info.string_index)
+        .collect();
+    let fingers = recorded
+        .left_hand
+        .iter()
+        .map(|finger| {
+            let info = &finger.finger_info;
+            let press = match PressState::from_str(&info.press) {
+                PressState::Keep => PressState::Open,
+                PressState::Mute
+                    if finger.finger_index == -1
+                        || muted_open_strings.contains(&info.string_index) =>
+                {
+                    PressState::Open
+                }
+                PressState::Mute => PressState::Pressed,
+                press => press,
+            };
+            LeftFinger {
+                finger_index: finger.finger_index,
+                finger_name: Finger::from_index(finger.finger_index).name().to_string(),
+                string_index: info.string_index,
+                fret: info.fret,
+                press,
+            }
+        })
+        .collect();
+    let mut hand = LeftHand::with_profile(fingers, recorded.use_barre, hand_profile);
+    hand.hand_position = recorded.hand_position;
+    hand
+}
+
+/// 找出每个段落里按法互不相同的备选
+///
+/// # 参数
+/// * `alternatives` - select_distinct_recorders选出的记录器
+/// * `section_ticks` - 每个段落第一个和最后一个音符事件的tick
+pub fn find_section_options(
+    alternatives: &[LeftHandRecorder],
+    section_ticks: &[(f64, f64)],
+) -> Vec<Vec<usize>> {
+    section_ticks
+        .iter()
+        .map(|&(start_tick, end_tick)| {
+            let mut seen: Vec<Vec<LeftHandPoseKey>> = Vec::new();
+            let mut options = Vec::new();
+            for (index, recorder) in alternatives.iter().enumerate() {
+                let keys: Vec<LeftHandPoseKey> = recorder
+                    .hand_pose_list
+                    .iter()
+                    .zip(&recorder.real_ticks)
+                    // 第一个手型是初始手型
+                    .skip(1)
+                    .filter(|(_, tick)| (start_tick..=end_tick).contains(*tick))
+                    .map(|(hand, _)| hand.pose_key())
+                    .collect();
+                if !seen.contains(&keys) {
+                    seen.push(keys);
+                    options.push(index);
+                }
+            }
+            options
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+    use crate::midi::midi_to_note::TempoChange;
+
+    /// 按给出的品位生成一个记录器，每个手型相隔480个tick
+    fn recorder_with_frets(frets: &[i32]) -> LeftHandRecorder {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let string = &guitar.guitar_strings[2];
+        let mut recorder = LeftHandRecorder::new();
+        for (index, &fret) in std::iter::once(&1).chain(frets).enumerate() {
+            let fingers = (1..5)
+                .map(|i| LeftFinger::new(i, string, fret + i - 1, "Pressed"))
+                .collect();
+            let hand = LeftHand::with_profile(fingers, false, HandProfile::default());
+            recorder.add_hand_pose(hand, 1.0, (index * 480) as f64);
+        }
+        recorder
+    }
+
+    #[test]
+    fn test_alternatives_differ_by_fingering() {
+        let best = recorder_with_frets(&[1, 3, 5, 7]);
+        // 只差一个手型，不算不同的按法
+        let near_copy = recorder_with_frets(&[1, 3, 5, 8]);
+        let other = recorder_with_frets(&[5, 7, 5, 7]);
+
+        let selected = select_distinct_recorders(vec![best, near_copy, other.clone()], 3, 2);
+        assert_eq!(selected.len(), 2);
+        assert_eq!(count_different_poses(&selected[1], &other), 0);
+
+        // 第三个手型两个备选相同，前两个手型不同
+        let options = find_section_options(&selected, &[(1440.0, 1440.0), (480.0, 960.0)]);
+        assert_eq!(options, vec![vec![0], vec![0, 1]]);
+    }
+
+    #[test]
+    fn test_compose_marks_splices_and_restores_solver_poses() {
+        let dir =
+            std::env::temp_dir().join(format!("fret_dance_alternatives_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let tempo_changes = vec![TempoChange {
+            track: 0,
+            tempo: 500000,
+            time: 0,
+        }];
+
+        let mut best = recorder_with_frets(&[1, 3, 5, 7]);
+        // 保存的最优解里第三个手型的小指按照音符时值保持按弦
+        best.hand_pose_list[3].fingers[3].press = PressState::Keep;
+        let other = recorder_with_frets(&[5, 7, 5, 7]);
+        let mut alternatives = Vec::new();
+        for (index, recorder) in [best, other].iter().enumerate() {
+            let file = dir
+                .join(format!("{}.json", index))
+                .to_string_lossy()
+                .to_string();
+            recorder.save(&file, &tempo_changes, 480, 30.0).unwrap();
+            alternatives.push(AlternativeInfo {
+                index,
+                file,
+                entropy: 0.0,
+                different_poses: 0,
+            });
+        }
+        let section = |start_tick: f64, end_tick: f64| AlternativeSection {
+            start_tick,
+            end_tick,
+            start_bar: 0,
+            end_bar: 0,
+            options: vec![0, 1],
+        };
+        let report = AlternativesReport {
+            alternatives,
+            sections: vec![section(480.0, 960.0), section(1440.0, 1920.0)],
+            use_harm_notes: false,
+        };
+
+        let composed = report
+            .compose_recorder(&[1, 0], HandProfile::default())
+            .unwrap();
+        let recorder = &composed.recorder;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let positions: Vec<i32> = recorder
+            .hand_pose_list
+            .iter()
+            .map(|hand| hand.hand_position)
+            .collect();
+        assert_eq!(positions, vec![5, 5, 7, 5, 7]);
+        assert_eq!(
+            recorder.real_ticks,
+            vec![480.0, 480.0, 960.0, 1440.0, 1920.0]
+        );
+        // 第二个备选在1440之前结束，从这里开始换回最优解
+        assert_eq!(composed.splices, vec![3]);
+        // 保持按弦的手指要在拼接后的序列上重新计算
+        let pinky = &recorder.hand_pose_list[3].fingers[3];
+        assert_eq!((pinky.finger_index, pinky.press), (4, PressState::Open));
+    }
+
+    #[test]
+    fn test_report_remembers_harmonic_setting() {
+        let report = AlternativesReport {
+            use_harm_notes: true,
+            ..AlternativesReport::default()
+        };
+        let json = serde_json::to_string(&report).unwrap();
+        let loaded: AlternativesReport = serde_json::from_str(&json).unwrap();
+        assert!(loaded.use_harm_notes);
+
+        // 之前保存的报告没有这个字段，按不使用泛音读取
+        let loaded: AlternativesReport =
+            serde_json::from_str(r#"{"alternatives": [], "sections": []}"#).unwrap();
+        assert!(!loaded.use_harm_notes);
+    }
+}
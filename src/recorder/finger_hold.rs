@@ -0,0 +1,290 @@
+// FingerHold.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::guitar::guitar_instance::Guitar;
+use crate::hand::left_finger::PressState;
+use crate::hand::left_hand::LeftHand;
+use crate::midi::midi_to_note::{MidiProcessor, NoteSpan};
+use crate::recorder::left_hand_recorder::LeftHandRecorder;
+
+/// 一根手指在音符结束时松开
+///
+/// Params:
+/// - finger_index: 松开的手指
+/// - real_tick: 音符结束的tick
+/// - frame: 音符结束的帧，保存记录时才计算
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct FingerRelease {
+    pub finger_index: i32,
+    pub real_tick: f64,
+    #[serde(default)]
+    pub frame: f64,
+}
+
+/// 按音符时值调整手指的结果
+///
+/// Params:
+/// - anchored: 音符还在持续，手指留在弦上保持按弦的次数
+/// - released: 音符在下一个手型之前结束，手指提前松开的次数
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HoldSummary {
+    pub anchored: usize,
+    pub released: usize,
+}
+
+/// 按MIDI里音符的时值调整手指按弦的时间
+///
+/// 音符持续到后面的手型时，如果手指在后面的手型里空闲、手没有换把、这根弦也没有弹新的音，
+/// 就让手指留在原处保持按弦；音符在下一个手型之前结束时，记录手指松开的时间，动画里手指在这个时间抬起。
+/// 找不到时值的音符保持原来的做法，手指一直按到下一个手型。
+/// 求解前超出音域的音会移八度放进音域，MIDI里的音也按同样的方式移动以后再和手型发出的音比较
+///
+/// # 参数
+/// * `note_spans` - 从MIDI读取的每个音符的时值
+pub fn apply_note_durations(
+    recorder: &mut LeftHandRecorder,
+    note_spans: &[NoteSpan],
+    guitar: &Guitar,
+) -> HoldSummary {
+    // 每个音符和求解时实际使用的音高放在一起
+    let midi_processor = MidiProcessor::new();
+    let (min_note, max_note) = guitar.get_note_range();
+    let mut spans: HashMap<u64, Vec<(i32, &NoteSpan)>> = HashMap::new();
+    for span in note_spans {
+        let Some(&played) = midi_processor
+            .compress_notes(&[span.note], min_note, max_note)
+            .first()
+        else {
+            continue;
+        };
+        spans
+            .entry(span.real_tick.to_bits())
+            .or_default()
+            .push((played, span));
+    }
+
+    let pose_count = recorder.hand_pose_list.len();
+    let mut releases = vec![Vec::new(); pose_count];
+    let mut summary = HoldSummary::default();
+    // 第一个手型是初始手型
+    for index in 1..pose_count {
+        let Some(starting) = spans.get(&recorder.real_ticks[index].to_bits()) else {
+            continue;
+        };
+        let hand = &recorder.hand_pose_list[index];
+        let hand_position = hand.hand_position;
+        let owners = hand.sounding_frets();
+
+        for finger_index in 1..=4 {
+            let strings: Vec<(i32, i32)> = owners
+                .iter()
+                .filter(|(_, (_, owner))| *owner == finger_index)
+                .map(|(&string_index, &(fret, _))| (string_index, fret))
+                .collect();
+            // 手指发出的所有音都结束以后才能松开
+            let Some(end_tick) = strings
+                .iter()
+                .map(|&(string_index, fret)| note_end(starting, guitar, string_index, fret))
+                .collect::<Option<Vec<f64>>>()
+                .and_then(|ends| ends.into_iter().reduce(f64::max))
+            else {
+                continue;
+            };
+
+            // 只有按一根弦的手指可以留在原处，横按的手指跟着手型移动
+            let mut last = index;
+            let pressed = recorder.hand_pose_list[index].fingers.iter().any(|finger| {
+                finger.finger_index == finger_index && finger.press == PressState::Pressed
+            });
+            if let [(string_index, fret)] = strings[..]
+                && pressed
+            {
+                while last + 1 < pose_count
+                    && recorder.real_ticks[last + 1] < end_tick
+                    && anchor_finger(
+                        &mut recorder.hand_pose_list[last + 1],
+                        hand_position,
+                        finger_index,
+                        string_index,
+                        fret,
+                    )
+                {
+                    last += 1;
+                    summary.anchored += 1;
+                }
+            }
+
+            let next_tick = recorder
+                .real_ticks
+                .get(last + 1)
+                .copied()
+                .unwrap_or(f64::INFINITY);
+            if end_tick < next_tick {
+                releases[last].push(FingerRelease {
+                    finger_index,
+                    real_tick: end_tick,
+                    frame: 0.0,
+                });
+                summary.released += 1;
+            }
+        }
+    }
+
+    recorder.releases = releases;
+    summary
+}
+
+/// 这个位置发出的音符结束的tick，MIDI里找不到这个音时返回None
+///
+/// # 参数
+/// * `starting` - 这个手型开始的音符，以及求解时这个音符移进音域以后的音高
+fn note_end(
+    starting: &[(i32, &NoteSpan)],
+    guitar: &Guitar,
+    string_index: i32,
+    fret: i32,
+) -> Option<f64> {
+    let note = guitar
+        .guitar_strings
+        .get(string_index as usize)?
+        .get_note_by_fret(fret)?;
+    starting
+        .iter()
+        .filter(|(played, _)| *played == note)
+        .map(|(_, span)| span)
+        .filter(|span| span.string_index < 0 || span.string_index == string_index)
+        .map(|span| span.end_tick)
+        .reduce(f64::max)
+}
+
+/// 让手指在后面的手型里继续按住之前的位置，手指不空闲时返回false
+fn anchor_finger(
+    hand: &mut LeftHand,
+    hand_position: i32,
+    finger_index: i32,
+    string_index: i32,
+    fret: i32,
+) -> bool {
+    // 换把以后手指已经离开了原来的位置，这根弦弹了新的音时之前的音也已经停止
+    if hand.hand_position != hand_position || hand.sounding_frets().contains_key(&string_index) {
+        return false;
+    }
+    let Some(finger) = hand
+        .fingers
+        .iter_mut()
+        .find(|finger| finger.finger_index == finger_index)
+    else {
+        return false;
+    };
+    if finger.press != PressState::Open {
+        return false;
+    }
+    finger.string_index = string_index;
+    finger.fret = fret;
+    finger.press = PressState::Keep;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+    use crate::hand::hand_profile::HandProfile;
+    use crate::hand::left_finger::LeftFinger;
+
+    #[test]
+    fn test_sustained_note_keeps_finger_and_short_note_releases() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let strings = &guitar.guitar_strings;
+        let hand = |fingers: Vec<LeftFinger>| {
+            let mut hand = LeftHand::with_profile(fingers, false, HandProfile::default());
+            hand.hand_position = 2;
+            hand
+        };
+        // 第一个手型食指按5弦2品，第二个手型无名指按3弦4品，食指空闲
+        let first = hand(vec![
+            LeftFinger::new(1, &strings[4], 2, "Pressed"),
+            LeftFinger::new(2, &strings[4], 3, "Open"),
+            LeftFinger::new(3, &strings[4], 4, "Open"),
+            LeftFinger::new(4, &strings[4], 5, "Open"),
+        ]);
+        let second = hand(vec![
+            LeftFinger::new(1, &strings[2], 2, "Open"),
+            LeftFinger::new(2, &strings[2], 3, "Open"),
+            LeftFinger::new(3, &strings[2], 4, "Pressed"),
+            LeftFinger::new(4, &strings[2], 5, "Open"),
+        ]);
+        let mut recorder = LeftHandRecorder::new();
+        recorder.add_hand_pose(first.clone(), 0.0, 0.0);
+        recorder.add_hand_pose(first, 0.0, 0.0);
+        recorder.add_hand_pose(second, 0.0, 480.0);
+
+        let span = |real_tick: f64, end_tick: f64, string_index: usize, fret: i32| NoteSpan {
+            real_tick,
+            end_tick,
+            note: strings[string_index].get_note_by_fret(fret).unwrap(),
+            string_index: -1,
+        };
+        // 5弦的音一直持续到第二个音之后，3弦的音是短促的断奏
+        let note_spans = [span(0.0, 960.0, 4, 2), span(480.0, 600.0, 2, 4)];
+
+        let summary = apply_note_durations(&mut recorder, &note_spans, &guitar);
+        assert_eq!(
+            summary,
+            HoldSummary {
+                anchored: 1,
+                released: 2
+            }
+        );
+        let index_finger = &recorder.hand_pose_list[2].fingers[0];
+        assert_eq!(index_finger.press, PressState::Keep);
+        assert_eq!((index_finger.string_index, index_finger.fret), (4, 2));
+        // 保持按弦的手指不会让这根弦重新发声
+        assert_eq!(recorder.hand_pose_list[2].sounding_notes(&guitar).len(), 1);
+        assert!(recorder.releases[1].is_empty());
+        let mut released: Vec<(i32, f64)> = recorder.releases[2]
+            .iter()
+            .map(|release| (release.finger_index, release.real_tick))
+            .collect();
+        released.sort_by_key(|&(finger_index, _)| finger_index);
+        assert_eq!(released, vec![(1, 960.0), (3, 600.0)]);
+    }
+
+    #[test]
+    fn test_octave_shifted_note_keeps_its_duration() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let strings = &guitar.guitar_strings;
+        let (min_note, _) = guitar.get_note_range();
+        let mut hand = LeftHand::with_profile(
+            vec![
+                LeftFinger::new(1, &strings[5], 2, "Pressed"),
+                LeftFinger::new(2, &strings[5], 3, "Open"),
+                LeftFinger::new(3, &strings[5], 4, "Open"),
+                LeftFinger::new(4, &strings[5], 5, "Open"),
+            ],
+            false,
+            HandProfile::default(),
+        );
+        hand.hand_position = 2;
+        let mut recorder = LeftHandRecorder::new();
+        recorder.add_hand_pose(hand.clone(), 0.0, 0.0);
+        recorder.add_hand_pose(hand.clone(), 0.0, 0.0);
+        recorder.add_hand_pose(hand, 0.0, 960.0);
+
+        // MIDI里的音比最低音还低，求解时移高了一个八度，在6弦2品演奏
+        let played = strings[5].get_note_by_fret(2).unwrap();
+        assert!(played - 12 < min_note);
+        let note_spans = [NoteSpan {
+            real_tick: 0.0,
+            end_tick: 240.0,
+            note: played - 12,
+            string_index: -1,
+        }];
+
+        let summary = apply_note_durations(&mut recorder, &note_spans, &guitar);
+        assert_eq!(summary.released, 1);
+        assert_eq!(recorder.releases[1][0].real_tick, 240.0);
+    }
+}
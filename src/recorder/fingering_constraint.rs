@@ -0,0 +1,409 @@
+// FingeringConstraint.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+
+use crate::guitar::guitar_instance::Guitar;
+use crate::hand::left_hand::LeftHand;
+use crate::midi::midi_to_note::{MidiProcessor, NoteInfo, TimeSignatureChange};
+use crate::recorder::left_hand_recorder::LeftHandRecorder;
+
+/// 约束作用的范围，不填end时只作用于start这一个位置
+///
+/// 文件里写成`{"by": "bar", "start": 12}`，by可以是`tick`、`bar`或`note_index`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "by", rename_all = "snake_case")]
+pub enum ConstraintRange {
+    /// 按real_tick，两端都包含
+    Tick {
+        start: f64,
+        #[serde(default)]
+        end: Option<f64>,
+    },
+    /// 按小节，从1开始计数
+    Bar {
+        start: i32,
+        #[serde(default)]
+        end: Option<i32>,
+    },
+    /// 按音符事件在notes_map里的下标，从0开始计数
+    NoteIndex {
+        start: usize,
+        #[serde(default)]
+        end: Option<usize>,
+    },
+}
+
+impl ConstraintRange {
+    fn contains(&self, real_tick: f64, bar: i32, note_index: usize) -> bool {
+        match *self {
+            ConstraintRange::Tick { start, end } => {
+                (start..=end.unwrap_or(start)).contains(&real_tick)
+            }
+            ConstraintRange::Bar { start, end } => (start..=end.unwrap_or(start)).contains(&bar),
+            ConstraintRange::NoteIndex { start, end } => {
+                (start..=end.unwrap_or(start)).contains(&note_index)
+            }
+        }
+    }
+}
+
+/// 编曲者指定的一条按法约束
+///
+/// Params:
+/// - range: 作用范围
+/// - note: 只约束这个音高的音符，不填时约束范围内的所有音符，音域外被移动八度的音符使用移动后的音高
+/// - string_index: 音符必须在这根弦上
+/// - fret: 音符必须在这个品格上，0表示空弦
+/// - finger: 音符必须用这根手指按，1-4，0是拇指，-1是空弦
+/// - barre: true表示必须横按，false表示不能横按
+/// - hand_position: 手必须在这个把位上，也就是食指所在的品格
+/// - penalty: 不满足时增加的熵，不填时是硬约束，不满足的按法直接去掉
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FingeringConstraint {
+    pub range: ConstraintRange,
+    #[serde(default)]
+    pub note: Option<i32>,
+    #[serde(default)]
+    pub string_index: Option<i32>,
+    #[serde(default)]
+    pub fret: Option<i32>,
+    #[serde(default)]
+    pub finger: Option<i32>,
+    #[serde(default)]
+    pub barre: Option<bool>,
+    #[serde(default)]
+    pub hand_position: Option<i32>,
+    #[serde(default)]
+    pub penalty: Option<f64>,
+}
+
+impl FingeringConstraint {
+    /// 判断手型是否满足约束，指定的音符不在这一组音符里时总是满足
+    ///
+    /// # 参数
+    /// * `hand` - 弹出这组音符的手型
+    /// * `notes` - 处理后的音符，用来区分发声的手指和保持不动的手指
+    pub fn is_satisfied_by(&self, hand: &LeftHand, notes: &[i32], guitar: &Guitar) -> bool {
+        // 要约束的音符不在这一组音符里时约束不起作用
+        if self.note.is_some_and(|note| !notes.contains(&note)) {
+            return true;
+        }
+        if self.barre.is_some_and(|barre| barre != hand.use_barre) {
+            return false;
+        }
+        if self
+            .hand_position
+            .is_some_and(|position| position != hand.hand_position)
+        {
+            return false;
+        }
+        if self.string_index.is_none() && self.fret.is_none() && self.finger.is_none() {
+            return true;
+        }
+
+        let targets: Vec<(i32, i32, i32)> = sounding_positions(hand, guitar)
+            .into_iter()
+            .filter(|&(pitch, ..)| notes.contains(&pitch) && self.note.is_none_or(|n| n == pitch))
+            .map(|(_, string_index, fret, finger)| (string_index, fret, finger))
+            .collect();
+
+        // 手型没有弹出要约束的音符，无法满足
+        !targets.is_empty()
+            && targets.iter().all(|&(string_index, fret, finger)| {
+                self.string_index.is_none_or(|s| s == string_index)
+                    && self.fret.is_none_or(|f| f == fret)
+                    && self.finger.is_none_or(|f| f == finger)
+            })
+    }
+
+    pub fn is_hard(&self) -> bool {
+        self.penalty.is_none()
+    }
+
+    /// 约束的文字描述，用于输出报告
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(note) = self.note {
+            parts.push(format!("音符{}", note));
+        }
+        if let Some(string_index) = self.string_index {
+            parts.push(format!("第{}弦", string_index + 1));
+        }
+        if let Some(fret) = self.fret {
+            parts.push(format!("第{}品", fret));
+        }
+        if let Some(finger) = self.finger {
+            parts.push(format!("手指{}", finger));
+        }
+        if let Some(barre) = self.barre {
+            parts.push(if barre { "横按" } else { "不横按" }.to_string());
+        }
+        if let Some(hand_position) = self.hand_position {
+            parts.push(format!("第{}把位", hand_position));
+        }
+        parts.join("，")
+    }
+}
+
+/// 手型里所有发声的位置，返回(音高, 弦, 品格, 手指)
+///
+/// 每根弦只有品格最高的手指发声，发声的位置和LeftHand::sounding_frets一致
+fn sounding_positions(hand: &LeftHand, guitar: &Guitar) -> Vec<(i32, i32, i32, i32)> {
+    hand.sounding_frets()
+        .into_iter()
+        .filter_map(|(string_index, (fret, finger))| {
+            let pitch = guitar
+                .guitar_strings
+                .get(string_index as usize)?
+                .get_note_by_fret(fret)?;
+            Some((pitch, string_index, fret, finger))
+        })
+        .collect()
+}
+
+/// 一组约束对手型增加的熵，违反硬约束时返回None
+pub fn constraint_penalty(
+    constraints: &[&FingeringConstraint],
+    hand: &LeftHand,
+    notes: &[i32],
+    guitar: &Guitar,
+) -> Option<f64> {
+    let mut penalty = 0.0;
+    for constraint in constraints {
+        if constraint.is_satisfied_by(hand, notes, guitar) {
+            continue;
+        }
+        penalty += constraint.penalty?;
+    }
+    Some(penalty)
+}
+
+/// 最终结果中没有满足的约束
+#[derive(Debug, Clone)]
+pub struct UnsatisfiedConstraint {
+    pub constraint_index: usize,
+    pub real_tick: f64,
+}
+
+/// 按法约束文件
+///
+/// 读取后需要调用bind_events，把按小节和音符下标写的范围换算成real_tick
+#[derive(Clone, Debug, Default)]
+pub struct FingeringConstraints {
+    pub constraints: Vec<FingeringConstraint>,
+    /// 每个音符事件的real_tick对应的约束下标
+    by_tick: HashMap<u64, Vec<usize>>,
+}
+
+impl FingeringConstraints {
+    pub fn new(constraints: Vec<FingeringConstraint>) -> Self {
+        FingeringConstraints {
+            constraints,
+            by_tick: HashMap::new(),
+        }
+    }
+
+    /// 从JSON文件读取约束，文件内容是一个FingeringConstraint数组
+    pub fn load_from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = File::open(path)?;
+        let constraints: Vec<FingeringConstraint> = serde_json::from_reader(BufReader::new(file))?;
+        Ok(Self::new(constraints))
+    }
+
+    pub fn len(&self) -> usize {
+        self.constraints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.constraints.is_empty()
+    }
+
+    /// 找出每条约束作用的音符事件，返回没有作用到任何事件的约束下标
+    pub fn bind_events(
+        &mut self,
+        notes_map: &[NoteInfo],
+        time_signatures: &[TimeSignatureChange],
+        ticks_per_beat: u16,
+    ) -> Vec<usize> {
+        let midi_processor = MidiProcessor::new();
+        let mut used = vec![false; self.constraints.len()];
+        self.by_tick.clear();
+
+        for (note_index, note_info) in notes_map.iter().enumerate() {
+            let (bar, _) = midi_processor.calculate_bar_position(
+                time_signatures,
+                ticks_per_beat,
+                note_info.real_tick,
+            );
+            for (index, constraint) in self.constraints.iter().enumerate() {
+                if constraint
+                    .range
+                    .contains(note_info.real_tick, bar, note_index)
+                {
+                    used[index] = true;
+                    self.by_tick
+                        .entry(note_info.real_tick.to_bits())
+                        .or_default()
+                        .push(index);
+                }
+            }
+        }
+
+        (0..used.len()).filter(|&index| !used[index]).collect()
+    }
+
+    /// 作用于real_tick这个音符事件的约束
+    pub fn constraints_at(&self, real_tick: f64) -> Vec<&FingeringConstraint> {
+        self.by_tick
+            .get(&real_tick.to_bits())
+            .map(|indices| {
+                indices
+                    .iter()
+                    .map(|&index| &self.constraints[index])
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// 检查左手记录中没有满足的约束
+    ///
+    /// # 参数
+    /// * `notes_map` - 求解时使用的处理过的音符事件，约束按求解器看到的音符判断
+    pub fn find_unsatisfied(
+        &self,
+        recorder: &LeftHandRecorder,
+        notes_map: &[NoteInfo],
+        guitar: &Guitar,
+    ) -> Vec<UnsatisfiedConstraint> {
+        let notes_by_tick: HashMap<u64, &NoteInfo> = notes_map
+            .iter()
+            .map(|note_info| (note_info.real_tick.to_bits(), note_info))
+            .collect();
+
+        let mut result = Vec::new();
+        // 第一个手型是初始手型
+        for (hand, &real_tick) in recorder
+            .hand_pose_list
+            .iter()
+            .zip(&recorder.real_ticks)
+            .skip(1)
+        {
+            let (Some(indices), Some(note_info)) = (
+                self.by_tick.get(&real_tick.to_bits()),
+                notes_by_tick.get(&real_tick.to_bits()),
+            ) else {
+                continue;
+            };
+            for &constraint_index in indices {
+                if !self.constraints[constraint_index].is_satisfied_by(
+                    hand,
+                    &note_info.notes,
+                    guitar,
+                ) {
+                    result.push(UnsatisfiedConstraint {
+                        constraint_index,
+                        real_tick,
+                    });
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+    use crate::hand::hand_profile::HandProfile;
+    use crate::hand::left_finger::LeftFinger;
+
+    fn constraint(note: Option<i32>, string_index: i32) -> FingeringConstraint {
+        FingeringConstraint {
+            range: ConstraintRange::NoteIndex {
+                start: 0,
+                end: None,
+            },
+            note,
+            string_index: Some(string_index),
+            fret: None,
+            finger: None,
+            barre: None,
+            hand_position: None,
+            penalty: None,
+        }
+    }
+
+    #[test]
+    fn test_note_scoped_constraint_only_applies_to_its_note() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let strings = &guitar.guitar_strings;
+        // 食指在第二弦5品弹E4
+        let hand = LeftHand::with_profile(
+            vec![
+                LeftFinger::new(1, &strings[1], 5, "Pressed"),
+                LeftFinger::new(2, &strings[1], 6, "Open"),
+                LeftFinger::new(3, &strings[1], 7, "Open"),
+                LeftFinger::new(4, &strings[1], 8, "Open"),
+            ],
+            false,
+            HandProfile::default(),
+        );
+
+        assert!(constraint(Some(64), 1).is_satisfied_by(&hand, &[64], &guitar));
+        assert!(!constraint(Some(64), 0).is_satisfied_by(&hand, &[64], &guitar));
+        // 约束的音符不在这个事件里，约束不起作用
+        assert!(constraint(Some(67), 0).is_satisfied_by(&hand, &[64], &guitar));
+        // 不指定音符时约束这个事件的所有音符
+        assert!(!constraint(None, 0).is_satisfied_by(&hand, &[64], &guitar));
+        // 手型没有弹出要约束的音符
+        assert!(!constraint(Some(67), 0).is_satisfied_by(&hand, &[67], &guitar));
+
+        let soft = FingeringConstraint {
+            penalty: Some(2.0),
+            ..constraint(Some(64), 0)
+        };
+        let vacuous = constraint(Some(67), 0);
+        assert_eq!(
+            constraint_penalty(&[&soft, &vacuous], &hand, &[64], &guitar),
+            Some(2.0)
+        );
+        assert_eq!(
+            constraint_penalty(&[&constraint(Some(64), 0)], &hand, &[64], &guitar),
+            None
+        );
+    }
+
+    #[test]
+    fn test_constraints_only_see_sounding_positions() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let strings = &guitar.guitar_strings;
+        // 第5品横按，无名指在五弦第7品，小指在二三弦第7品部分横按
+        let hand = LeftHand::with_profile(
+            vec![
+                LeftFinger::new(1, &strings[5], 5, "Barre"),
+                LeftFinger::new(2, &strings[4], 6, "Open"),
+                LeftFinger::new(3, &strings[4], 7, "Pressed"),
+                LeftFinger::new(4, &strings[2], 7, "PartialBarre2Strings"),
+            ],
+            true,
+            HandProfile::default(),
+        );
+        let notes = [45, 52, 55, 62, 66, 69];
+
+        // 部分横按只覆盖二三弦，一弦仍然是横按的第5品
+        assert!(constraint(Some(69), 0).is_satisfied_by(&hand, &notes, &guitar));
+        assert!(!constraint(Some(71), 0).is_satisfied_by(&hand, &[71], &guitar));
+        assert!(constraint(Some(66), 1).is_satisfied_by(&hand, &notes, &guitar));
+        // 五弦上无名指的品格更高，横按的第5品不发声
+        assert!(!constraint(Some(50), 4).is_satisfied_by(&hand, &[50], &guitar));
+        let ring_finger = FingeringConstraint {
+            finger: Some(3),
+            ..constraint(Some(52), 4)
+        };
+        assert!(ring_finger.is_satisfied_by(&hand, &notes, &guitar));
+    }
+}
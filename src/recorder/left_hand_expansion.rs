@@ -0,0 +1,492 @@
+// left_hand_expansion.rs
+
+use crate::guitar::guitar_instance::Guitar;
+use crate::hand::chord_shape::ChordShapeLibrary;
+use crate::hand::hand_profile::HandProfile;
+use crate::hand::left_hand::{HandPosition, LeftHand};
+use crate::hand::left_hand_cost::{LeftHandCostModel, LeftHandCostTerms};
+use crate::midi::midi_to_note::{NoteInfo, TempoMap};
+use crate::recorder::fingering_constraint::{
+    FingeringConstraint, FingeringConstraints, constraint_penalty,
+};
+use crate::recorder::position_plan::PositionPlan;
+use crate::recorder::recorder_pool::enumerate_finger_positions;
+
+/// 展开左手按法的规则
+///
+/// 束搜索、动态规划和求解追踪都通过这里生成候选按法，
+/// 所以追踪里比较的按法和求解时比较的按法完全相同
+pub struct ExpansionRules<'a> {
+    pub guitar: &'a Guitar,
+    pub cost_model: &'a dyn LeftHandCostModel,
+    pub tempo_map: Option<&'a TempoMap>,
+    pub hand_profile: &'a HandProfile,
+    pub chord_shape_library: Option<&'a ChordShapeLibrary>,
+    pub chord_shape_bonus: f64,
+    pub chord_shapes_compete: bool,
+    pub fingering_constraints: &'a FingeringConstraints,
+    pub position_plan: &'a PositionPlan,
+}
+
+/// 一个事件为了找到按法放宽的条件，追踪和回溯时用同样的条件重新展开
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Relaxation {
+    /// 指型库里匹配的按法都无法过渡，改用通用的按法枚举
+    pub generic_fingerings: bool,
+    /// 规划的把位附近没有能过渡的按法，改用所有按法
+    pub ignore_plan: bool,
+    /// 没有按法能满足而放弃的硬约束，是constraints_at返回的列表中的下标，
+    /// 其它的约束和所有软约束的惩罚仍然有效
+    pub dropped_constraints: Vec<usize>,
+}
+
+/// 一个事件的所有候选按法，指型库按法在前，通用按法在后
+pub struct EventFingerings<'a> {
+    pub note_info: &'a NoteInfo,
+    pub positions: Vec<HandPosition>,
+    /// positions中前shape_count个按法来自指型库
+    shape_count: usize,
+    /// 作用于这个事件的约束
+    constraints: Vec<&'a FingeringConstraint>,
+    /// 放弃的硬约束在constraints中的下标
+    dropped_constraints: Vec<usize>,
+}
+
+impl EventFingerings<'_> {
+    /// 是否存在按法，不考虑能否从之前的手型过渡
+    pub fn has_positions(&self) -> bool {
+        !self.positions.is_empty()
+    }
+
+    /// 是否只包含指型库里的按法，这时还可以退回到通用的按法枚举
+    fn shapes_only(&self) -> bool {
+        self.shape_count > 0 && self.shape_count == self.positions.len()
+    }
+}
+
+/// 从一个手型过渡到一个候选按法得到的新手型
+///
+/// Params:
+/// - hand: 新的手型
+/// - candidate: 按法在EventFingerings::positions中的下标
+/// - entropy: 消耗模型计算出来的熵
+/// - bonus: 使用指型库按法减少的熵
+/// - penalty: 没有满足软约束增加的熵
+#[derive(Debug, Clone)]
+pub struct Expansion {
+    pub hand: LeftHand,
+    pub candidate: usize,
+    pub entropy: f64,
+    pub bonus: f64,
+    pub penalty: f64,
+}
+
+impl Expansion {
+    /// 这一步增加的熵，减去奖励后不小于0，再加上约束的惩罚
+    pub fn cost(&self) -> f64 {
+        (self.entropy - self.bonus).max(0.0) + self.penalty
+    }
+}
+
+/// 一个事件展开的结果
+///
+/// Params:
+/// - expansions: 每个之前的手型展开得到的新手型，顺序与传入的手型相同
+/// - relaxation: 展开时放宽的条件
+/// - has_positions: 这个事件是否存在按法，不考虑能否从之前的手型过渡
+pub struct EventExpansion {
+    pub expansions: Vec<Vec<Expansion>>,
+    pub relaxation: Relaxation,
+    pub has_positions: bool,
+}
+
+impl ExpansionRules<'_> {
+    /// 两个real_tick之间的秒数，没有设置速度表时为None
+    pub fn time_gap(&self, from_tick: f64, to_tick: f64) -> Option<f64> {
+        self.tempo_map
+            .map(|tempo_map| tempo_map.seconds_between(from_tick, to_tick))
+    }
+
+    /// 在放宽的条件下生成一个事件的候选按法
+    ///
+    /// 优先只使用指型库里匹配的按法，没有匹配的指型或者放宽了条件时才枚举通用按法，
+    /// 设置了chord_shapes_compete时两者一起参与比较。设置了把位规划时只保留规划的把位附近的按法，
+    /// 已经知道弦的音符只能用指定的位置，不使用指型库
+    pub fn event_fingerings<'e>(
+        &'e self,
+        note_info: &'e NoteInfo,
+        relaxation: &Relaxation,
+    ) -> EventFingerings<'e> {
+        let real_tick = note_info.real_tick;
+        let restrict = |hand_positions: Vec<HandPosition>| {
+            if relaxation.ignore_plan {
+                hand_positions
+            } else {
+                self.position_plan.restrict(real_tick, hand_positions)
+            }
+        };
+        let shape_positions = match self.chord_shape_library {
+            Some(library) if note_info.string_indices.is_empty() => {
+                restrict(library.match_notes(&note_info.notes, self.guitar))
+            }
+            _ => Vec::new(),
+        };
+        let generic_positions = if shape_positions.is_empty()
+            || self.chord_shapes_compete
+            || relaxation.generic_fingerings
+        {
+            restrict(enumerate_finger_positions(
+                &note_info.notes,
+                &note_info.string_indices,
+                self.guitar,
+                self.hand_profile,
+            ))
+        } else {
+            Vec::new()
+        };
+        let constraints = self.fingering_constraints.constraints_at(real_tick);
+
+        let shape_count = shape_positions.len();
+        let mut positions = shape_positions;
+        positions.extend(generic_positions);
+        EventFingerings {
+            note_info,
+            positions,
+            shape_count,
+            constraints,
+            dropped_constraints: relaxation.dropped_constraints.clone(),
+        }
+    }
+
+    /// 从一个手型过渡到第candidate个按法，无法过渡或者违反硬约束时返回None
+    pub fn expand_candidate(
+        &self,
+        hand: &LeftHand,
+        event: &EventFingerings,
+        candidate: usize,
+        time_gap: Option<f64>,
+    ) -> Option<Expansion> {
+        let (new_fingers, entropy, use_barre) = hand.generate_next_hands_with_cost_model(
+            self.guitar,
+            &event.positions.get(candidate)?.positions,
+            self.cost_model,
+            time_gap,
+        )?;
+        let new_hand = hand.next_hand(new_fingers, use_barre);
+        let constraints: Vec<&FingeringConstraint> = event
+            .constraints
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !event.dropped_constraints.contains(index))
+            .map(|(_, &constraint)| constraint)
+            .collect();
+        let penalty =
+            constraint_penalty(&constraints, &new_hand, &event.note_info.notes, self.guitar)?;
+        Some(Expansion {
+            hand: new_hand,
+            candidate,
+            entropy,
+            bonus: if candidate < event.shape_count {
+                self.chord_shape_bonus
+            } else {
+                0.0
+            },
+            penalty,
+        })
+    }
+
+    /// 从一个手型过渡到所有候选按法，相同的手型可能出现多次，由调用者保留熵最小的
+    pub fn expand_hand(
+        &self,
+        hand: &LeftHand,
+        event: &EventFingerings,
+        time_gap: Option<f64>,
+    ) -> Vec<Expansion> {
+        (0..event.positions.len())
+            .filter_map(|candidate| self.expand_candidate(hand, event, candidate, time_gap))
+            .collect()
+    }
+
+    /// 按expansion的同样条件计算消耗的各项组成，用于输出追踪
+    pub fn cost_terms(
+        &self,
+        hand: &LeftHand,
+        event: &EventFingerings,
+        expansion: &Expansion,
+        time_gap: Option<f64>,
+    ) -> LeftHandCostTerms {
+        hand.generate_next_hands_with_cost_terms(
+            self.guitar,
+            &event.positions[expansion.candidate].positions,
+            self.cost_model,
+            time_gap,
+        )
+        .map(|(_, cost_terms, _)| cost_terms)
+        .unwrap_or_default()
+    }
+
+    /// 展开一个事件，没有任何手型能过渡时逐步放宽条件
+    ///
+    /// 先只用规划的把位附近的按法，没有能过渡的按法时改用所有按法，仍然没有时才放宽约束：
+    /// 只放弃没有任何按法能满足的硬约束，剩下的硬约束仍然无法同时满足时才放弃所有硬约束，
+    /// 软约束的惩罚始终保留，没有满足的约束在最后统一报告
+    ///
+    /// # 参数
+    /// * `expand_all` - 用候选按法展开所有之前的手型，返回每个手型的展开结果
+    pub fn expand_event<F>(&self, note_info: &NoteInfo, expand_all: F) -> EventExpansion
+    where
+        F: Fn(&EventFingerings) -> Vec<Vec<Expansion>>,
+    {
+        let any_expansion =
+            |expansions: &[Vec<Expansion>]| expansions.iter().any(|e| !e.is_empty());
+        // 指型库里的按法都无法过渡时，才退回到通用的按法枚举
+        let expand_with_fallback = |mut relaxation: Relaxation| {
+            let mut event = self.event_fingerings(note_info, &relaxation);
+            let mut expansions = expand_all(&event);
+            if !any_expansion(&expansions) && event.shapes_only() {
+                relaxation.generic_fingerings = true;
+                event = self.event_fingerings(note_info, &relaxation);
+                expansions = expand_all(&event);
+            }
+            (event, expansions, relaxation)
+        };
+        let (mut event, mut expansions, mut relaxation) =
+            expand_with_fallback(Relaxation::default());
+        if !any_expansion(&expansions)
+            && self
+                .position_plan
+                .position_at(note_info.real_tick)
+                .is_some()
+        {
+            (event, expansions, relaxation) = expand_with_fallback(Relaxation {
+                ignore_plan: true,
+                ..Relaxation::default()
+            });
+        }
+        let has_positions = event.has_positions();
+        let hard_constraints: Vec<usize> = (0..event.constraints.len())
+            .filter(|&index| event.constraints[index].is_hard())
+            .collect();
+        if any_expansion(&expansions) || hard_constraints.is_empty() {
+            return EventExpansion {
+                expansions,
+                relaxation,
+                has_positions,
+            };
+        }
+
+        // 先放弃所有硬约束展开，再找出没有任何按法能满足的硬约束
+        let all_dropped = Relaxation {
+            dropped_constraints: hard_constraints.clone(),
+            ..relaxation.clone()
+        };
+        let mut expansions = expand_all(&self.event_fingerings(note_info, &all_dropped));
+        let satisfied_by_any = |index: usize| {
+            expansions.iter().flatten().any(|expansion| {
+                event.constraints[index].is_satisfied_by(
+                    &expansion.hand,
+                    &note_info.notes,
+                    self.guitar,
+                )
+            })
+        };
+        let unsatisfiable: Vec<usize> = hard_constraints
+            .iter()
+            .copied()
+            .filter(|&index| !satisfied_by_any(index))
+            .collect();
+        let kept: Vec<usize> = hard_constraints
+            .iter()
+            .copied()
+            .filter(|index| !unsatisfiable.contains(index))
+            .collect();
+        let satisfies_kept = |expansion: &Expansion| {
+            kept.iter().all(|&index| {
+                event.constraints[index].is_satisfied_by(
+                    &expansion.hand,
+                    &note_info.notes,
+                    self.guitar,
+                )
+            })
+        };
+
+        let relaxation = if expansions.iter().flatten().any(satisfies_kept) {
+            for expansions in expansions.iter_mut() {
+                expansions.retain(satisfies_kept);
+            }
+            Relaxation {
+                dropped_constraints: unsatisfiable,
+                ..relaxation
+            }
+        } else {
+            all_dropped
+        };
+        EventExpansion {
+            expansions,
+            relaxation,
+            has_positions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+    use crate::hand::left_finger::LeftFinger;
+    use crate::hand::left_hand_cost::WeightedLeftHandCostModel;
+    use crate::recorder::fingering_constraint::ConstraintRange;
+
+    fn pin(note: i32, string_index: i32, penalty: Option<f64>) -> FingeringConstraint {
+        FingeringConstraint {
+            range: ConstraintRange::NoteIndex {
+                start: 0,
+                end: None,
+            },
+            note: Some(note),
+            string_index: Some(string_index),
+            fret: None,
+            finger: None,
+            barre: None,
+            hand_position: None,
+            penalty,
+        }
+    }
+
+    #[test]
+    fn test_only_unsatisfiable_hard_constraints_are_dropped() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let init_string = &guitar.guitar_strings[2];
+        let init_hand = LeftHand::with_profile(
+            (1..5)
+                .map(|i| LeftFinger::new(i, init_string, i, "Pressed"))
+                .collect(),
+            false,
+            HandProfile::default(),
+        );
+        let notes_map = vec![NoteInfo {
+            notes: vec![57, 64],
+            real_tick: 0.0,
+            string_indices: Vec::new(),
+        }];
+        // A3在第一弦上弹不出来，E4固定在第二弦上，软约束希望A3在第三弦上
+        let mut constraints = FingeringConstraints::new(vec![
+            pin(57, 0, None),
+            pin(64, 1, None),
+            pin(57, 2, Some(3.0)),
+        ]);
+        assert!(constraints.bind_events(&notes_map, &[], 480).is_empty());
+        let cost_model = WeightedLeftHandCostModel::default();
+        let plan = PositionPlan::default();
+        let rules = ExpansionRules {
+            guitar: &guitar,
+            cost_model: &cost_model,
+            tempo_map: None,
+            hand_profile: &HandProfile::default(),
+            chord_shape_library: None,
+            chord_shape_bonus: 0.0,
+            chord_shapes_compete: false,
+            fingering_constraints: &constraints,
+            position_plan: &plan,
+        };
+
+        let result = rules.expand_event(&notes_map[0], |event| {
+            vec![rules.expand_hand(&init_hand, event, None)]
+        });
+        assert_eq!(result.relaxation.dropped_constraints, vec![0]);
+        let expansions = &result.expansions[0];
+        assert!(!expansions.is_empty());
+        let notes = &notes_map[0].notes;
+        for expansion in expansions {
+            // 没有放弃的硬约束仍然满足，软约束的惩罚仍然计算
+            assert!(constraints.constraints[1].is_satisfied_by(&expansion.hand, notes, &guitar));
+            let soft_satisfied =
+                constraints.constraints[2].is_satisfied_by(&expansion.hand, notes, &guitar);
+            assert_eq!(expansion.penalty, if soft_satisfied { 0.0 } else { 3.0 });
+        }
+        assert!(expansions.iter().any(|expansion| expansion.penalty > 0.0));
+
+        // 追踪和回溯使用同样的放宽条件得到同样的候选
+        let event = rules.event_fingerings(&notes_map[0], &result.relaxation);
+        assert_eq!(
+            rules.expand_hand(&init_hand, &event, None).len(),
+            expansions.len()
+        );
+    }
+
+    #[test]
+    fn test_generic_fingerings_only_when_no_shape_transitions() {
+        use crate::hand::chord_shape::{ChordShape, ShapePosition};
+
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let init_string = &guitar.guitar_strings[2];
+        let init_hand = LeftHand::with_profile(
+            (1..5)
+                .map(|i| LeftFinger::new(i, init_string, i, "Pressed"))
+                .collect(),
+            false,
+            HandProfile::default(),
+        );
+        let library = ChordShapeLibrary::new(vec![ChordShape {
+            name: "C高把位".to_string(),
+            category: "test".to_string(),
+            movable: false,
+            positions: vec![
+                ShapePosition {
+                    string_index: 5,
+                    fret: 8,
+                    finger: 4,
+                },
+                ShapePosition {
+                    string_index: 4,
+                    fret: 7,
+                    finger: 3,
+                },
+                ShapePosition {
+                    string_index: 3,
+                    fret: 5,
+                    finger: 1,
+                },
+            ],
+        }]);
+        let notes_map = vec![NoteInfo {
+            notes: vec![48, 52, 55],
+            real_tick: 0.0,
+            string_indices: Vec::new(),
+        }];
+        let cost_model = WeightedLeftHandCostModel::default();
+        let plan = PositionPlan::default();
+        let expand = |constraints: &FingeringConstraints| {
+            let rules = ExpansionRules {
+                guitar: &guitar,
+                cost_model: &cost_model,
+                tempo_map: None,
+                hand_profile: &HandProfile::default(),
+                chord_shape_library: Some(&library),
+                chord_shape_bonus: 0.0,
+                chord_shapes_compete: false,
+                fingering_constraints: constraints,
+                position_plan: &plan,
+            };
+            let event = rules.event_fingerings(&notes_map[0], &Relaxation::default());
+            let result = rules.expand_event(&notes_map[0], |event| {
+                vec![rules.expand_hand(&init_hand, event, None)]
+            });
+            (event.positions.len(), result)
+        };
+
+        // 指型能过渡时不枚举通用按法
+        let (shape_only_count, result) = expand(&FingeringConstraints::default());
+        assert_eq!(shape_only_count, 1);
+        assert_eq!(result.relaxation, Relaxation::default());
+        assert_eq!(result.expansions[0].len(), 1);
+
+        // C3固定在第五弦上，指型违反这个硬约束，退回到通用按法而不是放弃约束
+        let mut constraints = FingeringConstraints::new(vec![pin(48, 4, None)]);
+        assert!(constraints.bind_events(&notes_map, &[], 480).is_empty());
+        let (_, result) = expand(&constraints);
+        assert!(result.relaxation.generic_fingerings);
+        assert!(result.relaxation.dropped_constraints.is_empty());
+        assert!(!result.expansions[0].is_empty());
+    }
+}
@@ -0,0 +1,182 @@
+// LeftHandMute.rs
+
+use std::collections::HashMap;
+
+use crate::guitar::guitar_instance::Guitar;
+use crate::hand::left_finger::PressState;
+use crate::hand::left_hand::LeftHand;
+use crate::midi::midi_to_note::MutedNote;
+use crate::recorder::left_hand_recorder::LeftHandRecorder;
+
+/// 把求解出的手型里演奏闷音的手指改成轻触弦，返回没有办法闷音的音符
+///
+/// 按法求解时闷音和普通音符一样需要手指的位置，求解完成后再把对应的手指改成Mute，
+/// 这样闷音仍然会由右手拨弦，但是弦不会振动
+///
+/// # 参数
+/// * `muted_notes` - 从MIDI读取的闷音
+pub fn apply_muted_notes(
+    recorder: &mut LeftHandRecorder,
+    muted_notes: &[MutedNote],
+    guitar: &Guitar,
+) -> Vec<MutedNote> {
+    let pose_indices: HashMap<u64, usize> = recorder
+        .real_ticks
+        .iter()
+        .enumerate()
+        // 第一个手型是初始手型
+        .skip(1)
+        .map(|(index, tick)| (tick.to_bits(), index))
+        .collect();
+
+    let mut unmuted = Vec::new();
+    for muted_note in muted_notes {
+        let muted = pose_indices
+            .get(&muted_note.real_tick.to_bits())
+            .is_some_and(|&index| {
+                mute_note(&mut recorder.hand_pose_list[index], muted_note, guitar)
+            });
+        if !muted {
+            unmuted.push(*muted_note);
+        }
+    }
+    unmuted
+}
+
+/// 把手型里发出这个音的手指改成轻触弦
+///
+/// 按弦的手指直接改成轻触；空弦音需要一个没有按弦的手指放到这根弦上，
+/// 没有空闲的手指时只标记空弦，弦仍然不会振动。横按发出的音没有办法单独闷掉，返回false
+fn mute_note(hand: &mut LeftHand, muted_note: &MutedNote, guitar: &Guitar) -> bool {
+    let plays_note = |string_index: i32, fret: i32| {
+        (muted_note.string_index < 0 || muted_note.string_index == string_index)
+            && guitar
+                .guitar_strings
+                .get(string_index as usize)
+                .and_then(|string| string.get_note_by_fret(fret))
+                == Some(muted_note.note)
+    };
+
+    // 同一根弦上只有品格最高的手指发声
+    let highest_fret = |hand: &LeftHand, string_index: i32| {
+        hand.fingers
+            .iter()
+            .filter(|finger| finger.finger_index >= 0 && finger.string_index == string_index)
+            .filter(|finger| matches!(finger.press, PressState::Pressed | PressState::Mute))
+            .map(|finger| finger.fret)
+            .max()
+    };
+    let pressed = (0..hand.fingers.len()).find(|&index| {
+        let finger = &hand.fingers[index];
+        finger.finger_index >= 0
+            && finger.press == PressState::Pressed
+            && plays_note(finger.string_index, finger.fret)
+            && highest_fret(hand, finger.string_index) == Some(finger.fret)
+    });
+    if let Some(index) = pressed {
+        hand.fingers[index].press = PressState::Mute;
+        return true;
+    }
+
+    let Some(open_index) = hand.fingers.iter().position(|finger| {
+        finger.finger_index == -1
+            && finger.press != PressState::Mute
+            && plays_note(finger.string_index, 0)
+    }) else {
+        return false;
+    };
+    let string_index = hand.fingers[open_index].string_index;
+    hand.fingers[open_index].press = PressState::Mute;
+
+    // 找一个空闲的手指轻触这根弦，优先使用离食指远的手指，不挡住其它按弦的手指
+    let hand_position = hand.hand_position;
+    if let Some(finger) = hand
+        .fingers
+        .iter_mut()
+        .filter(|finger| (1..=4).contains(&finger.finger_index))
+        .filter(|finger| finger.press == PressState::Open)
+        .max_by_key(|finger| finger.finger_index)
+    {
+        finger.string_index = string_index;
+        finger.fret = (hand_position + finger.finger_index - 1).max(1);
+        finger.press = PressState::Mute;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+    use crate::hand::hand_profile::HandProfile;
+    use crate::hand::left_finger::LeftFinger;
+
+    #[test]
+    fn test_muted_notes_are_not_sounding() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let strings = &guitar.guitar_strings;
+        // 食指按3弦2品，5弦是空弦音，其它手指抬起
+        let fingers = vec![
+            LeftFinger::new(1, &strings[2], 2, "Pressed"),
+            LeftFinger::new(2, &strings[2], 3, "Open"),
+            LeftFinger::new(3, &strings[2], 4, "Open"),
+            LeftFinger::new(4, &strings[2], 5, "Open"),
+            LeftFinger::new(-1, &strings[4], 0, "Open"),
+        ];
+        let hand = LeftHand::with_profile(fingers, false, HandProfile::default());
+        let pressed_note = strings[2].get_note_by_fret(2).unwrap();
+        let open_note = strings[4].get_note_by_fret(0).unwrap();
+        assert_eq!(hand.sounding_notes(&guitar).len(), 2);
+
+        let mut recorder = LeftHandRecorder::new();
+        recorder.add_hand_pose(hand.clone(), 0.0, 0.0);
+        recorder.add_hand_pose(hand, 1.0, 480.0);
+        let muted_notes = [
+            MutedNote {
+                real_tick: 480.0,
+                note: pressed_note,
+                string_index: -1,
+            },
+            MutedNote {
+                real_tick: 480.0,
+                note: open_note,
+                string_index: 4,
+            },
+            // 这个音没有手指在按
+            MutedNote {
+                real_tick: 480.0,
+                note: pressed_note + 1,
+                string_index: -1,
+            },
+        ];
+
+        let unmuted = apply_muted_notes(&mut recorder, &muted_notes, &guitar);
+        assert_eq!(unmuted, vec![muted_notes[2]]);
+        let muted_hand = &recorder.hand_pose_list[1];
+        assert!(muted_hand.sounding_notes(&guitar).is_empty());
+        // 小指放到空弦上制音
+        assert!(muted_hand.fingers.iter().any(|finger| {
+            finger.finger_index == 4 && finger.string_index == 4 && finger.press == PressState::Mute
+        }));
+        // 初始手型不受影响
+        assert_eq!(recorder.hand_pose_list[0].sounding_notes(&guitar).len(), 2);
+    }
+
+    #[test]
+    fn test_mute_finger_silences_lower_pressed_finger() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let strings = &guitar.guitar_strings;
+        // 食指按3弦2品，中指在同一根弦的3品轻触，食指的音也不会发声
+        let fingers = vec![
+            LeftFinger::new(1, &strings[2], 2, "Pressed"),
+            LeftFinger::new(2, &strings[2], 3, "Mute"),
+            LeftFinger::new(3, &strings[1], 4, "Pressed"),
+            LeftFinger::new(4, &strings[1], 5, "Open"),
+        ];
+        let hand = LeftHand::with_profile(fingers, false, HandProfile::default());
+        assert_eq!(
+            hand.sounding_notes(&guitar),
+            vec![strings[1].get_note_by_fret(4).unwrap()]
+        );
+    }
+}
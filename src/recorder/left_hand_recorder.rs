@@ -1,26 +1,45 @@
 // left_hand_recorder.rs
 use serde::{Deserialize, Serialize};
 use serde_json;
+use std::collections::HashMap;
 use std::fs::File;
 use std::vec::Vec;
 
 // 假设已存在的模块和结构体
+use crate::guitar::chord_recognition::{ChordName, identify_chord};
+use crate::guitar::guitar_instance::Guitar;
+use crate::hand::left_finger::LeftFinger;
 use crate::hand::left_hand::LeftHand;
-use crate::midi::midi_to_note::{MidiProcessor, TempoChange};
+use crate::midi::midi_to_note::{MidiProcessor, NoteInfo, NoteSpan, TempoChange, TempoMap};
+use crate::recorder::finger_hold::FingerRelease;
+use crate::recorder::legato::{LegatoInfo, detect_legato};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FingerInfo {
     pub string_index: i32,
     pub fret: i32,
     pub press: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RecordedFinger {
     pub finger_index: i32,
     pub finger_info: FingerInfo,
 }
 
+impl RecordedFinger {
+    pub fn from_finger(finger: &LeftFinger) -> Self {
+        RecordedFinger {
+            finger_index: finger.finger_index,
+            finger_info: FingerInfo {
+                string_index: finger.string_index,
+                fret: finger.fret,
+                press: format!("{:?}", finger.press),
+            },
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RecordedLeftHand {
     pub real_tick: f64,
@@ -28,13 +47,37 @@ pub struct RecordedLeftHand {
     pub left_hand: Vec<RecordedFinger>,
     pub use_barre: bool,
     pub hand_position: i32,
+    #[serde(default)]
+    pub chord: Option<ChordName>,
+    /// 用连奏技巧演奏时的信息，这时右手不需要拨弦
+    #[serde(default)]
+    pub legato: Option<LegatoInfo>,
+    /// 音符在下一个手型之前结束时，手指提前松开的时间
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub releases: Vec<FingerRelease>,
+}
+/// 保存记录时识别和弦需要的信息
+#[derive(Clone, Copy)]
+pub struct ChordNaming<'a> {
+    /// 用来计算发声音符的吉他
+    pub guitar: &'a Guitar,
+    /// 求解时使用的音符事件，和弦只用事件实际弹奏的音识别，
+    /// 找不到音符事件的手型用所有发声的音识别
+    pub notes_map: &'a [NoteInfo],
+    /// 变调夹位置，和弦名以实际发声的音高计算
+    pub capo_number: i32,
 }
+
 #[derive(Debug, Clone)]
 pub struct LeftHandRecorder {
     pub hand_pose_list: Vec<LeftHand>,
     pub current_entropy: f64,
     pub entropies: Vec<f64>,
     pub real_ticks: Vec<f64>,
+    /// 与手型一一对应的连奏信息，调用mark_legato以后才有
+    pub legato: Vec<Option<LegatoInfo>>,
+    /// 与手型一一对应的提前松开的手指，调用apply_note_durations以后才有
+    pub releases: Vec<Vec<FingerRelease>>,
 }
 
 impl LeftHandRecorder {
@@ -45,6 +88,8 @@ impl LeftHandRecorder {
             current_entropy: 0.0,
             entropies: Vec::new(),
             real_ticks: Vec::new(),
+            legato: Vec::new(),
+            releases: Vec::new(),
         }
     }
 
@@ -60,6 +105,8 @@ impl LeftHandRecorder {
             current_entropy,
             entropies,
             real_ticks,
+            legato: Vec::new(),
+            releases: Vec::new(),
         }
     }
 
@@ -71,6 +118,34 @@ impl LeftHandRecorder {
         self.real_ticks.push(real_tick);
     }
 
+    /// 识别可以用击弦、勾弦和滑弦演奏的音，返回识别出来的数量
+    ///
+    /// # 参数
+    /// * `guitar` - 吉他
+    /// * `notes_map` - 音符事件，只有单音才能用连奏演奏
+    /// * `note_spans` - 每个音符的时值，这根弦上的上一个音已经结束时要重新拨弦
+    /// * `tempo_map` - 速度表
+    /// * `max_gap` - 两个音之间最长的间隔，单位是秒，不大于0时不识别
+    pub fn mark_legato(
+        &mut self,
+        guitar: &Guitar,
+        notes_map: &[NoteInfo],
+        note_spans: &[NoteSpan],
+        tempo_map: &TempoMap,
+        max_gap: f64,
+    ) -> usize {
+        self.legato = detect_legato(
+            guitar,
+            &self.hand_pose_list,
+            &self.real_ticks,
+            notes_map,
+            note_spans,
+            tempo_map,
+            max_gap,
+        );
+        self.legato.iter().flatten().count()
+    }
+
     /// 获取当前手部姿态
     pub fn current_hand_pose(&self) -> Option<&LeftHand> {
         self.hand_pose_list.last()
@@ -107,9 +182,29 @@ impl LeftHandRecorder {
         tempo_changes: &Vec<TempoChange>, // (time, tempo) tuples
         ticks_per_beat: u16,
         fps: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.save_with_chord_names(json_file_path, tempo_changes, ticks_per_beat, fps, None)
+    }
+
+    /// 保存记录到JSON文件，并为每个手型写入识别出来的和弦名
+    ///
+    /// # 参数
+    /// * `chord_naming` - 识别和弦需要的信息，为None时不识别和弦
+    pub fn save_with_chord_names(
+        &self,
+        json_file_path: &str,
+        tempo_changes: &Vec<TempoChange>,
+        ticks_per_beat: u16,
+        fps: f64,
+        chord_naming: Option<ChordNaming>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         let mut hands_dict = Vec::new();
         let midi_processor = MidiProcessor::new();
+        let events: HashMap<u64, &NoteInfo> = chord_naming
+            .iter()
+            .flat_map(|naming| naming.notes_map)
+            .map(|note_info| (note_info.real_tick.to_bits(), note_info))
+            .collect();
 
         for i in 1..self.hand_pose_list.len() {
             let real_tick = self.real_ticks[i];
@@ -117,20 +212,20 @@ impl LeftHandRecorder {
                 midi_processor.calculate_frame(tempo_changes, ticks_per_beat, fps, real_tick);
 
             let left_hand = &self.hand_pose_list[i];
-            let mut hand_info = Vec::new();
+            let hand_info = left_hand
+                .fingers
+                .iter()
+                .map(RecordedFinger::from_finger)
+                .collect();
 
-            for finger in &left_hand.fingers {
-                let finger_info = FingerInfo {
-                    string_index: finger.string_index,
-                    fret: finger.fret,
-                    press: format!("{:?}", finger.press),
+            let chord = chord_naming.as_ref().and_then(|naming| {
+                let notes: Vec<i32> = match events.get(&real_tick.to_bits()) {
+                    Some(note_info) => left_hand.played_notes(naming.guitar, &note_info.notes),
+                    None => left_hand.sounding_notes(naming.guitar),
                 };
-
-                hand_info.push(RecordedFinger {
-                    finger_index: finger.finger_index,
-                    finger_info,
-                });
-            }
+                let notes: Vec<i32> = notes.iter().map(|note| note + naming.capo_number).collect();
+                identify_chord(&notes)
+            });
 
             hands_dict.push(RecordedLeftHand {
                 real_tick,
@@ -138,6 +233,23 @@ impl LeftHandRecorder {
                 left_hand: hand_info,
                 use_barre: left_hand.use_barre,
                 hand_position: left_hand.hand_position,
+                chord,
+                legato: self.legato.get(i).cloned().flatten(),
+                releases: self
+                    .releases
+                    .get(i)
+                    .into_iter()
+                    .flatten()
+                    .map(|release| FingerRelease {
+                        frame: midi_processor.calculate_frame(
+                            tempo_changes,
+                            ticks_per_beat,
+                            fps,
+                            release.real_tick,
+                        ),
+                        ..*release
+                    })
+                    .collect(),
             });
         }
 
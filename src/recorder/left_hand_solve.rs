@@ -0,0 +1,830 @@
+// LeftHandSolve.rs
+
+use serde::Serialize;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use crate::guitar::guitar_instance::Guitar;
+use crate::hand::chord_shape::ChordShapeLibrary;
+use crate::hand::hand_profile::HandProfile;
+use crate::hand::left_hand::LeftHand;
+use crate::hand::left_hand_cost::{
+    LeftHandCostModel, LeftHandCostWeights, LeftHandTransition, WeightedLeftHandCostModel,
+};
+use crate::job_config::{JobConfig, LeftHandSolverKind};
+use crate::midi::midi_to_note::{MidiProcessor, NoteInfo, TempoChange, TempoMap};
+use crate::recorder::fingering_constraint::{FingeringConstraint, FingeringConstraints};
+use crate::recorder::left_hand_recorder::LeftHandRecorder;
+use crate::recorder::left_hand_trace::LeftHandTrace;
+use crate::recorder::note_fallback::{FallbackSettings, FallbackStrategy};
+use crate::recorder::phrase_repeat::{PhraseConsistencyMode, PhraseRepeats};
+use crate::recorder::position_plan::{PositionPlan, split_phrases};
+use crate::recorder::recorder_pool::{HandPoseRecordPool, HandRecorder, UnprocessableNoteInfo};
+use crate::recorder::segment_solver::{
+    LeftHandSegmentCache, SegmentSolution, SegmentSolveReport, solve_in_segments, split_segments,
+};
+use crate::recorder::viterbi_solver::{ViterbiLeftHandSolver, count_different_poses};
+
+/// 求解以后生成求解追踪，重复乐句复制完手型以后才调用
+type LeftHandTracer<'a> = Box<dyn FnOnce(&LeftHandRecorder) -> LeftHandTrace + 'a>;
+
+/// 一个求解器的结果：最优解、无法处理的音符和生成追踪的函数
+type SolverResult<'a> = (
+    LeftHandRecorder,
+    Vec<UnprocessableNoteInfo>,
+    Option<LeftHandTracer<'a>>,
+);
+
+/// 求解左手按法需要的输入，不包含读取文件和界面的状态
+///
+/// Params:
+/// - guitar: 求解使用的吉他，包括是否使用泛音
+/// - notes_map: 要求解的音符事件，用MidiProcessor::processed_notes_map处理过，求解器和把位规划使用同样的音符
+/// - init_hand: 第一个音符事件之前的手型，它的手型数据也用于求解
+/// - job_config: 任务配置
+/// - tempo_changes: 速度变化
+/// - ticks_per_beat: 每拍的tick数
+/// - section_ticks: 段落标记的tick，分段求解和把位规划都在段落标记处分段
+/// - chord_shape_library: 和弦指型库
+/// - fingering_constraints: 已经绑定到音符事件的按法约束
+pub struct LeftHandSolveInput<'a> {
+    pub guitar: &'a Guitar,
+    pub notes_map: &'a [NoteInfo],
+    pub init_hand: LeftHand,
+    pub job_config: &'a JobConfig,
+    pub tempo_changes: &'a [TempoChange],
+    pub ticks_per_beat: u16,
+    pub section_ticks: &'a [f64],
+    pub chord_shape_library: Option<&'a ChordShapeLibrary>,
+    pub fingering_constraints: Option<&'a FingeringConstraints>,
+}
+
+/// 左手求解的结果
+///
+/// Params:
+/// - recorder: 最终使用的按法，已经复制了重复乐句的手型
+/// - unprocessable_notes: 无法正常演奏的音符和采用的退化方案
+/// - trace: 开启追踪时的求解追踪
+/// - ranked_recorders: 按熵排序的解，用于输出备选按法，没有开启备选按法时为空
+/// - segment_report: 分段求解时的统计
+pub struct LeftHandSolveOutput {
+    pub recorder: LeftHandRecorder,
+    pub unprocessable_notes: Vec<UnprocessableNoteInfo>,
+    pub trace: Option<LeftHandTrace>,
+    pub ranked_recorders: Vec<LeftHandRecorder>,
+    pub segment_report: Option<SegmentSolveReport>,
+}
+
+/// 是否输出备选按法，分段求解时只有每一段的最优解，不输出备选按法
+pub fn alternatives_enabled(job_config: &JobConfig) -> bool {
+    !job_config.segment_solving && job_config.alternative_count > 0
+}
+
+/// 按任务配置检测重复乐句，关闭乐句一致性时返回空的结果
+pub fn detect_phrase_repeats(notes_map: &[NoteInfo], job_config: &JobConfig) -> PhraseRepeats {
+    if job_config.phrase_consistency == PhraseConsistencyMode::Off {
+        PhraseRepeats::default()
+    } else {
+        PhraseRepeats::detect(notes_map, job_config.phrase_min_events)
+    }
+}
+
+/// 按任务配置为每个乐句规划把位，没有开启把位规划时返回空的规划
+///
+/// 休止一拍以上或者碰到段落标记时开始新的乐句
+pub fn plan_positions(input: &LeftHandSolveInput, hand_profile: &HandProfile) -> PositionPlan {
+    if !input.job_config.position_planning {
+        return PositionPlan::default();
+    }
+    let segments = split_segments(
+        input.notes_map,
+        input.ticks_per_beat as f64,
+        input.section_ticks,
+    );
+    let phrases = split_phrases(segments, input.job_config.position_phrase_events);
+    PositionPlan::plan(
+        input.notes_map,
+        &phrases,
+        input.guitar,
+        hand_profile,
+        input.fingering_constraints,
+        input.job_config.position_tolerance,
+    )
+}
+
+/// 一根弦在缓存键里的内容
+#[derive(Serialize)]
+struct StringKey {
+    base_note: i32,
+    start_fret: i32,
+    fret_count: i32,
+}
+
+/// 分段求解器读取的任务配置
+///
+/// 线程数不改变结果，追踪、备选按法、按弦时值、连奏和休息姿势只在求解以后使用，
+/// 分段、重复乐句和把位规划的设置已经体现在每一段的音符和每个事件的键里
+#[derive(Serialize)]
+struct SegmentSolverSettings<'a> {
+    left_hand_cost: &'a LeftHandCostWeights,
+    left_hand_solver: LeftHandSolverKind,
+    beam_width: usize,
+    chord_shape_bonus: f64,
+    chord_shapes_compete: bool,
+    fallback_strategies: &'a [FallbackStrategy],
+    roll_gap_beats: f64,
+    phrase_consistency: PhraseConsistencyMode,
+    phrase_bonus: f64,
+}
+
+/// 影响所有分段的设置，序列化以后作为分段缓存键的一部分
+#[derive(Serialize)]
+struct SolverConfigKey<'a> {
+    settings: SegmentSolverSettings<'a>,
+    hand_profile: &'a HandProfile,
+    strings: Vec<StringKey>,
+    use_harm_notes: bool,
+    tempo_changes: Vec<(u64, u32)>,
+    chord_shape_library: Option<&'a ChordShapeLibrary>,
+}
+
+/// 只影响一个音符事件的设置：作用于它的按法约束、重复乐句和规划的把位
+#[derive(Serialize)]
+struct EventKey<'a> {
+    constraints: Vec<&'a FingeringConstraint>,
+    reference_tick: Option<f64>,
+    position: Option<i32>,
+}
+
+/// 一次求解共用的设置，用来创建两种求解器
+struct LeftHandSolveContext<'a> {
+    input: &'a LeftHandSolveInput<'a>,
+    hand_profile: HandProfile,
+    cost_model: Arc<dyn LeftHandCostModel>,
+    tempo_map: TempoMap,
+    fallback_settings: FallbackSettings,
+    phrase_repeats: PhraseRepeats,
+    position_plan: PositionPlan,
+}
+
+impl<'a> LeftHandSolveContext<'a> {
+    fn new(
+        input: &'a LeftHandSolveInput<'a>,
+        phrase_repeats: PhraseRepeats,
+        position_plan: PositionPlan,
+    ) -> Self {
+        let job_config = input.job_config;
+        LeftHandSolveContext {
+            input,
+            hand_profile: *input.init_hand.get_hand_profile(),
+            cost_model: Arc::new(WeightedLeftHandCostModel::new(
+                job_config.left_hand_cost.clone(),
+            )),
+            tempo_map: TempoMap::new(input.tempo_changes, input.ticks_per_beat),
+            // 找不到按法时的退化方式，琶音的间隔从拍数换算成tick
+            fallback_settings: FallbackSettings {
+                strategies: job_config.fallback_strategies.clone(),
+                roll_gap_ticks: job_config.roll_gap_beats * input.ticks_per_beat as f64,
+            },
+            phrase_repeats,
+            position_plan,
+        }
+    }
+
+    fn job_config(&self) -> &JobConfig {
+        self.input.job_config
+    }
+
+    /// 为kind这个求解器生成追踪时返回追踪的备选数，只为最终使用的求解器生成追踪
+    fn trace_runner_ups(&self, kind: LeftHandSolverKind) -> Option<usize> {
+        let job_config = self.job_config();
+        (job_config.left_hand_trace && kind == job_config.left_hand_solver)
+            .then_some(job_config.trace_runner_up_count)
+    }
+
+    /// 按当前设置创建束搜索的记录器池，从init_tick时刻的init_hand开始求解
+    fn new_record_pool(&self, init_hand: &LeftHand, init_tick: f64) -> HandPoseRecordPool {
+        let job_config = self.job_config();
+        let mut init_recorder = LeftHandRecorder::new();
+        init_recorder.add_hand_pose(init_hand.clone(), 0.0, init_tick);
+
+        let mut pool = HandPoseRecordPool::new(job_config.beam_width.max(1));
+        pool.insert_new_hand_pose_recorder(HandRecorder::Left(init_recorder), Some(0));
+        if let Some(library) = self.input.chord_shape_library {
+            pool.set_chord_shape_library(library.clone(), job_config.chord_shape_bonus);
+            pool.set_chord_shapes_compete(job_config.chord_shapes_compete);
+        }
+        pool.set_left_hand_cost_model(self.cost_model.clone());
+        pool.set_thread_count(job_config.thread_count);
+        pool.set_tempo_map(self.tempo_map.clone());
+        pool.set_hand_profile(self.hand_profile);
+        if let Some(constraints) = self.input.fingering_constraints {
+            pool.set_fingering_constraints(constraints.clone());
+        }
+        pool.set_fallback_settings(self.fallback_settings.clone());
+        pool.set_phrase_consistency(
+            self.phrase_repeats.clone(),
+            job_config.phrase_consistency,
+            job_config.phrase_bonus,
+        );
+        pool.set_position_plan(self.position_plan.clone());
+        pool.set_trace_enabled(self.trace_runner_ups(LeftHandSolverKind::Beam).is_some());
+        pool
+    }
+
+    /// 按当前设置创建动态规划求解器
+    fn new_viterbi_solver(&self) -> ViterbiLeftHandSolver {
+        let job_config = self.job_config();
+        let mut viterbi_solver = ViterbiLeftHandSolver::new();
+        if let Some(library) = self.input.chord_shape_library {
+            viterbi_solver.set_chord_shape_library(library.clone(), job_config.chord_shape_bonus);
+            viterbi_solver.set_chord_shapes_compete(job_config.chord_shapes_compete);
+        }
+        viterbi_solver.set_left_hand_cost_model(self.cost_model.clone());
+        viterbi_solver.set_tempo_map(self.tempo_map.clone());
+        viterbi_solver.set_hand_profile(self.hand_profile);
+        if let Some(constraints) = self.input.fingering_constraints {
+            viterbi_solver.set_fingering_constraints(constraints.clone());
+        }
+        viterbi_solver.set_fallback_settings(self.fallback_settings.clone());
+        viterbi_solver.set_position_plan(self.position_plan.clone());
+        viterbi_solver
+            .set_trace_enabled(self.trace_runner_ups(LeftHandSolverKind::Viterbi).is_some());
+        viterbi_solver
+    }
+
+    /// 影响所有分段的设置，只包含分段求解器读取的配置
+    fn config_key(&self) -> Result<String, serde_json::Error> {
+        let job_config = self.job_config();
+        let guitar = self.input.guitar;
+        serde_json::to_string(&SolverConfigKey {
+            settings: SegmentSolverSettings {
+                left_hand_cost: &job_config.left_hand_cost,
+                left_hand_solver: job_config.left_hand_solver,
+                beam_width: job_config.beam_width,
+                chord_shape_bonus: job_config.chord_shape_bonus,
+                chord_shapes_compete: job_config.chord_shapes_compete,
+                fallback_strategies: &job_config.fallback_strategies,
+                roll_gap_beats: job_config.roll_gap_beats,
+                phrase_consistency: job_config.phrase_consistency,
+                phrase_bonus: job_config.phrase_bonus,
+            },
+            hand_profile: &self.hand_profile,
+            strings: guitar
+                .guitar_strings
+                .iter()
+                .map(|string| StringKey {
+                    base_note: string.get_base_note(),
+                    start_fret: string.get_start_fret(),
+                    fret_count: string.get_fret_count(),
+                })
+                .collect(),
+            use_harm_notes: guitar.use_harm_notes(),
+            tempo_changes: self
+                .input
+                .tempo_changes
+                .iter()
+                .map(|change| (change.time, change.tempo))
+                .collect(),
+            chord_shape_library: self.input.chord_shape_library,
+        })
+    }
+
+    /// 只影响这一段的设置，每个音符事件一项
+    fn segment_key(&self, notes: &[NoteInfo]) -> String {
+        let events: Vec<EventKey> = notes
+            .iter()
+            .map(|note_info| EventKey {
+                constraints: self
+                    .input
+                    .fingering_constraints
+                    .map(|constraints| constraints.constraints_at(note_info.real_tick))
+                    .unwrap_or_default(),
+                reference_tick: self.phrase_repeats.reference_tick(note_info.real_tick),
+                position: self.position_plan.position_at(note_info.real_tick),
+            })
+            .collect();
+        serde_json::to_string(&events).unwrap_or_default()
+    }
+
+    /// 从boundary_tick时刻的boundary手型开始用最终使用的求解器求解一段音符
+    fn solve_segment(
+        &self,
+        boundary: &LeftHand,
+        boundary_tick: f64,
+        notes: &[NoteInfo],
+    ) -> SegmentSolution {
+        let guitar = self.input.guitar;
+        match self.job_config().left_hand_solver {
+            LeftHandSolverKind::Beam => {
+                let mut pool = self.new_record_pool(boundary, boundary_tick);
+                let (mut current, mut previous) = (0, 0);
+                pool.update_left_handrecorder_pool(
+                    guitar,
+                    &notes.to_vec(),
+                    &MidiProcessor::new(),
+                    &mut current,
+                    &mut previous,
+                    |_| {},
+                );
+                let recorder = match pool.get_best_recorder() {
+                    HandRecorder::Left(recorder) => recorder,
+                    HandRecorder::Right(_) => LeftHandRecorder::new(),
+                };
+                SegmentSolution::from_recorder(&recorder, pool.get_unprocessable_notes().clone())
+            }
+            LeftHandSolverKind::Viterbi => {
+                let mut viterbi_solver = self.new_viterbi_solver();
+                viterbi_solver.set_init_tick(boundary_tick);
+                let recorder = viterbi_solver.solve(
+                    boundary.clone(),
+                    guitar,
+                    notes,
+                    &MidiProcessor::new(),
+                    |_| {},
+                );
+                SegmentSolution::from_recorder(
+                    &recorder,
+                    viterbi_solver.get_unprocessable_notes().clone(),
+                )
+            }
+        }
+    }
+
+    /// 分段求解，只重新计算有变化的分段
+    fn solve_segmented(
+        &self,
+        cache: &Mutex<LeftHandSegmentCache>,
+    ) -> Result<
+        (
+            LeftHandRecorder,
+            Vec<UnprocessableNoteInfo>,
+            SegmentSolveReport,
+        ),
+        Box<dyn Error>,
+    > {
+        let job_config = self.job_config();
+        let min_rest_ticks = job_config.segment_min_rest_beats * self.input.ticks_per_beat as f64;
+        let segments = split_segments(
+            self.input.notes_map,
+            min_rest_ticks,
+            self.input.section_ticks,
+        );
+        let config_key = self.config_key()?;
+        Ok(solve_in_segments(
+            &self.input.init_hand,
+            self.input.notes_map,
+            &segments,
+            &config_key,
+            |notes| self.segment_key(notes),
+            cache,
+            |boundary, boundary_tick, notes| self.solve_segment(boundary, boundary_tick, notes),
+        ))
+    }
+
+    /// 用束搜索求解整首曲子，ranked_recorders不为None时写入按熵排序的解
+    fn solve_beam<F>(
+        &self,
+        ranked_recorders: Option<&mut Vec<LeftHandRecorder>>,
+        console_callback: F,
+    ) -> Result<SolverResult<'a>, Box<dyn Error>>
+    where
+        F: Fn(&str),
+    {
+        let guitar = self.input.guitar;
+        let mut pool = self.new_record_pool(&self.input.init_hand, 0.0);
+        let (mut current_recorder_num, mut previous_recorder_num) = (0, 0);
+        pool.update_left_handrecorder_pool(
+            guitar,
+            &self.input.notes_map.to_vec(),
+            &MidiProcessor::new(),
+            &mut current_recorder_num,
+            &mut previous_recorder_num,
+            console_callback,
+        );
+
+        let recorder = match pool.get_best_recorder() {
+            HandRecorder::Left(recorder) => recorder,
+            _ => return Err("Expected LeftHandRecorder".into()),
+        };
+        if let Some(ranked_recorders) = ranked_recorders {
+            *ranked_recorders = pool.get_ranked_left_recorders(self.job_config().beam_width.max(1));
+        }
+        let unprocessable_notes = pool.get_unprocessable_notes().clone();
+        let tracer = self
+            .trace_runner_ups(LeftHandSolverKind::Beam)
+            .map(|runner_up_count| {
+                Box::new(move |recorder: &LeftHandRecorder| {
+                    pool.trace_left_hand_recorder(recorder, guitar, runner_up_count)
+                }) as LeftHandTracer
+            });
+        Ok((recorder, unprocessable_notes, tracer))
+    }
+
+    /// 用动态规划求解整首曲子的全局最优解，ranked_recorders不为None时写入按熵排序的解
+    fn solve_viterbi<F>(
+        &self,
+        ranked_recorders: Option<&mut Vec<LeftHandRecorder>>,
+        console_callback: F,
+    ) -> SolverResult<'a>
+    where
+        F: Fn(&str),
+    {
+        let guitar = self.input.guitar;
+        let mut viterbi_solver = self.new_viterbi_solver();
+        if ranked_recorders.is_some() {
+            // 很多终点只在结尾有区别，多回溯一些才能选出足够的不同按法
+            viterbi_solver.set_alternative_count(self.job_config().alternative_count * 4);
+        }
+        let recorder = viterbi_solver.solve(
+            self.input.init_hand.clone(),
+            guitar,
+            self.input.notes_map,
+            &MidiProcessor::new(),
+            console_callback,
+        );
+        if let Some(ranked_recorders) = ranked_recorders {
+            *ranked_recorders = viterbi_solver.get_ranked_recorders().clone();
+        }
+        let unprocessable_notes = viterbi_solver.get_unprocessable_notes().clone();
+        let tracer = self
+            .trace_runner_ups(LeftHandSolverKind::Viterbi)
+            .map(|runner_up_count| {
+                Box::new(move |recorder: &LeftHandRecorder| {
+                    viterbi_solver.trace_left_hand_recorder(recorder, guitar, runner_up_count)
+                }) as LeftHandTracer
+            });
+        (recorder, unprocessable_notes, tracer)
+    }
+
+    /// 把第一次出现的按法复制到重复的乐句上，返回复制的手型数
+    ///
+    /// 动态规划和分段求解时求解器看不到之前的路径，只能在求解后复制，
+    /// 使用了退化方案或者有按法约束的事件保持不变，奖励相同按法时只复制多出来的熵不超过奖励的手型
+    fn reuse_repeated_phrases(
+        &self,
+        recorder: &mut LeftHandRecorder,
+        unprocessable_notes: &[UnprocessableNoteInfo],
+    ) -> usize {
+        let job_config = self.job_config();
+        self.phrase_repeats.apply_to_recorder(
+            recorder,
+            job_config.phrase_consistency,
+            job_config.phrase_bonus,
+            |tick| {
+                unprocessable_notes
+                    .iter()
+                    .any(|info| info.real_tick == tick)
+                    || self
+                        .input
+                        .fingering_constraints
+                        .is_some_and(|constraints| !constraints.constraints_at(tick).is_empty())
+            },
+            |from, to, from_tick, to_tick| {
+                self.cost_model.transition_cost(&LeftHandTransition {
+                    old_hand: from,
+                    new_fingers: &to.fingers,
+                    new_hand_position: to.hand_position,
+                    use_barre: to.use_barre,
+                    guitar: self.input.guitar,
+                    time_gap: Some(self.tempo_map.seconds_between(from_tick, to_tick)),
+                })
+            },
+        )
+    }
+}
+
+/// 按任务配置求解左手按法
+///
+/// 依次规划把位、检测重复乐句，再用设置的求解器整首求解或者分段求解，
+/// 需要时把第一次出现的按法复制到重复的乐句上，最后生成求解追踪
+///
+/// # 参数
+/// * `cache` - 分段求解的缓存，只有开启分段求解时使用
+/// * `console_callback` - 输出求解过程的信息
+pub fn solve_left_hand<F>(
+    input: &LeftHandSolveInput,
+    cache: &Mutex<LeftHandSegmentCache>,
+    console_callback: F,
+) -> Result<LeftHandSolveOutput, Box<dyn Error>>
+where
+    F: Fn(&str),
+{
+    let job_config = input.job_config;
+    let hand_profile = *input.init_hand.get_hand_profile();
+    let solver = job_config.left_hand_solver;
+    let compare_solvers = job_config.compare_left_hand_solvers;
+
+    console_callback(&format!("左手风格：{}", job_config.left_hand_style));
+    console_callback("==============================");
+    console_callback("开始生成左手按弦数据");
+
+    // 检测重复乐句，让重复的乐句使用相同的按法
+    let phrase_mode = job_config.phrase_consistency;
+    let phrase_repeats = detect_phrase_repeats(input.notes_map, job_config);
+    if phrase_mode != PhraseConsistencyMode::Off {
+        console_callback(&format!(
+            "检测到{}处重复乐句，共{}个音符事件，处理方式：{}",
+            phrase_repeats.phrases.len(),
+            phrase_repeats.repeated_event_count(),
+            phrase_mode.label()
+        ));
+    }
+
+    // 先为每个乐句规划把位，之后的按法求解只使用规划的把位附近的按法
+    let position_plan = plan_positions(input, &hand_profile);
+    if job_config.position_planning {
+        console_callback(&format!(
+            "把位规划：共{}个乐句，换把{}次",
+            position_plan.phrases.len(),
+            position_plan.shift_count()
+        ));
+    }
+
+    let context = LeftHandSolveContext::new(input, phrase_repeats, position_plan);
+
+    let segmented = job_config.segment_solving;
+    let alternatives = alternatives_enabled(job_config);
+    let mut ranked_recorders = Vec::new();
+    let mut segment_report = None;
+
+    let (mut recorder, unprocessable_notes, tracer) = if segmented {
+        if compare_solvers || job_config.left_hand_trace {
+            console_callback("注意：分段求解时不比较两种求解器，也不输出左手求解追踪");
+        }
+        let (recorder, unprocessable_notes, report) = context.solve_segmented(cache)?;
+        console_callback(&format!(
+            "分段求解：共{}段，其中{}段使用了缓存，重新计算了{}段",
+            report.segment_count,
+            report.reused_count,
+            report.segment_count - report.reused_count
+        ));
+        segment_report = Some(report);
+        (recorder, unprocessable_notes, None)
+    } else {
+        // 束搜索
+        let beam_result = if solver == LeftHandSolverKind::Beam || compare_solvers {
+            console_callback(&format!("使用束搜索求解，束宽为{}", job_config.beam_width));
+            let ranked = (alternatives && solver == LeftHandSolverKind::Beam)
+                .then_some(&mut ranked_recorders);
+            Some(context.solve_beam(ranked, &console_callback)?)
+        } else {
+            None
+        };
+
+        // 动态规划求最优解
+        let viterbi_result = if solver == LeftHandSolverKind::Viterbi || compare_solvers {
+            console_callback("使用动态规划求解全局最优解");
+            let ranked = (alternatives && solver == LeftHandSolverKind::Viterbi)
+                .then_some(&mut ranked_recorders);
+            Some(context.solve_viterbi(ranked, &console_callback))
+        } else {
+            None
+        };
+
+        // 比较束搜索结果与最优解的差距
+        if let (Some((beam_recorder, _, _)), Some((optimal_recorder, _, _))) =
+            (&beam_result, &viterbi_result)
+        {
+            let beam_entropy = beam_recorder.current_entropy;
+            let optimal_entropy = optimal_recorder.current_entropy;
+            let gap = beam_entropy - optimal_entropy;
+            let gap_percent = if optimal_entropy > 0.0 {
+                gap / optimal_entropy * 100.0
+            } else {
+                0.0
+            };
+            console_callback(&format!(
+                "束搜索结果的熵为{:.3}，最优解的熵为{:.3}，差距为{:.3} ({:.2}%)，有{}个手型不同",
+                beam_entropy,
+                optimal_entropy,
+                gap,
+                gap_percent,
+                count_different_poses(beam_recorder, optimal_recorder)
+            ));
+        }
+
+        match solver {
+            LeftHandSolverKind::Beam => beam_result,
+            LeftHandSolverKind::Viterbi => viterbi_result,
+        }
+        .ok_or("没有得到左手按弦结果")?
+    };
+
+    // 束搜索在求解时已经处理了重复乐句，但是分段求解时每一段只能看到自己的候选，
+    // 不同分段之间的重复乐句只能在求解以后复制
+    let reuse_after_solving = solver == LeftHandSolverKind::Viterbi || segmented;
+    if reuse_after_solving && !context.phrase_repeats.is_empty() {
+        let copied = context.reuse_repeated_phrases(&mut recorder, &unprocessable_notes);
+        console_callback(&format!("重复乐句复用了{}个手型", copied));
+    }
+
+    // 复制重复乐句的手型以后再生成求解追踪，追踪里的手型和最终结果一致
+    let trace = tracer.map(|trace| trace(&recorder));
+
+    Ok(LeftHandSolveOutput {
+        recorder,
+        unprocessable_notes,
+        trace,
+        ranked_recorders,
+        segment_report,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+    use crate::hand::left_finger::LeftFinger;
+
+    fn note_info(note: i32, real_tick: f64) -> NoteInfo {
+        NoteInfo {
+            notes: vec![note],
+            real_tick,
+            string_indices: Vec::new(),
+        }
+    }
+
+    fn init_hand(guitar: &Guitar) -> LeftHand {
+        let init_string = &guitar.guitar_strings[2];
+        let fingers = (1..5)
+            .map(|i| LeftFinger::new(i, init_string, i, "Pressed"))
+            .collect();
+        LeftHand::with_profile(fingers, false, HandProfile::default())
+    }
+
+    fn solve_input<'a>(
+        guitar: &'a Guitar,
+        notes_map: &'a [NoteInfo],
+        job_config: &'a JobConfig,
+    ) -> LeftHandSolveInput<'a> {
+        LeftHandSolveInput {
+            guitar,
+            notes_map,
+            init_hand: init_hand(guitar),
+            job_config,
+            tempo_changes: &[],
+            ticks_per_beat: 480,
+            section_ticks: &[],
+            chord_shape_library: None,
+            fingering_constraints: None,
+        }
+    }
+
+    fn config_key(guitar: &Guitar, job_config: &JobConfig) -> String {
+        let input = solve_input(guitar, &[], job_config);
+        LeftHandSolveContext::new(&input, PhraseRepeats::default(), PositionPlan::default())
+            .config_key()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_config_key_ignores_output_only_settings() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let job_config = JobConfig::default();
+        let key = config_key(&guitar, &job_config);
+
+        let mut traced = job_config.clone();
+        traced.left_hand_trace = true;
+        traced.trace_runner_up_count = 5;
+        traced.compare_left_hand_solvers = true;
+        assert_eq!(config_key(&guitar, &traced), key);
+
+        // 求解以后才使用的设置不会让缓存失效
+        let mut post_solve = job_config.clone();
+        post_solve.hold_note_durations = !post_solve.hold_note_durations;
+        post_solve.legato_max_gap += 0.1;
+        post_solve.rest_pose_min_length += 1.0;
+        post_solve.alternative_count += 2;
+        post_solve.left_hand_style = "edited".to_string();
+        post_solve.thread_count += 1;
+        assert_eq!(config_key(&guitar, &post_solve), key);
+
+        let mut weighted = job_config.clone();
+        weighted.left_hand_cost.max_hand_speed += 1.0;
+        assert_ne!(config_key(&guitar, &weighted), key);
+
+        let harmonic_guitar = InstrumentProfile::default().create_guitar(true);
+        assert_ne!(config_key(&harmonic_guitar, &job_config), key);
+    }
+
+    #[test]
+    fn test_segmented_solve_reports_cached_segments() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        // 两段之间有四拍的休止
+        let notes_map = vec![
+            note_info(55, 0.0),
+            note_info(57, 480.0),
+            note_info(60, 2880.0),
+            note_info(62, 3360.0),
+        ];
+        let job_config = JobConfig {
+            segment_solving: true,
+            alternative_count: 3,
+            ..JobConfig::default()
+        };
+        let input = solve_input(&guitar, &notes_map, &job_config);
+        let cache = Mutex::new(LeftHandSegmentCache::default());
+
+        let first = solve_left_hand(&input, &cache, |_| {}).unwrap();
+        let report = first.segment_report.unwrap();
+        assert_eq!((report.segment_count, report.reused_count), (2, 0));
+        assert_eq!(first.recorder.hand_pose_list.len(), notes_map.len() + 1);
+        // 分段求解时不输出备选按法
+        assert!(!alternatives_enabled(&job_config));
+        assert!(first.ranked_recorders.is_empty());
+
+        let second = solve_left_hand(&input, &cache, |_| {}).unwrap();
+        let report = second.segment_report.unwrap();
+        assert_eq!((report.segment_count, report.reused_count), (2, 2));
+        assert_eq!(
+            second.recorder.hand_pose_list.len(),
+            first.recorder.hand_pose_list.len()
+        );
+
+        // 修改按弦时值和连奏间隔不需要重新求解
+        let edited_config = JobConfig {
+            hold_note_durations: !job_config.hold_note_durations,
+            legato_max_gap: job_config.legato_max_gap + 0.1,
+            ..job_config.clone()
+        };
+        let edited_input = solve_input(&guitar, &notes_map, &edited_config);
+        let third = solve_left_hand(&edited_input, &cache, |_| {}).unwrap();
+        let report = third.segment_report.unwrap();
+        assert_eq!((report.segment_count, report.reused_count), (2, 2));
+    }
+
+    #[test]
+    fn test_whole_solve_returns_ranked_recorders_and_trace() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let notes_map = vec![
+            note_info(55, 0.0),
+            note_info(57, 480.0),
+            note_info(59, 960.0),
+        ];
+        for solver in [LeftHandSolverKind::Beam, LeftHandSolverKind::Viterbi] {
+            let job_config = JobConfig {
+                left_hand_solver: solver,
+                left_hand_trace: true,
+                alternative_count: 2,
+                ..JobConfig::default()
+            };
+            let input = solve_input(&guitar, &notes_map, &job_config);
+            let cache = Mutex::new(LeftHandSegmentCache::default());
+            let output = solve_left_hand(&input, &cache, |_| {}).unwrap();
+
+            assert!(output.segment_report.is_none());
+            assert!(output.unprocessable_notes.is_empty());
+            assert!(output.trace.is_some());
+            assert!(!output.ranked_recorders.is_empty());
+            assert_eq!(
+                output.ranked_recorders[0].current_entropy,
+                output.recorder.current_entropy
+            );
+        }
+    }
+
+    #[test]
+    fn test_segmented_solve_keeps_riff_consistent_across_rest() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        // riff之后在高把位弹一段，休止四拍以后riff再出现，两次riff在不同的分段里
+        let riff = [55, 57, 59, 60];
+        let mut notes: Vec<(i32, f64)> = Vec::new();
+        for (i, &note) in riff.iter().chain(&[79, 81, 83, 84]).enumerate() {
+            notes.push((note, i as f64 * 240.0));
+        }
+        for (i, &note) in riff.iter().enumerate() {
+            notes.push((note, 3840.0 + i as f64 * 240.0));
+        }
+        let notes_map: Vec<NoteInfo> = notes
+            .iter()
+            .map(|&(note, real_tick)| note_info(note, real_tick))
+            .collect();
+        let solve = |mode: PhraseConsistencyMode| {
+            let job_config = JobConfig {
+                segment_solving: true,
+                phrase_consistency: mode,
+                // 从高把位回到第一次的按法要多花很多熵，奖励足够大时才复制
+                phrase_bonus: 1000.0,
+                ..JobConfig::default()
+            };
+            let input = solve_input(&guitar, &notes_map, &job_config);
+            let cache = Mutex::new(LeftHandSegmentCache::default());
+            let output = solve_left_hand(&input, &cache, |_| {}).unwrap();
+            assert_eq!(output.segment_report.unwrap().segment_count, 2);
+            output.recorder
+        };
+        let riff_keys = |recorder: &LeftHandRecorder, start: usize| -> Vec<_> {
+            recorder.hand_pose_list[start..start + riff.len()]
+                .iter()
+                .map(|hand| hand.pose_key())
+                .collect()
+        };
+
+        // 第一个手型是初始手型
+        let off = solve(PhraseConsistencyMode::Off);
+        assert_ne!(riff_keys(&off, 1), riff_keys(&off, 9));
+        let bonus = solve(PhraseConsistencyMode::Bonus);
+        assert_eq!(riff_keys(&bonus, 1), riff_keys(&bonus, 9));
+    }
+}
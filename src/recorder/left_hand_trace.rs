@@ -0,0 +1,320 @@
+// left_hand_trace.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+
+use crate::hand::left_hand::{LeftHand, LeftHandPoseKey};
+use crate::hand::left_hand_cost::LeftHandCostTerms;
+use crate::midi::midi_to_note::NoteInfo;
+use crate::recorder::left_hand_expansion::{ExpansionRules, Relaxation};
+use crate::recorder::left_hand_recorder::{LeftHandRecorder, RecordedFinger};
+
+/// 求解过程中处理过的一个音符事件
+#[derive(Debug, Clone)]
+pub struct TracedEvent {
+    /// 处理后的音符
+    pub note_info: NoteInfo,
+    /// 处理完这个事件后保留的状态数，束搜索是池子里的记录器数，动态规划是这一层的手型数
+    pub pool_size: usize,
+    /// 求解时为了找到按法放宽的条件
+    pub relaxation: Relaxation,
+}
+
+/// 追踪里的一个按法
+///
+/// Params:
+/// - fingers: 所有手指的位置
+/// - hand_position: 把位
+/// - use_barre: 是否横按
+/// - cost: 这一步的熵，已经减去指型库的奖励，加上软约束的惩罚
+/// - cost_terms: 消耗模型计算出来的各项消耗
+/// - chord_shape_bonus: 使用指型库按法减少的熵
+/// - constraint_penalty: 没有满足软约束增加的熵
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TracedFingering {
+    pub fingers: Vec<RecordedFinger>,
+    pub hand_position: i32,
+    pub use_barre: bool,
+    pub cost: f64,
+    pub cost_terms: LeftHandCostTerms,
+    pub chord_shape_bonus: f64,
+    pub constraint_penalty: f64,
+}
+
+impl TracedFingering {
+    fn new(hand: &LeftHand, cost_terms: LeftHandCostTerms, bonus: f64, penalty: f64) -> Self {
+        TracedFingering {
+            fingers: hand
+                .fingers
+                .iter()
+                .map(RecordedFinger::from_finger)
+                .collect(),
+            hand_position: hand.hand_position,
+            use_barre: hand.use_barre,
+            cost: (cost_terms.total() - bonus).max(0.0) + penalty,
+            cost_terms,
+            chord_shape_bonus: bonus,
+            constraint_penalty: penalty,
+        }
+    }
+}
+
+/// 胜出路径上的一步
+///
+/// Params:
+/// - real_tick: 音符事件的tick
+/// - time: 距离乐曲开始的秒数，没有速度表时为None
+/// - notes: 处理后的音符
+/// - pool_size: 处理完这个事件后求解器保留的状态数
+/// - entropy: 到这一步为止的累计熵
+/// - repeated: 没有按法能从上一个手型过渡过来，重复了上一个手型
+/// - chosen: 胜出路径选择的按法
+/// - runner_ups: 从同一个手型出发的其它按法，按熵从小到大排列
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LeftHandTraceStep {
+    pub real_tick: f64,
+    pub time: Option<f64>,
+    pub notes: Vec<i32>,
+    pub pool_size: usize,
+    pub entropy: f64,
+    pub repeated: bool,
+    pub chosen: TracedFingering,
+    pub runner_ups: Vec<TracedFingering>,
+}
+
+/// 左手求解过程的追踪，记录胜出路径上每一步的消耗组成和落选的按法
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct LeftHandTrace {
+    pub steps: Vec<LeftHandTraceStep>,
+}
+
+impl LeftHandTrace {
+    pub fn load_from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = File::open(path)?;
+        let trace = serde_json::from_reader(BufReader::new(file))?;
+        Ok(trace)
+    }
+
+    pub fn save_to_file(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+/// 为求解得到的左手记录器生成追踪
+///
+/// 每一步都从胜出路径的上一个手型出发，按求解器同样的规则和放宽条件重新展开所有按法，
+/// 所以落选的按法和胜出的按法是在同样的条件下比较的
+///
+/// # 参数
+/// * `recorder` - 求解结果，最后events.len()个手型与事件一一对应
+/// * `events` - 求解时处理过的音符事件
+/// * `rules` - 求解时使用的展开规则
+/// * `runner_up_count` - 每一步最多保留的落选按法数
+pub fn build_left_hand_trace(
+    recorder: &LeftHandRecorder,
+    events: &[TracedEvent],
+    rules: &ExpansionRules,
+    runner_up_count: usize,
+) -> LeftHandTrace {
+    let offset = recorder
+        .hand_pose_list
+        .len()
+        .saturating_sub(events.len() + 1);
+
+    let steps = events
+        .iter()
+        .enumerate()
+        .filter_map(|(i, event)| {
+            let index = offset + i;
+            let previous = recorder.hand_pose_list.get(index)?;
+            let chosen = recorder.hand_pose_list.get(index + 1)?;
+            let real_tick = event.note_info.real_tick;
+            let time_gap = rules.time_gap(recorder.real_ticks[index], real_tick);
+
+            let mut candidates = expand_hand(previous, event, rules, time_gap);
+            let chosen_key = chosen.pose_key();
+            let chosen_index = candidates.iter().position(|(key, _)| *key == chosen_key);
+            let (repeated, chosen_fingering) = match chosen_index {
+                Some(index) => (false, candidates.remove(index).1),
+                None => (
+                    true,
+                    TracedFingering::new(chosen, LeftHandCostTerms::default(), 0.0, 0.0),
+                ),
+            };
+
+            Some(LeftHandTraceStep {
+                real_tick,
+                time: rules
+                    .tempo_map
+                    .map(|tempo_map| tempo_map.seconds_at(real_tick)),
+                notes: event.note_info.notes.clone(),
+                pool_size: event.pool_size,
+                entropy: recorder.entropies[index + 1],
+                repeated,
+                chosen: chosen_fingering,
+                runner_ups: candidates
+                    .into_iter()
+                    .take(runner_up_count)
+                    .map(|(_, fingering)| fingering)
+                    .collect(),
+            })
+        })
+        .collect();
+
+    LeftHandTrace { steps }
+}
+
+/// 在求解时的放宽条件下展开一个手型的所有按法，相同的手型只保留熵最小的一个，按熵从小到大排列
+fn expand_hand(
+    hand: &LeftHand,
+    event: &TracedEvent,
+    rules: &ExpansionRules,
+    time_gap: Option<f64>,
+) -> Vec<(LeftHandPoseKey, TracedFingering)> {
+    let fingerings = rules.event_fingerings(&event.note_info, &event.relaxation);
+    let mut candidates: Vec<(LeftHandPoseKey, TracedFingering)> = Vec::new();
+    let mut index_by_pose: HashMap<LeftHandPoseKey, usize> = HashMap::new();
+
+    for expansion in rules.expand_hand(hand, &fingerings, time_gap) {
+        let cost_terms = rules.cost_terms(hand, &fingerings, &expansion, time_gap);
+        let fingering = TracedFingering::new(
+            &expansion.hand,
+            cost_terms,
+            expansion.bonus,
+            expansion.penalty,
+        );
+        let key = expansion.hand.pose_key();
+        match index_by_pose.get(&key) {
+            Some(&index) => {
+                if fingering.cost < candidates[index].1.cost {
+                    candidates[index].1 = fingering;
+                }
+            }
+            None => {
+                index_by_pose.insert(key.clone(), candidates.len());
+                candidates.push((key, fingering));
+            }
+        }
+    }
+
+    candidates.sort_by(|a, b| a.1.cost.total_cmp(&b.1.cost));
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+    use crate::hand::left_finger::LeftFinger;
+    use crate::hand::left_hand_cost::{LeftHandCostWeights, WeightedLeftHandCostModel};
+    use crate::midi::midi_to_note::MidiProcessor;
+    use crate::recorder::viterbi_solver::ViterbiLeftHandSolver;
+
+    #[test]
+    fn test_trace_matches_solved_path() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let init_string = &guitar.guitar_strings[2];
+        let init_hand = LeftHand::new(
+            (1..5)
+                .map(|i| LeftFinger::new(i, init_string, i, "Pressed"))
+                .collect(),
+            false,
+            5.73,
+        );
+        let notes_map: Vec<NoteInfo> = [vec![48, 52, 55, 60, 64], vec![64], vec![67], vec![72]]
+            .iter()
+            .enumerate()
+            .map(|(i, notes)| NoteInfo {
+                notes: notes.clone(),
+                real_tick: i as f64 * 480.0,
+                string_indices: Vec::new(),
+            })
+            .collect();
+
+        let cost_model = WeightedLeftHandCostModel::new(LeftHandCostWeights::classical());
+        let mut solver = ViterbiLeftHandSolver::new();
+        solver.set_trace_enabled(true);
+        solver.set_left_hand_cost_model(std::sync::Arc::new(cost_model.clone()));
+        let recorder = solver.solve(
+            init_hand,
+            &guitar,
+            &notes_map,
+            &MidiProcessor::new(),
+            |_| {},
+        );
+        let trace = solver.trace_left_hand_recorder(&recorder, &guitar, 3);
+
+        assert_eq!(trace.steps.len(), notes_map.len());
+        for (i, step) in trace.steps.iter().enumerate() {
+            assert!(!step.repeated);
+            assert!(step.runner_ups.len() <= 3);
+            // 胜出按法的熵与记录器里这一步的熵相同，落选的按法按熵从小到大排列
+            let step_cost = recorder.entropies[i + 1] - recorder.entropies[i];
+            assert!((step.chosen.cost - step_cost).abs() < 1e-9);
+            assert!(
+                step.runner_ups
+                    .windows(2)
+                    .all(|pair| pair[0].cost <= pair[1].cost)
+            );
+        }
+    }
+
+    #[test]
+    fn test_beam_trace_uses_solver_expansion_and_can_be_disabled() {
+        use crate::recorder::recorder_pool::{HandPoseRecordPool, HandRecorder};
+
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let init_string = &guitar.guitar_strings[2];
+        let init_hand = LeftHand::new(
+            (1..5)
+                .map(|i| LeftFinger::new(i, init_string, i, "Pressed"))
+                .collect(),
+            false,
+            5.73,
+        );
+        let notes_map: Vec<NoteInfo> = [vec![45, 52, 57, 60, 64], vec![62], vec![65, 57]]
+            .iter()
+            .enumerate()
+            .map(|(i, notes)| NoteInfo {
+                notes: notes.clone(),
+                real_tick: i as f64 * 480.0,
+                string_indices: Vec::new(),
+            })
+            .collect();
+        let solve = |trace_enabled: bool| {
+            let mut init_recorder = LeftHandRecorder::new();
+            init_recorder.add_hand_pose(init_hand.clone(), 0.0, 0.0);
+            let mut pool = HandPoseRecordPool::new(20);
+            pool.set_trace_enabled(trace_enabled);
+            pool.insert_new_hand_pose_recorder(HandRecorder::Left(init_recorder), Some(0));
+            let (mut current, mut previous) = (0, 0);
+            pool.update_left_handrecorder_pool(
+                &guitar,
+                &notes_map,
+                &MidiProcessor::new(),
+                &mut current,
+                &mut previous,
+                |_| {},
+            );
+            let HandRecorder::Left(recorder) = pool.get_best_recorder() else {
+                panic!("Expected LeftHandRecorder");
+            };
+            let trace = pool.trace_left_hand_recorder(&recorder, &guitar, 2);
+            (trace, recorder)
+        };
+
+        // 没有打开追踪时不记录音符事件
+        assert!(solve(false).0.steps.is_empty());
+        let (trace, recorder) = solve(true);
+        assert_eq!(trace.steps.len(), notes_map.len());
+        for (i, step) in trace.steps.iter().enumerate() {
+            assert!(!step.repeated);
+            let step_cost = recorder.entropies[i + 1] - recorder.entropies[i];
+            assert!((step.chosen.cost - step_cost).abs() < 1e-9);
+        }
+    }
+}
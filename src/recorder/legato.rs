@@ -0,0 +1,562 @@
+// Legato.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::guitar::guitar_instance::Guitar;
+use crate::hand::left_finger::PressState;
+use crate::hand::left_hand::LeftHand;
+use crate::midi::midi_to_note::{NoteInfo, NoteSpan, TempoMap};
+
+/// 连奏技巧，这些音符由左手发声，右手不需要再拨弦
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum LegatoTechnique {
+    /// 击弦，后面的手指直接砸到更高的品格上
+    HammerOn,
+    /// 勾弦，按弦的手指勾弦离开，让更低的品格发声
+    PullOff,
+    /// 滑弦，同一根手指按着弦滑到新的品格
+    Slide,
+}
+
+impl LegatoTechnique {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LegatoTechnique::HammerOn => "击弦",
+            LegatoTechnique::PullOff => "勾弦",
+            LegatoTechnique::Slide => "滑弦",
+        }
+    }
+}
+
+/// 一个用连奏技巧演奏的音符
+///
+/// Params:
+/// - technique: 连奏技巧
+/// - string_index: 所在的弦
+/// - from_fret: 上一个音的品格，0表示空弦
+/// - to_fret: 这个音的品格，0表示空弦
+/// - finger_index: 让这个音发声的手指，空弦为-1
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LegatoInfo {
+    pub technique: LegatoTechnique,
+    pub string_index: i32,
+    pub from_fret: i32,
+    pub to_fret: i32,
+    pub finger_index: i32,
+}
+
+/// 一个手型里新弹出的单音
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PlayedNote {
+    string_index: i32,
+    fret: i32,
+    finger_index: i32,
+}
+
+/// 手型里每根弦上发声的音，同一根弦上只有品格最高的按弦手指发声
+///
+/// 横按会同时发出多个音，返回None
+fn sounding_notes(hand: &LeftHand) -> Option<HashMap<i32, PlayedNote>> {
+    // 闷音不发出音高，不能用连奏演奏，轻触的手指下面按弦的音也不发声
+    let muted_strings = hand.muted_strings();
+    let mut notes: HashMap<i32, PlayedNote> = HashMap::new();
+    for finger in &hand.fingers {
+        if muted_strings.contains(&finger.string_index) {
+            continue;
+        }
+        let fret = if finger.finger_index == -1 {
+            0
+        } else {
+            match finger.press {
+                PressState::Open | PressState::Keep => continue,
+                PressState::Pressed => finger.fret,
+                _ => return None,
+            }
+        };
+        let note = PlayedNote {
+            string_index: finger.string_index,
+            fret,
+            finger_index: finger.finger_index,
+        };
+        notes
+            .entry(finger.string_index)
+            .and_modify(|old| {
+                if fret > old.fret {
+                    *old = note;
+                }
+            })
+            .or_insert(note);
+    }
+    Some(notes)
+}
+
+/// 找出手型相对于上一个手型新弹出的音，只有正好一个单音时才返回
+///
+/// 发声的品格没有变化的弦是保持不动的，不算新弹出的音
+fn single_played_note(
+    previous: &HashMap<i32, PlayedNote>,
+    hand: &HashMap<i32, PlayedNote>,
+) -> Option<PlayedNote> {
+    let mut played = hand
+        .values()
+        .filter(|note| previous.get(&note.string_index).map(|old| old.fret) != Some(note.fret));
+    match (played.next(), played.next()) {
+        (Some(note), None) => Some(*note),
+        _ => None,
+    }
+}
+
+/// 判断从上一个音到这个音是否可以用连奏技巧演奏
+///
+/// # 参数
+/// * `previous` - 弹出上一个音时的手型
+/// * `hand` - 弹出这个音时的手型
+fn classify_legato(
+    from: PlayedNote,
+    to: PlayedNote,
+    previous: &LeftHand,
+    hand: &LeftHand,
+) -> Option<LegatoTechnique> {
+    if from.string_index != to.string_index || from.fret == to.fret {
+        return None;
+    }
+
+    // 同一根手指沿着弦移动
+    if from.finger_index > 0 && from.finger_index == to.finger_index {
+        return Some(LegatoTechnique::Slide);
+    }
+
+    if to.fret > from.fret {
+        // 击弦时，上一个音的手指要一直按着，后面的手指才能砸下去
+        let from_is_held = from.finger_index == -1
+            || hand.fingers.iter().any(|finger| {
+                finger.finger_index == from.finger_index
+                    && finger.string_index == from.string_index
+                    && finger.fret == from.fret
+                    && finger.press != PressState::Open
+            });
+        (from_is_held && to.finger_index > from.finger_index).then_some(LegatoTechnique::HammerOn)
+    } else {
+        // 勾弦时，发声的手指要提前按好，或者至少手不能换把
+        let same_position = to.finger_index == -1
+            || previous.hand_position == hand.hand_position
+            || previous.fingers.iter().any(|finger| {
+                finger.finger_index == to.finger_index
+                    && finger.string_index == to.string_index
+                    && finger.fret == to.fret
+                    && finger.press == PressState::Pressed
+            });
+        (same_position && to.finger_index < from.finger_index).then_some(LegatoTechnique::PullOff)
+    }
+}
+
+/// 在real_tick之前开始的最后一个音高为note的音在什么时候结束，只看这根弦或者不知道弦的音
+///
+/// # 参数
+/// * `spans_by_note` - 按音高分组的音符时值
+fn from_note_end(
+    spans_by_note: &HashMap<i32, Vec<&NoteSpan>>,
+    note: i32,
+    string_index: i32,
+    real_tick: f64,
+) -> Option<f64> {
+    spans_by_note
+        .get(&note)?
+        .iter()
+        .filter(|span| {
+            (span.string_index == -1 || span.string_index == string_index)
+                && span.real_tick < real_tick
+        })
+        .max_by(|a, b| a.real_tick.total_cmp(&b.real_tick))
+        .map(|span| span.end_tick)
+}
+
+/// 识别一串左手手型里可以用连奏技巧演奏的音
+///
+/// 音符事件只有一个音，手型只有一个新弹出的音，这根弦上一个手型也在发声，
+/// 并且间隔不超过max_gap秒时，根据音高方向和手指的连续性判断是击弦、勾弦还是滑弦。
+/// 和弦里的音即使品格没有变化也要重新拨弦，不会被当成连奏。
+/// 这根弦上的上一个音和这个音重叠是MIDI里常见的连奏写法，
+/// 上一个音在这个音开始之前就已经结束时弦已经停止振动，这个音要重新拨弦
+///
+/// # 参数
+/// * `guitar` - 吉他，用来计算上一个音的音高
+/// * `hand_pose_list` - 左手手型，第一个是初始手型
+/// * `real_ticks` - 每个手型的时间
+/// * `notes_map` - 音符事件，用real_tick对应到手型，找不到音符事件的手型不识别
+/// * `note_spans` - 每个音符的时值，找不到上一个音的时值时认为它一直响到这个音开始
+/// * `tempo_map` - 速度表
+/// * `max_gap` - 两个音之间最长的间隔，单位是秒，不大于0时不识别
+///
+/// 返回与手型一一对应的连奏信息
+pub fn detect_legato(
+    guitar: &Guitar,
+    hand_pose_list: &[LeftHand],
+    real_ticks: &[f64],
+    notes_map: &[NoteInfo],
+    note_spans: &[NoteSpan],
+    tempo_map: &TempoMap,
+    max_gap: f64,
+) -> Vec<Option<LegatoInfo>> {
+    let mut result = vec![None; hand_pose_list.len()];
+    if max_gap <= 0.0 {
+        return result;
+    }
+
+    let events: HashMap<u64, &NoteInfo> = notes_map
+        .iter()
+        .map(|note_info| (note_info.real_tick.to_bits(), note_info))
+        .collect();
+    let mut spans_by_note: HashMap<i32, Vec<&NoteSpan>> = HashMap::new();
+    for span in note_spans {
+        spans_by_note.entry(span.note).or_default().push(span);
+    }
+
+    for i in 2..hand_pose_list.len() {
+        let (previous, hand) = (&hand_pose_list[i - 1], &hand_pose_list[i]);
+        let gap = tempo_map.seconds_between(real_ticks[i - 1], real_ticks[i]);
+        if gap <= 0.0 || gap > max_gap {
+            continue;
+        }
+        // 只有单音才能用连奏演奏
+        let Some(&[_]) = events
+            .get(&real_ticks[i].to_bits())
+            .map(|note_info| note_info.notes.as_slice())
+        else {
+            continue;
+        };
+
+        let (Some(previous_notes), Some(notes)) = (sounding_notes(previous), sounding_notes(hand))
+        else {
+            continue;
+        };
+        // 上一个音必须是同一根弦上正在发声的音
+        let Some(to) = single_played_note(&previous_notes, &notes) else {
+            continue;
+        };
+        let Some(&from) = previous_notes.get(&to.string_index) else {
+            continue;
+        };
+        // 短弦上空弦和按弦的音高差不等于品格差，要按弦本身计算上一个音的音高，
+        // 上一个音已经结束时要重新拨弦
+        let Some(from_note) =
+            guitar.guitar_strings[to.string_index as usize].get_note_by_fret(from.fret)
+        else {
+            continue;
+        };
+        if from_note_end(&spans_by_note, from_note, to.string_index, real_ticks[i])
+            .is_some_and(|end_tick| end_tick < real_ticks[i])
+        {
+            continue;
+        }
+
+        result[i] = classify_legato(from, to, previous, hand).map(|technique| LegatoInfo {
+            technique,
+            string_index: to.string_index,
+            from_fret: from.fret,
+            to_fret: to.fret,
+            finger_index: to.finger_index,
+        });
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+    use crate::hand::left_finger::LeftFinger;
+
+    /// 每个手型对应一个只有一个音的音符事件
+    fn single_notes(real_ticks: &[f64]) -> Vec<NoteInfo> {
+        real_ticks
+            .iter()
+            .map(|&real_tick| NoteInfo {
+                notes: vec![60],
+                real_tick,
+                string_indices: Vec::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_detect_hammer_on_pull_off_and_slide() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let string = &guitar.guitar_strings[2];
+        let hand = |pressed: &[(i32, i32)]| {
+            let fingers = (1..5)
+                .map(
+                    |finger_index| match pressed.iter().find(|(f, _)| *f == finger_index) {
+                        Some(&(_, fret)) => LeftFinger::new(finger_index, string, fret, "Pressed"),
+                        None => LeftFinger::new(finger_index, string, 4 + finger_index, "Open"),
+                    },
+                )
+                .collect();
+            LeftHand::new(fingers, false, 5.73)
+        };
+
+        let hand_pose_list = vec![
+            hand(&[(1, 1), (2, 2), (3, 3), (4, 4)]),
+            hand(&[(1, 5)]),
+            hand(&[(1, 5), (2, 7)]),
+            hand(&[(1, 5)]),
+            hand(&[(1, 7)]),
+            hand(&[(1, 9)]),
+        ];
+        // 最后一个音间隔太长，不算连奏
+        let real_ticks = vec![0.0, 480.0, 600.0, 720.0, 840.0, 1920.0];
+        let notes_map = single_notes(&real_ticks);
+        let tempo_map = TempoMap::new(&[], 480);
+
+        let legato = detect_legato(
+            &guitar,
+            &hand_pose_list,
+            &real_ticks,
+            &notes_map,
+            &[],
+            &tempo_map,
+            0.25,
+        );
+        let techniques: Vec<_> = legato
+            .iter()
+            .map(|info| info.as_ref().map(|info| info.technique))
+            .collect();
+        assert_eq!(
+            techniques,
+            vec![
+                None,
+                None,
+                Some(LegatoTechnique::HammerOn),
+                Some(LegatoTechnique::PullOff),
+                Some(LegatoTechnique::Slide),
+                None,
+            ]
+        );
+        assert!(
+            detect_legato(
+                &guitar,
+                &hand_pose_list,
+                &real_ticks,
+                &notes_map,
+                &[],
+                &tempo_map,
+                0.0
+            )
+            .iter()
+            .all(Option::is_none)
+        );
+
+        // 第三弦空弦是55，每个手型按出的音高
+        let pitched: Vec<NoteInfo> = [55, 60, 62, 60, 62, 64]
+            .into_iter()
+            .zip(&real_ticks)
+            .map(|(note, &real_tick)| NoteInfo {
+                notes: vec![note],
+                real_tick,
+                string_indices: Vec::new(),
+            })
+            .collect();
+        let techniques_with = |length: f64| -> Vec<Option<LegatoTechnique>> {
+            let note_spans: Vec<NoteSpan> = pitched
+                .iter()
+                .map(|note_info| NoteSpan {
+                    real_tick: note_info.real_tick,
+                    end_tick: note_info.real_tick + length,
+                    note: note_info.notes[0],
+                    string_index: -1,
+                })
+                .collect();
+            detect_legato(
+                &guitar,
+                &hand_pose_list,
+                &real_ticks,
+                &pitched,
+                &note_spans,
+                &tempo_map,
+                0.25,
+            )
+            .iter()
+            .map(|info| info.as_ref().map(|info| info.technique))
+            .collect()
+        };
+        // 上一个音和下一个音重叠仍然是连奏
+        assert_eq!(techniques_with(240.0), techniques);
+        // 上一个音在下一个音开始之前就结束了，要重新拨弦
+        assert!(techniques_with(60.0).iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_replucked_dyad_is_not_legato() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        // 第三弦第五品的音保持不变，第二弦从第六品换到第七品
+        let hand = |third_finger: &str| {
+            let fingers = vec![
+                LeftFinger::new(1, &guitar.guitar_strings[2], 5, "Pressed"),
+                LeftFinger::new(2, &guitar.guitar_strings[1], 6, "Pressed"),
+                LeftFinger::new(3, &guitar.guitar_strings[1], 7, third_finger),
+                LeftFinger::new(4, &guitar.guitar_strings[1], 8, "Open"),
+            ];
+            LeftHand::new(fingers, false, 5.73)
+        };
+        let hand_pose_list = vec![hand("Open"), hand("Open"), hand("Pressed")];
+        let real_ticks = vec![0.0, 480.0, 600.0];
+        let tempo_map = TempoMap::new(&[], 480);
+        // 两个音一起重新拨弦
+        let dyads: Vec<NoteInfo> = [vec![60, 65], vec![60, 65], vec![60, 66]]
+            .into_iter()
+            .zip(&real_ticks)
+            .map(|(notes, &real_tick)| NoteInfo {
+                notes,
+                real_tick,
+                string_indices: Vec::new(),
+            })
+            .collect();
+        let legato = detect_legato(
+            &guitar,
+            &hand_pose_list,
+            &real_ticks,
+            &dyads,
+            &[],
+            &tempo_map,
+            0.25,
+        );
+        assert!(legato.iter().all(Option::is_none));
+
+        // 同样的手型只弹一个音时才是击弦
+        let legato = detect_legato(
+            &guitar,
+            &hand_pose_list,
+            &real_ticks,
+            &single_notes(&real_ticks),
+            &[],
+            &tempo_map,
+            0.25,
+        );
+        assert_eq!(
+            legato[2].as_ref().map(|info| info.technique),
+            Some(LegatoTechnique::HammerOn)
+        );
+    }
+
+    #[test]
+    fn test_held_bass_does_not_block_melody_hammer_on() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let (melody, bass) = (&guitar.guitar_strings[2], &guitar.guitar_strings[5]);
+        let hand = |third_finger: &str| {
+            let fingers = vec![
+                LeftFinger::new(1, melody, 5, "Pressed"),
+                LeftFinger::new(2, bass, 3, "Pressed"),
+                LeftFinger::new(3, melody, 7, third_finger),
+                LeftFinger::new(4, melody, 8, "Open"),
+            ];
+            LeftHand::new(fingers, false, 5.73)
+        };
+        let hand_pose_list = vec![hand("Open"), hand("Open"), hand("Pressed")];
+        let real_ticks = vec![0.0, 480.0, 600.0];
+        // 低音和旋律一起弹出，低音一直响，旋律在第三弦从第五品击弦到第七品
+        let notes_map: Vec<NoteInfo> = [vec![43, 60], vec![43, 60], vec![62]]
+            .into_iter()
+            .zip(&real_ticks)
+            .map(|(notes, &real_tick)| NoteInfo {
+                notes,
+                real_tick,
+                string_indices: Vec::new(),
+            })
+            .collect();
+        let span = |real_tick: f64, end_tick: f64, note: i32| NoteSpan {
+            real_tick,
+            end_tick,
+            note,
+            string_index: -1,
+        };
+        let note_spans = vec![
+            span(480.0, 1920.0, 43),
+            span(480.0, 620.0, 60),
+            span(600.0, 720.0, 62),
+        ];
+        let legato = detect_legato(
+            &guitar,
+            &hand_pose_list,
+            &real_ticks,
+            &notes_map,
+            &note_spans,
+            &TempoMap::new(&[], 480),
+            0.25,
+        );
+        assert_eq!(
+            legato[2].as_ref().map(|info| info.technique),
+            Some(LegatoTechnique::HammerOn)
+        );
+    }
+
+    #[test]
+    fn test_short_string_open_note_pitch() {
+        let guitar = InstrumentProfile::presets()
+            .into_iter()
+            .find(|profile| profile.name == "5弦班卓琴 (Open G)")
+            .unwrap()
+            .create_guitar(false);
+        let short_string = &guitar.guitar_strings[4];
+        let open_note = short_string.get_base_note();
+        let hand = |first_finger: &str| {
+            let mut fingers: Vec<LeftFinger> = (1..5)
+                .map(|finger_index| {
+                    let press = if finger_index == 1 {
+                        first_finger
+                    } else {
+                        "Open"
+                    };
+                    LeftFinger::new(finger_index, short_string, 6 + finger_index, press)
+                })
+                .collect();
+            fingers.push(LeftFinger::new(-1, short_string, 0, "Open"));
+            LeftHand::new(fingers, false, 5.73)
+        };
+        // 第五弦空弦之后在第七品按出高两个半音的音
+        let hand_pose_list = vec![hand("Open"), hand("Open"), hand("Pressed")];
+        let real_ticks = vec![0.0, 480.0, 600.0];
+        let notes_map: Vec<NoteInfo> = [open_note, open_note, open_note + 2]
+            .into_iter()
+            .zip(&real_ticks)
+            .map(|(note, &real_tick)| NoteInfo {
+                notes: vec![note],
+                real_tick,
+                string_indices: Vec::new(),
+            })
+            .collect();
+        let technique_with = |open_end: f64| {
+            let note_spans = vec![
+                NoteSpan {
+                    real_tick: 480.0,
+                    end_tick: open_end,
+                    note: open_note,
+                    string_index: -1,
+                },
+                NoteSpan {
+                    real_tick: 600.0,
+                    end_tick: 720.0,
+                    note: open_note + 2,
+                    string_index: -1,
+                },
+            ];
+            detect_legato(
+                &guitar,
+                &hand_pose_list,
+                &real_ticks,
+                &notes_map,
+                &note_spans,
+                &TempoMap::new(&[], 480),
+                0.25,
+            )[2]
+            .as_ref()
+            .map(|info| info.technique)
+        };
+
+        assert_eq!(technique_with(620.0), Some(LegatoTechnique::HammerOn));
+        // 空弦的音已经结束，要重新拨弦
+        assert_eq!(technique_with(540.0), None);
+    }
+}
@@ -0,0 +1,171 @@
+// LongRest.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::midi::midi_to_note::{MidiProcessor, NoteInfo, NoteSpan, TempoChange, TempoMap};
+
+/// 长休止的长度单位
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RestLengthUnit {
+    /// 拍数，不受速度影响
+    Beats,
+    /// 秒数，慢速的曲子里同样拍数的休止更容易回到休息姿势
+    Seconds,
+}
+
+impl RestLengthUnit {
+    pub fn all() -> [RestLengthUnit; 2] {
+        [RestLengthUnit::Beats, RestLengthUnit::Seconds]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RestLengthUnit::Beats => "拍",
+            RestLengthUnit::Seconds => "秒",
+        }
+    }
+}
+
+/// 一段长休止，从前面所有的音符都结束开始，到下一个音符开始为止
+///
+/// Params:
+/// - start_tick, end_tick: 休止开始和结束的tick
+/// - start_frame, end_frame: 休止开始和结束的帧
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct LongRest {
+    pub start_tick: f64,
+    pub end_tick: f64,
+    pub start_frame: f64,
+    pub end_frame: f64,
+}
+
+/// 找出曲子里所有不短于min_length的休止，曲子开始之前和结束之后的休止不计算在内
+///
+/// 音符的结束时间来自MIDI的音符关闭事件，找不到时值的音符按在开始时就结束处理
+///
+/// # 参数
+/// * `note_spans` - 从MIDI读取的每个音符的时值
+/// * `min_length` - 休止最短的长度，不大于0时不识别
+/// * `unit` - min_length的单位
+pub fn detect_long_rests(
+    notes_map: &[NoteInfo],
+    note_spans: &[NoteSpan],
+    min_length: f64,
+    unit: RestLengthUnit,
+    tempo_changes: &Vec<TempoChange>,
+    ticks_per_beat: u16,
+    fps: f64,
+) -> Vec<LongRest> {
+    if min_length <= 0.0 {
+        return Vec::new();
+    }
+
+    let mut note_ends: HashMap<u64, f64> = HashMap::new();
+    for span in note_spans {
+        let end_tick = note_ends.entry(span.real_tick.to_bits()).or_insert(0.0);
+        *end_tick = end_tick.max(span.end_tick);
+    }
+
+    let tempo_map = TempoMap::new(tempo_changes, ticks_per_beat);
+    let midi_processor = MidiProcessor::new();
+    let mut long_rests = Vec::new();
+    // 到目前为止所有音符都结束的tick
+    let mut sounding_until: Option<f64> = None;
+    for note_info in notes_map
+        .iter()
+        .filter(|note_info| !note_info.notes.is_empty())
+    {
+        let real_tick = note_info.real_tick;
+        if let Some(start_tick) = sounding_until
+            && start_tick < real_tick
+        {
+            let length = match unit {
+                RestLengthUnit::Beats => (real_tick - start_tick) / ticks_per_beat as f64,
+                RestLengthUnit::Seconds => tempo_map.seconds_between(start_tick, real_tick),
+            };
+            if length >= min_length {
+                long_rests.push(LongRest {
+                    start_tick,
+                    end_tick: real_tick,
+                    start_frame: midi_processor.calculate_frame(
+                        tempo_changes,
+                        ticks_per_beat,
+                        fps,
+                        start_tick,
+                    ),
+                    end_frame: midi_processor.calculate_frame(
+                        tempo_changes,
+                        ticks_per_beat,
+                        fps,
+                        real_tick,
+                    ),
+                });
+            }
+        }
+
+        let end_tick = note_ends
+            .get(&real_tick.to_bits())
+            .copied()
+            .unwrap_or(real_tick)
+            .max(real_tick);
+        sounding_until = Some(sounding_until.map_or(end_tick, |until| until.max(end_tick)));
+    }
+    long_rests
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rest_starts_when_all_notes_have_ended() {
+        let note_info = |real_tick: f64| NoteInfo {
+            notes: vec![60],
+            real_tick,
+            string_indices: Vec::new(),
+        };
+        let span = |real_tick: f64, end_tick: f64| NoteSpan {
+            real_tick,
+            end_tick,
+            note: 60,
+            string_index: -1,
+        };
+        let notes_map = [note_info(0.0), note_info(480.0), note_info(4800.0)];
+        // 第一个音一直持续到第二个音之后，第二个音是短音
+        let note_spans = [span(0.0, 1920.0), span(480.0, 600.0), span(4800.0, 5280.0)];
+        // 120BPM，一拍0.5秒
+        let tempo_changes = vec![TempoChange {
+            track: 0,
+            tempo: 500_000,
+            time: 0,
+        }];
+        let detect = |min_length: f64, unit: RestLengthUnit| {
+            detect_long_rests(
+                &notes_map,
+                &note_spans,
+                min_length,
+                unit,
+                &tempo_changes,
+                480,
+                30.0,
+            )
+        };
+
+        let long_rests = detect(4.0, RestLengthUnit::Beats);
+        assert_eq!(
+            long_rests,
+            vec![LongRest {
+                start_tick: 1920.0,
+                end_tick: 4800.0,
+                start_frame: 60.0,
+                end_frame: 150.0,
+            }]
+        );
+        // 休止是6拍，也就是3秒
+        assert!(detect(4.0, RestLengthUnit::Seconds).is_empty());
+        assert_eq!(detect(3.0, RestLengthUnit::Seconds), long_rests);
+        assert!(detect(0.0, RestLengthUnit::Beats).is_empty());
+    }
+}
@@ -0,0 +1,478 @@
+// NoteFallback.rs
+
+use serde::{Deserialize, Serialize};
+
+use crate::guitar::guitar_instance::Guitar;
+use crate::hand::chord_diagnosis::{is_notes_playable, iter_playable_subsets};
+use crate::hand::hand_profile::HandProfile;
+use crate::midi::midi_to_note::NoteInfo;
+use crate::recorder::recorder_pool::enumerate_finger_positions;
+
+/// 每种退化方式最多尝试的方案数
+const MAX_PLANS_PER_STRATEGY: usize = 8;
+
+/// 一组音符找不到按法时的退化方式
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum FallbackStrategy {
+    /// 把一个音移高或移低八度
+    OctaveShift,
+    /// 把和弦拆成从低到高快速演奏的几组
+    Roll,
+    /// 去掉最少的音符，优先保留低音和旋律
+    DropNotes,
+}
+
+impl FallbackStrategy {
+    /// 默认的尝试顺序，不丢音的方式在前
+    pub fn default_order() -> Vec<FallbackStrategy> {
+        vec![
+            FallbackStrategy::OctaveShift,
+            FallbackStrategy::Roll,
+            FallbackStrategy::DropNotes,
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FallbackStrategy::OctaveShift => "移八度",
+            FallbackStrategy::Roll => "分解成琶音",
+            FallbackStrategy::DropNotes => "去掉部分音符",
+        }
+    }
+}
+
+/// 退化设置
+///
+/// Params:
+/// - strategies: 依次尝试的退化方式，为空时和以前一样直接重复上一个手型
+/// - roll_gap_ticks: 分解成琶音时相邻两组之间的tick间隔，下一个音符事件太近时会缩小，
+///   保证所有组都在下一个音符事件之前演奏
+#[derive(Clone, Debug)]
+pub struct FallbackSettings {
+    pub strategies: Vec<FallbackStrategy>,
+    pub roll_gap_ticks: f64,
+}
+
+impl Default for FallbackSettings {
+    fn default() -> Self {
+        FallbackSettings {
+            strategies: FallbackStrategy::default_order(),
+            roll_gap_ticks: 30.0,
+        }
+    }
+}
+
+/// 一个退化方案，parts里的音符事件依次演奏，只有琶音会有多个事件
+#[derive(Clone, Debug)]
+pub struct FallbackPlan {
+    pub strategy: FallbackStrategy,
+    pub parts: Vec<NoteInfo>,
+    pub dropped_notes: Vec<i32>,
+    pub shifted_notes: Vec<(i32, i32)>,
+}
+
+/// 实际采用的退化方式，记录在无法处理的音符信息里
+///
+/// Params:
+/// - strategy: 采用的退化方式，为None表示所有方式都失败，重复了上一个手型
+/// - played: 实际演奏的音符事件
+/// - dropped_notes: 被放弃的音符
+/// - shifted_notes: 移了八度的音符，(原来的音, 实际演奏的音)
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct FallbackResolution {
+    pub strategy: Option<FallbackStrategy>,
+    pub played: Vec<NoteInfo>,
+    pub dropped_notes: Vec<i32>,
+    pub shifted_notes: Vec<(i32, i32)>,
+}
+
+impl FallbackResolution {
+    /// 所有方式都失败时的记录，原来的音符全部被放弃
+    pub fn repeated(note_info: &NoteInfo) -> Self {
+        FallbackResolution {
+            strategy: None,
+            played: Vec::new(),
+            dropped_notes: note_info.notes.clone(),
+            shifted_notes: Vec::new(),
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self.strategy {
+            Some(strategy) => strategy.label(),
+            None => "重复上一个手型",
+        }
+    }
+}
+
+impl FallbackPlan {
+    /// 采用这个方案的记录，无法过渡而重复了上一个手型的组不算演奏，它的音符记为放弃
+    ///
+    /// # 参数
+    /// * `failed_parts` - 无法过渡的组在parts中的下标
+    pub fn resolution(&self, failed_parts: &[usize]) -> FallbackResolution {
+        let mut dropped_notes = self.dropped_notes.clone();
+        let mut played = Vec::new();
+        for (index, part) in self.parts.iter().enumerate() {
+            if failed_parts.contains(&index) {
+                dropped_notes.extend(&part.notes);
+            } else {
+                played.push(part.clone());
+            }
+        }
+        FallbackResolution {
+            strategy: Some(self.strategy),
+            played,
+            dropped_notes,
+            shifted_notes: self.shifted_notes.clone(),
+        }
+    }
+}
+
+/// 判断一个音符事件是否存在按法，已经知道弦的音符只能使用指定的弦
+fn is_note_info_playable(
+    note_info: &NoteInfo,
+    guitar: &Guitar,
+    hand_profile: &HandProfile,
+) -> bool {
+    if note_info.string_indices.is_empty() {
+        is_notes_playable(&note_info.notes, guitar, hand_profile)
+    } else {
+        !enumerate_finger_positions(
+            &note_info.notes,
+            &note_info.string_indices,
+            guitar,
+            hand_profile,
+        )
+        .is_empty()
+    }
+}
+
+/// 只保留部分音符，同时保留对应的弦
+fn keep_notes(note_info: &NoteInfo, keep: impl Fn(usize) -> bool) -> NoteInfo {
+    let notes = (0..note_info.notes.len())
+        .filter(|&i| keep(i))
+        .map(|i| note_info.notes[i])
+        .collect();
+    let string_indices = if note_info.string_indices.is_empty() {
+        Vec::new()
+    } else {
+        (0..note_info.string_indices.len())
+            .filter(|&i| keep(i))
+            .map(|i| note_info.string_indices[i])
+            .collect()
+    };
+    NoteInfo {
+        notes,
+        real_tick: note_info.real_tick,
+        string_indices,
+    }
+}
+
+/// 按照设置的顺序生成所有可以演奏的退化方案
+///
+/// 方案只保证单独可以演奏，能否从之前的手型过渡需要求解器自己尝试
+///
+/// # 参数
+/// * `note_info` - 处理过的音符事件
+/// * `next_tick` - 下一个音符事件的real_tick，琶音的所有组都要在它之前演奏
+/// * `settings` - 退化设置
+pub fn fallback_plans(
+    note_info: &NoteInfo,
+    next_tick: Option<f64>,
+    guitar: &Guitar,
+    hand_profile: &HandProfile,
+    settings: &FallbackSettings,
+) -> Vec<FallbackPlan> {
+    let mut plans = Vec::new();
+    for strategy in &settings.strategies {
+        match strategy {
+            FallbackStrategy::OctaveShift => {
+                plans.extend(octave_shift_plans(note_info, guitar, hand_profile))
+            }
+            FallbackStrategy::Roll => plans.extend(roll_plan(
+                note_info,
+                next_tick,
+                guitar,
+                hand_profile,
+                settings.roll_gap_ticks,
+            )),
+            FallbackStrategy::DropNotes => {
+                plans.extend(drop_notes_plans(note_info, guitar, hand_profile))
+            }
+        }
+    }
+    plans
+}
+
+/// 每次只移动一个音，中间的音优先，然后是旋律，最后才动低音
+///
+/// 已经知道弦的音符不能移八度
+fn octave_shift_plans(
+    note_info: &NoteInfo,
+    guitar: &Guitar,
+    hand_profile: &HandProfile,
+) -> Vec<FallbackPlan> {
+    let notes = &note_info.notes;
+    if !note_info.string_indices.is_empty() || notes.len() < 2 {
+        return Vec::new();
+    }
+    let (min_note, max_note) = guitar.get_note_range();
+    let bass = *notes.iter().min().unwrap();
+    let melody = *notes.iter().max().unwrap();
+
+    let mut order: Vec<usize> = (0..notes.len()).collect();
+    order.sort_by_key(|&i| (notes[i] == bass, notes[i] == melody, notes[i]));
+
+    let mut plans = Vec::new();
+    for index in order {
+        for shift in [-12, 12] {
+            let shifted = notes[index] + shift;
+            if shifted < min_note || shifted > max_note || notes.contains(&shifted) {
+                continue;
+            }
+            let mut new_notes = notes.clone();
+            new_notes[index] = shifted;
+            if !is_notes_playable(&new_notes, guitar, hand_profile) {
+                continue;
+            }
+            plans.push(FallbackPlan {
+                strategy: FallbackStrategy::OctaveShift,
+                parts: vec![NoteInfo {
+                    notes: new_notes,
+                    real_tick: note_info.real_tick,
+                    string_indices: Vec::new(),
+                }],
+                dropped_notes: Vec::new(),
+                shifted_notes: vec![(notes[index], shifted)],
+            });
+            if plans.len() >= MAX_PLANS_PER_STRATEGY {
+                return plans;
+            }
+        }
+    }
+    plans
+}
+
+/// 从低音开始，把尽可能多的音放进同一组，得到从低到高依次演奏的几组
+///
+/// 有单独一个音都无法演奏时不能分解，
+/// 下一个音符事件太近时缩小每组之间的间隔，让最后一组也在下一个音符事件之前
+fn roll_plan(
+    note_info: &NoteInfo,
+    next_tick: Option<f64>,
+    guitar: &Guitar,
+    hand_profile: &HandProfile,
+    roll_gap_ticks: f64,
+) -> Option<FallbackPlan> {
+    if roll_gap_ticks <= 0.0 || note_info.notes.len() < 2 {
+        return None;
+    }
+    let mut order: Vec<usize> = (0..note_info.notes.len()).collect();
+    order.sort_by_key(|&i| note_info.notes[i]);
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut current: Vec<usize> = Vec::new();
+    for index in order {
+        current.push(index);
+        let part = keep_notes(note_info, |i| current.contains(&i));
+        if is_note_info_playable(&part, guitar, hand_profile) {
+            continue;
+        }
+        current.pop();
+        if current.is_empty() {
+            return None;
+        }
+        groups.push(std::mem::take(&mut current));
+        current.push(index);
+        let single = keep_notes(note_info, |i| i == index);
+        if !is_note_info_playable(&single, guitar, hand_profile) {
+            return None;
+        }
+    }
+    groups.push(current);
+    if groups.len() < 2 {
+        return None;
+    }
+    let roll_gap_ticks = match next_tick {
+        Some(next_tick) => {
+            roll_gap_ticks.min((next_tick - note_info.real_tick) / groups.len() as f64)
+        }
+        None => roll_gap_ticks,
+    };
+    if roll_gap_ticks <= 0.0 {
+        return None;
+    }
+
+    let parts = groups
+        .iter()
+        .enumerate()
+        .map(|(group_index, group)| {
+            let mut part = keep_notes(note_info, |i| group.contains(&i));
+            part.real_tick += roll_gap_ticks * group_index as f64;
+            part
+        })
+        .collect();
+    Some(FallbackPlan {
+        strategy: FallbackStrategy::Roll,
+        parts,
+        dropped_notes: Vec::new(),
+        shifted_notes: Vec::new(),
+    })
+}
+
+/// 去掉最少的音符，和find_closest_playable_subset的顺序相同
+fn drop_notes_plans(
+    note_info: &NoteInfo,
+    guitar: &Guitar,
+    hand_profile: &HandProfile,
+) -> Vec<FallbackPlan> {
+    let notes = &note_info.notes;
+    let note_count = {
+        let mut unique = notes.clone();
+        unique.sort();
+        unique.dedup();
+        unique.len()
+    };
+    let make_plan = |part: NoteInfo| FallbackPlan {
+        strategy: FallbackStrategy::DropNotes,
+        dropped_notes: notes
+            .iter()
+            .filter(|note| !part.notes.contains(note))
+            .copied()
+            .collect(),
+        parts: vec![part],
+        shifted_notes: Vec::new(),
+    };
+
+    if note_info.string_indices.is_empty() {
+        return iter_playable_subsets(notes, guitar, hand_profile)
+            .filter(|subset| subset.len() < note_count)
+            .take(MAX_PLANS_PER_STRATEGY)
+            .map(|subset| {
+                make_plan(NoteInfo {
+                    notes: subset,
+                    real_tick: note_info.real_tick,
+                    string_indices: Vec::new(),
+                })
+            })
+            .collect();
+    }
+
+    // 已经知道弦的音符只尝试去掉一个音，先去掉中间较高的音，最后才去掉旋律和低音
+    let mut order: Vec<usize> = (0..notes.len()).collect();
+    order.sort_by_key(|&i| notes[i]);
+    let mut inner: Vec<usize> = order[1..order.len().saturating_sub(1)].to_vec();
+    inner.reverse();
+    let drop_order: Vec<usize> = inner
+        .into_iter()
+        .chain(order.iter().rev().take(1).copied())
+        .chain(order.iter().take(1).copied())
+        .collect();
+    drop_order
+        .into_iter()
+        .map(|dropped| keep_notes(note_info, |i| i != dropped))
+        .filter(|part| !part.notes.is_empty() && is_note_info_playable(part, guitar, hand_profile))
+        .take(MAX_PLANS_PER_STRATEGY)
+        .map(make_plan)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+
+    fn note_info(notes: Vec<i32>) -> NoteInfo {
+        NoteInfo {
+            notes,
+            real_tick: 960.0,
+            string_indices: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_fallback_plans_keep_bass_and_melody() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let hand_profile = HandProfile::default();
+        // 低音E和高音E之间挤了四个相邻的半音，无法同时按出来
+        let unplayable = note_info(vec![40, 60, 61, 62, 63, 76]);
+        assert!(!is_notes_playable(
+            &unplayable.notes,
+            &guitar,
+            &hand_profile
+        ));
+
+        let plans = fallback_plans(
+            &unplayable,
+            None,
+            &guitar,
+            &hand_profile,
+            &FallbackSettings::default(),
+        );
+        assert!(!plans.is_empty());
+        for plan in &plans {
+            for part in &plan.parts {
+                assert!(is_notes_playable(&part.notes, &guitar, &hand_profile));
+            }
+        }
+
+        let drop_plan = plans
+            .iter()
+            .find(|plan| plan.strategy == FallbackStrategy::DropNotes)
+            .expect("去掉音符的方案总是存在");
+        let kept = &drop_plan.parts[0].notes;
+        assert!(kept.contains(&40) && kept.contains(&76));
+        assert!(!drop_plan.dropped_notes.is_empty());
+
+        if let Some(roll) = plans
+            .iter()
+            .find(|plan| plan.strategy == FallbackStrategy::Roll)
+        {
+            let mut rolled: Vec<i32> = roll.parts.iter().flat_map(|p| p.notes.clone()).collect();
+            rolled.sort();
+            assert_eq!(rolled, vec![40, 60, 61, 62, 63, 76]);
+            assert!(roll.parts[1].real_tick > roll.parts[0].real_tick);
+        }
+    }
+
+    #[test]
+    fn test_roll_parts_stay_before_next_event() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let hand_profile = HandProfile::default();
+        let unplayable = note_info(vec![40, 60, 61, 62, 63, 76]);
+        let settings = FallbackSettings {
+            strategies: vec![FallbackStrategy::Roll],
+            roll_gap_ticks: 120.0,
+        };
+        let free = roll_plan(&unplayable, None, &guitar, &hand_profile, 120.0)
+            .expect("每个音都能单独演奏，可以分解成琶音");
+        assert!(free.parts.len() >= 2);
+        assert_eq!(free.parts[1].real_tick - free.parts[0].real_tick, 120.0);
+
+        // 下一个音符事件只隔了60tick，所有组都要挤在它之前
+        let next_tick = unplayable.real_tick + 60.0;
+        let plans = fallback_plans(
+            &unplayable,
+            Some(next_tick),
+            &guitar,
+            &hand_profile,
+            &settings,
+        );
+        let roll = &plans[0];
+        assert_eq!(roll.parts.len(), free.parts.len());
+        assert_eq!(roll.parts[0].real_tick, unplayable.real_tick);
+        assert!(
+            roll.parts
+                .windows(2)
+                .all(|pair| pair[0].real_tick < pair[1].real_tick)
+        );
+        assert!(roll.parts.iter().all(|part| part.real_tick < next_tick));
+
+        // 无法过渡的组不算演奏，它的音符记为放弃
+        let resolution = roll.resolution(&[1]);
+        assert_eq!(resolution.played.len(), roll.parts.len() - 1);
+        assert_eq!(resolution.dropped_notes, roll.parts[1].notes);
+    }
+}
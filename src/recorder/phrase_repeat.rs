@@ -0,0 +1,386 @@
+// PhraseRepeat.rs
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::hand::left_hand::LeftHand;
+use crate::midi::midi_to_note::NoteInfo;
+use crate::recorder::left_hand_recorder::LeftHandRecorder;
+
+/// 重复乐句的按法一致性
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PhraseConsistencyMode {
+    /// 不处理重复乐句
+    Off,
+    /// 重复时使用和第一次相同的按法可以减少熵，仍然允许其它按法
+    Bonus,
+    /// 重复时尽量直接使用第一次的按法
+    Reuse,
+}
+
+impl PhraseConsistencyMode {
+    pub fn all() -> [PhraseConsistencyMode; 3] {
+        [
+            PhraseConsistencyMode::Off,
+            PhraseConsistencyMode::Bonus,
+            PhraseConsistencyMode::Reuse,
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PhraseConsistencyMode::Off => "不处理",
+            PhraseConsistencyMode::Bonus => "奖励相同按法",
+            PhraseConsistencyMode::Reuse => "直接复用按法",
+        }
+    }
+}
+
+/// 一次乐句重复
+///
+/// Params:
+/// - source_start: 第一次出现时第一个事件在notes_map中的下标
+/// - repeat_start: 重复时第一个事件在notes_map中的下标
+/// - len: 乐句包含的事件数
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RepeatedPhrase {
+    pub source_start: usize,
+    pub repeat_start: usize,
+    pub len: usize,
+}
+
+/// 重复乐句的检测结果
+///
+/// Params:
+/// - phrases: 所有的乐句重复，按出现的顺序排列
+/// - reference_ticks: 重复乐句里的事件对应的第一次出现的事件，都用real_tick表示
+#[derive(Clone, Debug, Default)]
+pub struct PhraseRepeats {
+    pub phrases: Vec<RepeatedPhrase>,
+    reference_ticks: HashMap<u64, f64>,
+}
+
+impl PhraseRepeats {
+    /// 找出notes_map里重复出现的音符序列
+    ///
+    /// 只比较音高和指定的弦，不比较节奏。一段乐句多次重复时，
+    /// 每次重复都指向最早出现的那一次
+    ///
+    /// # 参数
+    /// * `min_len` - 乐句最少包含的事件数，太短的重复只是巧合
+    pub fn detect(notes_map: &[NoteInfo], min_len: usize) -> Self {
+        let min_len = min_len.max(1);
+        let keys: Vec<EventKey> = notes_map.iter().map(event_key).collect();
+        let mut references: Vec<Option<usize>> = vec![None; notes_map.len()];
+        let mut phrases = Vec::new();
+        let mut first_seen: HashMap<&[EventKey], usize> = HashMap::new();
+
+        let mut index = 0;
+        while index + min_len <= keys.len() {
+            let window = &keys[index..index + min_len];
+            // 空事件不参与乐句
+            if window.iter().any(|(notes, _)| notes.is_empty()) {
+                index += 1;
+                continue;
+            }
+            let source_start = match first_seen.get(window) {
+                Some(&source) if source + min_len <= index => source,
+                _ => {
+                    first_seen.entry(window).or_insert(index);
+                    index += 1;
+                    continue;
+                }
+            };
+
+            // 尽量向后延长，但是重复不能和第一次出现重叠
+            let mut len = min_len;
+            while index + len < keys.len()
+                && source_start + len < index
+                && keys[source_start + len] == keys[index + len]
+            {
+                len += 1;
+            }
+            for offset in 0..len {
+                let source = source_start + offset;
+                references[index + offset] = Some(references[source].unwrap_or(source));
+            }
+            phrases.push(RepeatedPhrase {
+                source_start,
+                repeat_start: index,
+                len,
+            });
+            index += len;
+        }
+
+        let reference_ticks = references
+            .iter()
+            .enumerate()
+            .filter_map(|(index, reference)| {
+                reference.map(|source| {
+                    (
+                        notes_map[index].real_tick.to_bits(),
+                        notes_map[source].real_tick,
+                    )
+                })
+            })
+            .collect();
+
+        PhraseRepeats {
+            phrases,
+            reference_ticks,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.phrases.is_empty()
+    }
+
+    /// 重复乐句里的事件数
+    pub fn repeated_event_count(&self) -> usize {
+        self.reference_ticks.len()
+    }
+
+    /// real_tick这个事件是重复乐句的一部分时，返回第一次出现时对应事件的real_tick
+    pub fn reference_tick(&self, real_tick: f64) -> Option<f64> {
+        self.reference_ticks.get(&real_tick.to_bits()).copied()
+    }
+
+    /// 把第一次出现时的手型复制到每次重复上，返回复制的手型数
+    ///
+    /// 用于没有办法在求解时考虑整条路径的求解器，skip_tick返回true的事件不复制，
+    /// 例如使用了退化方案或者有按法约束的事件。复制以后重新计算进入和离开这个手型的熵，
+    /// Reuse模式总是复制，Bonus模式只在多出来的熵不超过bonus时复制
+    ///
+    /// # 参数
+    /// * `mode` - 重复乐句的按法一致性，Off时不复制
+    /// * `bonus` - Bonus模式下使用相同按法可以多出来的熵
+    /// * `transition_cost` - 两个手型之间的消耗，参数是之前的手型、之后的手型和它们的real_tick
+    pub fn apply_to_recorder(
+        &self,
+        recorder: &mut LeftHandRecorder,
+        mode: PhraseConsistencyMode,
+        bonus: f64,
+        skip_tick: impl Fn(f64) -> bool,
+        transition_cost: impl Fn(&LeftHand, &LeftHand, f64, f64) -> f64,
+    ) -> usize {
+        if mode == PhraseConsistencyMode::Off {
+            return 0;
+        }
+        let pose_indices: HashMap<u64, usize> = recorder
+            .real_ticks
+            .iter()
+            .enumerate()
+            // 第一个手型是初始手型
+            .skip(1)
+            .map(|(index, tick)| (tick.to_bits(), index))
+            .collect();
+        // 按时间顺序复制，后面的手型用前面已经复制好的手型计算消耗
+        let mut copies: Vec<(usize, usize)> = self
+            .reference_ticks
+            .iter()
+            .filter(|&(&tick_bits, &reference_tick)| {
+                !skip_tick(f64::from_bits(tick_bits)) && !skip_tick(reference_tick)
+            })
+            .filter_map(|(tick_bits, reference_tick)| {
+                Some((
+                    *pose_indices.get(tick_bits)?,
+                    *pose_indices.get(&reference_tick.to_bits())?,
+                ))
+            })
+            .collect();
+        copies.sort_unstable();
+
+        // 每一步增加的熵
+        let mut steps: Vec<f64> = recorder
+            .entropies
+            .iter()
+            .scan(0.0, |previous, &entropy| {
+                let step = entropy - *previous;
+                *previous = entropy;
+                Some(step)
+            })
+            .collect();
+        let ticks = &recorder.real_ticks;
+        let mut copied = 0;
+        for (target, source) in copies {
+            let poses = &recorder.hand_pose_list;
+            if poses[target].pose_key() == poses[source].pose_key() {
+                continue;
+            }
+            let hand = &poses[source];
+            let entering =
+                transition_cost(&poses[target - 1], hand, ticks[target - 1], ticks[target]);
+            let leaving = poses
+                .get(target + 1)
+                .map(|next| transition_cost(hand, next, ticks[target], ticks[target + 1]));
+            let old_cost = steps[target] + steps.get(target + 1).copied().unwrap_or_default();
+            let new_cost = entering + leaving.unwrap_or_default();
+            if mode == PhraseConsistencyMode::Bonus && new_cost - old_cost > bonus {
+                continue;
+            }
+
+            recorder.hand_pose_list[target] = recorder.hand_pose_list[source].clone();
+            steps[target] = entering;
+            if let Some(leaving) = leaving {
+                steps[target + 1] = leaving;
+            }
+            copied += 1;
+        }
+
+        let mut entropy = 0.0;
+        for (total, step) in recorder.entropies.iter_mut().zip(steps) {
+            entropy += step;
+            *total = entropy;
+        }
+        recorder.current_entropy = entropy;
+        copied
+    }
+}
+
+/// 比较事件时使用的键，(排序后的音高, 对应的弦)
+type EventKey = (Vec<i32>, Vec<i32>);
+
+/// 音高排序后比较，已经知道弦时弦也要相同
+fn event_key(note_info: &NoteInfo) -> EventKey {
+    let mut pairs: Vec<(i32, i32)> = note_info
+        .notes
+        .iter()
+        .enumerate()
+        .map(|(i, &note)| (note, note_info.string_indices.get(i).copied().unwrap_or(-1)))
+        .collect();
+    pairs.sort();
+    pairs.into_iter().unzip()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+    use crate::hand::left_finger::LeftFinger;
+
+    fn notes_map(notes: &[i32]) -> Vec<NoteInfo> {
+        notes
+            .iter()
+            .enumerate()
+            .map(|(i, &note)| NoteInfo {
+                notes: vec![note],
+                real_tick: (i * 240) as f64,
+                string_indices: Vec::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_detect_repeated_riff() {
+        // 同一个四音riff出现三次，中间夹着别的音
+        let riff = [40, 43, 45, 47];
+        let mut notes = riff.to_vec();
+        notes.extend([50, 52]);
+        notes.extend(riff);
+        notes.extend(riff);
+        let notes_map = notes_map(&notes);
+
+        let repeats = PhraseRepeats::detect(&notes_map, 4);
+        assert_eq!(repeats.repeated_event_count(), 8);
+        // 第三次重复也指向第一次出现
+        assert_eq!(repeats.reference_tick(notes_map[10].real_tick), Some(0.0));
+        assert_eq!(
+            repeats.reference_tick(notes_map[7].real_tick),
+            Some(notes_map[1].real_tick)
+        );
+        assert_eq!(repeats.reference_tick(notes_map[4].real_tick), None);
+        assert!(
+            PhraseRepeats::detect(&notes_map, 5)
+                .phrases
+                .iter()
+                .all(|phrase| phrase.len >= 5)
+        );
+    }
+
+    #[test]
+    fn test_apply_to_recorder_recosts_and_honours_bonus() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let hand = |position: i32| {
+            let finger = LeftFinger::new(1, &guitar.guitar_strings[2], position, "Pressed");
+            LeftHand::new(vec![finger], false, 5.73)
+        };
+        // 第一次出现在第一把位，重复时在第三把位，之后的音在第五把位
+        let notes_map = notes_map(&[40, 43, 45, 47, 40, 43, 45, 47, 50]);
+        let repeats = PhraseRepeats::detect(&notes_map, 4);
+        assert_eq!(repeats.repeated_event_count(), 4);
+        let positions = [1, 1, 1, 1, 3, 3, 3, 3, 5];
+        let new_recorder = || {
+            let mut recorder = LeftHandRecorder::new();
+            recorder.add_hand_pose(hand(1), 0.0, -240.0);
+            for (note_info, &position) in notes_map.iter().zip(&positions) {
+                let previous = recorder.current_hand_pose().unwrap().hand_position;
+                let cost = ((position - previous) as f64).powi(2);
+                recorder.add_hand_pose(hand(position), cost, note_info.real_tick);
+            }
+            recorder
+        };
+        let transition_cost = |from: &LeftHand, to: &LeftHand, _: f64, _: f64| {
+            ((to.hand_position - from.hand_position) as f64).powi(2)
+        };
+        let hand_positions = |recorder: &LeftHandRecorder| -> Vec<i32> {
+            recorder
+                .hand_pose_list
+                .iter()
+                .skip(1)
+                .map(|hand| hand.hand_position)
+                .collect()
+        };
+
+        let mut recorder = new_recorder();
+        let copied = repeats.apply_to_recorder(
+            &mut recorder,
+            PhraseConsistencyMode::Off,
+            0.5,
+            |_| false,
+            transition_cost,
+        );
+        assert_eq!(copied, 0);
+        assert_eq!(recorder.current_entropy, 8.0);
+
+        // 复制最后一个手型以后离开它的熵从4变成16，超过了奖励
+        let mut recorder = new_recorder();
+        let copied = repeats.apply_to_recorder(
+            &mut recorder,
+            PhraseConsistencyMode::Bonus,
+            0.5,
+            |_| false,
+            transition_cost,
+        );
+        assert_eq!(copied, 3);
+        assert_eq!(hand_positions(&recorder), vec![1, 1, 1, 1, 1, 1, 1, 3, 5]);
+        assert_eq!(recorder.current_entropy, 8.0);
+
+        // 直接复用时全部复制，熵按复制后的手型重新计算
+        let mut recorder = new_recorder();
+        let copied = repeats.apply_to_recorder(
+            &mut recorder,
+            PhraseConsistencyMode::Reuse,
+            0.5,
+            |_| false,
+            transition_cost,
+        );
+        assert_eq!(copied, 4);
+        assert_eq!(hand_positions(&recorder), vec![1, 1, 1, 1, 1, 1, 1, 1, 5]);
+        assert_eq!(recorder.current_entropy, 16.0);
+        assert_eq!(recorder.entropies.last(), Some(&16.0));
+
+        // 跳过的事件保持原来的手型
+        let mut recorder = new_recorder();
+        let skipped = notes_map[5].real_tick;
+        let copied = repeats.apply_to_recorder(
+            &mut recorder,
+            PhraseConsistencyMode::Reuse,
+            0.5,
+            |tick| tick == skipped,
+            transition_cost,
+        );
+        assert_eq!(copied, 3);
+        assert_eq!(hand_positions(&recorder)[5], 3);
+    }
+}
@@ -0,0 +1,448 @@
+// PositionPlan.rs
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::guitar::guitar_instance::Guitar;
+use crate::hand::hand_profile::HandProfile;
+use crate::hand::left_hand::HandPosition;
+use crate::midi::midi_to_note::NoteInfo;
+use crate::recorder::fingering_constraint::{FingeringConstraint, FingeringConstraints};
+use crate::recorder::recorder_pool::enumerate_finger_positions;
+
+/// 事件的音符在把位之外时增加的消耗，让每个乐句尽量选择能按到所有音符的把位
+const OUT_OF_POSITION_COST: f64 = 5.0;
+/// 两个乐句之间换把的固定消耗
+const SHIFT_COST: f64 = 2.0;
+/// 换把时每移动一品增加的消耗
+const SHIFT_COST_PER_FRET: f64 = 0.5;
+
+/// 按法对应的把位，也就是食指所在的品格，只用空弦时返回None
+///
+/// 只看按弦的手指，伸展的手指按食指位置换算，拇指不参与计算
+pub fn implied_hand_position(hand_position: &HandPosition) -> Option<i32> {
+    hand_position
+        .positions
+        .iter()
+        .filter(|position| (1..=4).contains(&position.finger) && position.fret > 0)
+        .map(|position| (position.fret - (position.finger - 1)).max(1))
+        .min()
+}
+
+/// 一个乐句规划的把位
+///
+/// Params:
+/// - start_tick, end_tick: 乐句第一个和最后一个音符事件的tick
+/// - position: 规划的把位
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlannedPhrase {
+    pub start_tick: f64,
+    pub end_tick: f64,
+    pub position: i32,
+}
+
+/// 把位规划的结果，按法求解时只使用规划的把位附近的按法
+///
+/// Params:
+/// - phrases: 每个乐句规划的把位
+/// - tolerance: 按法的把位和规划的把位最多相差的品数
+/// - positions: 每个音符事件规划的把位，用real_tick表示事件
+#[derive(Clone, Debug, Default)]
+pub struct PositionPlan {
+    pub phrases: Vec<PlannedPhrase>,
+    pub tolerance: i32,
+    positions: HashMap<u64, i32>,
+}
+
+impl PositionPlan {
+    /// 为整首曲子规划把位
+    ///
+    /// 先为每个乐句计算在每个把位演奏的消耗，再用动态规划选出全曲换把最少的把位序列，
+    /// 乐句内部的音符都在同一个把位上演奏，
+    /// 硬约束排除的按法不参与规划，避免规划的把位和约束指定的按法冲突
+    ///
+    /// # 参数
+    /// * `notes_map` - 处理过的音符事件，和求解器展开的音符相同
+    /// * `phrases` - 每个乐句在notes_map中的下标范围
+    /// * `constraints` - 已经绑定到音符事件的按法约束
+    /// * `tolerance` - 按法的把位和规划的把位最多相差的品数
+    pub fn plan(
+        notes_map: &[NoteInfo],
+        phrases: &[Range<usize>],
+        guitar: &Guitar,
+        hand_profile: &HandProfile,
+        constraints: Option<&FingeringConstraints>,
+        tolerance: i32,
+    ) -> Self {
+        let tolerance = tolerance.max(0);
+        let max_position = (guitar.get_fret_count() - 3).max(1);
+        let position_count = max_position as usize;
+
+        // 每个乐句在每个把位演奏的消耗
+        let phrase_costs: Vec<Vec<f64>> = phrases
+            .iter()
+            .map(|phrase| {
+                let mut costs = vec![0.0; position_count];
+                for note_info in &notes_map[phrase.clone()] {
+                    let hard_constraints: Vec<&FingeringConstraint> = constraints
+                        .map(|constraints| constraints.constraints_at(note_info.real_tick))
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter(|constraint| constraint.is_hard())
+                        .collect();
+                    let Some(reachable) =
+                        reachable_positions(note_info, guitar, hand_profile, &hard_constraints)
+                    else {
+                        continue;
+                    };
+                    for (index, cost) in costs.iter_mut().enumerate() {
+                        *cost += event_cost(&reachable, index as i32 + 1, tolerance);
+                    }
+                }
+                costs
+            })
+            .collect();
+
+        // 在乐句和把位组成的格子上做动态规划，从第一把位开始
+        let mut costs: Vec<f64> = (0..position_count)
+            .map(|index| shift_cost(1, index as i32 + 1))
+            .collect();
+        let mut parents: Vec<Vec<usize>> = Vec::with_capacity(phrases.len());
+        for phrase_cost in &phrase_costs {
+            let mut next_costs = vec![f64::INFINITY; position_count];
+            let mut layer_parents = vec![0; position_count];
+            for (to, next_cost) in next_costs.iter_mut().enumerate() {
+                for (from, cost) in costs.iter().enumerate() {
+                    let total = cost + shift_cost(from as i32 + 1, to as i32 + 1);
+                    if total < *next_cost {
+                        *next_cost = total;
+                        layer_parents[to] = from;
+                    }
+                }
+                *next_cost += phrase_cost[to];
+            }
+            costs = next_costs;
+            parents.push(layer_parents);
+        }
+
+        // 回溯出每个乐句的把位
+        let mut chosen = vec![0; phrases.len()];
+        let mut current = (0..position_count)
+            .min_by(|&a, &b| costs[a].total_cmp(&costs[b]))
+            .unwrap_or_default();
+        for (phrase_index, layer_parents) in parents.iter().enumerate().rev() {
+            chosen[phrase_index] = current;
+            current = layer_parents[current];
+        }
+
+        let mut plan = PositionPlan {
+            phrases: Vec::with_capacity(phrases.len()),
+            tolerance,
+            positions: HashMap::new(),
+        };
+        for (phrase, index) in phrases.iter().zip(chosen) {
+            let position = index as i32 + 1;
+            let notes = &notes_map[phrase.clone()];
+            let (Some(first), Some(last)) = (notes.first(), notes.last()) else {
+                continue;
+            };
+            for note_info in notes {
+                plan.positions
+                    .insert(note_info.real_tick.to_bits(), position);
+            }
+            plan.phrases.push(PlannedPhrase {
+                start_tick: first.real_tick,
+                end_tick: last.real_tick,
+                position,
+            });
+        }
+        plan
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.phrases.is_empty()
+    }
+
+    /// 相邻乐句把位不同的次数
+    pub fn shift_count(&self) -> usize {
+        self.phrases
+            .windows(2)
+            .filter(|pair| pair[0].position != pair[1].position)
+            .count()
+    }
+
+    /// real_tick这个事件规划的把位
+    pub fn position_at(&self, real_tick: f64) -> Option<i32> {
+        self.positions.get(&real_tick.to_bits()).copied()
+    }
+
+    /// 只保留规划的把位附近的按法，只用空弦的按法在任何把位都可以使用
+    ///
+    /// 没有按法在规划的把位附近时返回空列表，由求解时改用不受规划限制的按法，
+    /// 规划只影响按法的选择，不会让能演奏的音符变得无法演奏
+    pub fn restrict(&self, real_tick: f64, hand_positions: Vec<HandPosition>) -> Vec<HandPosition> {
+        let Some(planned) = self.position_at(real_tick) else {
+            return hand_positions;
+        };
+        hand_positions
+            .into_iter()
+            .filter(|hand_position| {
+                implied_hand_position(hand_position)
+                    .is_none_or(|position| (position - planned).abs() <= self.tolerance)
+            })
+            .collect()
+    }
+}
+
+/// 一个事件所有按法对应的把位，没有按法或者可以只用空弦时返回None，不参与规划
+///
+/// 只使用满足所有硬约束的按法，没有按法能满足时约束会在求解时放弃，这里也不再考虑
+fn reachable_positions(
+    note_info: &NoteInfo,
+    guitar: &Guitar,
+    hand_profile: &HandProfile,
+    hard_constraints: &[&FingeringConstraint],
+) -> Option<Vec<i32>> {
+    if note_info.notes.is_empty() {
+        return None;
+    }
+    let hand_positions = enumerate_finger_positions(
+        &note_info.notes,
+        &note_info.string_indices,
+        guitar,
+        hand_profile,
+    );
+    let allowed: Vec<&HandPosition> = hand_positions
+        .iter()
+        .filter(|hand_position| {
+            hard_constraints
+                .iter()
+                .all(|constraint| allows(constraint, hand_position, &note_info.notes, guitar))
+        })
+        .collect();
+    let allowed = if allowed.is_empty() {
+        hand_positions.iter().collect()
+    } else {
+        allowed
+    };
+    let mut reachable = Vec::new();
+    for hand_position in allowed {
+        reachable.push(implied_hand_position(hand_position)?);
+    }
+    reachable.sort_unstable();
+    reachable.dedup();
+    (!reachable.is_empty()).then_some(reachable)
+}
+
+/// 按法是否可能满足约束，只检查按法本身能确定的把位、弦、品格和手指，横按要等生成手型后才知道
+fn allows(
+    constraint: &FingeringConstraint,
+    hand_position: &HandPosition,
+    notes: &[i32],
+    guitar: &Guitar,
+) -> bool {
+    if constraint.note.is_some_and(|note| !notes.contains(&note)) {
+        return true;
+    }
+    if let (Some(position), Some(implied)) = (
+        constraint.hand_position,
+        implied_hand_position(hand_position),
+    ) && position != implied
+    {
+        return false;
+    }
+    hand_position.positions.iter().all(|position| {
+        let pitch = guitar
+            .guitar_strings
+            .get(position.string_index as usize)
+            .and_then(|string| string.get_note_by_fret(position.fret));
+        pitch.is_none_or(|pitch| constraint.note.is_some_and(|note| note != pitch))
+            || (constraint
+                .string_index
+                .is_none_or(|s| s == position.string_index)
+                && constraint.fret.is_none_or(|f| f == position.fret)
+                && constraint.finger.is_none_or(|f| f == position.finger))
+    })
+}
+
+/// 在position把位演奏一个事件的消耗，和最近的按法相差的品数，超出范围时再加上固定消耗
+fn event_cost(reachable: &[i32], position: i32, tolerance: i32) -> f64 {
+    let distance = reachable
+        .iter()
+        .map(|&reachable| (reachable - position).abs())
+        .min()
+        .unwrap_or_default();
+    if distance > tolerance {
+        OUT_OF_POSITION_COST + distance as f64
+    } else {
+        distance as f64 * 0.1
+    }
+}
+
+fn shift_cost(from: i32, to: i32) -> f64 {
+    if from == to {
+        0.0
+    } else {
+        SHIFT_COST + (from - to).abs() as f64 * SHIFT_COST_PER_FRET
+    }
+}
+
+/// 把分段再按事件数切成乐句，太长的段落里也可以换把
+pub fn split_phrases(segments: Vec<Range<usize>>, max_events: usize) -> Vec<Range<usize>> {
+    let max_events = max_events.max(1);
+    segments
+        .into_iter()
+        .flat_map(|segment| {
+            segment
+                .clone()
+                .step_by(max_events)
+                .map(move |start| start..(start + max_events).min(segment.end))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+    use crate::hand::left_finger::LeftFinger;
+    use crate::hand::left_hand::LeftHand;
+    use crate::hand::left_hand_cost::WeightedLeftHandCostModel;
+    use crate::recorder::fingering_constraint::ConstraintRange;
+    use crate::recorder::left_hand_expansion::{ExpansionRules, Relaxation};
+    use crate::recorder::segment_solver::split_segments;
+
+    fn note_info(notes: Vec<i32>, real_tick: f64) -> NoteInfo {
+        NoteInfo {
+            notes,
+            real_tick,
+            string_indices: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_phrase_stays_in_one_position() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let hand_profile = HandProfile::default();
+        // 一段音阶组成一个乐句，每个音都有好几个把位可以按到
+        let notes_map: Vec<NoteInfo> = [60, 62, 64, 65, 67, 69]
+            .into_iter()
+            .enumerate()
+            .map(|(index, note)| note_info(vec![note], (index * 240) as f64))
+            .collect();
+        let segments = split_segments(&notes_map, 0.0, &[]);
+        let phrases = split_phrases(segments.clone(), 8);
+        assert_eq!(phrases, segments);
+        assert_eq!(split_phrases(segments, 4), vec![0..4, 4..6]);
+
+        let plan = PositionPlan::plan(&notes_map, &phrases, &guitar, &hand_profile, None, 1);
+        assert_eq!(plan.phrases.len(), 1);
+        assert_eq!(plan.shift_count(), 0);
+        let position = plan.phrases[0].position;
+
+        // 限制之后的按法都在规划的把位附近
+        for note_info in &notes_map {
+            let hand_positions =
+                enumerate_finger_positions(&note_info.notes, &[], &guitar, &hand_profile);
+            let restricted = plan.restrict(note_info.real_tick, hand_positions);
+            assert!(!restricted.is_empty());
+            assert!(restricted.iter().all(|hand_position| {
+                implied_hand_position(hand_position)
+                    .is_none_or(|implied| (implied - position).abs() <= 1)
+            }));
+        }
+    }
+
+    #[test]
+    fn test_hard_constraints_guide_the_plan() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let hand_profile = HandProfile::default();
+        let notes_map: Vec<NoteInfo> = [60, 62, 64, 65]
+            .into_iter()
+            .enumerate()
+            .map(|(index, note)| note_info(vec![note], (index * 240) as f64))
+            .collect();
+        let phrases = split_segments(&notes_map, 0.0, &[]);
+        let free = PositionPlan::plan(&notes_map, &phrases, &guitar, &hand_profile, None, 0);
+        let pinned_position = if free.phrases[0].position == 7 { 5 } else { 7 };
+
+        // 整个乐句都要求在另一个把位上演奏，规划的把位跟着约束走
+        let mut constraints = FingeringConstraints::new(vec![FingeringConstraint {
+            range: ConstraintRange::NoteIndex {
+                start: 0,
+                end: None,
+            },
+            note: None,
+            string_index: None,
+            fret: None,
+            finger: None,
+            barre: None,
+            hand_position: Some(pinned_position),
+            penalty: None,
+        }]);
+        assert!(constraints.bind_events(&notes_map, &[], 480).is_empty());
+        let plan = PositionPlan::plan(
+            &notes_map,
+            &phrases,
+            &guitar,
+            &hand_profile,
+            Some(&constraints),
+            0,
+        );
+        assert_eq!(plan.phrases[0].position, pinned_position);
+    }
+
+    #[test]
+    fn test_expansion_ignores_plan_when_nothing_fits() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let hand_profile = HandProfile::default();
+        // F2只能在第六弦第一品按，规划的把位离得太远
+        let event = note_info(vec![41], 0.0);
+        let plan = PositionPlan {
+            phrases: vec![PlannedPhrase {
+                start_tick: 0.0,
+                end_tick: 0.0,
+                position: 12,
+            }],
+            tolerance: 0,
+            positions: HashMap::from([(event.real_tick.to_bits(), 12)]),
+        };
+        let hand_positions = enumerate_finger_positions(&event.notes, &[], &guitar, &hand_profile);
+        assert!(!hand_positions.is_empty());
+        assert!(plan.restrict(event.real_tick, hand_positions).is_empty());
+
+        let init_string = &guitar.guitar_strings[2];
+        let init_hand = LeftHand::with_profile(
+            (1..5)
+                .map(|i| LeftFinger::new(i, init_string, i, "Pressed"))
+                .collect(),
+            false,
+            hand_profile,
+        );
+        let cost_model = WeightedLeftHandCostModel::default();
+        let constraints = FingeringConstraints::default();
+        let rules = ExpansionRules {
+            guitar: &guitar,
+            cost_model: &cost_model,
+            tempo_map: None,
+            hand_profile: &hand_profile,
+            chord_shape_library: None,
+            chord_shape_bonus: 0.0,
+            chord_shapes_compete: false,
+            fingering_constraints: &constraints,
+            position_plan: &plan,
+        };
+        let result = rules.expand_event(&event, |fingerings| {
+            vec![rules.expand_hand(&init_hand, fingerings, None)]
+        });
+        assert!(result.has_positions);
+        assert_eq!(
+            result.relaxation,
+            Relaxation {
+                ignore_plan: true,
+                ..Relaxation::default()
+            }
+        );
+        assert!(!result.expansions[0].is_empty());
+    }
+}
@@ -1,18 +1,36 @@
 // hand_pose_record_pool.rs
 use serde_json;
 use std::cmp::Ordering;
-use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::fs::File;
 use std::io::BufReader;
+use std::sync::Arc;
 use std::time::Instant;
 
-use crate::guitar::guitar_chord::convert_notes_to_chord;
+use crate::guitar::guitar_chord::{
+    PlayableChords, get_note_positions, get_note_positions_on_strings,
+};
 use crate::guitar::guitar_instance::Guitar;
+use crate::hand::chord_diagnosis::{UnplayableDiagnosis, diagnose_unplayable_notes};
+use crate::hand::chord_shape::ChordShapeLibrary;
+use crate::hand::hand_profile::HandProfile;
 use crate::hand::left_finger::PressState;
-use crate::hand::left_hand::{LeftHand, convert_chord_to_finger_positions};
+use crate::hand::left_hand::{
+    HandPosition, LeftHand, LeftHandPoseKey, convert_chord_to_finger_positions_for_hand,
+};
+use crate::hand::left_hand_cost::{LeftHandCostModel, WeightedLeftHandCostModel};
 use crate::hand::right_hand::{RightHand, RightHandCombination};
-use crate::midi::midi_to_note::{MidiProcessor, NoteInfo, TempoChange};
+use crate::midi::midi_to_note::{MidiProcessor, NoteInfo, TempoChange, TempoMap};
+use crate::recorder::fingering_constraint::FingeringConstraints;
+use crate::recorder::left_hand_expansion::{
+    EventFingerings, Expansion, ExpansionRules, Relaxation,
+};
 use crate::recorder::left_hand_recorder::LeftHandRecorder;
+use crate::recorder::left_hand_trace::{LeftHandTrace, TracedEvent, build_left_hand_trace};
+use crate::recorder::note_fallback::{FallbackResolution, FallbackSettings, fallback_plans};
+use crate::recorder::phrase_repeat::{PhraseConsistencyMode, PhraseRepeats};
+use crate::recorder::position_plan::PositionPlan;
 use crate::recorder::right_hand_recorder::RightHandRecorder;
 
 #[derive(Debug)]
@@ -133,59 +151,199 @@ impl HasCurrentEntropy for RightHandRecorder {
         self.current_entropy
     }
 }
-/// 用于堆操作的包装结构
+/// 池子里保存的单个手型
+#[derive(Debug, Clone)]
+enum HandPose {
+    Left(LeftHand),
+    Right(RightHand),
+}
+
+/// 手型节点，只记录父节点的索引，完整的记录器在需要时再从叶子节点回溯出来
+#[derive(Debug, Clone)]
+struct PoseNode {
+    pose: HandPose,
+    parent: Option<usize>,
+    /// 到这个节点为止的累计熵
+    entropy: f64,
+    real_tick: f64,
+}
+
+impl HandPose {
+    /// 手型的状态标识，标识相同的手型之后的消耗也完全相同
+    fn state_key(&self) -> PoseStateKey {
+        match self {
+            HandPose::Left(hand) => PoseStateKey::Left(hand.pose_key()),
+            HandPose::Right(hand) => PoseStateKey::Right(hand.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum PoseStateKey {
+    Left(LeftHandPoseKey),
+    Right(RightHand),
+}
+
+/// 当前这一步生成的候选节点，还没有写入节点池
 #[derive(Debug)]
-struct RecorderRef {
-    recorder: HandRecorder,
+struct Candidate {
+    node: PoseNode,
+    /// 生成顺序，熵相同时先生成的优先保留，保证结果是确定的
+    seq: usize,
 }
 
-impl PartialEq for RecorderRef {
+impl PartialEq for Candidate {
     fn eq(&self, other: &Self) -> bool {
-        self.recorder.current_entropy() == other.recorder.current_entropy()
+        self.cmp(other) == Ordering::Equal
     }
 }
 
-impl Eq for RecorderRef {}
+impl Eq for Candidate {}
 
-impl PartialOrd for RecorderRef {
+impl PartialOrd for Candidate {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        // 为了实现最大堆，我们需要反向比较（熵值大的优先级低）
-        other
-            .recorder
-            .current_entropy()
-            .partial_cmp(&self.recorder.current_entropy())
+        Some(self.cmp(other))
     }
 }
 
-impl Ord for RecorderRef {
+impl Ord for Candidate {
     fn cmp(&self, other: &Self) -> Ordering {
-        // 为了实现最大堆，我们需要反向比较
-        other
-            .recorder
-            .current_entropy()
-            .partial_cmp(&self.recorder.current_entropy())
-            .unwrap_or(Ordering::Equal)
+        self.node
+            .entropy
+            .total_cmp(&other.node.entropy)
+            .then(self.seq.cmp(&other.seq))
     }
 }
 
+/// 当前这一步的候选集合
+///
+/// 左手结束手型相同的候选只保留熵最小的一个，否则池子很容易被历史不同、
+/// 但是当前手型相同的记录器占满，失去多样性。
+/// 只有之后的消耗只取决于当前手型时合并才不会丢掉更优的解，
+/// 重复乐句的一致性会回头比较更早的手型，这时不合并。
+/// 右手的候选保持每个记录器单独保留的做法，不合并
+#[derive(Debug)]
+struct CandidateSet {
+    candidates: Vec<Candidate>,
+    index_by_pose: HashMap<LeftHandPoseKey, usize>,
+    capacity: usize,
+    /// 是否合并结束手型相同的左手候选
+    merge_by_pose: bool,
+}
+
+impl CandidateSet {
+    fn new(capacity: usize) -> Self {
+        CandidateSet {
+            candidates: Vec::new(),
+            index_by_pose: HashMap::new(),
+            capacity,
+            merge_by_pose: true,
+        }
+    }
+
+    fn offer(&mut self, node: PoseNode) {
+        let HandPose::Left(hand) = &node.pose else {
+            self.push(node);
+            return;
+        };
+        if !self.merge_by_pose {
+            self.push(node);
+            return;
+        }
+        match self.index_by_pose.entry(hand.pose_key()) {
+            Entry::Occupied(entry) => {
+                let existing = &mut self.candidates[*entry.get()];
+                if node.entropy < existing.node.entropy {
+                    existing.node = node;
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(self.candidates.len());
+                self.push(node);
+            }
+        }
+    }
+
+    fn push(&mut self, node: PoseNode) {
+        let seq = self.candidates.len();
+        self.candidates.push(Candidate { node, seq });
+    }
+
+    /// 取出熵最小的capacity个候选，按熵从小到大排列
+    fn drain_sorted(&mut self) -> Vec<PoseNode> {
+        self.index_by_pose.clear();
+        let mut candidates = std::mem::take(&mut self.candidates);
+        candidates.sort_unstable();
+        candidates.truncate(self.capacity);
+        candidates
+            .into_iter()
+            .map(|candidate| candidate.node)
+            .collect()
+    }
+}
+
+/// 节点池超过这个数量才开始回收无用节点
+const MIN_GC_THRESHOLD: usize = 4096;
+
 /// 无法处理的音符信息结构体
 #[derive(Debug, Clone)]
 pub struct UnprocessableNoteInfo {
     pub real_tick: f64,
     pub notes: Vec<i32>,
-    pub reason: String,
+    pub diagnosis: UnplayableDiagnosis,
+    /// 实际采用的退化方式，记录了哪些音被放弃或者移了八度
+    pub fallback: FallbackResolution,
 }
 
-/// 手势记录器池，使用优先队列实现
+/// 手势记录器池
+///
+/// 所有手型只在节点池里保存一份，每个节点记录父节点的索引，
+/// 池子里的记录器只是叶子节点的索引，需要完整记录时再沿父节点回溯
 pub struct HandPoseRecordPool {
-    /// 优先队列，存储记录器（最大堆，熵值最大的在顶部）
-    recorders: BinaryHeap<RecorderRef>,
+    /// 节点池
+    nodes: Vec<PoseNode>,
+    /// 当前池子里的记录器，存储叶子节点的索引，按熵从小到大排列
+    recorders: Vec<usize>,
+    /// 当前这一步生成的候选
+    candidates: CandidateSet,
     /// 池子的最大容量
     capacity: usize,
     /// 之前的手势记录器池
-    pre_recorders: Vec<HandRecorder>,
+    pre_recorders: Vec<usize>,
+    /// 节点池超过这个数量时回收无用的节点
+    gc_threshold: usize,
     /// 无法处理的音符组合列表
     unprocessable_notes: Vec<UnprocessableNoteInfo>,
+    /// 和弦指型库，为None时只使用通用的按法枚举
+    chord_shape_library: Option<ChordShapeLibrary>,
+    /// 使用指型库按法时减少的熵值
+    chord_shape_bonus: f64,
+    /// 指型库按法和通用按法一起参与比较，为false时只在指型库按法无法过渡时使用通用按法
+    chord_shapes_compete: bool,
+    /// 左手消耗模型
+    left_hand_cost_model: Arc<dyn LeftHandCostModel>,
+    /// 扩展左手候选时使用的线程数
+    thread_count: usize,
+    /// 速度表，设置后左手消耗会考虑换把的速度
+    tempo_map: Option<TempoMap>,
+    /// 角色的手型数据，枚举按法时使用其中的跨度限制
+    hand_profile: HandProfile,
+    /// 编曲者指定的按法约束
+    fingering_constraints: FingeringConstraints,
+    /// 是否记录处理过的音符事件，只有记录了事件才能生成求解追踪
+    trace_enabled: bool,
+    /// 处理过的左手音符事件，用于生成求解追踪
+    traced_events: Vec<TracedEvent>,
+    /// 找不到按法时的退化设置
+    fallback_settings: FallbackSettings,
+    /// 曲子里重复出现的乐句
+    phrase_repeats: PhraseRepeats,
+    /// 重复乐句的按法一致性
+    phrase_consistency: PhraseConsistencyMode,
+    /// 重复乐句使用相同按法时减少的熵值
+    phrase_bonus: f64,
+    /// 把位规划，为空时不限制按法的把位
+    position_plan: PositionPlan,
 }
 
 impl HandPoseRecordPool {
@@ -195,20 +353,184 @@ impl HandPoseRecordPool {
     /// * `capacity` - 池子的容量
     pub fn new(capacity: usize) -> Self {
         HandPoseRecordPool {
-            recorders: BinaryHeap::with_capacity(capacity),
+            nodes: Vec::new(),
+            recorders: Vec::with_capacity(capacity),
+            candidates: CandidateSet::new(capacity),
             capacity,
             pre_recorders: Vec::new(),
+            gc_threshold: MIN_GC_THRESHOLD,
             unprocessable_notes: Vec::new(),
+            chord_shape_library: None,
+            chord_shape_bonus: 0.5,
+            chord_shapes_compete: false,
+            left_hand_cost_model: Arc::new(WeightedLeftHandCostModel::default()),
+            thread_count: 1,
+            tempo_map: None,
+            hand_profile: HandProfile::default(),
+            fingering_constraints: FingeringConstraints::default(),
+            trace_enabled: false,
+            traced_events: Vec::new(),
+            fallback_settings: FallbackSettings::default(),
+            phrase_repeats: PhraseRepeats::default(),
+            phrase_consistency: PhraseConsistencyMode::Off,
+            phrase_bonus: 0.0,
+            position_plan: PositionPlan::default(),
         }
     }
 
+    /// 设置速度表，设置后左手消耗会考虑两个手型之间的时间间隔
+    pub fn set_tempo_map(&mut self, tempo_map: TempoMap) {
+        self.tempo_map = Some(tempo_map);
+    }
+
+    /// 设置角色的手型数据，初始手型需要用同样的数据创建
+    pub fn set_hand_profile(&mut self, hand_profile: HandProfile) {
+        self.hand_profile = hand_profile;
+    }
+
+    /// 设置按法约束，约束需要先调用bind_events找到作用的音符事件
+    pub fn set_fingering_constraints(&mut self, constraints: FingeringConstraints) {
+        self.fingering_constraints = constraints;
+    }
+
+    /// 设置是否记录生成求解追踪需要的音符事件，求解之前设置
+    pub fn set_trace_enabled(&mut self, enabled: bool) {
+        self.trace_enabled = enabled;
+    }
+
+    /// 设置找不到按法时的退化方式
+    pub fn set_fallback_settings(&mut self, settings: FallbackSettings) {
+        self.fallback_settings = settings;
+    }
+
+    /// 设置重复乐句的按法一致性
+    ///
+    /// # 参数
+    /// * `repeats` - 从notes_map里检测出的重复乐句
+    /// * `mode` - 一致性的处理方式
+    /// * `bonus` - Bonus模式下使用和第一次相同的手型时减少的熵值
+    pub fn set_phrase_consistency(
+        &mut self,
+        repeats: PhraseRepeats,
+        mode: PhraseConsistencyMode,
+        bonus: f64,
+    ) {
+        // 重复乐句的消耗取决于第一次出现时的手型，历史不同的候选不能按手型合并
+        self.candidates.merge_by_pose = mode == PhraseConsistencyMode::Off || repeats.is_empty();
+        self.phrase_repeats = repeats;
+        self.phrase_consistency = mode;
+        self.phrase_bonus = bonus;
+    }
+
+    /// 设置把位规划，之后每个事件只使用规划的把位附近的按法
+    pub fn set_position_plan(&mut self, plan: PositionPlan) {
+        self.position_plan = plan;
+    }
+
+    /// 设置扩展左手候选时使用的线程数，0表示使用所有可用的CPU核心
+    pub fn set_thread_count(&mut self, thread_count: usize) {
+        self.thread_count = if thread_count == 0 {
+            std::thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            thread_count
+        };
+    }
+
+    /// 设置左手消耗模型
+    pub fn set_left_hand_cost_model(&mut self, cost_model: Arc<dyn LeftHandCostModel>) {
+        self.left_hand_cost_model = cost_model;
+    }
+
+    /// 设置和弦指型库
+    ///
+    /// # 参数
+    /// * `library` - 指型库
+    /// * `bonus` - 使用指型库按法时每一步减少的熵值
+    pub fn set_chord_shape_library(&mut self, library: ChordShapeLibrary, bonus: f64) {
+        self.chord_shape_library = Some(library);
+        self.chord_shape_bonus = bonus;
+    }
+
+    /// 设置指型库按法是否和通用按法一起参与比较
+    pub fn set_chord_shapes_compete(&mut self, compete: bool) {
+        self.chord_shapes_compete = compete;
+    }
+
     /// 准备记录，将当前池子移动到之前池子，清空当前池子
     pub fn ready_for_record(&mut self) {
-        self.pre_recorders = self.recorders.drain().map(|rr| rr.recorder).collect();
-        self.recorders.clear();
+        self.pre_recorders = std::mem::take(&mut self.recorders);
+    }
+
+    /// 把这一步保留下来的候选写入节点池，成为当前池子里的记录器
+    fn commit_candidates(&mut self) {
+        for node in self.candidates.drain_sorted() {
+            self.recorders.push(self.nodes.len());
+            self.nodes.push(node);
+        }
+        if self.nodes.len() > self.gc_threshold {
+            self.collect_garbage();
+        }
+    }
+
+    /// 回收已经不在任何记录器路径上的节点
+    ///
+    /// 父节点总是比子节点先写入，所以只需要一次正向遍历就能重新编号
+    fn collect_garbage(&mut self) {
+        let mut alive = vec![false; self.nodes.len()];
+        for &leaf in self.recorders.iter().chain(self.pre_recorders.iter()) {
+            let mut current = Some(leaf);
+            while let Some(index) = current {
+                if alive[index] {
+                    break;
+                }
+                alive[index] = true;
+                current = self.nodes[index].parent;
+            }
+        }
+
+        let mut new_indices = vec![usize::MAX; self.nodes.len()];
+        let mut new_nodes = Vec::with_capacity(alive.iter().filter(|&&a| a).count());
+        for (index, node) in std::mem::take(&mut self.nodes).into_iter().enumerate() {
+            if !alive[index] {
+                continue;
+            }
+            new_indices[index] = new_nodes.len();
+            new_nodes.push(PoseNode {
+                parent: node.parent.map(|parent| new_indices[parent]),
+                ..node
+            });
+        }
+        self.nodes = new_nodes;
+
+        for index in self
+            .recorders
+            .iter_mut()
+            .chain(self.pre_recorders.iter_mut())
+        {
+            *index = new_indices[*index];
+        }
+        self.gc_threshold = (self.nodes.len() * 2).max(MIN_GC_THRESHOLD);
+    }
+
+    /// 沿着父节点回溯，返回路径上real_tick这一步的手型标识
+    fn ancestor_pose_key(&self, leaf: usize, real_tick: f64) -> Option<PoseStateKey> {
+        let mut current = Some(leaf);
+        while let Some(index) = current {
+            let node = &self.nodes[index];
+            if node.real_tick == real_tick {
+                return Some(node.pose.state_key());
+            }
+            if node.real_tick < real_tick {
+                return None;
+            }
+            current = node.parent;
+        }
+        None
     }
 
     /// 插入新的手势记录器
+    ///
+    /// 记录器里的手型会作为一条新的路径写入节点池
     pub fn insert_new_hand_pose_recorder(
         &mut self,
         new_recorder: HandRecorder,
@@ -220,30 +542,59 @@ impl HandPoseRecordPool {
             None => return,
         };
 
-        // 插入新的元素
-        let mut recorders_vec: Vec<RecorderRef> = self.recorders.drain().collect();
-        recorders_vec.insert(
-            index,
-            RecorderRef {
-                recorder: new_recorder,
-            },
-        );
+        let (poses, entropies, real_ticks): (Vec<HandPose>, Vec<f64>, Vec<f64>) = match new_recorder
+        {
+            HandRecorder::Left(recorder) => (
+                recorder
+                    .hand_pose_list
+                    .into_iter()
+                    .map(HandPose::Left)
+                    .collect(),
+                recorder.entropies,
+                recorder.real_ticks,
+            ),
+            HandRecorder::Right(recorder) => (
+                recorder
+                    .hand_pose_list
+                    .into_iter()
+                    .map(HandPose::Right)
+                    .collect(),
+                recorder.entropies,
+                recorder.real_ticks,
+            ),
+        };
+
+        // 没有手型的记录器无法继续扩展
+        if poses.is_empty() {
+            return;
+        }
 
-        // 如果插入后的大小超过了 capacity，移除最后一个元素
-        if recorders_vec.len() > self.capacity {
-            recorders_vec.pop();
+        let mut parent = None;
+        for (i, pose) in poses.into_iter().enumerate() {
+            self.nodes.push(PoseNode {
+                pose,
+                parent,
+                entropy: entropies.get(i).copied().unwrap_or(0.0),
+                real_tick: real_ticks.get(i).copied().unwrap_or(0.0),
+            });
+            parent = Some(self.nodes.len() - 1);
         }
 
-        // 重新填充堆
-        for recorder in recorders_vec {
-            self.recorders.push(recorder);
+        if let Some(leaf) = parent {
+            self.recorders.insert(index.min(self.recorders.len()), leaf);
+            // 如果插入后的大小超过了 capacity，移除最后一个元素
+            self.recorders.truncate(self.capacity);
         }
     }
 
     /// 生成左手记录器
+    ///
+    /// # 参数
+    /// * `next_tick` - 下一个音符事件的real_tick，退化成琶音时所有组都在它之前演奏
     pub fn generate_left_hand_recorder(
         &mut self,
         guitar_note: &NoteInfo,
+        next_tick: Option<f64>,
         guitar: &Guitar,
         midi_processor: &MidiProcessor,
         current_recorder_num: &mut usize,
@@ -259,138 +610,257 @@ impl HandPoseRecordPool {
         }
 
         // 定义吉他的最低音和最高音
-        let min_note = guitar.guitar_strings.last().unwrap().get_base_note();
-        let max_note = guitar.guitar_strings.first().unwrap().get_base_note() + 22;
+        let (min_note, max_note) = guitar.get_note_range();
 
         // 处理音符，确保它们在吉他的音域范围内
-        let processed_notes: Vec<i32> = midi_processor.processed_notes(notes, min_note, max_note);
+        let processed = midi_processor.processed_note_info(
+            guitar_note,
+            min_note,
+            max_note,
+            guitar.guitar_strings.len(),
+        );
+        let processed_notes = &processed.notes;
 
         // 如果处理后没有有效音符，返回
         if processed_notes.is_empty() {
             return;
         }
 
-        // 计算所有可能的和弦，包含音符在吉他上的位置信息
-        let chords = convert_notes_to_chord(&processed_notes, guitar);
-
         // 准备记录，将当前池子移动到之前池子，清空当前池子
         self.ready_for_record();
+        let (has_finger_positions, relaxation) = self.expand_and_commit(&processed, guitar);
 
-        // 计算所有可能的按法，包含手指在吉他上的位置信息
-        let mut finger_positions_list = Vec::new();
-        for chord in chords {
-            let possible_finger_positions = convert_chord_to_finger_positions(&chord.positions);
-            if !possible_finger_positions.is_empty() {
-                finger_positions_list.extend(possible_finger_positions);
-            }
-        }
+        *previous_recorder_num = *current_recorder_num;
+        *current_recorder_num = self.len();
 
-        // 如果没有找到合适的按法，记录日志
-        if finger_positions_list.is_empty() {
-            // 保存无法处理的音符组合
+        // 如果无法生成正常的按法，依次尝试退化方案，都失败时才重复最佳记录器的最后一个手型
+        if *current_recorder_num == 0 && !self.pre_recorders.is_empty() {
+            // 有按法但是无法从之前的手型过渡时不需要再诊断
+            let diagnosis = if has_finger_positions {
+                UnplayableDiagnosis::no_valid_transition()
+            } else {
+                diagnose_unplayable_notes(
+                    processed_notes,
+                    &processed.string_indices,
+                    guitar,
+                    &self.hand_profile,
+                )
+            };
+            let fallback = self.apply_fallback(&processed, next_tick, guitar);
             self.unprocessable_notes.push(UnprocessableNoteInfo {
                 real_tick,
                 notes: processed_notes.clone(),
-                reason: "没有找到合适的按法".to_string(),
+                diagnosis,
+                fallback,
             });
+            *current_recorder_num = self.len();
+            return;
         }
 
-        // 遍历之前的手势记录器和按法列表，生成新的记录器
-        for prev_recorder in &self.pre_recorders {
-            // 只处理左手记录器
-            if let HandRecorder::Left(left_recorder) = prev_recorder {
-                // 获取当前手型，如果为空则跳过
-                let old_hand = match left_recorder.current_hand_pose() {
-                    Some(hand) => hand,
-                    None => continue, // 跳过空的手型
-                };
+        self.trace_event(processed, relaxation);
+    }
 
-                // 遍历按法列表，根据按法生成新的左手对象
-                for finger_positions in &finger_positions_list {
-                    if let Some((new_fingers, entropy, use_barre)) =
-                        old_hand.generate_next_hands(guitar, &finger_positions.positions)
-                    {
-                        let new_entropy = left_recorder.current_entropy + entropy;
-                        let current_size = self.recorders.len();
-
-                        // 检查是否应该插入
-                        let current_hand_pose_record_is_largest = current_size > 0
-                            && new_entropy
-                                >= self
-                                    .recorders
-                                    .peek()
-                                    .map_or(0.0, |recorder| recorder.recorder.current_entropy());
-
-                        // 如果池子已满且新记录器熵值不小于当前最大熵值，则不插入
-                        if current_size == self.capacity && current_hand_pose_record_is_largest {
-                            continue;
-                        }
+    /// 展开左手按法的规则，求解追踪使用同样的规则
+    fn expansion_rules<'a>(&'a self, guitar: &'a Guitar) -> ExpansionRules<'a> {
+        ExpansionRules {
+            guitar,
+            cost_model: self.left_hand_cost_model.as_ref(),
+            tempo_map: self.tempo_map.as_ref(),
+            hand_profile: &self.hand_profile,
+            chord_shape_library: self.chord_shape_library.as_ref(),
+            chord_shape_bonus: self.chord_shape_bonus,
+            chord_shapes_compete: self.chord_shapes_compete,
+            fingering_constraints: &self.fingering_constraints,
+            position_plan: &self.position_plan,
+        }
+    }
 
-                        // 创建新的记录器
-                        let mut new_hand_pose_list = left_recorder.hand_pose_list.clone();
-                        let new_hand = LeftHand::new(
-                            new_fingers,
-                            use_barre,
-                            old_hand.get_max_finger_distance(),
-                        );
-                        new_hand_pose_list.push(new_hand);
-
-                        let mut new_entropys = left_recorder.entropies.clone();
-                        new_entropys.push(new_entropy);
-
-                        let mut new_real_ticks = left_recorder.real_ticks.clone();
-                        new_real_ticks.push(real_tick);
-
-                        let new_recorder = LeftHandRecorder::with_data(
-                            new_hand_pose_list,
-                            new_entropy,
-                            new_entropys,
-                            new_real_ticks,
-                        );
-
-                        // 执行插入操作
-                        self.recorders.push(RecorderRef {
-                            recorder: HandRecorder::Left(new_recorder),
-                        });
-
-                        // 如果插入后超过了容量，移除熵值最大的记录器
-                        if self.recorders.len() > self.capacity {
-                            self.recorders.pop();
-                        }
-                    }
+    /// 用一个音符事件扩展之前池子里的所有记录器，保留下来的候选成为当前池子
+    ///
+    /// 返回这个事件是否存在按法，不考虑能否从之前的手型过渡，以及展开时放宽的条件
+    fn expand_and_commit(&mut self, note_info: &NoteInfo, guitar: &Guitar) -> (bool, Relaxation) {
+        let rules = self.expansion_rules(guitar);
+        let result = rules.expand_event(note_info, |event| {
+            self.expand_left_hand_recorders(&rules, event)
+        });
+
+        // 重复乐句里的事件需要和第一次出现时的手型比较
+        let reference_tick = match self.phrase_consistency {
+            PhraseConsistencyMode::Off => None,
+            _ => self.phrase_repeats.reference_tick(note_info.real_tick),
+        };
+        let mut new_nodes = Vec::new();
+        for (&parent, expansions) in self.pre_recorders.iter().zip(result.expansions) {
+            let parent_entropy = self.nodes[parent].entropy;
+            let mut nodes: Vec<PoseNode> = expansions
+                .into_iter()
+                .map(|expansion| PoseNode {
+                    entropy: parent_entropy + expansion.cost(),
+                    pose: HandPose::Left(expansion.hand),
+                    parent: Some(parent),
+                    real_tick: note_info.real_tick,
+                })
+                .collect();
+            if let Some(reference) =
+                reference_tick.and_then(|tick| self.ancestor_pose_key(parent, tick))
+            {
+                apply_phrase_consistency(
+                    &mut nodes,
+                    parent_entropy,
+                    &reference,
+                    self.phrase_consistency,
+                    self.phrase_bonus,
+                );
+            }
+            new_nodes.extend(nodes);
+        }
+
+        for node in new_nodes {
+            self.candidates.offer(node);
+        }
+        self.commit_candidates();
+        (result.has_positions, result.relaxation)
+    }
+
+    /// 设置追踪时记录处理过的音符事件，只有记录了事件才能生成求解追踪
+    fn trace_event(&mut self, note_info: NoteInfo, relaxation: Relaxation) {
+        if self.trace_enabled {
+            self.traced_events.push(TracedEvent {
+                note_info,
+                pool_size: self.len(),
+                relaxation,
+            });
+        }
+    }
+
+    /// 依次尝试退化方案，返回实际采用的方案
+    ///
+    /// 琶音的第一组能过渡就采用整个方案，之后的组无法过渡时重复上一个手型，不算演奏了这一组
+    fn apply_fallback(
+        &mut self,
+        note_info: &NoteInfo,
+        next_tick: Option<f64>,
+        guitar: &Guitar,
+    ) -> FallbackResolution {
+        let plans = fallback_plans(
+            note_info,
+            next_tick,
+            guitar,
+            &self.hand_profile,
+            &self.fallback_settings,
+        );
+        for plan in plans {
+            let (first, rest) = plan
+                .parts
+                .split_first()
+                .expect("退化方案至少有一个音符事件");
+            let (_, relaxation) = self.expand_and_commit(first, guitar);
+            if self.recorders.is_empty() {
+                continue;
+            }
+            self.trace_event(first.clone(), relaxation);
+
+            let mut failed_parts = Vec::new();
+            for (index, part) in rest.iter().enumerate() {
+                self.ready_for_record();
+                let (_, relaxation) = self.expand_and_commit(part, guitar);
+                if self.recorders.is_empty() {
+                    self.repeat_best_pose(part.real_tick);
+                    failed_parts.push(index + 1);
                 }
+                self.trace_event(part.clone(), relaxation);
             }
+            return plan.resolution(&failed_parts);
         }
 
-        *previous_recorder_num = *current_recorder_num;
-        *current_recorder_num = self.len();
+        self.repeat_best_pose(note_info.real_tick);
+        self.trace_event(note_info.clone(), Relaxation::default());
+        FallbackResolution::repeated(note_info)
+    }
 
-        // 如果无法生成正常的按法，就取当前最佳的recoder，然后为它再添加一个最后的按法，清空其它的recorder
-        if *current_recorder_num == 0 && !self.pre_recorders.is_empty() {
-            let best_recorder: &HandRecorder = self.get_best_pre_recorder();
-
-            // 克隆最佳记录器的所有数据
-            let mut new_hand_pose_list = best_recorder.clone_left_hand_pose_list();
-            let new_entropy = best_recorder.current_entropy();
-            let mut new_entropys = best_recorder.entropies().clone();
-            let mut new_real_ticks = best_recorder.real_ticks().clone();
-
-            // 添加重复的最后一个手型
-            if let Some(last_hand) = new_hand_pose_list.last() {
-                new_hand_pose_list.push(last_hand.clone());
-                new_entropys.push(new_entropy);
-                new_real_ticks.push(real_tick);
-
-                let new_recorder = LeftHandRecorder::with_data(
-                    new_hand_pose_list,
-                    new_entropy,
-                    new_entropys,
-                    new_real_ticks,
-                );
+    /// 取之前池子里最佳的记录器，重复它的最后一个手型，作为当前池子里唯一的记录器
+    fn repeat_best_pose(&mut self, real_tick: f64) {
+        let best = self.best_index(&self.pre_recorders);
+        let best_node = &self.nodes[best];
+        let repeated = PoseNode {
+            pose: best_node.pose.clone(),
+            parent: Some(best),
+            entropy: best_node.entropy,
+            real_tick,
+        };
+        self.recorders.push(self.nodes.len());
+        self.nodes.push(repeated);
+    }
 
-                self.insert_new_hand_pose_recorder(HandRecorder::Left(new_recorder), Some(0));
+    /// 扩展之前池子里的所有左手记录器，返回每个记录器展开得到的新手型
+    ///
+    /// 之前的记录器被平均分给多个线程，结果按记录器原来的顺序返回，
+    /// 所以无论使用多少个线程，结果都是一样的
+    fn expand_left_hand_recorders(
+        &self,
+        rules: &ExpansionRules,
+        event: &EventFingerings,
+    ) -> Vec<Vec<Expansion>> {
+        let expand = |parent: usize| -> Vec<Expansion> {
+            let parent_node = &self.nodes[parent];
+            match &parent_node.pose {
+                HandPose::Left(hand) => rules.expand_hand(
+                    hand,
+                    event,
+                    rules.time_gap(parent_node.real_tick, event.note_info.real_tick),
+                ),
+                HandPose::Right(_) => Vec::new(),
             }
+        };
+
+        let thread_count = self.thread_count.min(self.pre_recorders.len()).max(1);
+        if thread_count == 1 {
+            return self
+                .pre_recorders
+                .iter()
+                .map(|&parent| expand(parent))
+                .collect();
         }
+
+        let chunk_size = self.pre_recorders.len().div_ceil(thread_count);
+        let expand = &expand;
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .pre_recorders
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|&parent| expand(parent))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("左手候选扩展线程出错"))
+                .collect()
+        })
+    }
+
+    /// 为池子里得到的左手记录器生成每一步的消耗追踪，需要在求解前调用set_trace_enabled
+    ///
+    /// # 参数
+    /// * `recorder` - 从池子里取出的左手记录器
+    /// * `runner_up_count` - 每一步最多保留的落选按法数
+    pub fn trace_left_hand_recorder(
+        &self,
+        recorder: &LeftHandRecorder,
+        guitar: &Guitar,
+        runner_up_count: usize,
+    ) -> LeftHandTrace {
+        build_left_hand_trace(
+            recorder,
+            &self.traced_events,
+            &self.expansion_rules(guitar),
+            runner_up_count,
+        )
     }
 
     /// 获取无法处理的音符组合列表
@@ -426,6 +896,7 @@ impl HandPoseRecordPool {
             let guitar_note = &notes_map[i];
             self.generate_left_hand_recorder(
                 guitar_note,
+                notes_map.get(i + 1).map(|next| next.real_tick),
                 guitar,
                 midi_processor,
                 current_recorder_num,
@@ -467,6 +938,11 @@ impl HandPoseRecordPool {
             None => return,
         };
 
+        // 击弦、勾弦和滑弦由左手发声，右手不用拨弦
+        if item.get("legato").is_some_and(|v| !v.is_null()) {
+            return;
+        }
+
         // 获取leftHand数组
         let left_hand = match item.get("left_hand").and_then(|v| v.as_array()) {
             Some(hand) => hand,
@@ -500,8 +976,8 @@ impl HandPoseRecordPool {
 
                     let press = PressState::from_str(&press_value).to_i32();
 
-                    // 检查按弦类型条件（在0到5之间，不包括0和5）
-                    if press <= 0 || press >= 5 {
+                    // 抬起和保留的手指不发声，轻触弦的闷音仍然需要右手拨弦
+                    if press <= 0 || press == PressState::Keep.to_i32() {
                         continue;
                     }
 
@@ -511,7 +987,7 @@ impl HandPoseRecordPool {
                         .and_then(|v| v.as_i64())
                         .unwrap_or(0);
 
-                    if press == 1 {
+                    if press == 1 || press == PressState::Mute.to_i32() {
                         touched_strings.push(string_index as i32);
                     } else if press > 1 {
                         // 当press大于1时，添加从string_index到0的所有弦索引
@@ -539,102 +1015,63 @@ impl HandPoseRecordPool {
 
         let all_strings: Vec<i32> = (0..=max_string_index as i32).collect();
 
-        // 直接处理之前的记录器，避免生成所有组合再处理
-        let pre_recorders = std::mem::take(&mut self.pre_recorders);
+        // 可能的右手组合只与要拨动的弦有关，所有记录器共用一份
+        let possible_combinations =
+            self.generate_right_hand_combinations(touched_strings.clone(), all_strings);
 
         // 遍历之前的手势记录器
-        for prev_recorder in &pre_recorders {
-            if let HandRecorder::Right(right_recorder) = prev_recorder {
-                if let Some(last_hand) = right_recorder.hand_pose_list.last() {
-                    // 为当前情况生成可能的右手组合
-                    let possible_combinations = self.generate_right_hand_combinations(
-                        touched_strings.clone(),
-                        all_strings.clone(),
-                    );
-
-                    if possible_combinations.is_empty() {
-                        println!(
-                            "当前要拨动的弦是{:?}，当前右手状态是{:?}。",
-                            touched_strings, last_hand
-                        );
-                        continue;
-                    }
-
-                    // 遍历所有可能的组合
-                    for combination in &possible_combinations {
-                        let used_fingers = combination.used_fingers.clone();
-                        let right_finger_positions = combination.right_finger_positions.clone();
-
-                        // 验证右手姿势
-                        let is_valid = last_hand.validate_right_hand(
-                            Some(used_fingers.clone()),
-                            Some(right_finger_positions.clone()),
-                        );
-
-                        if !is_valid {
-                            continue;
-                        }
-
-                        let is_arpeggio = touched_strings.len() > 4;
-                        let right_hand = RightHand::new(
-                            used_fingers.clone(),
-                            right_finger_positions.clone(),
-                            last_hand.used_fingers.clone(),
-                            is_arpeggio,
-                            last_hand.is_playing_bass,
-                            touched_strings.clone(),
-                        );
-
-                        let entropy = last_hand.calculate_diff(&right_hand);
-                        let new_entropy = right_recorder.current_entropy + entropy;
-
-                        // 检查是否应该插入
-                        let current_hand_pose_record_is_smallest = self.recorders.len() > 0
-                            && new_entropy
-                                <= self.recorders.peek().map_or(f64::MAX, |recorder| {
-                                    recorder.recorder.current_entropy()
-                                });
-
-                        // 如果池子已满且新记录器熵值不小于当前最小熵值，则不插入
-                        if self.recorders.len() == self.capacity
-                            && !current_hand_pose_record_is_smallest
-                        {
-                            continue;
-                        }
-
-                        // 创建新的记录器
-                        let mut new_hand_pose_list = right_recorder.hand_pose_list.clone();
-                        new_hand_pose_list.push(right_hand);
+        for &parent in &self.pre_recorders {
+            let parent_node = &self.nodes[parent];
+            let last_hand = match &parent_node.pose {
+                HandPose::Right(hand) => hand,
+                HandPose::Left(_) => continue,
+            };
+
+            if possible_combinations.is_empty() {
+                println!(
+                    "当前要拨动的弦是{:?}，当前右手状态是{:?}。",
+                    touched_strings, last_hand
+                );
+                continue;
+            }
 
-                        let mut new_entropies = right_recorder.entropies.clone();
-                        new_entropies.push(new_entropy);
+            // 遍历所有可能的组合
+            for combination in &possible_combinations {
+                let used_fingers = combination.used_fingers.clone();
+                let right_finger_positions = combination.right_finger_positions.clone();
 
-                        let mut new_real_ticks = right_recorder.real_ticks.clone();
-                        new_real_ticks.push(real_tick);
+                // 验证右手姿势
+                let is_valid = last_hand.validate_right_hand(
+                    Some(used_fingers.clone()),
+                    Some(right_finger_positions.clone()),
+                );
 
-                        let new_recorder = RightHandRecorder::with_data(
-                            new_hand_pose_list,
-                            new_entropy,
-                            new_entropies,
-                            new_real_ticks,
-                        );
+                if !is_valid {
+                    continue;
+                }
 
-                        // 执行插入操作
-                        self.recorders.push(RecorderRef {
-                            recorder: HandRecorder::Right(new_recorder),
-                        });
+                let is_arpeggio = touched_strings.len() > 4;
+                let right_hand = RightHand::new(
+                    used_fingers,
+                    right_finger_positions,
+                    last_hand.used_fingers.clone(),
+                    is_arpeggio,
+                    last_hand.is_playing_bass,
+                    touched_strings.clone(),
+                );
 
-                        // 如果插入后超过了容量，移除熵值最大的记录器
-                        if self.recorders.len() > self.capacity {
-                            self.recorders.pop();
-                        }
-                    }
-                }
+                let entropy = last_hand.calculate_diff(&right_hand);
+                let new_entropy = parent_node.entropy + entropy;
+                self.candidates.offer(PoseNode {
+                    pose: HandPose::Right(right_hand),
+                    parent: Some(parent),
+                    entropy: new_entropy,
+                    real_tick,
+                });
             }
         }
 
-        // 恢复pre_recorders
-        self.pre_recorders = pre_recorders;
+        self.commit_candidates();
     }
 
     /// 高效生成右手组合的方法
@@ -645,11 +1082,12 @@ impl HandPoseRecordPool {
     ) -> Vec<RightHandCombination> {
         let mut combinations = Vec::new();
 
-        // 特殊情况：如果触弦数超过4根，使用琶音方式
+        // 特殊情况：如果触弦数超过4根，使用琶音方式，拇指放在最低的弦上
         if touched_strings.len() > 4 {
+            let max_string_index = all_strings.last().copied().unwrap_or(0);
             combinations.push(RightHandCombination {
                 used_fingers: Vec::new(),
-                right_finger_positions: vec![5, 2, 1, 0],
+                right_finger_positions: vec![max_string_index, 2, 1, 0],
             });
             return combinations;
         }
@@ -808,85 +1246,220 @@ impl HandPoseRecordPool {
     /// 获取当前池子中的最大熵值
     pub fn max_entropy(&self) -> Option<f64> {
         self.recorders
-            .peek()
-            .map(|rr| rr.recorder.current_entropy())
+            .iter()
+            .map(|&index| self.nodes[index].entropy)
+            .max_by(|a, b| a.total_cmp(b))
     }
 
     /// 获取当前池子中的最小熵值
     pub fn min_entropy(&self) -> Option<f64> {
         self.recorders
             .iter()
-            .map(|rr| rr.recorder.current_entropy())
-            .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .map(|&index| self.nodes[index].entropy)
+            .min_by(|a, b| a.total_cmp(b))
     }
 
     /// 获取池子中所有记录器的熵值
     pub fn get_all_entropies(&self) -> Vec<f64> {
         self.recorders
             .iter()
-            .map(|rr| rr.recorder.current_entropy())
+            .map(|&index| self.nodes[index].entropy)
             .collect()
     }
 
     /// 获取所有记录器（消耗所有权）
     pub fn into_recorders(self) -> Vec<HandRecorder> {
-        self.recorders.into_iter().map(|rr| rr.recorder).collect()
+        self.recorders
+            .iter()
+            .map(|&index| self.build_recorder(index))
+            .collect()
     }
 
     /// 获取池子中最差的记录器（熵值最大的）
-    pub fn get_worst_recorder(&self) -> &HandRecorder {
-        &self
-            .recorders
-            .iter()
-            .max_by(|a, b| {
-                a.recorder
-                    .current_entropy()
-                    .partial_cmp(&b.recorder.current_entropy())
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            })
-            .expect("Recorder pool should not be empty")
-            .recorder
+    pub fn get_worst_recorder(&self) -> HandRecorder {
+        assert!(
+            !self.recorders.is_empty(),
+            "Recorder pool should not be empty"
+        );
+        self.build_recorder(self.worst_index(&self.recorders))
     }
 
-    pub fn get_best_recorder(&self) -> &HandRecorder {
-        &self
-            .recorders
-            .iter()
-            .min_by(|a, b| {
-                a.recorder
-                    .current_entropy()
-                    .partial_cmp(&b.recorder.current_entropy())
-                    .unwrap_or(std::cmp::Ordering::Equal)
+    /// 获取池子中最好的记录器（熵值最小的）
+    pub fn get_best_recorder(&self) -> HandRecorder {
+        assert!(
+            !self.recorders.is_empty(),
+            "Recorder pool should not be empty"
+        );
+        self.build_recorder(self.best_index(&self.recorders))
+    }
+
+    /// 按熵从小到大获取池子中最多max_count个左手记录器
+    pub fn get_ranked_left_recorders(&self, max_count: usize) -> Vec<LeftHandRecorder> {
+        let mut leaves = self.recorders.clone();
+        leaves.sort_by(|&a, &b| self.nodes[a].entropy.total_cmp(&self.nodes[b].entropy));
+        leaves
+            .into_iter()
+            .take(max_count)
+            .filter_map(|leaf| match self.build_recorder(leaf) {
+                HandRecorder::Left(recorder) => Some(recorder),
+                HandRecorder::Right(_) => None,
             })
-            .expect("Recorder pool should not be empty")
-            .recorder
+            .collect()
     }
 
     /// 获取之前记录器中最差的一个（熵值最大的）
-    pub fn get_worst_pre_recorder(&self) -> &HandRecorder {
-        self.pre_recorders
+    pub fn get_worst_pre_recorder(&self) -> HandRecorder {
+        assert!(
+            !self.pre_recorders.is_empty(),
+            "Previous recorders should not be empty"
+        );
+        self.build_recorder(self.worst_index(&self.pre_recorders))
+    }
+
+    /// 获取之前记录器中最好的一个（熵值最小的）
+    pub fn get_best_pre_recorder(&self) -> HandRecorder {
+        assert!(
+            !self.pre_recorders.is_empty(),
+            "Previous recorders should not be empty"
+        );
+        self.build_recorder(self.best_index(&self.pre_recorders))
+    }
+
+    /// 熵值最小的叶子节点，熵相同时取靠前的
+    fn best_index(&self, leaves: &[usize]) -> usize {
+        leaves
             .iter()
-            .max_by(|a, b| {
-                a.current_entropy()
-                    .partial_cmp(&b.current_entropy())
-                    .unwrap_or(std::cmp::Ordering::Equal)
+            .copied()
+            .reduce(|best, index| {
+                if self.nodes[index].entropy < self.nodes[best].entropy {
+                    index
+                } else {
+                    best
+                }
             })
-            .expect("Previous recorders should not be empty")
+            .unwrap_or_default()
     }
 
-    /// 获取之前记录器中最好的一个（熵值最小的）
-    pub fn get_best_pre_recorder(&self) -> &HandRecorder {
-        self.pre_recorders
+    /// 熵值最大的叶子节点，熵相同时取靠后的
+    fn worst_index(&self, leaves: &[usize]) -> usize {
+        leaves
             .iter()
-            .min_by(|a, b| {
-                a.current_entropy()
-                    .partial_cmp(&b.current_entropy())
-                    .unwrap_or(std::cmp::Ordering::Equal)
+            .copied()
+            .reduce(|worst, index| {
+                if self.nodes[index].entropy >= self.nodes[worst].entropy {
+                    index
+                } else {
+                    worst
+                }
             })
-            .expect("Previous recorders should not be empty")
+            .unwrap_or_default()
+    }
+
+    /// 从叶子节点沿父节点回溯，重建完整的记录器
+    fn build_recorder(&self, leaf: usize) -> HandRecorder {
+        let mut path = Vec::new();
+        let mut current = Some(leaf);
+        while let Some(index) = current {
+            path.push(index);
+            current = self.nodes[index].parent;
+        }
+        path.reverse();
+
+        let entropies: Vec<f64> = path.iter().map(|&i| self.nodes[i].entropy).collect();
+        let real_ticks: Vec<f64> = path.iter().map(|&i| self.nodes[i].real_tick).collect();
+        let current_entropy = self.nodes[leaf].entropy;
+
+        match &self.nodes[leaf].pose {
+            HandPose::Left(_) => {
+                let hand_pose_list = path
+                    .iter()
+                    .filter_map(|&i| match &self.nodes[i].pose {
+                        HandPose::Left(hand) => Some(hand.clone()),
+                        HandPose::Right(_) => None,
+                    })
+                    .collect();
+                HandRecorder::Left(LeftHandRecorder::with_data(
+                    hand_pose_list,
+                    current_entropy,
+                    entropies,
+                    real_ticks,
+                ))
+            }
+            HandPose::Right(_) => {
+                let hand_pose_list = path
+                    .iter()
+                    .filter_map(|&i| match &self.nodes[i].pose {
+                        HandPose::Right(hand) => Some(hand.clone()),
+                        HandPose::Left(_) => None,
+                    })
+                    .collect();
+                HandRecorder::Right(RightHandRecorder::with_data(
+                    hand_pose_list,
+                    current_entropy,
+                    entropies,
+                    real_ticks,
+                ))
+            }
+        }
     }
 }
 
+/// 按照一致性设置处理重复乐句里的候选
+///
+/// Bonus模式下和第一次出现相同的手型减少熵，但是这一步的熵不会小于0；
+/// Reuse模式下只要有相同的手型，就只保留相同的手型
+fn apply_phrase_consistency(
+    nodes: &mut Vec<PoseNode>,
+    parent_entropy: f64,
+    reference: &PoseStateKey,
+    mode: PhraseConsistencyMode,
+    bonus: f64,
+) {
+    match mode {
+        PhraseConsistencyMode::Off => {}
+        PhraseConsistencyMode::Bonus => {
+            for node in nodes.iter_mut() {
+                if node.pose.state_key() == *reference {
+                    node.entropy = (node.entropy - bonus).max(parent_entropy);
+                }
+            }
+        }
+        PhraseConsistencyMode::Reuse => {
+            if nodes.iter().any(|node| node.pose.state_key() == *reference) {
+                nodes.retain(|node| node.pose.state_key() == *reference);
+            }
+        }
+    }
+}
+
+/// 枚举一组音符所有可能的按法，包含手指在吉他上的位置信息
+///
+/// string_indices不为空时每个音符只使用指定的弦，只需要选择手指
+pub(crate) fn enumerate_finger_positions(
+    notes: &[i32],
+    string_indices: &[i32],
+    guitar: &Guitar,
+    hand_profile: &HandProfile,
+) -> Vec<HandPosition> {
+    let note_positions = if string_indices.is_empty() {
+        get_note_positions(notes, guitar)
+    } else {
+        get_note_positions_on_strings(notes, string_indices, guitar)
+    };
+    let mut finger_positions_list = Vec::new();
+    for chord in PlayableChords::with_max_span(note_positions, hand_profile.max_span) {
+        let possible_finger_positions = convert_chord_to_finger_positions_for_hand(
+            &chord.positions,
+            guitar.guitar_strings.len() as i32,
+            hand_profile,
+        );
+        if !possible_finger_positions.is_empty() {
+            finger_positions_list.extend(possible_finger_positions);
+        }
+    }
+    finger_positions_list
+}
+
 // 为HandPoseRecordPool实现默认构造函数
 impl Default for HandPoseRecordPool {
     fn default() -> Self {
@@ -905,4 +1478,612 @@ mod tests {
         assert_eq!(pool.len(), 0);
         assert!(pool.is_empty());
     }
+
+    /// 从第三弦一到四品的手型开始，用束搜索求解一段音符
+    fn solve_left_hand(
+        notes_map: &[NoteInfo],
+        capacity: usize,
+        threads: usize,
+        hand_profile: HandProfile,
+    ) -> HandPoseRecordPool {
+        solve_left_hand_with(notes_map, capacity, threads, hand_profile, |_| {})
+    }
+
+    /// 和solve_left_hand一样，求解前用configure修改池子的设置
+    fn solve_left_hand_with(
+        notes_map: &[NoteInfo],
+        capacity: usize,
+        threads: usize,
+        hand_profile: HandProfile,
+        configure: impl FnOnce(&mut HandPoseRecordPool),
+    ) -> HandPoseRecordPool {
+        use crate::guitar::instrument_profile::InstrumentProfile;
+        use crate::hand::left_finger::LeftFinger;
+
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let init_string = &guitar.guitar_strings[2];
+        let fingers = (1..5)
+            .map(|i| LeftFinger::new(i, init_string, i, "Pressed"))
+            .collect();
+        let mut init_recorder = LeftHandRecorder::new();
+        init_recorder.add_hand_pose(
+            LeftHand::with_profile(fingers, false, hand_profile),
+            0.0,
+            0.0,
+        );
+
+        let mut pool = HandPoseRecordPool::new(capacity);
+        pool.set_thread_count(threads);
+        pool.set_hand_profile(hand_profile);
+        pool.insert_new_hand_pose_recorder(HandRecorder::Left(init_recorder), Some(0));
+        configure(&mut pool);
+        let (mut current, mut previous) = (0, 0);
+        pool.update_left_handrecorder_pool(
+            &guitar,
+            &notes_map.to_vec(),
+            &MidiProcessor::new(),
+            &mut current,
+            &mut previous,
+            |_| {},
+        );
+        pool
+    }
+
+    #[test]
+    fn test_best_path_is_rebuilt_from_parent_nodes() {
+        let melody = [64, 67, 69, 71, 72, 74, 76, 72];
+        let notes_map: Vec<NoteInfo> = (0..200)
+            .map(|i| NoteInfo {
+                notes: vec![melody[i % melody.len()]],
+                real_tick: i as f64 * 240.0,
+                string_indices: Vec::new(),
+            })
+            .collect();
+
+        let pool = solve_left_hand(&notes_map, 50, 1, HandProfile::default());
+        let best = pool.get_best_recorder();
+        assert_eq!(best.len(), notes_map.len() + 1);
+        assert_eq!(
+            best.real_ticks()[1..],
+            notes_map.iter().map(|n| n.real_tick).collect::<Vec<_>>()[..]
+        );
+        assert!(best.entropies().windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(best.entropies().last().copied(), pool.min_entropy());
+    }
+
+    #[test]
+    fn test_thread_count_does_not_change_result() {
+        let chords = [
+            vec![48, 52, 55, 60, 64],
+            vec![45, 52, 57, 60, 64],
+            vec![67],
+            vec![69],
+            vec![41, 48, 53, 57, 60, 65],
+            vec![72, 60],
+        ];
+        let notes_map: Vec<NoteInfo> = (0..30)
+            .map(|i| NoteInfo {
+                notes: chords[i % chords.len()].clone(),
+                real_tick: i as f64 * 240.0,
+                string_indices: Vec::new(),
+            })
+            .collect();
+
+        let single = solve_left_hand(&notes_map, 20, 1, HandProfile::default());
+        let multi = solve_left_hand(&notes_map, 20, 4, HandProfile::default());
+        assert_eq!(single.get_all_entropies(), multi.get_all_entropies());
+        let (HandRecorder::Left(a), HandRecorder::Left(b)) =
+            (single.get_best_recorder(), multi.get_best_recorder())
+        else {
+            panic!("Expected LeftHandRecorder");
+        };
+        let keys = |r: &LeftHandRecorder| -> Vec<_> {
+            r.hand_pose_list
+                .iter()
+                .map(|hand| hand.pose_key())
+                .collect()
+        };
+        assert_eq!(keys(&a), keys(&b));
+    }
+
+    #[test]
+    fn test_hand_profile_without_barre_never_barres() {
+        let chords = [vec![41, 48, 53, 57, 60, 65], vec![43, 50, 55, 59, 62, 67]];
+        let notes_map: Vec<NoteInfo> = (0..6)
+            .map(|i| NoteInfo {
+                notes: chords[i % chords.len()].clone(),
+                real_tick: i as f64 * 480.0,
+                string_indices: Vec::new(),
+            })
+            .collect();
+        let uses_barre = |pool: HandPoseRecordPool| match pool.get_best_recorder() {
+            HandRecorder::Left(recorder) => recorder.hand_pose_list.iter().any(|hand| {
+                hand.use_barre
+                    || hand.fingers.iter().any(|finger| {
+                        matches!(
+                            finger.press,
+                            PressState::Barre
+                                | PressState::PartialBarre2Strings
+                                | PressState::PartialBarre3Strings
+                        )
+                    })
+            }),
+            HandRecorder::Right(_) => panic!("Expected LeftHandRecorder"),
+        };
+
+        assert!(uses_barre(solve_left_hand(
+            &notes_map,
+            20,
+            1,
+            HandProfile::default()
+        )));
+        let small_hand = HandProfile {
+            can_barre: false,
+            ..HandProfile::default()
+        };
+        assert!(!uses_barre(solve_left_hand(&notes_map, 20, 1, small_hand)));
+    }
+
+    /// 枚举出来的所有拇指按弦的位置
+    fn thumb_positions(notes: &[i32], guitar: &Guitar) -> Vec<(i32, i32)> {
+        let thumb_over = HandProfile {
+            thumb_over: true,
+            ..HandProfile::default()
+        };
+        enumerate_finger_positions(notes, &[], guitar, &thumb_over)
+            .iter()
+            .flat_map(|hand_position| &hand_position.positions)
+            .filter(|position| position.finger == 0)
+            .map(|position| (position.string_index, position.fret))
+            .collect()
+    }
+
+    #[test]
+    fn test_thumb_only_frets_the_bass_side_strings() {
+        use crate::guitar::instrument_profile::InstrumentProfile;
+        use crate::hand::left_finger::LeftFinger;
+        use crate::hand::left_hand::thumb_can_reach_string;
+
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        // 拇指按六弦3品的低音G，其它手指按四弦5品的G，二弦是空弦
+        let notes = [43, 55, 59];
+        let thumb_over = HandProfile {
+            thumb_over: true,
+            ..HandProfile::default()
+        };
+        let hand_positions = enumerate_finger_positions(&notes, &[], &guitar, &thumb_over);
+        let thumbs = thumb_positions(&notes, &guitar);
+
+        assert!(thumbs.contains(&(5, 3)));
+        let string_count = guitar.guitar_strings.len() as i32;
+        assert!(
+            thumbs
+                .iter()
+                .all(|&(string_index, _)| thumb_can_reach_string(string_index, string_count))
+        );
+
+        // 至少有一个拇指按法可以从初始手型过渡过去
+        let init_string = &guitar.guitar_strings[2];
+        let init_hand = LeftHand::with_profile(
+            (1..5)
+                .map(|i| LeftFinger::new(i, init_string, i, "Pressed"))
+                .collect(),
+            false,
+            thumb_over,
+        );
+        assert!(hand_positions.iter().any(|hand_position| {
+            hand_position.positions.iter().any(|p| p.finger == 0)
+                && init_hand
+                    .generate_next_hands(&guitar, &hand_position.positions)
+                    .is_some()
+        }));
+
+        assert!(
+            enumerate_finger_positions(&notes, &[], &guitar, &HandProfile::default())
+                .iter()
+                .all(|hand_position| hand_position.positions.iter().all(|p| p.finger != 0))
+        );
+    }
+
+    #[test]
+    fn test_thumb_frets_reentrant_strings_by_position() {
+        use crate::guitar::instrument_profile::InstrumentProfile;
+
+        let preset = |name: &str| {
+            InstrumentProfile::presets()
+                .into_iter()
+                .find(|profile| profile.name == name)
+                .unwrap()
+                .create_guitar(false)
+        };
+        let note = |guitar: &Guitar, string_index: usize, fret: i32| {
+            guitar.guitar_strings[string_index]
+                .get_note_by_fret(fret)
+                .unwrap()
+        };
+
+        // 高G调弦的g弦在低音侧边缘，音却比c弦和e弦高，拇指仍然可以按它
+        let ukulele = preset("尤克里里 (高G调弦)");
+        assert!(
+            ukulele.guitar_strings[3].get_base_note() > ukulele.guitar_strings[1].get_base_note()
+        );
+        let notes = [note(&ukulele, 3, 2), note(&ukulele, 1, 3)];
+        let thumbs = thumb_positions(&notes, &ukulele);
+        assert!(thumbs.contains(&(3, 2)));
+        assert!(thumbs.iter().all(|&(string_index, _)| string_index >= 2));
+
+        // 班卓琴的短弦在低音侧边缘，从第5品的弦枕往上按
+        let banjo = preset("5弦班卓琴 (Open G)");
+        let notes = [note(&banjo, 4, 7), note(&banjo, 2, 8)];
+        let thumbs = thumb_positions(&notes, &banjo);
+        assert!(thumbs.contains(&(4, 7)));
+        assert!(thumbs.iter().all(|&(string_index, _)| string_index >= 3));
+    }
+
+    #[test]
+    fn test_channel_per_string_midi_fills_string_indices() {
+        use crate::midi::midi_to_note::{ChannelPerString, MuteDetection};
+        use midly::num::{u4, u7, u15, u28};
+        use midly::{
+            Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind,
+        };
+
+        let event = |delta: u32, channel: u8, key: u8, vel: u8| TrackEvent {
+            delta: u28::new(delta),
+            kind: TrackEventKind::Midi {
+                channel: u4::new(channel),
+                message: MidiMessage::NoteOn {
+                    key: u7::new(key),
+                    vel: u7::new(vel),
+                },
+            },
+        };
+        // 第0弦用通道1，通道7之后不是弦
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(u15::new(480)),
+        ));
+        smf.tracks.push(vec![
+            event(0, 2, 64, 100),
+            event(0, 1, 71, 100),
+            event(0, 9, 36, 100),
+            event(480, 2, 64, 0),
+            event(0, 1, 71, 0),
+            event(0, 9, 36, 0),
+            event(0, 6, 45, 100),
+            event(480, 6, 45, 0),
+            TrackEvent {
+                delta: u28::new(0),
+                kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+            },
+        ]);
+        let path = std::env::temp_dir().join(format!(
+            "fret_dance_channel_per_string_{}.mid",
+            std::process::id()
+        ));
+        smf.save(&path).unwrap();
+
+        let (notes_map, _, _, muted_notes, note_spans) = MidiProcessor::new()
+            .midi_to_guitar_notes(
+                path.to_str().unwrap(),
+                &Vec::new(),
+                480,
+                60.0,
+                &[0],
+                0,
+                Some(ChannelPerString {
+                    first_channel: 1,
+                    string_count: 6,
+                }),
+                false,
+                0,
+                &MuteDetection::default(),
+            )
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let events: Vec<(f64, Vec<i32>, Vec<i32>)> = notes_map
+            .into_iter()
+            .map(|n| (n.real_tick, n.notes, n.string_indices))
+            .collect();
+        // 通道9的音符被丢掉，E4在第二弦上而不是空弦
+        assert_eq!(
+            events,
+            vec![(0.0, vec![64, 71], vec![1, 0]), (480.0, vec![45], vec![5]),]
+        );
+        assert!(muted_notes.is_empty());
+        assert!(
+            note_spans
+                .iter()
+                .all(|span| span.note != 36 && span.string_index >= 0)
+        );
+    }
+
+    #[test]
+    fn test_notes_with_string_indices_stay_on_their_strings() {
+        // E4和B4分别固定在第二弦和第三弦上，而不是空弦
+        let notes_map = vec![
+            NoteInfo {
+                notes: vec![64],
+                real_tick: 0.0,
+                string_indices: vec![1],
+            },
+            NoteInfo {
+                notes: vec![64, 71],
+                real_tick: 480.0,
+                string_indices: vec![1, 0],
+            },
+            NoteInfo {
+                notes: vec![59, 64],
+                real_tick: 960.0,
+                string_indices: vec![2, 1],
+            },
+        ];
+        let guitar =
+            crate::guitar::instrument_profile::InstrumentProfile::default().create_guitar(false);
+        for note_info in &notes_map {
+            let hand_positions = enumerate_finger_positions(
+                &note_info.notes,
+                &note_info.string_indices,
+                &guitar,
+                &HandProfile::default(),
+            );
+            assert!(!hand_positions.is_empty());
+            for hand_position in &hand_positions {
+                let mut positions: Vec<(i32, i32)> = hand_position
+                    .positions
+                    .iter()
+                    .map(|p| (p.string_index, p.fret))
+                    .collect();
+                positions.sort();
+                let mut expected: Vec<(i32, i32)> = note_info
+                    .notes
+                    .iter()
+                    .zip(&note_info.string_indices)
+                    .map(|(&note, &string_index)| {
+                        let string = &guitar.guitar_strings[string_index as usize];
+                        (string_index, string.get_fret_by_note(note).unwrap())
+                    })
+                    .collect();
+                expected.sort();
+                assert_eq!(positions, expected);
+            }
+        }
+
+        let pool = solve_left_hand(&notes_map, 10, 1, HandProfile::default());
+        assert_eq!(pool.get_best_recorder().len(), notes_map.len() + 1);
+        assert!(pool.get_unprocessable_notes().is_empty());
+    }
+
+    #[test]
+    fn test_chord_shapes_replace_generic_fingerings_unless_competing() {
+        use crate::hand::chord_shape::{ChordShape, ShapePosition};
+
+        // 指型库里只有一个在高把位按C大三和弦的指型，通用枚举里有更省力的按法
+        let library = ChordShapeLibrary::new(vec![ChordShape {
+            name: "C高把位".to_string(),
+            category: "test".to_string(),
+            movable: false,
+            positions: vec![
+                ShapePosition {
+                    string_index: 5,
+                    fret: 8,
+                    finger: 4,
+                },
+                ShapePosition {
+                    string_index: 4,
+                    fret: 7,
+                    finger: 3,
+                },
+                ShapePosition {
+                    string_index: 3,
+                    fret: 5,
+                    finger: 1,
+                },
+            ],
+        }]);
+        let notes_map = vec![NoteInfo {
+            notes: vec![48, 52, 55],
+            real_tick: 480.0,
+            string_indices: Vec::new(),
+        }];
+        let solve = |bonus: Option<f64>, compete: bool| {
+            let pool = solve_left_hand_with(&notes_map, 10, 1, HandProfile::default(), |pool| {
+                if let Some(bonus) = bonus {
+                    pool.set_chord_shape_library(library.clone(), bonus);
+                    pool.set_chord_shapes_compete(compete);
+                }
+            });
+            match pool.get_best_recorder() {
+                HandRecorder::Left(recorder) => recorder,
+                HandRecorder::Right(_) => panic!("Expected LeftHandRecorder"),
+            }
+        };
+        let uses_shape = |recorder: &LeftHandRecorder| {
+            recorder.hand_pose_list[1].fingers.iter().any(|finger| {
+                finger.finger_index == 4 && finger.string_index == 5 && finger.fret == 8
+            })
+        };
+
+        let without_library = solve(None, false);
+        assert!(!uses_shape(&without_library));
+        // 默认只使用指型库里匹配的按法，没有奖励也不会枚举通用按法
+        assert!(uses_shape(&solve(Some(0.0), false)));
+        // 一起比较时指型只是众多按法中的一个，没有奖励时结果和不使用指型库相同
+        let no_bonus = solve(Some(0.0), true);
+        assert!(!uses_shape(&no_bonus));
+        assert!((no_bonus.current_entropy - without_library.current_entropy).abs() < 1e-9);
+        // 奖励足够大时才选择指型库里的按法
+        assert!(uses_shape(&solve(Some(1000.0), true)));
+    }
+
+    #[test]
+    fn test_only_left_hand_candidates_are_merged_by_pose() {
+        use crate::guitar::instrument_profile::InstrumentProfile;
+        use crate::hand::left_finger::LeftFinger;
+
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let fingers: Vec<LeftFinger> = (1..5)
+            .map(|i| LeftFinger::new(i, &guitar.guitar_strings[2], i, "Pressed"))
+            .collect();
+        let left_hand = LeftHand::with_profile(fingers, false, HandProfile::default());
+        let right_hand = RightHand::new(
+            vec!["i".to_string()],
+            vec![2],
+            vec!["p".to_string()],
+            false,
+            false,
+            vec![2],
+        );
+        let node = |pose: HandPose, parent: usize, entropy: f64| PoseNode {
+            pose,
+            parent: Some(parent),
+            entropy,
+            real_tick: 480.0,
+        };
+
+        // 两个记录器走到了相同的左手手型，只保留熵小的一个
+        let mut candidates = CandidateSet::new(10);
+        candidates.offer(node(HandPose::Left(left_hand.clone()), 0, 2.0));
+        candidates.offer(node(HandPose::Left(left_hand), 1, 1.0));
+        let kept = candidates.drain_sorted();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].parent, Some(1));
+
+        // 右手的记录器各自保留
+        candidates.offer(node(HandPose::Right(right_hand.clone()), 0, 2.0));
+        candidates.offer(node(HandPose::Right(right_hand), 1, 1.0));
+        let kept: Vec<Option<usize>> = candidates
+            .drain_sorted()
+            .iter()
+            .map(|node| node.parent)
+            .collect();
+        assert_eq!(kept, vec![Some(1), Some(0)]);
+    }
+
+    #[test]
+    fn test_left_hand_candidates_are_not_merged_with_phrase_consistency() {
+        use crate::guitar::instrument_profile::InstrumentProfile;
+        use crate::hand::left_finger::LeftFinger;
+
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let fingers: Vec<LeftFinger> = (1..5)
+            .map(|i| LeftFinger::new(i, &guitar.guitar_strings[2], i, "Pressed"))
+            .collect();
+        let left_hand = LeftHand::with_profile(fingers, false, HandProfile::default());
+        let notes_map: Vec<NoteInfo> = [55, 57, 59, 60, 55, 57, 59, 60]
+            .iter()
+            .enumerate()
+            .map(|(i, &note)| NoteInfo {
+                notes: vec![note],
+                real_tick: i as f64 * 480.0,
+                string_indices: Vec::new(),
+            })
+            .collect();
+        let repeats = PhraseRepeats::detect(&notes_map, 4);
+        assert!(!repeats.is_empty());
+
+        // 手型相同但是历史不同的两个候选，重复乐句会回头比较更早的手型，两个都要保留
+        let mut pool = HandPoseRecordPool::new(10);
+        pool.set_phrase_consistency(repeats.clone(), PhraseConsistencyMode::Bonus, 10.0);
+        for (parent, entropy) in [(0, 2.0), (1, 1.0)] {
+            pool.candidates.offer(PoseNode {
+                pose: HandPose::Left(left_hand.clone()),
+                parent: Some(parent),
+                entropy,
+                real_tick: 480.0,
+            });
+        }
+        assert_eq!(pool.candidates.drain_sorted().len(), 2);
+
+        // 关闭一致性以后恢复按手型合并
+        pool.set_phrase_consistency(repeats, PhraseConsistencyMode::Off, 10.0);
+        for (parent, entropy) in [(0, 2.0), (1, 1.0)] {
+            pool.candidates.offer(PoseNode {
+                pose: HandPose::Left(left_hand.clone()),
+                parent: Some(parent),
+                entropy,
+                real_tick: 480.0,
+            });
+        }
+        assert_eq!(pool.candidates.drain_sorted().len(), 1);
+    }
+
+    #[test]
+    fn test_custom_left_hand_cost_model_changes_fingering() {
+        use crate::hand::left_hand_cost::{
+            LeftHandCostModel, LeftHandCostTerms, LeftHandTransition, WeightedLeftHandCostModel,
+        };
+
+        /// 在默认消耗的基础上，不允许手指放在第9品以下
+        struct HighPositionOnly(WeightedLeftHandCostModel);
+
+        impl LeftHandCostModel for HighPositionOnly {
+            fn cost_terms(&self, transition: &LeftHandTransition) -> LeftHandCostTerms {
+                let mut terms = self.0.cost_terms(transition);
+                let low_fingers = transition
+                    .new_fingers
+                    .iter()
+                    .filter(|finger| finger.finger_index > 0 && finger.fret < 9)
+                    .count();
+                terms.high_fret += 1000.0 * low_fingers as f64;
+                terms
+            }
+        }
+
+        let notes_map: Vec<NoteInfo> = (0..4)
+            .map(|i| NoteInfo {
+                notes: vec![69],
+                real_tick: (i + 1) as f64 * 480.0,
+                string_indices: Vec::new(),
+            })
+            .collect();
+        // 初始手型之后所有手指所在的品格
+        let finger_frets = |pool: &HandPoseRecordPool| -> Vec<i32> {
+            let HandRecorder::Left(best) = pool.get_best_recorder() else {
+                panic!("Expected LeftHandRecorder");
+            };
+            best.hand_pose_list[1..]
+                .iter()
+                .flat_map(|hand| hand.fingers.iter())
+                .map(|finger| finger.fret)
+                .collect()
+        };
+
+        let default = solve_left_hand(&notes_map, 20, 1, HandProfile::default());
+        assert!(finger_frets(&default).iter().any(|&fret| fret < 9));
+
+        let custom = solve_left_hand_with(&notes_map, 20, 1, HandProfile::default(), |pool| {
+            pool.set_left_hand_cost_model(Arc::new(HighPositionOnly(
+                WeightedLeftHandCostModel::default(),
+            )));
+        });
+        assert_eq!(custom.get_best_recorder().len(), notes_map.len() + 1);
+        assert!(finger_frets(&custom).iter().all(|&fret| fret >= 9));
+    }
+
+    #[test]
+    fn test_muted_notes_are_still_plucked_by_right_hand() {
+        let init_hand = RightHand::new(vec![], vec![5, 2, 1, 0], vec![], false, false, vec![]);
+        let mut init_recorder = RightHandRecorder::new();
+        init_recorder.add_hand_pose(init_hand, 0.0, 0.0);
+        let mut pool = HandPoseRecordPool::new(10);
+        pool.insert_new_hand_pose_recorder(HandRecorder::Right(init_recorder), Some(0));
+
+        // 整个事件只有一个闷音，左手轻触四弦
+        let item = serde_json::json!({
+            "real_tick": 480.0,
+            "left_hand": [{
+                "finger_index": 2,
+                "finger_info": {"string_index": 3, "fret": 5, "press": "Mute"},
+            }],
+        });
+        pool.generate_right_hand_recorder(&item, 5);
+
+        let HandRecorder::Right(best) = pool.get_best_recorder() else {
+            panic!("Expected RightHandRecorder");
+        };
+        assert_eq!(best.hand_pose_list.len(), 2);
+        assert_eq!(best.hand_pose_list[1].touched_strings, vec![3]);
+    }
 }
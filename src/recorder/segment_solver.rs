@@ -0,0 +1,358 @@
+// SegmentSolver.rs
+
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::sync::{Mutex, PoisonError};
+
+use crate::hand::left_hand::LeftHand;
+use crate::midi::midi_to_note::NoteInfo;
+use crate::recorder::left_hand_recorder::LeftHandRecorder;
+use crate::recorder::recorder_pool::UnprocessableNoteInfo;
+
+/// 缓存超过这个数量时清空，避免长时间编辑时占用太多内存
+const MAX_CACHED_SEGMENTS: usize = 4096;
+
+/// 在长休止和段落标记处把notes_map切成几段，返回每一段在notes_map中的下标范围
+///
+/// # 参数
+/// * `min_rest_ticks` - 相邻两个音符事件的间隔不小于这个tick数时分段，不大于0时不按休止分段
+/// * `section_ticks` - 段落标记的tick，标记之后的第一个事件开始新的一段
+pub fn split_segments(
+    notes_map: &[NoteInfo],
+    min_rest_ticks: f64,
+    section_ticks: &[f64],
+) -> Vec<Range<usize>> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    for index in 1..notes_map.len() {
+        let previous_tick = notes_map[index - 1].real_tick;
+        let real_tick = notes_map[index].real_tick;
+        let long_rest = min_rest_ticks > 0.0 && real_tick - previous_tick >= min_rest_ticks;
+        let new_section = section_ticks
+            .iter()
+            .any(|&tick| previous_tick < tick && tick <= real_tick);
+        if long_rest || new_section {
+            segments.push(start..index);
+            start = index;
+        }
+    }
+    if start < notes_map.len() {
+        segments.push(start..notes_map.len());
+    }
+    segments
+}
+
+/// 一段的求解结果，不包含开始时的边界手型
+///
+/// Params:
+/// - hands: 每一步的手型
+/// - step_entropies: 每一步增加的熵
+/// - real_ticks: 每一步的tick
+/// - unprocessable_notes: 这一段里无法处理的音符
+#[derive(Clone, Debug, Default)]
+pub struct SegmentSolution {
+    pub hands: Vec<LeftHand>,
+    pub step_entropies: Vec<f64>,
+    pub real_ticks: Vec<f64>,
+    pub unprocessable_notes: Vec<UnprocessableNoteInfo>,
+}
+
+impl SegmentSolution {
+    /// 从以边界手型开始的记录器得到这一段的结果
+    pub fn from_recorder(
+        recorder: &LeftHandRecorder,
+        unprocessable_notes: Vec<UnprocessableNoteInfo>,
+    ) -> Self {
+        let step_entropies = recorder
+            .entropies
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .collect();
+        SegmentSolution {
+            hands: recorder.hand_pose_list.iter().skip(1).cloned().collect(),
+            step_entropies,
+            real_ticks: recorder.real_ticks.iter().skip(1).copied().collect(),
+            unprocessable_notes,
+        }
+    }
+}
+
+/// 分段求解结果的缓存，键由这一段的音符、求解设置和边界手型计算得到
+#[derive(Debug, Default)]
+pub struct LeftHandSegmentCache {
+    entries: HashMap<u64, SegmentSolution>,
+}
+
+impl LeftHandSegmentCache {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn insert(&mut self, key: u64, solution: SegmentSolution) {
+        if self.entries.len() >= MAX_CACHED_SEGMENTS {
+            self.entries.clear();
+        }
+        self.entries.insert(key, solution);
+    }
+}
+
+/// 分段求解的统计
+///
+/// Params:
+/// - segment_count: 分段数
+/// - reused_count: 直接使用缓存的分段数
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SegmentSolveReport {
+    pub segment_count: usize,
+    pub reused_count: usize,
+}
+
+/// 计算一段的缓存键
+///
+/// # 参数
+/// * `config_fingerprint` - 影响所有分段的设置，例如消耗权重、调弦和速度变化
+/// * `segment_fingerprint` - 只影响这一段的设置，例如作用于这一段的按法约束
+/// * `boundary` - 这一段开始时的手型
+/// * `boundary_tick` - 边界手型的real_tick，影响第一个音符事件的换把速度
+fn segment_key(
+    config_fingerprint: &str,
+    segment_fingerprint: &str,
+    notes: &[NoteInfo],
+    boundary: &LeftHand,
+    boundary_tick: f64,
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    config_fingerprint.hash(&mut hasher);
+    segment_fingerprint.hash(&mut hasher);
+    for note_info in notes {
+        note_info.notes.hash(&mut hasher);
+        note_info.real_tick.to_bits().hash(&mut hasher);
+        note_info.string_indices.hash(&mut hasher);
+    }
+    boundary.pose_key().hash(&mut hasher);
+    boundary_tick.to_bits().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 分段求解左手按法，只重新计算输入有变化的分段
+///
+/// 每一段从上一段最后的手型和tick开始求解，某一段的结果变化后，只要它最后的手型没变，
+/// 后面的分段依然可以使用缓存。求解时不锁住缓存，界面可以同时读取缓存的状态
+///
+/// # 参数
+/// * `init_hand` - 第一段开始时的手型
+/// * `segments` - split_segments得到的分段
+/// * `segment_fingerprint` - 计算一段音符自己的设置指纹
+/// * `solve_segment` - 从边界手型和它的real_tick开始求解一段音符
+#[allow(clippy::too_many_arguments)]
+pub fn solve_in_segments<S, F>(
+    init_hand: &LeftHand,
+    notes_map: &[NoteInfo],
+    segments: &[Range<usize>],
+    config_fingerprint: &str,
+    segment_fingerprint: S,
+    cache: &Mutex<LeftHandSegmentCache>,
+    mut solve_segment: F,
+) -> (
+    LeftHandRecorder,
+    Vec<UnprocessableNoteInfo>,
+    SegmentSolveReport,
+)
+where
+    S: Fn(&[NoteInfo]) -> String,
+    F: FnMut(&LeftHand, f64, &[NoteInfo]) -> SegmentSolution,
+{
+    let mut recorder = LeftHandRecorder::new();
+    recorder.add_hand_pose(init_hand.clone(), 0.0, 0.0);
+    let mut unprocessable_notes = Vec::new();
+    let mut report = SegmentSolveReport {
+        segment_count: segments.len(),
+        reused_count: 0,
+    };
+
+    for segment in segments {
+        let notes = &notes_map[segment.clone()];
+        let boundary = recorder
+            .current_hand_pose()
+            .cloned()
+            .unwrap_or_else(|| init_hand.clone());
+        let boundary_tick = recorder.real_ticks.last().copied().unwrap_or_default();
+        let key = segment_key(
+            config_fingerprint,
+            &segment_fingerprint(notes),
+            notes,
+            &boundary,
+            boundary_tick,
+        );
+
+        // 缓存里只有求解结果，之前的线程出错时缓存仍然可以使用
+        let cached = cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entries
+            .get(&key)
+            .cloned();
+        let solution = match cached {
+            Some(solution) => {
+                report.reused_count += 1;
+                solution
+            }
+            None => {
+                let solution = solve_segment(&boundary, boundary_tick, notes);
+                cache
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .insert(key, solution.clone());
+                solution
+            }
+        };
+
+        for ((hand, &entropy), &real_tick) in solution
+            .hands
+            .into_iter()
+            .zip(&solution.step_entropies)
+            .zip(&solution.real_ticks)
+        {
+            recorder.add_hand_pose(hand, entropy, real_tick);
+        }
+        unprocessable_notes.extend(solution.unprocessable_notes);
+    }
+
+    (recorder, unprocessable_notes, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+    use crate::hand::hand_profile::HandProfile;
+    use crate::hand::left_finger::LeftFinger;
+
+    fn note_info(note: i32, real_tick: f64) -> NoteInfo {
+        NoteInfo {
+            notes: vec![note],
+            real_tick,
+            string_indices: Vec::new(),
+        }
+    }
+
+    /// 每一段都保持边界手型不动，只记录求解了哪些分段
+    fn solve_counting(
+        solved: &mut Vec<f64>,
+    ) -> impl FnMut(&LeftHand, f64, &[NoteInfo]) -> SegmentSolution + '_ {
+        move |boundary, _, notes| {
+            solved.push(notes[0].real_tick);
+            SegmentSolution {
+                hands: notes.iter().map(|_| boundary.clone()).collect(),
+                step_entropies: vec![1.0; notes.len()],
+                real_ticks: notes.iter().map(|note| note.real_tick).collect(),
+                unprocessable_notes: Vec::new(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_only_changed_segments_are_solved_again() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let init_string = &guitar.guitar_strings[2];
+        let fingers = (1..5)
+            .map(|i| LeftFinger::new(i, init_string, i, "Pressed"))
+            .collect();
+        let init_hand = LeftHand::with_profile(fingers, false, HandProfile::default());
+
+        // 两段之间有四拍的休止
+        let mut notes_map = vec![
+            note_info(55, 0.0),
+            note_info(57, 480.0),
+            note_info(55, 2880.0),
+            note_info(57, 3360.0),
+        ];
+        let segments = split_segments(&notes_map, 1920.0, &[]);
+        assert_eq!(segments, vec![0..2, 2..4]);
+        assert_eq!(split_segments(&notes_map, 1920.0, &[960.0]).len(), 2);
+        assert_eq!(split_segments(&notes_map, 0.0, &[400.0]), vec![0..1, 1..4]);
+
+        let cache = Mutex::new(LeftHandSegmentCache::default());
+        let mut solved = Vec::new();
+        let (recorder, _, report) = solve_in_segments(
+            &init_hand,
+            &notes_map,
+            &segments,
+            "config",
+            |_| String::new(),
+            &cache,
+            solve_counting(&mut solved),
+        );
+        assert_eq!(recorder.hand_pose_list.len(), 5);
+        assert_eq!(recorder.current_entropy, 4.0);
+        assert_eq!(report.reused_count, 0);
+        assert_eq!(solved, vec![0.0, 2880.0]);
+
+        // 只修改第二段的音符，第一段使用缓存
+        notes_map[3].notes = vec![59];
+        let mut solved = Vec::new();
+        let (_, _, report) = solve_in_segments(
+            &init_hand,
+            &notes_map,
+            &segments,
+            "config",
+            |_| String::new(),
+            &cache,
+            solve_counting(&mut solved),
+        );
+        assert_eq!(report.reused_count, 1);
+        assert_eq!(solved, vec![2880.0]);
+    }
+
+    #[test]
+    fn test_segments_start_at_previous_segment_tick() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let init_string = &guitar.guitar_strings[2];
+        let fingers = (1..5)
+            .map(|i| LeftFinger::new(i, init_string, i, "Pressed"))
+            .collect();
+        let init_hand = LeftHand::with_profile(fingers, false, HandProfile::default());
+        let notes_map = vec![
+            note_info(55, 0.0),
+            note_info(57, 480.0),
+            note_info(55, 2880.0),
+            note_info(57, 3360.0),
+        ];
+        let segments = split_segments(&notes_map, 1920.0, &[]);
+
+        let cache = Mutex::new(LeftHandSegmentCache::default());
+        let mut boundary_ticks = Vec::new();
+        let mut solve = |boundary: &LeftHand, boundary_tick: f64, notes: &[NoteInfo]| {
+            // 求解的时候缓存没有被锁住
+            assert!(cache.try_lock().is_ok());
+            boundary_ticks.push(boundary_tick);
+            SegmentSolution {
+                hands: notes.iter().map(|_| boundary.clone()).collect(),
+                step_entropies: vec![0.0; notes.len()],
+                real_ticks: notes.iter().map(|note| note.real_tick).collect(),
+                unprocessable_notes: Vec::new(),
+            }
+        };
+        solve_in_segments(
+            &init_hand,
+            &notes_map,
+            &segments,
+            "config",
+            |_| String::new(),
+            &cache,
+            &mut solve,
+        );
+        // 第二段从第一段最后一个音符事件的tick开始，不是从0开始
+        assert_eq!(boundary_ticks, vec![0.0, 480.0]);
+    }
+}
@@ -0,0 +1,742 @@
+// ViterbiSolver.rs
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::guitar::guitar_instance::Guitar;
+use crate::hand::chord_diagnosis::{UnplayableDiagnosis, diagnose_unplayable_notes};
+use crate::hand::chord_shape::ChordShapeLibrary;
+use crate::hand::hand_profile::HandProfile;
+use crate::hand::left_hand::{LeftHand, LeftHandPoseKey};
+use crate::hand::left_hand_cost::{LeftHandCostModel, WeightedLeftHandCostModel};
+use crate::midi::midi_to_note::{MidiProcessor, NoteInfo, TempoMap};
+use crate::recorder::fingering_constraint::FingeringConstraints;
+use crate::recorder::left_hand_expansion::{EventExpansion, ExpansionRules, Relaxation};
+use crate::recorder::left_hand_recorder::LeftHandRecorder;
+use crate::recorder::left_hand_trace::{LeftHandTrace, TracedEvent, build_left_hand_trace};
+use crate::recorder::note_fallback::{FallbackResolution, FallbackSettings, fallback_plans};
+use crate::recorder::position_plan::PositionPlan;
+use crate::recorder::recorder_pool::UnprocessableNoteInfo;
+
+/// 状态是通过哪个按法得到的
+#[derive(Clone, Copy, Debug)]
+enum Choice {
+    /// 候选按法列表中的下标
+    Candidate(usize),
+    /// 无法过渡时重复上一个手型
+    Repeat,
+}
+
+/// 格子图里的一个节点，只保存回溯需要的信息
+#[derive(Clone, Copy, Debug)]
+struct LatticeNode {
+    parent: usize,
+    choice: Choice,
+}
+
+/// 当前层的状态，完整的手型只在当前层保存
+struct ActiveState {
+    hand: LeftHand,
+    cost: f64,
+}
+
+/// 左手最优解求解器
+///
+/// 在(事件, 手型)组成的格子图上做Viterbi动态规划，转移和消耗与束搜索使用的
+/// `generate_next_hands_with_cost_model`完全相同，但是不限制每一层的状态数，
+/// 相同的手型只保留消耗最小的一条路径，所以得到的是全局最优解
+pub struct ViterbiLeftHandSolver {
+    /// 和弦指型库，为None时只使用通用的按法枚举
+    chord_shape_library: Option<ChordShapeLibrary>,
+    /// 使用指型库按法时减少的熵值
+    chord_shape_bonus: f64,
+    /// 指型库按法和通用按法一起参与比较，为false时只在指型库按法无法过渡时使用通用按法
+    chord_shapes_compete: bool,
+    /// 左手消耗模型
+    left_hand_cost_model: Arc<dyn LeftHandCostModel>,
+    /// 无法处理的音符组合列表
+    unprocessable_notes: Vec<UnprocessableNoteInfo>,
+    /// 求解过程中单层的最大状态数
+    max_layer_size: usize,
+    /// 速度表，设置后左手消耗会考虑换把的速度
+    tempo_map: Option<TempoMap>,
+    /// 角色的手型数据，枚举按法时使用其中的跨度限制
+    hand_profile: HandProfile,
+    /// 编曲者指定的按法约束
+    fingering_constraints: FingeringConstraints,
+    /// 是否记录处理过的音符事件，只有记录了事件才能生成求解追踪
+    trace_enabled: bool,
+    /// 处理过的音符事件，用于生成求解追踪
+    traced_events: Vec<TracedEvent>,
+    /// 找不到按法时的退化设置
+    fallback_settings: FallbackSettings,
+    /// 除最优解外额外回溯的终点数，为0时不生成备选解
+    alternative_count: usize,
+    /// 按消耗排序的备选解，第一个就是最优解
+    ranked_recorders: Vec<LeftHandRecorder>,
+    /// 把位规划，为空时不限制按法的把位
+    position_plan: PositionPlan,
+    /// 初始手型的real_tick，分段求解时是上一段最后一个音符事件的tick
+    init_tick: f64,
+}
+
+impl ViterbiLeftHandSolver {
+    pub fn new() -> Self {
+        ViterbiLeftHandSolver {
+            chord_shape_library: None,
+            chord_shape_bonus: 0.5,
+            chord_shapes_compete: false,
+            left_hand_cost_model: Arc::new(WeightedLeftHandCostModel::default()),
+            unprocessable_notes: Vec::new(),
+            max_layer_size: 0,
+            tempo_map: None,
+            hand_profile: HandProfile::default(),
+            fingering_constraints: FingeringConstraints::default(),
+            trace_enabled: false,
+            traced_events: Vec::new(),
+            fallback_settings: FallbackSettings::default(),
+            alternative_count: 0,
+            ranked_recorders: Vec::new(),
+            position_plan: PositionPlan::default(),
+            init_tick: 0.0,
+        }
+    }
+
+    /// 设置速度表，设置后左手消耗会考虑两个手型之间的时间间隔
+    pub fn set_tempo_map(&mut self, tempo_map: TempoMap) {
+        self.tempo_map = Some(tempo_map);
+    }
+
+    /// 设置角色的手型数据，初始手型需要用同样的数据创建
+    pub fn set_hand_profile(&mut self, hand_profile: HandProfile) {
+        self.hand_profile = hand_profile;
+    }
+
+    /// 设置按法约束，约束需要先调用bind_events找到作用的音符事件
+    pub fn set_fingering_constraints(&mut self, constraints: FingeringConstraints) {
+        self.fingering_constraints = constraints;
+    }
+
+    /// 设置是否记录生成求解追踪需要的音符事件，求解之前设置
+    pub fn set_trace_enabled(&mut self, enabled: bool) {
+        self.trace_enabled = enabled;
+    }
+
+    /// 设置找不到按法时的退化方式
+    pub fn set_fallback_settings(&mut self, settings: FallbackSettings) {
+        self.fallback_settings = settings;
+    }
+
+    /// 设置把位规划，之后每个事件只使用规划的把位附近的按法
+    pub fn set_position_plan(&mut self, plan: PositionPlan) {
+        self.position_plan = plan;
+    }
+
+    /// 设置初始手型的real_tick，第一个音符事件的换把速度按它计算
+    pub fn set_init_tick(&mut self, init_tick: f64) {
+        self.init_tick = init_tick;
+    }
+
+    /// 设置求解时额外回溯的终点数
+    ///
+    /// 每个终点手型只保留一条最优路径，所以备选解是以不同手型结束的最优路径，
+    /// 它们在结尾之前可能有很长一段完全相同
+    pub fn set_alternative_count(&mut self, count: usize) {
+        self.alternative_count = count;
+    }
+
+    /// 获取按消耗排序的备选解，没有设置备选数时为空
+    pub fn get_ranked_recorders(&self) -> &Vec<LeftHandRecorder> {
+        &self.ranked_recorders
+    }
+
+    /// 设置和弦指型库
+    pub fn set_chord_shape_library(&mut self, library: ChordShapeLibrary, bonus: f64) {
+        self.chord_shape_library = Some(library);
+        self.chord_shape_bonus = bonus;
+    }
+
+    /// 设置指型库按法是否和通用按法一起参与比较
+    pub fn set_chord_shapes_compete(&mut self, compete: bool) {
+        self.chord_shapes_compete = compete;
+    }
+
+    /// 设置左手消耗模型
+    pub fn set_left_hand_cost_model(&mut self, cost_model: Arc<dyn LeftHandCostModel>) {
+        self.left_hand_cost_model = cost_model;
+    }
+
+    /// 获取无法处理的音符组合列表
+    pub fn get_unprocessable_notes(&self) -> &Vec<UnprocessableNoteInfo> {
+        &self.unprocessable_notes
+    }
+
+    /// 获取求解过程中单层的最大状态数
+    pub fn get_max_layer_size(&self) -> usize {
+        self.max_layer_size
+    }
+
+    /// 求解全曲的最优左手按法
+    ///
+    /// # 参数
+    /// * `init_hand` - 初始手型
+    /// * `notes_map` - 音符事件，和束搜索比较时要传入同一份处理过的音符，
+    ///   处理过的音符不会再被精简，两个求解器展开的音符完全相同
+    /// * `callback` - 进度输出
+    pub fn solve<F>(
+        &mut self,
+        init_hand: LeftHand,
+        guitar: &Guitar,
+        notes_map: &[NoteInfo],
+        midi_processor: &MidiProcessor,
+        callback: F,
+    ) -> LeftHandRecorder
+    where
+        F: Fn(&str),
+    {
+        let start_time = Instant::now();
+        let (min_note, max_note) = guitar.get_note_range();
+        let total_steps = notes_map.len();
+
+        self.unprocessable_notes.clear();
+        self.traced_events.clear();
+        self.ranked_recorders.clear();
+        self.max_layer_size = 1;
+
+        let mut layers: Vec<Vec<LatticeNode>> = Vec::new();
+        // 每一层对应的处理后音符和放宽的条件，回溯时用来重新生成手型
+        let mut steps: Vec<(NoteInfo, Relaxation)> = Vec::new();
+        let mut active = vec![ActiveState {
+            hand: init_hand.clone(),
+            cost: 0.0,
+        }];
+        // 当前层所有状态的real_tick都相同，初始手型在init_tick时刻
+        let mut previous_tick = self.init_tick;
+
+        for (i, guitar_note) in notes_map.iter().enumerate() {
+            let real_tick = guitar_note.real_tick;
+            let processed = midi_processor.processed_note_info(
+                guitar_note,
+                min_note,
+                max_note,
+                guitar.guitar_strings.len(),
+            );
+            let processed_notes = &processed.notes;
+            if guitar_note.notes.is_empty() || processed_notes.is_empty() {
+                continue;
+            }
+
+            let time_gap = self.time_gap(previous_tick, real_tick);
+            let (next_active, next_nodes, result) =
+                self.advance_layer(&active, &processed, guitar, time_gap);
+
+            if next_active.is_empty() {
+                // 有按法但是无法从之前的手型过渡时不需要再诊断
+                let diagnosis = if result.has_positions {
+                    UnplayableDiagnosis::no_valid_transition()
+                } else {
+                    diagnose_unplayable_notes(
+                        processed_notes,
+                        &processed.string_indices,
+                        guitar,
+                        &self.hand_profile,
+                    )
+                };
+                let fallback = self.apply_fallback(
+                    &mut active,
+                    &mut layers,
+                    &mut steps,
+                    &mut previous_tick,
+                    &processed,
+                    notes_map.get(i + 1).map(|next| next.real_tick),
+                    guitar,
+                );
+                self.unprocessable_notes.push(UnprocessableNoteInfo {
+                    real_tick,
+                    notes: processed_notes.clone(),
+                    diagnosis,
+                    fallback,
+                });
+            } else {
+                self.push_layer(
+                    &mut layers,
+                    &mut steps,
+                    next_nodes,
+                    &next_active,
+                    (processed, result.relaxation),
+                );
+                active = next_active;
+                previous_tick = real_tick;
+            }
+
+            // 每处理10项报告一次进度
+            if i % 10 == 0 || i == total_steps - 1 {
+                callback(&format!(
+                    "处理进度: {}/{} (当前状态数: {})",
+                    i + 1,
+                    total_steps,
+                    active.len()
+                ));
+            }
+        }
+
+        // 按消耗给终点排序，消耗最小的终点就是最优解，其余的终点用于生成备选解
+        let mut endpoints: Vec<usize> = (0..active.len()).collect();
+        endpoints.sort_by(|&a, &b| active[a].cost.total_cmp(&active[b].cost));
+        endpoints.truncate(self.alternative_count.max(1));
+
+        let mut ranked: Vec<LeftHandRecorder> = endpoints
+            .into_iter()
+            .map(|endpoint| {
+                let choices = Self::backtrack(&layers, endpoint);
+                self.rebuild_path(init_hand.clone(), guitar, &steps, &choices)
+            })
+            .collect();
+        let recorder = ranked.remove(0);
+        if self.alternative_count > 0 {
+            ranked.insert(0, recorder.clone());
+        }
+        self.ranked_recorders = ranked;
+
+        callback(&format!(
+            "最优解求解完成，一共费时：{:} 秒，单层最大状态数：{}",
+            start_time.elapsed().as_secs_f64(),
+            self.max_layer_size
+        ));
+
+        recorder
+    }
+
+    /// 为求解结果生成每一步的消耗追踪，需要在求解前调用set_trace_enabled
+    ///
+    /// # 参数
+    /// * `recorder` - solve返回的记录器
+    /// * `runner_up_count` - 每一步最多保留的落选按法数
+    pub fn trace_left_hand_recorder(
+        &self,
+        recorder: &LeftHandRecorder,
+        guitar: &Guitar,
+        runner_up_count: usize,
+    ) -> LeftHandTrace {
+        build_left_hand_trace(
+            recorder,
+            &self.traced_events,
+            &self.expansion_rules(guitar),
+            runner_up_count,
+        )
+    }
+
+    /// 展开左手按法的规则，束搜索和求解追踪使用同样的规则
+    fn expansion_rules<'a>(&'a self, guitar: &'a Guitar) -> ExpansionRules<'a> {
+        ExpansionRules {
+            guitar,
+            cost_model: self.left_hand_cost_model.as_ref(),
+            tempo_map: self.tempo_map.as_ref(),
+            hand_profile: &self.hand_profile,
+            chord_shape_library: self.chord_shape_library.as_ref(),
+            chord_shape_bonus: self.chord_shape_bonus,
+            chord_shapes_compete: self.chord_shapes_compete,
+            fingering_constraints: &self.fingering_constraints,
+            position_plan: &self.position_plan,
+        }
+    }
+
+    /// 用一个音符事件扩展当前层，返回下一层的状态、节点和展开的结果
+    ///
+    /// 相同的手型只保留消耗最小的一条路径
+    fn advance_layer(
+        &self,
+        active: &[ActiveState],
+        note_info: &NoteInfo,
+        guitar: &Guitar,
+        time_gap: Option<f64>,
+    ) -> (Vec<ActiveState>, Vec<LatticeNode>, EventExpansion) {
+        let rules = self.expansion_rules(guitar);
+        let mut result = rules.expand_event(note_info, |event| {
+            active
+                .iter()
+                .map(|state| rules.expand_hand(&state.hand, event, time_gap))
+                .collect()
+        });
+
+        let mut next_active: Vec<ActiveState> = Vec::new();
+        let mut next_nodes: Vec<LatticeNode> = Vec::new();
+        let mut pose_index: HashMap<LeftHandPoseKey, usize> = HashMap::new();
+        for (parent, expansions) in std::mem::take(&mut result.expansions)
+            .into_iter()
+            .enumerate()
+        {
+            for expansion in expansions {
+                let cost = active[parent].cost + expansion.cost();
+                let node = LatticeNode {
+                    parent,
+                    choice: Choice::Candidate(expansion.candidate),
+                };
+                match pose_index.entry(expansion.hand.pose_key()) {
+                    Entry::Occupied(entry) => {
+                        let index = *entry.get();
+                        if cost < next_active[index].cost {
+                            next_active[index] = ActiveState {
+                                hand: expansion.hand,
+                                cost,
+                            };
+                            next_nodes[index] = node;
+                        }
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(next_active.len());
+                        next_active.push(ActiveState {
+                            hand: expansion.hand,
+                            cost,
+                        });
+                        next_nodes.push(node);
+                    }
+                }
+            }
+        }
+        (next_active, next_nodes, result)
+    }
+
+    /// 把新的一层写入格子图
+    fn push_layer(
+        &mut self,
+        layers: &mut Vec<Vec<LatticeNode>>,
+        steps: &mut Vec<(NoteInfo, Relaxation)>,
+        nodes: Vec<LatticeNode>,
+        next_active: &[ActiveState],
+        step: (NoteInfo, Relaxation),
+    ) {
+        self.max_layer_size = self.max_layer_size.max(next_active.len());
+        if self.trace_enabled {
+            self.traced_events.push(TracedEvent {
+                note_info: step.0.clone(),
+                pool_size: next_active.len(),
+                relaxation: step.1.clone(),
+            });
+        }
+        layers.push(nodes);
+        steps.push(step);
+    }
+
+    /// 所有状态都重复上一个手型的一层
+    fn repeat_layer(active: &[ActiveState]) -> Vec<LatticeNode> {
+        (0..active.len())
+            .map(|parent| LatticeNode {
+                parent,
+                choice: Choice::Repeat,
+            })
+            .collect()
+    }
+
+    /// 当前层所有状态都无法过渡时，依次尝试退化方案，返回实际采用的方案
+    ///
+    /// 琶音的第一组能过渡就采用整个方案，之后的组无法过渡时每个状态都重复上一个手型，
+    /// 不算演奏了这一组
+    ///
+    /// # 参数
+    /// * `next_tick` - 下一个音符事件的real_tick，退化成琶音时所有组都在它之前演奏
+    #[allow(clippy::too_many_arguments)]
+    fn apply_fallback(
+        &mut self,
+        active: &mut Vec<ActiveState>,
+        layers: &mut Vec<Vec<LatticeNode>>,
+        steps: &mut Vec<(NoteInfo, Relaxation)>,
+        previous_tick: &mut f64,
+        note_info: &NoteInfo,
+        next_tick: Option<f64>,
+        guitar: &Guitar,
+    ) -> FallbackResolution {
+        let plans = fallback_plans(
+            note_info,
+            next_tick,
+            guitar,
+            &self.hand_profile,
+            &self.fallback_settings,
+        );
+        for plan in plans {
+            let mut accepted = false;
+            let mut failed_parts = Vec::new();
+            for (index, part) in plan.parts.iter().enumerate() {
+                let time_gap = self.time_gap(*previous_tick, part.real_tick);
+                let (next_active, next_nodes, result) =
+                    self.advance_layer(active, part, guitar, time_gap);
+                if next_active.is_empty() {
+                    if !accepted {
+                        break;
+                    }
+                    let nodes = Self::repeat_layer(active);
+                    let step = (part.clone(), Relaxation::default());
+                    self.push_layer(layers, steps, nodes, active, step);
+                    failed_parts.push(index);
+                } else {
+                    let step = (part.clone(), result.relaxation);
+                    self.push_layer(layers, steps, next_nodes, &next_active, step);
+                    *active = next_active;
+                }
+                accepted = true;
+                *previous_tick = part.real_tick;
+            }
+            if accepted {
+                return plan.resolution(&failed_parts);
+            }
+        }
+
+        let nodes = Self::repeat_layer(active);
+        let step = (note_info.clone(), Relaxation::default());
+        self.push_layer(layers, steps, nodes, active, step);
+        *previous_tick = note_info.real_tick;
+        FallbackResolution::repeated(note_info)
+    }
+
+    /// 从第endpoint个终点回溯出每一步的选择
+    fn backtrack(layers: &[Vec<LatticeNode>], endpoint: usize) -> Vec<Choice> {
+        let mut choices = vec![Choice::Repeat; layers.len()];
+        let mut current = endpoint;
+        for (layer_index, layer) in layers.iter().enumerate().rev() {
+            let node = layer[current];
+            choices[layer_index] = node.choice;
+            current = node.parent;
+        }
+        choices
+    }
+
+    /// 按回溯得到的选择重新生成手型，得到最终的记录器
+    fn rebuild_path(
+        &self,
+        init_hand: LeftHand,
+        guitar: &Guitar,
+        steps: &[(NoteInfo, Relaxation)],
+        choices: &[Choice],
+    ) -> LeftHandRecorder {
+        let rules = self.expansion_rules(guitar);
+        let mut recorder = LeftHandRecorder::new();
+        recorder.add_hand_pose(init_hand.clone(), 0.0, self.init_tick);
+        let mut hand = init_hand;
+        let mut previous_tick = self.init_tick;
+
+        for ((note_info, relaxation), choice) in steps.iter().zip(choices) {
+            let real_tick = note_info.real_tick;
+            let mut step_cost = 0.0;
+            // 用求解时同样的放宽条件重新生成候选按法，选择的下标才能对应到同一个按法
+            if let Choice::Candidate(candidate) = *choice
+                && let Some(expansion) = rules.expand_candidate(
+                    &hand,
+                    &rules.event_fingerings(note_info, relaxation),
+                    candidate,
+                    self.time_gap(previous_tick, real_tick),
+                )
+            {
+                step_cost = expansion.cost();
+                hand = expansion.hand;
+            }
+
+            recorder.add_hand_pose(hand.clone(), step_cost, real_tick);
+            previous_tick = real_tick;
+        }
+
+        recorder
+    }
+
+    /// 两个real_tick之间的秒数，没有设置速度表时为None
+    fn time_gap(&self, from_tick: f64, to_tick: f64) -> Option<f64> {
+        self.tempo_map
+            .as_ref()
+            .map(|tempo_map| tempo_map.seconds_between(from_tick, to_tick))
+    }
+}
+
+impl Default for ViterbiLeftHandSolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 统计两个左手记录器中不同手型的数量，长度不同时多出来的部分都算作不同
+pub fn count_different_poses(a: &LeftHandRecorder, b: &LeftHandRecorder) -> usize {
+    let different = a
+        .hand_pose_list
+        .iter()
+        .zip(&b.hand_pose_list)
+        .filter(|(hand_a, hand_b)| hand_a.pose_key() != hand_b.pose_key())
+        .count();
+    different + a.hand_pose_list.len().abs_diff(b.hand_pose_list.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guitar::instrument_profile::InstrumentProfile;
+    use crate::hand::left_finger::LeftFinger;
+    use crate::recorder::recorder_pool::{HandPoseRecordPool, HandRecorder};
+
+    #[test]
+    fn test_viterbi_is_not_worse_than_beam() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let init_string = &guitar.guitar_strings[2];
+        let init_hand = LeftHand::new(
+            (1..5)
+                .map(|i| LeftFinger::new(i, init_string, i, "Pressed"))
+                .collect(),
+            false,
+            5.73,
+        );
+        let chords = [
+            vec![48, 52, 55, 60, 64],
+            vec![45, 52, 57, 60, 64],
+            vec![41, 48, 53, 57, 60, 65],
+            vec![64],
+            vec![67],
+            vec![72, 60],
+        ];
+        let notes_map: Vec<NoteInfo> = chords
+            .iter()
+            .enumerate()
+            .map(|(i, notes)| NoteInfo {
+                notes: notes.clone(),
+                real_tick: i as f64 * 480.0,
+                string_indices: Vec::new(),
+            })
+            .collect();
+        let midi_processor = MidiProcessor::new();
+
+        let mut init_recorder = LeftHandRecorder::new();
+        init_recorder.add_hand_pose(init_hand.clone(), 0.0, 0.0);
+        let mut pool = HandPoseRecordPool::new(10);
+        pool.insert_new_hand_pose_recorder(HandRecorder::Left(init_recorder), Some(0));
+        let (mut current, mut previous) = (0, 0);
+        pool.update_left_handrecorder_pool(
+            &guitar,
+            &notes_map,
+            &midi_processor,
+            &mut current,
+            &mut previous,
+            |_| {},
+        );
+        let beam_entropy = pool.get_best_recorder().current_entropy();
+
+        let optimal = ViterbiLeftHandSolver::new().solve(
+            init_hand,
+            &guitar,
+            &notes_map,
+            &midi_processor,
+            |_| {},
+        );
+
+        assert_eq!(optimal.hand_pose_list.len(), notes_map.len() + 1);
+        assert!(optimal.current_entropy <= beam_entropy + 1e-9);
+    }
+
+    #[test]
+    fn test_fingering_constraints_pin_melody_to_string() {
+        use crate::recorder::fingering_constraint::{ConstraintRange, FingeringConstraint};
+
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let init_string = &guitar.guitar_strings[2];
+        let init_hand = LeftHand::new(
+            (1..5)
+                .map(|i| LeftFinger::new(i, init_string, i, "Pressed"))
+                .collect(),
+            false,
+            5.73,
+        );
+        let notes_map: Vec<NoteInfo> = [64, 67, 69, 71, 59]
+            .iter()
+            .enumerate()
+            .map(|(i, &note)| NoteInfo {
+                notes: vec![note],
+                real_tick: i as f64 * 480.0,
+                string_indices: Vec::new(),
+            })
+            .collect();
+        let pin_string = |start, end, string_index| FingeringConstraint {
+            range: ConstraintRange::NoteIndex {
+                start,
+                end: Some(end),
+            },
+            note: None,
+            string_index: Some(string_index),
+            fret: None,
+            finger: None,
+            barre: None,
+            hand_position: None,
+            penalty: None,
+        };
+        // 最后一个音在第一弦上弹不出来
+        let mut constraints =
+            FingeringConstraints::new(vec![pin_string(0, 3, 1), pin_string(4, 4, 0)]);
+        assert!(constraints.bind_events(&notes_map, &[], 480).is_empty());
+
+        let mut solver = ViterbiLeftHandSolver::new();
+        solver.set_fingering_constraints(constraints.clone());
+        let recorder = solver.solve(
+            init_hand,
+            &guitar,
+            &notes_map,
+            &MidiProcessor::new(),
+            |_| {},
+        );
+
+        assert_eq!(recorder.hand_pose_list.len(), notes_map.len() + 1);
+        let unsatisfied = constraints.find_unsatisfied(&recorder, &notes_map, &guitar);
+        assert_eq!(unsatisfied.len(), 1);
+        assert_eq!(unsatisfied[0].constraint_index, 1);
+        assert_eq!(unsatisfied[0].real_tick, 4.0 * 480.0);
+    }
+
+    #[test]
+    fn test_compare_solvers_on_shared_processed_notes() {
+        let guitar = InstrumentProfile::default().create_guitar(false);
+        let init_string = &guitar.guitar_strings[2];
+        let init_hand = LeftHand::new(
+            (1..5)
+                .map(|i| LeftFinger::new(i, init_string, i, "Pressed"))
+                .collect(),
+            false,
+            5.73,
+        );
+        let midi_processor = MidiProcessor::new();
+        let (min_note, max_note) = guitar.get_note_range();
+        let raw_notes_map = vec![NoteInfo {
+            notes: vec![40, 45, 50, 55, 59, 62, 64],
+            real_tick: 480.0,
+            string_indices: Vec::new(),
+        }];
+
+        // 7个音的和弦要随机去掉一个中间的音，只处理一次，两个求解器展开同样的音，最优解不会比束搜索差
+        for _ in 0..5 {
+            let notes_map = midi_processor.processed_notes_map(
+                &raw_notes_map,
+                min_note,
+                max_note,
+                guitar.guitar_strings.len(),
+            );
+            let mut init_recorder = LeftHandRecorder::new();
+            init_recorder.add_hand_pose(init_hand.clone(), 0.0, 0.0);
+            let mut pool = HandPoseRecordPool::new(10);
+            pool.insert_new_hand_pose_recorder(HandRecorder::Left(init_recorder), Some(0));
+            let (mut current, mut previous) = (0, 0);
+            pool.update_left_handrecorder_pool(
+                &guitar,
+                &notes_map,
+                &midi_processor,
+                &mut current,
+                &mut previous,
+                |_| {},
+            );
+            let mut solver = ViterbiLeftHandSolver::new();
+            let optimal = solver.solve(
+                init_hand.clone(),
+                &guitar,
+                &notes_map,
+                &midi_processor,
+                |_| {},
+            );
+
+            assert!(pool.get_unprocessable_notes().is_empty());
+            assert!(solver.get_unprocessable_notes().is_empty());
+            assert!(optimal.current_entropy <= pool.get_best_recorder().current_entropy() + 1e-9);
+        }
+    }
+}
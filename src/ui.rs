@@ -5,5 +5,7 @@ pub mod avatar_display;
 pub mod execute_operation;
 pub mod midi_info_scan;
 pub mod parameter_setting;
+pub mod show_alternatives;
 pub mod show_console;
+pub mod show_trace;
 pub mod theme;
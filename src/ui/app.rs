@@ -1,8 +1,15 @@
+use crate::animate::handedness::Handedness;
 use crate::fret_dancer::FretDancerState;
+use crate::guitar::instrument_profile::InstrumentProfile;
+use crate::hand::hand_profile::HandProfile;
+use crate::job_config::JobConfig;
+use crate::recorder::alternatives::AlternativesReport;
+use crate::recorder::left_hand_trace::LeftHandTrace;
+use crate::recorder::segment_solver::LeftHandSegmentCache;
 use crate::ui::theme;
 use eframe::egui;
 use serde::{Deserialize, Serialize};
-use std::sync::mpsc;
+use std::sync::{Arc, Mutex, mpsc};
 
 use crate::ui::about::show_about_dialog;
 
@@ -20,6 +27,12 @@ pub struct AvatarInfo {
     pub file: String,
     pub image: String,
     pub instrument: String,
+    /// 角色的手型数据，旧的配置文件里没有时使用默认手型
+    #[serde(default)]
+    pub hand_profile: HandProfile,
+    /// 持琴方式，旧的配置文件里没有时按右手持琴处理
+    #[serde(default)]
+    pub handedness: Handedness,
 }
 // 乐器调弦预设
 #[derive(Clone, Debug)]
@@ -66,6 +79,8 @@ pub struct FretDanceApp {
     pub track_numbers_str: String,
     pub selected_track: i32,
     pub channel_number: i32,
+    pub channel_per_string: bool,
+    pub first_string_channel: i32,
     pub fps: f64,
     pub guitar_string_notes: Vec<String>,
     pub octave_down_checkbox: bool,
@@ -73,6 +88,22 @@ pub struct FretDanceApp {
     pub use_harm_notes: bool,
     pub disable_barre: bool,
 
+    // 乐器配置
+    pub instrument_profile: InstrumentProfile,
+    pub instrument_profiles: Vec<InstrumentProfile>,
+
+    // 和弦指型库
+    pub use_chord_shapes: bool,
+    pub chord_shape_file: String,
+
+    // 按法约束
+    pub use_fingering_constraints: bool,
+    pub fingering_constraint_file: String,
+
+    // 任务配置
+    pub job_config: JobConfig,
+    pub job_config_file: String,
+
     // 预设调弦
     pub tuning_presets: Vec<TuningPreset>,
 
@@ -82,6 +113,13 @@ pub struct FretDanceApp {
     // 控制台输出
     pub(crate) console_output: String,
 
+    // 左手求解追踪，从追踪文件读取
+    pub(crate) left_hand_trace: Option<LeftHandTrace>,
+
+    // 左手备选按法，以及每个段落选择的备选序号
+    pub(crate) left_hand_alternatives: Option<AlternativesReport>,
+    pub(crate) alternative_choices: Vec<usize>,
+
     // Avatar信息
     pub(crate) avatar_infos: Vec<AvatarInfo>,
     pub(crate) current_avatar_info: Option<AvatarInfo>,
@@ -97,6 +135,8 @@ pub struct FretDanceApp {
     pub(crate) edit_avatar_json: String,
     pub(crate) edit_avatar_selected_json_path: String,
     pub(crate) edit_avatar_instrument: InstrumentType,
+    pub(crate) edit_avatar_hand_profile: HandProfile,
+    pub(crate) edit_avatar_handedness: Handedness,
     pub(crate) edit_avatar_mode: EditAvatarMode,
 
     // 主题设置
@@ -115,6 +155,9 @@ pub struct FretDanceApp {
     // FretDancer状态，用于在操作间共享
     pub fret_dancer_state: Option<FretDancerState>,
 
+    // 分段求解的缓存，在后台线程和界面之间共享
+    pub left_hand_segment_cache: Arc<Mutex<LeftHandSegmentCache>>,
+
     // 用于多线程通信的接收端
     pub output_receiver: Option<mpsc::Receiver<String>>,
 
@@ -130,15 +173,28 @@ impl Clone for FretDanceApp {
             track_numbers_str: self.track_numbers_str.clone(),
             selected_track: self.selected_track,
             channel_number: self.channel_number,
+            channel_per_string: self.channel_per_string,
+            first_string_channel: self.first_string_channel,
             fps: self.fps,
             guitar_string_notes: self.guitar_string_notes.clone(),
             octave_down_checkbox: self.octave_down_checkbox,
             capo_number: self.capo_number,
             use_harm_notes: self.use_harm_notes,
             disable_barre: self.disable_barre,
+            instrument_profile: self.instrument_profile.clone(),
+            instrument_profiles: self.instrument_profiles.clone(),
+            use_chord_shapes: self.use_chord_shapes,
+            chord_shape_file: self.chord_shape_file.clone(),
+            use_fingering_constraints: self.use_fingering_constraints,
+            fingering_constraint_file: self.fingering_constraint_file.clone(),
+            job_config: self.job_config.clone(),
+            job_config_file: self.job_config_file.clone(),
             tuning_presets: self.tuning_presets.clone(),
             avatar_options: self.avatar_options.clone(),
             console_output: self.console_output.clone(),
+            left_hand_trace: self.left_hand_trace.clone(),
+            left_hand_alternatives: self.left_hand_alternatives.clone(),
+            alternative_choices: self.alternative_choices.clone(),
             avatar_infos: self.avatar_infos.clone(),
             current_avatar_info: self.current_avatar_info.clone(),
             show_delete_confirmation: self.show_delete_confirmation,
@@ -149,6 +205,8 @@ impl Clone for FretDanceApp {
             edit_avatar_json: self.edit_avatar_json.clone(),
             edit_avatar_selected_json_path: self.edit_avatar_selected_json_path.clone(),
             edit_avatar_instrument: self.edit_avatar_instrument.clone(),
+            edit_avatar_hand_profile: self.edit_avatar_hand_profile,
+            edit_avatar_handedness: self.edit_avatar_handedness,
             edit_avatar_mode: self.edit_avatar_mode.clone(),
             dark_mode: self.dark_mode,
             midi_info_result: self.midi_info_result.clone(),
@@ -156,6 +214,7 @@ impl Clone for FretDanceApp {
             current_tab: self.current_tab,
             show_about_dialog: self.show_about_dialog,
             fret_dancer_state: self.fret_dancer_state.clone(),
+            left_hand_segment_cache: Arc::clone(&self.left_hand_segment_cache),
             output_receiver: None,
             is_processing: false,
         }
@@ -175,6 +234,8 @@ impl FretDanceApp {
             track_numbers_str: "1".to_string(),
             selected_track: 1,
             channel_number: -1,
+            channel_per_string: false,
+            first_string_channel: 0,
             fps: 30.0,
             guitar_string_notes: vec![
                 "e".to_string(),
@@ -188,6 +249,14 @@ impl FretDanceApp {
             capo_number: 0,
             use_harm_notes: false,
             disable_barre: false,
+            instrument_profile: InstrumentProfile::default(),
+            instrument_profiles: InstrumentProfile::presets(),
+            use_chord_shapes: true,
+            chord_shape_file: "asset/chord_shapes.json".to_string(),
+            use_fingering_constraints: false,
+            fingering_constraint_file: "output/fingering_constraints.json".to_string(),
+            job_config: JobConfig::default(),
+            job_config_file: "output/job_config.json".to_string(),
             tuning_presets: vec![
                 TuningPreset {
                     name: "标准调弦 (E A D G B E)".to_string(),
@@ -254,14 +323,20 @@ impl FretDanceApp {
             edit_avatar_json: String::new(),
             edit_avatar_selected_json_path: String::new(),
             edit_avatar_instrument: InstrumentType::FingerStyleGuitar,
+            edit_avatar_hand_profile: HandProfile::default(),
+            edit_avatar_handedness: Handedness::default(),
             edit_avatar_mode: EditAvatarMode::New,
             console_output: String::new(),
+            left_hand_trace: None,
+            left_hand_alternatives: None,
+            alternative_choices: Vec::new(),
             dark_mode: true,
             midi_info_result: String::new(),
             scanning_midi: false,
             current_tab: Tab::ParameterSetting,
             show_about_dialog: false,
             fret_dancer_state: None,
+            left_hand_segment_cache: Arc::new(Mutex::new(LeftHandSegmentCache::default())),
             output_receiver: None,
             is_processing: false,
         };
@@ -1,3 +1,4 @@
+use crate::guitar::instrument_profile::{InstrumentProfile, RightHandTechnique};
 use crate::ui::app::{AvatarInfo, EditAvatarMode, FretDanceApp};
 use std::fs;
 use std::path::Path;
@@ -33,39 +34,19 @@ impl FretDanceApp {
             .find(|info| info.name == self.avatar)
             .cloned();
 
-        // 根据乐器类型自动设置调弦预设
+        // 根据乐器类型自动设置乐器配置和调弦
         if let Some(ref avatar_info) = self.current_avatar_info {
-            let instrument_type = avatar_info.instrument.as_str();
-            match instrument_type {
-                "bass" => {
-                    // 如果是bass，设置bass调弦
-                    if let Some(bass_preset) =
-                        self.tuning_presets.iter().find(|p| p.name.contains("Bass"))
-                    {
-                        self.guitar_string_notes = bass_preset.notes.clone();
-                    }
-                }
-                "finger_style_guitar" | "electric_guitar" => {
-                    // 如果是吉他，设置标准吉他调弦
-                    if let Some(guitar_preset) = self
-                        .tuning_presets
-                        .iter()
-                        .find(|p| p.name.contains("标准调弦"))
-                    {
-                        self.guitar_string_notes = guitar_preset.notes.clone();
-                    }
-                }
-                _ => {
-                    // 默认使用标准吉他调弦
-                    if let Some(guitar_preset) = self
-                        .tuning_presets
-                        .iter()
-                        .find(|p| p.name.contains("标准调弦"))
-                    {
-                        self.guitar_string_notes = guitar_preset.notes.clone();
-                    }
-                }
+            let technique = RightHandTechnique::from_instrument(&avatar_info.instrument);
+            let is_bass_profile =
+                self.instrument_profile.right_hand_technique == RightHandTechnique::Bass;
+
+            // 只有当前配置与乐器类型不符时才替换，避免覆盖用户选择的扩展音域配置
+            if (technique == RightHandTechnique::Bass) != is_bass_profile {
+                self.instrument_profile =
+                    InstrumentProfile::default_for_instrument(&avatar_info.instrument);
+                self.guitar_string_notes = self.instrument_profile.tuning.clone();
             }
+            self.instrument_profile.right_hand_technique = technique;
         }
     }
 
@@ -195,6 +176,8 @@ impl FretDanceApp {
             file: json_filename,
             image: image_filename,
             instrument: self.edit_avatar_instrument.as_str().to_string(),
+            hand_profile: self.edit_avatar_hand_profile,
+            handedness: self.edit_avatar_handedness,
         };
 
         match self.edit_avatar_mode {
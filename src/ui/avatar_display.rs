@@ -1,3 +1,5 @@
+use crate::animate::handedness::Handedness;
+use crate::hand::hand_profile::HandProfile;
 use crate::ui::app::{EditAvatarMode, FretDanceApp, InstrumentType};
 use crate::ui::theme;
 use crate::utils::compare_json::compare_json_structure;
@@ -224,6 +226,53 @@ pub fn show_edit_avatar_interface(app: &mut FretDanceApp, ui: &mut egui::Ui) {
                         });
                 });
 
+                // 持琴方式，左手持琴的角色输出动画时会镜像
+                ui.horizontal(|ui| {
+                    ui.label("持琴方式:");
+                    egui::ComboBox::from_id_source("handedness_select")
+                        .selected_text(app.edit_avatar_handedness.label())
+                        .show_ui(ui, |ui| {
+                            for handedness in Handedness::all() {
+                                ui.selectable_value(
+                                    &mut app.edit_avatar_handedness,
+                                    handedness,
+                                    handedness.label(),
+                                );
+                            }
+                        });
+                })
+                .response
+                .on_hover_text("左手持琴时，人物数据仍按右手持琴录制，输出的动画会左右镜像");
+
+                ui.add_space(10.0);
+
+                // 手型数据，体型较小的角色跨度也小
+                let hand_profile = &mut app.edit_avatar_hand_profile;
+                ui.horizontal(|ui| {
+                    ui.label("手指张开品数:");
+                    for (span, hint) in hand_profile.finger_spans.iter_mut().zip([
+                        "食指-中指",
+                        "中指-无名指",
+                        "无名指-小指",
+                    ]) {
+                        ui.add(egui::DragValue::new(span).range(1..=4))
+                            .on_hover_text(hint);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("低把位延展品数:");
+                    ui.add(
+                        egui::DragValue::new(&mut hand_profile.low_position_stretch).range(0..=3),
+                    )
+                    .on_hover_text("低把位时中指-无名指、无名指-小指最多能张开的品格数");
+                    ui.label("最大跨度:");
+                    ui.add(egui::DragValue::new(&mut hand_profile.max_span).range(2..=8))
+                        .on_hover_text("低把位时食指到小指最多能跨越的品格数");
+                    ui.checkbox(&mut hand_profile.can_barre, "能够横按");
+                    ui.checkbox(&mut hand_profile.thumb_over, "拇指按弦")
+                        .on_hover_text("拇指从琴颈上方绕过来按最低的两根弦");
+                });
+
                 ui.add_space(20.0);
 
                 // 按钮行
@@ -288,6 +337,8 @@ fn prepare_edit_avatar(app: &mut FretDanceApp, mode: EditAvatarMode) {
             app.edit_avatar_json = String::new();
             app.edit_avatar_selected_json_path = String::new();
             app.edit_avatar_instrument = InstrumentType::FingerStyleGuitar;
+            app.edit_avatar_hand_profile = HandProfile::default();
+            app.edit_avatar_handedness = Handedness::default();
         }
         EditAvatarMode::Edit => {
             // 修改模式 - 填充当前Avatar信息
@@ -298,6 +349,8 @@ fn prepare_edit_avatar(app: &mut FretDanceApp, mode: EditAvatarMode) {
                 app.edit_avatar_json = avatar_info.file.clone();
                 app.edit_avatar_selected_json_path = String::new();
                 app.edit_avatar_instrument = InstrumentType::from_str(&avatar_info.instrument);
+                app.edit_avatar_hand_profile = avatar_info.hand_profile;
+                app.edit_avatar_handedness = avatar_info.handedness;
             }
         }
     }
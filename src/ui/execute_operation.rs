@@ -1,6 +1,8 @@
 use crate::fret_dancer::FretDancer;
 use crate::ui::app::FretDanceApp;
+use crate::ui::show_alternatives::show_left_hand_alternatives;
 use crate::ui::show_console::show_console_output;
+use crate::ui::show_trace::show_left_hand_trace;
 use crate::ui::theme;
 use eframe::egui;
 use std::sync::mpsc;
@@ -195,8 +197,13 @@ pub fn show_execute_operation(app: &mut FretDanceApp, ui: &mut egui::Ui) {
                                         .color(theme::get_label_color(ui))
                                         .size(12.0),
                                 ));
+                                let channel_text = if app.channel_per_string {
+                                    format!("每根弦一个通道，从{}开始", app.first_string_channel)
+                                } else {
+                                    app.channel_number.to_string()
+                                };
                                 ui.add(egui::Label::new(
-                                    egui::RichText::new(app.channel_number.to_string()).size(12.0),
+                                    egui::RichText::new(channel_text).size(12.0),
                                 ));
                             });
                         });
@@ -466,6 +473,14 @@ pub fn show_execute_operation(app: &mut FretDanceApp, ui: &mut egui::Ui) {
             // 右半部分：控制台输出
             ui.vertical(|ui| {
                 show_console_output(app, ui, 14.0);
+                if app.job_config.left_hand_trace || app.left_hand_trace.is_some() {
+                    ui.add_space(10.0);
+                    show_left_hand_trace(app, ui, 14.0);
+                }
+                if app.job_config.alternative_count > 0 || app.left_hand_alternatives.is_some() {
+                    ui.add_space(10.0);
+                    show_left_hand_alternatives(app, ui, 14.0);
+                }
             });
         });
 
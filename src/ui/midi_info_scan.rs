@@ -166,6 +166,18 @@ pub fn show_midi_info_scan(app: &mut FretDanceApp, ui: &mut egui::Ui) {
                         ));
                         ui.add(egui::DragValue::new(&mut app.channel_number).speed(1.0));
                     });
+
+                    // 按弦分通道，设置后每个音符的弦由通道决定
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut app.channel_per_string, "每根弦一个通道");
+                        if app.channel_per_string {
+                            ui.label("第一弦通道:");
+                            ui.add(
+                                egui::DragValue::new(&mut app.first_string_channel).range(0..=15),
+                            )
+                            .on_hover_text("最细的弦使用的通道，之后的弦依次使用下一个通道");
+                        }
+                    });
                 });
             });
 
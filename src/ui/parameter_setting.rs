@@ -1,3 +1,8 @@
+use crate::hand::left_hand_cost::LeftHandCostWeights;
+use crate::job_config::{JobConfig, LeftHandSolverKind};
+use crate::recorder::long_rest::RestLengthUnit;
+use crate::recorder::note_fallback::FallbackStrategy;
+use crate::recorder::phrase_repeat::PhraseConsistencyMode;
 use crate::ui::app::FretDanceApp;
 use crate::ui::avatar_display;
 use crate::ui::show_console::show_console_output;
@@ -55,8 +60,38 @@ pub fn show_parameter_setting(app: &mut FretDanceApp, ui: &mut egui::Ui) {
                                 ui.add(egui::DragValue::new(&mut app.fps).speed(1.0));
                             });
 
+                            // 乐器配置选择
+                            ui.horizontal(|ui| {
+                                ui.label("乐器配置:");
+                                egui::ComboBox::from_id_source("instrument_profile")
+                                    .selected_text(&app.instrument_profile.name)
+                                    .show_ui(ui, |ui| {
+                                        for profile in &app.instrument_profiles {
+                                            if ui
+                                                .selectable_label(
+                                                    app.instrument_profile.name == profile.name,
+                                                    &profile.name,
+                                                )
+                                                .clicked()
+                                            {
+                                                app.instrument_profile = profile.clone();
+                                                app.guitar_string_notes = profile.tuning.clone();
+                                            }
+                                        }
+                                    });
+                            });
+
+                            // 品数设置
+                            ui.horizontal(|ui| {
+                                ui.label("品数:");
+                                ui.add(
+                                    egui::DragValue::new(&mut app.instrument_profile.fret_count)
+                                        .range(12..=30),
+                                );
+                            });
+
                             // 吉他弦音高设置
-                            ui.label("吉他弦音高 (从最细的弦到最粗的弦):");
+                            ui.label("吉他弦音高 (从0弦开始，通常是最细的弦):");
                             // 添加预设调弦下拉菜单
                             ui.horizontal(|ui| {
                                 ui.label("常用调弦:");
@@ -105,6 +140,340 @@ pub fn show_parameter_setting(app: &mut FretDanceApp, ui: &mut egui::Ui) {
 
                             // Use harm notes checkbox
                             ui.checkbox(&mut app.disable_barre, "禁用横按");
+
+                            // 和弦指型库
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut app.use_chord_shapes, "使用和弦指型库");
+                                if app.use_chord_shapes {
+                                    ui.text_edit_singleline(&mut app.chord_shape_file);
+                                }
+                            });
+                            if app.use_chord_shapes {
+                                ui.horizontal(|ui| {
+                                    ui.label("指型库按法奖励:");
+                                    ui.add(
+                                        egui::DragValue::new(
+                                            &mut app.job_config.chord_shape_bonus,
+                                        )
+                                        .speed(0.05)
+                                        .range(0.0..=10.0),
+                                    );
+                                    ui.checkbox(
+                                        &mut app.job_config.chord_shapes_compete,
+                                        "与通用按法一起比较",
+                                    );
+                                });
+                            }
+
+                            // 按法约束
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut app.use_fingering_constraints, "使用按法约束");
+                                if app.use_fingering_constraints {
+                                    ui.text_edit_singleline(&mut app.fingering_constraint_file);
+                                }
+                            });
+
+                            // 左手风格，选择后会覆盖任务配置里的左手消耗权重
+                            ui.horizontal(|ui| {
+                                ui.label("左手风格:");
+                                egui::ComboBox::from_id_source("left_hand_style")
+                                    .selected_text(&app.job_config.left_hand_style)
+                                    .show_ui(ui, |ui| {
+                                        for (style, _) in LeftHandCostWeights::presets() {
+                                            if ui
+                                                .selectable_label(
+                                                    app.job_config.left_hand_style == style,
+                                                    style,
+                                                )
+                                                .clicked()
+                                            {
+                                                app.job_config.apply_left_hand_style(style);
+                                            }
+                                        }
+                                    });
+                            });
+
+                            // 换把速度上限，超过上限的换把会在报告中标记出来
+                            ui.horizontal(|ui| {
+                                ui.label("换把速度上限 (cm/s):");
+                                ui.add(
+                                    egui::DragValue::new(
+                                        &mut app.job_config.left_hand_cost.max_hand_speed,
+                                    )
+                                    .speed(1.0)
+                                    .range(1.0..=2000.0),
+                                );
+                            });
+
+                            // 左手求解器
+                            ui.horizontal(|ui| {
+                                ui.label("左手求解器:");
+                                egui::ComboBox::from_id_source("left_hand_solver")
+                                    .selected_text(app.job_config.left_hand_solver.label())
+                                    .show_ui(ui, |ui| {
+                                        for solver in LeftHandSolverKind::all() {
+                                            ui.selectable_value(
+                                                &mut app.job_config.left_hand_solver,
+                                                solver,
+                                                solver.label(),
+                                            );
+                                        }
+                                    });
+                                if app.job_config.left_hand_solver == LeftHandSolverKind::Beam {
+                                    ui.label("束宽:");
+                                    ui.add(
+                                        egui::DragValue::new(&mut app.job_config.beam_width)
+                                            .range(1..=10000),
+                                    );
+                                    ui.label("线程数:");
+                                    ui.add(
+                                        egui::DragValue::new(&mut app.job_config.thread_count)
+                                            .range(0..=256),
+                                    )
+                                    .on_hover_text("0表示使用所有可用的CPU核心");
+                                }
+                            });
+                            ui.checkbox(
+                                &mut app.job_config.compare_left_hand_solvers,
+                                "比较束搜索结果与最优解",
+                            );
+                            ui.horizontal(|ui| {
+                                ui.label("连奏最大间隔(秒):");
+                                ui.add(
+                                    egui::DragValue::new(&mut app.job_config.legato_max_gap)
+                                        .range(0.0..=2.0)
+                                        .speed(0.01),
+                                )
+                                .on_hover_text("同一根弦上间隔不超过这个时间的单音识别为击弦、勾弦或滑弦，0表示不识别");
+                            });
+                            ui.horizontal(|ui| {
+                                ui.checkbox(
+                                    &mut app.job_config.hold_note_durations,
+                                    "按音符时值按弦",
+                                )
+                                .on_hover_text(
+                                    "音符持续时空闲的手指留在弦上，断奏和休止时手指在音符结束时松开",
+                                );
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("长休止回到休息姿势:");
+                                ui.add(
+                                    egui::DragValue::new(&mut app.job_config.rest_pose_min_length)
+                                        .range(0.0..=64.0)
+                                        .speed(0.25),
+                                )
+                                .on_hover_text(
+                                    "休止不短于这个长度时双手移动到休息姿势，在下一个音之前回来，0表示不回到休息姿势",
+                                );
+                                egui::ComboBox::from_id_source("rest_pose_unit")
+                                    .selected_text(app.job_config.rest_pose_unit.label())
+                                    .show_ui(ui, |ui| {
+                                        for unit in RestLengthUnit::all() {
+                                            ui.selectable_value(
+                                                &mut app.job_config.rest_pose_unit,
+                                                unit,
+                                                unit.label(),
+                                            );
+                                        }
+                                    });
+                            });
+                            ui.horizontal(|ui| {
+                                ui.checkbox(
+                                    &mut app.job_config.left_hand_trace,
+                                    "输出左手求解追踪",
+                                )
+                                .on_hover_text("记录每一步的消耗组成、落选的按法和池子大小");
+                                if app.job_config.left_hand_trace {
+                                    ui.label("落选按法数:");
+                                    ui.add(
+                                        egui::DragValue::new(
+                                            &mut app.job_config.trace_runner_up_count,
+                                        )
+                                        .range(0..=50),
+                                    );
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("备选按法数:");
+                                ui.add(
+                                    egui::DragValue::new(&mut app.job_config.alternative_count)
+                                        .range(0..=10),
+                                )
+                                .on_hover_text("包含最优解，0表示不输出备选按法，分段求解时不输出");
+                                if app.job_config.alternative_count > 0 {
+                                    ui.label("至少不同的手型数:");
+                                    ui.add(
+                                        egui::DragValue::new(
+                                            &mut app.job_config.alternative_min_different_poses,
+                                        )
+                                        .range(1..=100),
+                                    );
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("无法按弦时:")
+                                    .on_hover_text("依次尝试勾选的处理方式，都失败时重复上一个手型");
+                                for strategy in FallbackStrategy::default_order() {
+                                    let strategies = &mut app.job_config.fallback_strategies;
+                                    let mut enabled = strategies.contains(&strategy);
+                                    if ui.checkbox(&mut enabled, strategy.label()).changed() {
+                                        // 保持默认的尝试顺序，不丢音的方式在前
+                                        strategies.retain(|&s| s != strategy);
+                                        if enabled {
+                                            strategies.push(strategy);
+                                            strategies.sort_by_key(|s| {
+                                                FallbackStrategy::default_order()
+                                                    .iter()
+                                                    .position(|d| d == s)
+                                            });
+                                        }
+                                    }
+                                }
+                                if app
+                                    .job_config
+                                    .fallback_strategies
+                                    .contains(&FallbackStrategy::Roll)
+                                {
+                                    ui.label("琶音间隔(拍):");
+                                    ui.add(
+                                        egui::DragValue::new(&mut app.job_config.roll_gap_beats)
+                                            .range(0.0..=1.0)
+                                            .speed(0.005),
+                                    );
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label("重复乐句:")
+                                    .on_hover_text("让重复出现的乐句使用和第一次相同的按法");
+                                egui::ComboBox::from_id_source("phrase_consistency")
+                                    .selected_text(app.job_config.phrase_consistency.label())
+                                    .show_ui(ui, |ui| {
+                                        for mode in PhraseConsistencyMode::all() {
+                                            ui.selectable_value(
+                                                &mut app.job_config.phrase_consistency,
+                                                mode,
+                                                mode.label(),
+                                            );
+                                        }
+                                    });
+                                if app.job_config.phrase_consistency != PhraseConsistencyMode::Off {
+                                    ui.label("最少音符数:");
+                                    ui.add(
+                                        egui::DragValue::new(
+                                            &mut app.job_config.phrase_min_events,
+                                        )
+                                        .range(2..=64),
+                                    );
+                                }
+                                if app.job_config.phrase_consistency == PhraseConsistencyMode::Bonus {
+                                    ui.label("奖励:");
+                                    ui.add(
+                                        egui::DragValue::new(&mut app.job_config.phrase_bonus)
+                                            .range(0.0..=10.0)
+                                            .speed(0.05),
+                                    )
+                                    .on_hover_text("动态规划求解器不支持奖励，会在求解后直接复用按法");
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut app.job_config.segment_solving, "分段求解")
+                                    .on_hover_text(
+                                        "在长休止和段落标记处分段，再次生成时只重新计算有变化的分段",
+                                    );
+                                if app.job_config.segment_solving {
+                                    ui.label("分段休止(拍):");
+                                    ui.add(
+                                        egui::DragValue::new(
+                                            &mut app.job_config.segment_min_rest_beats,
+                                        )
+                                        .range(0.0..=64.0)
+                                        .speed(0.25),
+                                    )
+                                    .on_hover_text("0表示只在段落标记处分段");
+                                    // 求解线程正在写入缓存时不等待，下一帧再显示
+                                    match app.left_hand_segment_cache.try_lock() {
+                                        Ok(mut cache) => {
+                                            ui.label(format!("已缓存{}段", cache.len()));
+                                            if ui.button("清空缓存").clicked() {
+                                                cache.clear();
+                                            }
+                                        }
+                                        Err(_) => {
+                                            ui.label("缓存更新中");
+                                        }
+                                    }
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut app.job_config.position_planning, "先规划把位")
+                                    .on_hover_text(
+                                        "先为每个乐句选择换把最少的把位，再在这个把位附近求解按法",
+                                    );
+                                if app.job_config.position_planning {
+                                    ui.label("乐句事件数:");
+                                    ui.add(
+                                        egui::DragValue::new(
+                                            &mut app.job_config.position_phrase_events,
+                                        )
+                                        .range(1..=64),
+                                    );
+                                    ui.label("把位容差(品):");
+                                    ui.add(
+                                        egui::DragValue::new(
+                                            &mut app.job_config.position_tolerance,
+                                        )
+                                        .range(0..=5),
+                                    );
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                let mute_detection = &mut app.job_config.mute_detection;
+                                ui.label("闷音键位:");
+                                ui.add(
+                                    egui::DragValue::new(&mut mute_detection.keyswitch_note)
+                                        .range(-1..=127),
+                                )
+                                .on_hover_text("按住这个MIDI音符时演奏的音都是闷音，-1表示不使用");
+                                ui.label("闷音力度:");
+                                ui.add(
+                                    egui::DragValue::new(&mut mute_detection.max_velocity)
+                                        .range(0..=127),
+                                )
+                                .on_hover_text("力度不超过这个值的音是闷音，0表示不使用");
+                                ui.label("闷音标记:");
+                                ui.add(
+                                    egui::TextEdit::singleline(&mut mute_detection.marker_text)
+                                        .desired_width(40.0),
+                                )
+                                .on_hover_text("和这个文字相同的MIDI标记处开始的音都是闷音");
+                            });
+
+                            // 任务配置文件
+                            ui.horizontal(|ui| {
+                                ui.label("任务配置文件:");
+                                ui.text_edit_singleline(&mut app.job_config_file);
+                                if ui.button("读取").clicked() {
+                                    match JobConfig::load_from_file(&app.job_config_file) {
+                                        Ok(config) => {
+                                            app.job_config = config;
+                                            app.append_console_output("任务配置读取完成");
+                                        }
+                                        Err(e) => app.append_console_output(&format!(
+                                            "读取任务配置失败: {}",
+                                            e
+                                        )),
+                                    }
+                                }
+                                if ui.button("保存").clicked() {
+                                    match app.job_config.save_to_file(&app.job_config_file) {
+                                        Ok(()) => app.append_console_output("任务配置保存完成"),
+                                        Err(e) => app.append_console_output(&format!(
+                                            "保存任务配置失败: {}",
+                                            e
+                                        )),
+                                    }
+                                }
+                            });
                         });
                     });
 
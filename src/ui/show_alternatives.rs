@@ -0,0 +1,144 @@
+use crate::fret_dancer::FretDancer;
+use crate::recorder::alternatives::AlternativesReport;
+use crate::ui::app::FretDanceApp;
+use crate::ui::theme;
+use eframe::egui;
+
+/// 显示左手备选按法，可以整体使用某个备选，也可以为每个段落分别选择
+pub fn show_left_hand_alternatives(app: &mut FretDanceApp, ui: &mut egui::Ui, size: f32) {
+    egui::Frame::group(ui.style())
+        .fill(theme::get_midi_info_bg_color(ui))
+        .rounding(4.0)
+        .inner_margin(10.0)
+        .stroke(egui::Stroke::new(1.0, theme::get_border_color(ui)))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            ui.vertical(|ui| {
+                ui.horizontal(|ui| {
+                    ui.add(egui::Label::new(
+                        egui::RichText::new("左手备选按法")
+                            .size(size)
+                            .color(theme::get_title_color(ui, false))
+                            .strong(),
+                    ));
+                    ui.separator();
+
+                    if ui.button("读取备选按法").clicked() {
+                        load_left_hand_alternatives(app);
+                    }
+                    if app.left_hand_alternatives.is_some() && ui.button("关闭").clicked() {
+                        app.left_hand_alternatives = None;
+                    }
+                });
+
+                let Some(report) = app.left_hand_alternatives.clone() else {
+                    return;
+                };
+                ui.add_space(5.0);
+
+                for info in &report.alternatives {
+                    ui.horizontal(|ui| {
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "备选{} | 熵 {:.3} | {}个手型不同",
+                                info.index, info.entropy, info.different_poses
+                            ))
+                            .monospace(),
+                        );
+                        if ui.button("整体使用").clicked() {
+                            let choices = vec![info.index; report.sections.len()];
+                            apply_alternative_choices(app, &report, &choices);
+                        }
+                    });
+                }
+
+                let choosable: Vec<usize> = (0..report.sections.len())
+                    .filter(|&index| report.sections[index].options.len() > 1)
+                    .collect();
+                if choosable.is_empty() {
+                    ui.label("每个段落的备选按法都相同");
+                    return;
+                }
+                ui.add_space(5.0);
+                ui.label("按段落选择:");
+
+                app.alternative_choices.resize(report.sections.len(), 0);
+                egui::ScrollArea::vertical()
+                    .id_source("left_hand_alternatives_scroll")
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        for &index in &choosable {
+                            let section = &report.sections[index];
+                            ui.horizontal(|ui| {
+                                ui.label(format!(
+                                    "第{}-{}小节",
+                                    section.start_bar, section.end_bar
+                                ));
+                                let choice = &mut app.alternative_choices[index];
+                                egui::ComboBox::from_id_source(("alternative_section", index))
+                                    .selected_text(format!("备选{}", choice))
+                                    .show_ui(ui, |ui| {
+                                        for &option in &section.options {
+                                            ui.selectable_value(
+                                                choice,
+                                                option,
+                                                format!("备选{}", option),
+                                            );
+                                        }
+                                    });
+                            });
+                        }
+                    });
+
+                if ui.button("应用选择").clicked() {
+                    let choices = app.alternative_choices.clone();
+                    apply_alternative_choices(app, &report, &choices);
+                }
+            });
+        });
+}
+
+/// 读取当前任务的备选按法
+fn load_left_hand_alternatives(app: &mut FretDanceApp) {
+    let Some(state) = &app.fret_dancer_state else {
+        app.append_console_output("读取备选按法失败: 请先初始化");
+        return;
+    };
+    let alternatives_file = state.left_hand_alternatives_file.clone();
+    match AlternativesReport::load_from_file(&alternatives_file) {
+        Ok(report) => {
+            app.append_console_output(&format!("已读取{}个备选按法", report.alternatives.len()));
+            app.alternative_choices = vec![0; report.sections.len()];
+            app.left_hand_alternatives = Some(report);
+        }
+        Err(e) => app.append_console_output(&format!(
+            "读取备选按法失败，请确认已设置备选按法数并生成了左手动作: {}",
+            e
+        )),
+    }
+}
+
+/// 按选择拼接左手记录，写入当前任务的左手记录文件，之后生成的左手动画会使用它
+fn apply_alternative_choices(
+    app: &mut FretDanceApp,
+    report: &AlternativesReport,
+    choices: &[usize],
+) {
+    let Some(state) = &app.fret_dancer_state else {
+        app.append_console_output("应用备选按法失败: 请先初始化");
+        return;
+    };
+    let recorder_file = state.left_hand_recorder_file.clone();
+    match FretDancer::apply_left_hand_alternatives(state, report, choices) {
+        Ok(warnings) => {
+            for warning in warnings {
+                app.append_console_output(&warning);
+            }
+            app.append_console_output(&format!(
+                "已把选择的按法写入: {}，请重新生成左手动画",
+                recorder_file
+            ));
+        }
+        Err(e) => app.append_console_output(&format!("应用备选按法失败: {}", e)),
+    }
+}
@@ -0,0 +1,152 @@
+use crate::guitar::music_note::MusicNote;
+use crate::recorder::left_hand_trace::{LeftHandTrace, TracedFingering};
+use crate::ui::app::FretDanceApp;
+use crate::ui::theme;
+use eframe::egui;
+
+/// 显示左手求解追踪，追踪文件由生成左手动作时写入
+pub fn show_left_hand_trace(app: &mut FretDanceApp, ui: &mut egui::Ui, size: f32) {
+    egui::Frame::group(ui.style())
+        .fill(theme::get_midi_info_bg_color(ui))
+        .rounding(4.0)
+        .inner_margin(10.0)
+        .stroke(egui::Stroke::new(1.0, theme::get_border_color(ui)))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            ui.vertical(|ui| {
+                ui.horizontal(|ui| {
+                    ui.add(egui::Label::new(
+                        egui::RichText::new("左手求解追踪")
+                            .size(size)
+                            .color(theme::get_title_color(ui, false))
+                            .strong(),
+                    ));
+                    ui.separator();
+
+                    if ui.button("读取追踪").clicked() {
+                        load_left_hand_trace(app);
+                    }
+                    if app.left_hand_trace.is_some() && ui.button("关闭").clicked() {
+                        app.left_hand_trace = None;
+                    }
+                });
+
+                let Some(trace) = &app.left_hand_trace else {
+                    return;
+                };
+                ui.add_space(5.0);
+
+                egui::ScrollArea::vertical()
+                    .id_source("left_hand_trace_scroll")
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        for (index, step) in trace.steps.iter().enumerate() {
+                            let note_names: Vec<String> = step
+                                .notes
+                                .iter()
+                                .map(|&note| MusicNote::new(note).get_keynote())
+                                .collect();
+                            let mut title = egui::RichText::new(format!(
+                                "tick {} | {} | 熵 {:.3} (+{:.3}) | 池子 {}",
+                                step.real_tick,
+                                note_names.join(" "),
+                                step.entropy,
+                                step.chosen.cost,
+                                step.pool_size
+                            ))
+                            .monospace();
+                            if step.repeated {
+                                title = title.color(egui::Color32::from_rgb(200, 200, 100));
+                            }
+
+                            egui::CollapsingHeader::new(title)
+                                .id_source(("left_hand_trace_step", index))
+                                .show(ui, |ui| {
+                                    if step.repeated {
+                                        ui.label("没有按法能从上一个手型过渡，重复了上一个手型");
+                                    }
+                                    show_fingering_grid(ui, index, &step.chosen, &step.runner_ups);
+                                });
+                        }
+                    });
+            });
+        });
+}
+
+/// 读取当前任务的追踪文件
+fn load_left_hand_trace(app: &mut FretDanceApp) {
+    let Some(state) = &app.fret_dancer_state else {
+        app.append_console_output("读取追踪失败: 请先初始化");
+        return;
+    };
+    let trace_file = state.left_hand_trace_file.clone();
+    match LeftHandTrace::load_from_file(&trace_file) {
+        Ok(trace) => {
+            app.append_console_output(&format!("已读取{}步左手求解追踪", trace.steps.len()));
+            app.left_hand_trace = Some(trace);
+        }
+        Err(e) => app.append_console_output(&format!(
+            "读取追踪失败，请确认已开启追踪并生成了左手动作: {}",
+            e
+        )),
+    }
+}
+
+/// 用表格对比胜出的按法和落选的按法
+fn show_fingering_grid(
+    ui: &mut egui::Ui,
+    index: usize,
+    chosen: &TracedFingering,
+    runner_ups: &[TracedFingering],
+) {
+    egui::Grid::new(("left_hand_trace_grid", index))
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("");
+            ui.label("按法");
+            ui.label("熵");
+            for (name, _) in chosen.cost_terms.named_terms() {
+                ui.label(name);
+            }
+            ui.end_row();
+
+            let rows = std::iter::once(("选中", chosen))
+                .chain(runner_ups.iter().map(|fingering| ("落选", fingering)));
+            for (label, fingering) in rows {
+                ui.label(label);
+                ui.monospace(describe_fingering(fingering));
+                ui.monospace(format!("{:.3}", fingering.cost));
+                for (_, value) in fingering.cost_terms.named_terms() {
+                    ui.monospace(format!("{:.3}", value));
+                }
+                ui.end_row();
+            }
+        });
+}
+
+/// 按法的简短描述，例如"1指5弦3品 空弦3弦"，弦号从1开始
+fn describe_fingering(fingering: &TracedFingering) -> String {
+    let mut parts: Vec<String> = fingering
+        .fingers
+        .iter()
+        .filter_map(|finger| {
+            let info = &finger.finger_info;
+            if finger.finger_index == -1 {
+                Some(format!("空弦{}弦", info.string_index + 1))
+            } else if info.press != "Open" {
+                Some(format!(
+                    "{}指{}弦{}品",
+                    finger.finger_index,
+                    info.string_index + 1,
+                    info.fret
+                ))
+            } else {
+                None
+            }
+        })
+        .collect();
+    if fingering.use_barre {
+        parts.push("横按".to_string());
+    }
+    format!("{}把 {}", fingering.hand_position, parts.join(" "))
+}